@@ -0,0 +1,124 @@
+use std::fmt::Write;
+
+use anyhow::{anyhow, bail, Result};
+
+use crate::text::parseutil::cleanwhite;
+
+/// A temporary capability to look up
+#[derive(Clone, Copy)]
+pub struct AList<'t, K, V>(pub &'t [(K, V)]);
+
+impl<'t, K: PartialEq, V> AList<'t, K, V> {
+    pub fn get(&self, key: &K) -> Option<&'t V> {
+        self.0.iter().find(|(k, _)| k == key).map(|(_, v)| v)
+    }
+}
+
+/// An owned association list: keeps the order of insertion, lookups
+/// are linear, which is fine for the small sizes it is meant for (like
+/// config files).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AListBuf<K, V>(pub Vec<(K, V)>);
+
+impl<K, V> Default for AListBuf<K, V> {
+    fn default() -> Self {
+        AListBuf(Vec::new())
+    }
+}
+
+impl<K: PartialEq, V> AListBuf<K, V> {
+    pub fn as_alist(&self) -> AList<'_, K, V> {
+        AList(&self.0)
+    }
+
+    pub fn get(&self, key: &K) -> Option<&V> {
+        self.as_alist().get(key)
+    }
+
+    /// Replace the value of an existing entry in place, or append a
+    /// new entry. Returns the old value.
+    pub fn set(&mut self, key: K, val: V) -> Option<V> {
+        match self.0.iter_mut().find(|(k, _)| *k == key) {
+            Some((_, v)) => Some(std::mem::replace(v, val)),
+            None => {
+                self.0.push((key, val));
+                None
+            }
+        }
+    }
+}
+
+impl AListBuf<String, String> {
+    /// Parse the `key = value` text format: one entry per line,
+    /// whitespace around keys and values is ignored, empty lines and
+    /// lines starting with `#` are skipped. Keys must be unique.
+    pub fn from_text(s: &str) -> Result<Self> {
+        let mut alist = AListBuf::default();
+        for (i, line) in s.lines().enumerate() {
+            let line = cleanwhite(line);
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (key, val) = line.split_once('=').ok_or_else(|| {
+                anyhow!("line {}: missing '=' in {line:?}", i + 1)
+            })?;
+            let key = cleanwhite(key);
+            if key.is_empty() {
+                bail!("line {}: empty key", i + 1)
+            }
+            if alist.set(key.into(), cleanwhite(val).into()).is_some() {
+                bail!("line {}: duplicate key {key:?}", i + 1)
+            }
+        }
+        Ok(alist)
+    }
+
+    /// The entries in the format read by `from_text`. Fails for
+    /// entries that can't be represented: keys that are empty, or
+    /// contain `=` or start with `#`, newlines in keys or values, and
+    /// leading or trailing whitespace.
+    pub fn to_text(&self) -> Result<String> {
+        let mut out = String::new();
+        for (key, val) in &self.0 {
+            if key.is_empty()
+                || key.contains('=')
+                || key.starts_with('#')
+                || key.contains('\n')
+                || val.contains('\n')
+                || cleanwhite(key) != key
+                || cleanwhite(val) != val
+            {
+                bail!("entry can't be represented as text: {key:?} = {val:?}")
+            }
+            writeln!(out, "{key} = {val}").expect("writing to a String");
+        }
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn t_text() -> Result<()> {
+        let alist = AListBuf::from_text(
+            "# editor config\n\nfoo = bar baz \n  x=1=2\nempty =\n",
+        )?;
+        assert_eq!(alist.get(&"foo".into()).unwrap(), "bar baz");
+        assert_eq!(alist.get(&"x".into()).unwrap(), "1=2");
+        assert_eq!(alist.get(&"empty".into()).unwrap(), "");
+        assert_eq!(alist.0[0].0, "foo");
+        let text = alist.to_text()?;
+        assert_eq!(text, "foo = bar baz\nx = 1=2\nempty = \n");
+        assert_eq!(AListBuf::from_text(&text)?, alist);
+
+        assert!(AListBuf::from_text("a = 1\na = 2").is_err());
+        assert!(AListBuf::from_text("a").is_err());
+        assert!(AListBuf::from_text("= 1").is_err());
+        let mut alist = AListBuf::default();
+        alist.set("a=b".to_string(), "1".to_string());
+        assert!(alist.to_text().is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,105 @@
+//! A Bloom filter: an approximate set that uses a fixed, small amount
+//! of memory, at the cost of false positives (`contains` may return
+//! true for items that were never inserted; never the opposite).
+
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash};
+
+use anyhow::{bail, Result};
+
+#[derive(Debug)]
+pub struct BloomFilter {
+    bits: Vec<u64>,
+    num_bits: u64,
+    num_hashes: u32,
+    hashers: (RandomState, RandomState),
+}
+
+impl BloomFilter {
+    /// A filter sized for `expected_items` insertions, to give a
+    /// false-positive rate of about `fp_rate` (which must be between
+    /// 0 and 1, exclusive) when that many have been inserted.
+    pub fn with_rate(expected_items: usize, fp_rate: f64) -> Result<Self> {
+        if !(fp_rate > 0. && fp_rate < 1.) {
+            bail!("false-positive rate must be between 0 and 1, got {fp_rate}")
+        }
+        let n = expected_items.max(1) as f64;
+        let ln2 = std::f64::consts::LN_2;
+        let num_bits = (-n * fp_rate.ln() / (ln2 * ln2)).ceil().max(64.);
+        let num_hashes = (num_bits / n * ln2).round().max(1.);
+        let num_bits = num_bits as u64;
+        Ok(BloomFilter {
+            bits: vec![0; num_bits.div_ceil(64) as usize],
+            num_bits,
+            num_hashes: num_hashes as u32,
+            hashers: (RandomState::new(), RandomState::new()),
+        })
+    }
+
+    pub fn num_bits(&self) -> u64 {
+        self.num_bits
+    }
+
+    pub fn num_hashes(&self) -> u32 {
+        self.num_hashes
+    }
+
+    /// The two hashes of `item` for double hashing.
+    fn hashes<T: Hash + ?Sized>(&self, item: &T) -> (u64, u64) {
+        let h1 = self.hashers.0.hash_one(item);
+        // Odd, so that the positions don't repeat early
+        let h2 = self.hashers.1.hash_one(item) | 1;
+        (h1, h2)
+    }
+
+    /// The `i`th bit position for the hashes `(h1, h2)`.
+    fn position(&self, (h1, h2): (u64, u64), i: u32) -> (usize, u64) {
+        let pos = h1.wrapping_add((i as u64).wrapping_mul(h2)) % self.num_bits;
+        ((pos / 64) as usize, 1 << (pos % 64))
+    }
+
+    pub fn insert<T: Hash + ?Sized>(&mut self, item: &T) {
+        let hashes = self.hashes(item);
+        for i in 0..self.num_hashes {
+            let (word, bit) = self.position(hashes, i);
+            self.bits[word] |= bit;
+        }
+    }
+
+    pub fn contains<T: Hash + ?Sized>(&self, item: &T) -> bool {
+        let hashes = self.hashes(item);
+        (0..self.num_hashes).all(|i| {
+            let (word, bit) = self.position(hashes, i);
+            self.bits[word] & bit != 0
+        })
+    }
+
+    /// The probability of a false positive given the bits set so far.
+    pub fn estimated_fp_rate(&self) -> f64 {
+        let ones: u64 = self.bits.iter().map(|w| w.count_ones() as u64).sum();
+        (ones as f64 / self.num_bits as f64).powi(self.num_hashes as i32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn t_bloom() -> Result<()> {
+        let mut filter = BloomFilter::with_rate(1000, 0.01)?;
+        assert_eq!(filter.num_hashes(), 7);
+        assert!(filter.estimated_fp_rate() == 0.);
+        for i in 0..1000 {
+            filter.insert(&i);
+        }
+        assert!((0..1000).all(|i| filter.contains(&i)));
+        let false_positives =
+            (1000..101000).filter(|i| filter.contains(i)).count();
+        assert!(false_positives < 2000, "{}", false_positives);
+        let rate = filter.estimated_fp_rate();
+        assert!(rate > 0.002 && rate < 0.03, "{}", rate);
+        assert!(BloomFilter::with_rate(10, 1.).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,283 @@
+//! Scaffolding for the binaries: their naming in `--help`, parsing
+//! the options, log initialization, reporting errors and panics in a
+//! uniform way (with exit codes depending on the kind of error, see
+//! `error`), and writing the output to a file instead of stdout.
+//!
+//! Usage:
+//!
+//! ```ignore
+//! #[derive(clap::Parser, Debug)]
+//! #[clap(name = chj_rustbin::cli_name!())]
+//! struct Opt { .. }
+//!
+//! impl CliOpt for Opt {}
+//!
+//! fn main() {
+//!     cli::main(run)
+//! }
+//!
+//! fn run(opt: Opt) -> Result<()> { .. }
+//! ```
+
+use std::ffi::OsString;
+use std::io::{stdout, BufWriter, Stdout, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use clap::{ArgMatches, Parser};
+use nix::sys::stat::{umask, Mode};
+
+use crate::error::{exit_code, ErrorKind};
+use crate::io::unix_fs::AtomicFile;
+
+/// The name for the `#[clap(name = ..)]` attribute of a binary's
+/// options: `<binary name> from chj-rustbin`.
+#[macro_export]
+macro_rules! cli_name {
+    () => {
+        concat!(env!("CARGO_BIN_NAME"), " from chj-rustbin")
+    };
+}
+
+/// Implemented by the options of binaries run via `main`.
+pub trait CliOpt: Parser {
+    /// The verbosity for log output: 0 for warnings and errors,
+    /// negative for less (errors only), higher for more (info, debug,
+    /// trace).
+    fn verbosity(&self) -> i8 {
+        0
+    }
+}
+
+/// Options to set the verbosity, to be `flatten`ed into a binary's
+/// options (and returned from `CliOpt::verbosity` via `level`).
+#[derive(clap::Args, Debug)]
+pub struct VerbosityArgs {
+    /// Show what is being done; repeat for more log output (info,
+    /// debug, trace; the `RUST_LOG` env var overrides this).
+    #[clap(short, long, parse(from_occurrences))]
+    pub verbose: u8,
+
+    /// Only show errors in the log output.
+    #[clap(short, long, conflicts_with = "verbose")]
+    pub quiet: bool,
+}
+
+/// Implement `CliOpt` for an options struct with a `verbosity:
+/// VerbosityArgs` field.
+#[macro_export]
+macro_rules! impl_cli_opt {
+    {$t:ty} => {
+        impl $crate::cli::CliOpt for $t {
+            fn verbosity(&self) -> i8 {
+                self.verbosity.level()
+            }
+        }
+    }
+}
+
+impl VerbosityArgs {
+    /// Whether `-v` was given (for binaries that report what they
+    /// are doing to stderr, in addition to the log output).
+    pub fn is_verbose(&self) -> bool {
+        self.verbose > 0
+    }
+
+    pub fn level(&self) -> i8 {
+        if self.quiet {
+            -1
+        } else {
+            self.verbose.min(i8::MAX as u8) as i8
+        }
+    }
+}
+
+/// Option to write the output to a file instead of stdout, to be
+/// `flatten`ed into a binary's options.
+#[derive(clap::Args, Debug)]
+pub struct OutputFileArgs {
+    /// Write the output to this file instead of stdout (`-` for
+    /// stdout). The file is replaced atomically once the output is
+    /// complete: readers see either the old or all of the new
+    /// contents, and it is left unchanged if there is an error.
+    #[clap(short = 'o', long, parse(from_os_str), value_name = "FILE")]
+    pub output_file: Option<PathBuf>,
+}
+
+impl OutputFileArgs {
+    pub fn open(&self) -> Result<OutputFile> {
+        match &self.output_file {
+            Some(path) if path.as_os_str() != "-" => {
+                // Like a shell redirection: keep the permissions of
+                // an existing file, or use the umask
+                let mode = match std::fs::metadata(path) {
+                    Ok(m) => m.permissions().mode() & 0o7777,
+                    Err(_) => {
+                        let mask = umask(Mode::empty());
+                        umask(mask);
+                        0o666 & !mask.bits()
+                    }
+                };
+                Ok(OutputFile::File(AtomicFile::create(path, mode)?))
+            }
+            _ => Ok(OutputFile::Stdout(BufWriter::new(stdout()))),
+        }
+    }
+}
+
+/// The output chosen via `OutputFileArgs`, buffered. `commit` has to
+/// be called once the output is complete, otherwise the file is not
+/// written.
+#[derive(Debug)]
+pub enum OutputFile {
+    Stdout(BufWriter<Stdout>),
+    File(AtomicFile),
+}
+
+impl OutputFile {
+    /// Flush stdout, or replace the file.
+    pub fn commit(self) -> Result<()> {
+        match self {
+            OutputFile::Stdout(mut out) => Ok(out.flush()?),
+            OutputFile::File(file) => file.commit(),
+        }
+    }
+}
+
+impl Write for OutputFile {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            OutputFile::Stdout(out) => out.write(buf),
+            OutputFile::File(file) => file.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            OutputFile::Stdout(out) => out.flush(),
+            OutputFile::File(file) => file.flush(),
+        }
+    }
+}
+
+/// For `util::cli_output::OutputArgs::output_to` (colors only for
+/// terminals).
+impl AsRawFd for OutputFile {
+    fn as_raw_fd(&self) -> RawFd {
+        match self {
+            OutputFile::Stdout(_) => 1,
+            OutputFile::File(file) => file.as_raw_fd(),
+        }
+    }
+}
+
+/// The name the program was called as (the file name of
+/// `argv[0]`), for messages.
+pub fn program_name() -> String {
+    std::env::args_os()
+        .next()
+        .and_then(|arg0| Path::new(&arg0).file_name().map(OsString::from))
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "chj-rustbin".into())
+}
+
+/// Initialize `log` output to stderr, for the given verbosity (see
+/// `CliOpt::verbosity`) unless the `RUST_LOG` env var is set.
+pub fn init_logging(verbosity: i8) {
+    let level = match verbosity {
+        i8::MIN..=-1 => "error",
+        0 => "warn",
+        1 => "info",
+        2 => "debug",
+        _ => "trace",
+    };
+    env_logger::Builder::from_env(
+        env_logger::Env::default().default_filter_or(level),
+    )
+    .init();
+}
+
+/// Print panics as a one-line message saying it's a bug, unless
+/// `RUST_BACKTRACE` is set, in which case the default hook is used.
+fn set_panic_hook(program_name: String) {
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        if std::env::var_os("RUST_BACKTRACE").is_some() {
+            default_hook(info)
+        } else {
+            eprintln!("{program_name}: internal error (a bug): {info}");
+        }
+    }));
+}
+
+/// Like `main`, but also passes the `ArgMatches` (needed e.g. to get
+/// the order of options, see `io::excludes::ExcludeArgs::rules`).
+pub fn main_with_matches<O: CliOpt>(
+    main: impl FnOnce(O, &ArgMatches) -> Result<()>,
+) -> ! {
+    main_with_status(|opt, matches| main(opt, matches).map(|()| 0))
+}
+
+/// `main` returns the exit status to use on success.
+fn main_with_status<O: CliOpt>(
+    main: impl FnOnce(O, &ArgMatches) -> Result<i32>,
+) -> ! {
+    let program_name = program_name();
+    set_panic_hook(program_name.clone());
+    let matches = O::command().get_matches();
+    let opt = O::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    init_logging(opt.verbosity());
+    match main(opt, &matches) {
+        Ok(status) => std::process::exit(status),
+        Err(e) => {
+            eprintln!("{program_name}: error: {e:#}");
+            std::process::exit(exit_code(&e))
+        }
+    }
+}
+
+/// Run `main` with the parsed options: prints errors as
+/// `<program>: error: <message with causes>` and exits with the
+/// status for their kind (see `error::exit_code`: 1 if not
+/// categorized; 0 on success).
+pub fn main<O: CliOpt>(main: impl FnOnce(O) -> Result<()>) -> ! {
+    main_with_matches(|opt, _| main(opt))
+}
+
+/// Like `main_with_matches`, for binaries doing a check (like
+/// test(1)): exits with status 0 if `main` returns true, 1 if it
+/// returns false. Since 1 is also the status of uncategorized errors,
+/// those are categorized as `default_kind` here, so that a negative
+/// result can be told apart from a failure.
+pub fn check_main_with_matches<O: CliOpt>(
+    default_kind: ErrorKind,
+    main: impl FnOnce(O, &ArgMatches) -> Result<bool>,
+) -> ! {
+    main_with_status(|opt, matches| match main(opt, matches) {
+        Ok(passed) => Ok(if passed { 0 } else { 1 }),
+        Err(e) => Err(default_kind.wrap_default(e)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn t_verbosity() {
+        #[derive(clap::Parser)]
+        struct Opt {
+            #[clap(flatten)]
+            verbosity: VerbosityArgs,
+        }
+        let t = |args: &[&str]| -> i8 {
+            Opt::try_parse_from(args).unwrap().verbosity.level()
+        };
+        assert_eq!(t(&["x"]), 0);
+        assert_eq!(t(&["x", "-vv"]), 2);
+        assert_eq!(t(&["x", "-q"]), -1);
+        assert!(Opt::try_parse_from(["x", "-q", "-v"]).is_err());
+    }
+}
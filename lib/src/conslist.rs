@@ -5,7 +5,6 @@
 /// will be to generate the code for versions using Rc, Arc or
 /// whatever when needed. (Or, use dyn?, or perhaps/probably rather,
 /// enum.)
-
 pub enum List<'t, T> {
     Pair(T, &'t List<'t, T>),
     Null,
@@ -22,13 +21,16 @@ impl<'t, T> List<'t, T> {
             List::Null => 0,
         }
     }
+    pub fn is_empty(&self) -> bool {
+        matches!(self, List::Null)
+    }
     pub fn first(&self) -> Option<&T> {
         match self {
             List::Pair(v, _) => Some(v),
             List::Null => None,
         }
     }
-    pub fn rest(&self) -> Option<&List<T>> {
+    pub fn rest(&self) -> Option<&List<'_, T>> {
         match self {
             List::Pair(_, r) => Some(r),
             List::Null => None,
@@ -47,14 +49,9 @@ impl<'t, T> List<'t, T> {
     pub fn as_ref_vec(&self) -> Vec<&T> {
         let mut vs = Vec::new();
         let mut r = self;
-        loop {
-            match r {
-                List::Pair(v, r2) => {
-                    vs.push(v);
-                    r = r2;
-                }
-                List::Null => break,
-            }
+        while let List::Pair(v, r2) = r {
+            vs.push(v);
+            r = r2;
         }
         vs
     }
@@ -64,14 +61,9 @@ impl<'t, T> List<'t, T> {
     {
         let mut vs: Vec<T> = Vec::new();
         let mut r = self;
-        loop {
-            match r {
-                List::Pair(v, r2) => {
-                    vs.push(v.clone());
-                    r = r2;
-                }
-                List::Null => break,
-            }
+        while let List::Pair(v, r2) = r {
+            vs.push(v.clone());
+            r = r2;
         }
         vs
     }
@@ -135,9 +127,9 @@ mod tests {
         assert_eq!(d.to_vec(), vec![13, 7, 5]);
         assert_eq!(e.to_vec(), vec![14, 9, 7, 5]);
 
-        assert_eq!(e.contains(&14), true);
-        assert_eq!(e.contains(&13), false);
-        assert_eq!(e.contains(&5), true);
+        assert!(e.contains(&14));
+        assert!(!e.contains(&13));
+        assert!(e.contains(&5));
     }
 
     #[test]
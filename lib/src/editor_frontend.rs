@@ -0,0 +1,1263 @@
+//! The implementation of the editor frontends `e` (for Emacs) and
+//! `e-vscode` (for VS Code, or Neovim): open each file given on the
+//! command line in a separate frame/window of an editor with a
+//! daemon/client architecture, starting the daemon if it isn't
+//! running. This is a re-implementation and combination of the `e`,
+//! `r`, `_e`, and `_e-gnu` scripts from
+//! <https://github.com/pflanze/chj-scripts>.
+
+use std::collections::HashMap;
+use std::ffi::{CStr, CString, OsStr, OsString};
+use std::fs::OpenOptions;
+use std::io::{stderr, BufRead, BufReader, ErrorKind, Write};
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::os::unix::io::FromRawFd;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{Duration, Instant, SystemTime};
+use std::{env, writeln};
+
+use anyhow::{anyhow, bail, Context, Result};
+use nix::fcntl::OFlag;
+use nix::unistd::{close, execvp, getpid, getuid, pipe2, Pid};
+
+use crate::cli::program_name;
+use crate::io::logfile::{LogEntry, LogEvent, LogFile, LogFormat, Rotation};
+use crate::io::rawfdreader::RawFdReader;
+use crate::io::readwithcontext::{Preprocessing, ReadWithContext};
+use crate::io::unix_fs::path_is_normal;
+use crate::process::{
+    capture, exit_by_signal, fork_session_proc, kill_until_gone, run_quietly,
+    run_session_proc, spawnp, wait_until_gone, waitpid_until_gone,
+    CaptureOptions, SessionProc, SpawnFds, Status,
+};
+use crate::text::parseutil::{cleanwhite, key_val};
+use crate::text::startswith::bytes_starts_with;
+use crate::time::realtime::parse_duration;
+
+fn do_debug() -> bool {
+    false
+}
+
+// There's no try_map, so:
+fn cstrings_from_osstrings(
+    osstrs: &mut dyn Iterator<Item = OsString>,
+) -> Result<Vec<CString>> {
+    let mut v: Vec<CString> = Vec::new();
+    for s in osstrs {
+        v.push(CString::new(s.into_vec())?);
+    }
+    Ok(v)
+}
+
+fn string_remove_start<'ts>(s: &'ts str, pat: &str) -> &'ts str {
+    if bytes_starts_with(s.as_bytes(), pat.as_bytes()) {
+        &s[pat.len()..]
+    } else {
+        s
+    }
+}
+
+fn xcheck_status(status: Status, cmd: &[CString]) -> Result<()> {
+    match status {
+        Status::Normalexit(exitcode) => {
+            if exitcode == 0 {
+                Ok(())
+            } else {
+                bail!(
+                    "command ended with error exit code {}: {:?}",
+                    exitcode,
+                    cmd
+                )
+            }
+        }
+        Status::Signalexit(signal) => {
+            bail!("command ended with signal {}: {:?}", signal, cmd)
+        }
+    }
+}
+
+fn ask_yn(question: &str) -> Result<bool> {
+    let mut opts = OpenOptions::new();
+    opts.read(true).write(true).create(false);
+    let opn = || opts.open("/dev/tty");
+    let mut inp = BufReader::new(opn()?);
+    let mut outp = opn()?;
+    for n in (1..5).rev() {
+        write!(outp, "{} (y/n) ", question)?;
+        let mut ans = String::new();
+        inp.read_line(&mut ans)?;
+        if ans.len() > 1 && ans.starts_with("y") {
+            return Ok(true);
+        } else if ans.len() > 1 && ans.starts_with("n") {
+            return Ok(false);
+        }
+        writeln!(outp, "Please answer with y or n, {} tries left", n)?;
+    }
+    bail!("Could not get an answer to the question {:?}", question)
+}
+
+// Output beyond this is dropped; the value we're after, on the last
+// line, would be lost then, hence this is an error.
+const BACKTICK_MAX_BYTES: usize = 64 * 1024;
+
+/// Run `cmd` and parse the last non-empty line of its output (clients
+/// like emacsclient may print warnings before the value). Returns None
+/// if it didn't finish within `timeout` (it is killed then).
+fn backtick<T: FromStr>(
+    cmd: &[CString],
+    do_redir_stderr: bool,
+    timeout: Option<Duration>,
+) -> Result<Option<T>> {
+    if do_debug() {
+        eprintln!("{}: backtick {:?}", program_name(), cmd)
+    }
+    let args: Vec<&OsStr> = cmd
+        .iter()
+        .map(|s| OsStr::from_bytes(s.as_bytes()))
+        .collect();
+    let captured = match capture(
+        &args,
+        &CaptureOptions {
+            max_bytes: Some(BACKTICK_MAX_BYTES),
+            with_stderr: do_redir_stderr,
+            timeout,
+        },
+    )? {
+        Some(captured) => captured,
+        None => return Ok(None),
+    };
+    xcheck_status(captured.status, cmd)?;
+    if captured.truncated {
+        bail!(
+            "output of command is larger than {} bytes: {:?}",
+            BACKTICK_MAX_BYTES,
+            cmd
+        )
+    }
+    let line = captured
+        .output
+        .split(|b| *b == b'\n')
+        .rev()
+        .find(|line| !line.is_empty())
+        .unwrap_or(b"");
+    let val = std::str::from_utf8(line)
+        .ok()
+        .and_then(|line| line.parse().ok())
+        .ok_or_else(|| {
+            anyhow!(
+                "parse error for input: {:?}",
+                String::from_utf8_lossy(line)
+            )
+        })?;
+    Ok(Some(val))
+}
+
+// Verify that env vars aren't anything unexpected
+fn verify_env() -> Result<()> {
+    // Emacs warns about that one, so verify it before ignoring its
+    // warning:
+    if let Some(got) = env::var_os("XDG_RUNTIME_DIR") {
+        let uid = getuid().as_raw();
+        let expected = OsString::from(format!("/run/user/{}", uid));
+        if got == expected {
+            Ok(())
+        } else {
+            bail!("expected XDG_RUNTIME_DIR env var, if set, to be {:?}, but got {:?}",
+                  expected, got)
+        }
+    } else {
+        Ok(())
+    }
+}
+
+/// The editors there are presets for (the `editor` config key).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Editor {
+    Emacs,
+    VsCode,
+    Nvim,
+}
+
+impl FromStr for Editor {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "emacs" => Ok(Editor::Emacs),
+            "vscode" => Ok(Editor::VsCode),
+            "nvim" => Ok(Editor::Nvim),
+            _ => bail!("unknown editor {s:?}, expecting emacs, vscode or nvim"),
+        }
+    }
+}
+
+impl Editor {
+    /// The name for messages.
+    pub fn name(self) -> &'static str {
+        match self {
+            Editor::Emacs => "Emacs",
+            Editor::VsCode => "VS Code",
+            Editor::Nvim => "Neovim",
+        }
+    }
+
+    /// Text the client prints on every run, to be dropped from its
+    /// output.
+    fn output_noise(self) -> Option<&'static str> {
+        match self {
+            // emacsclient *always* prints this (to indicate that the
+            // buffer needs to be closed)
+            Editor::Emacs => Some("Waiting for Emacs..."),
+            Editor::VsCode | Editor::Nvim => None,
+        }
+    }
+
+    /// Whether `line`, as the first output of the client, means that
+    /// the daemon is being started (instead of being an error
+    /// message to pass on).
+    fn is_startup_message(self, line: &str) -> bool {
+        match self {
+            Editor::Emacs => {
+                line.contains("have you started the server?")
+                    || line.contains("due to a long standing Gtk+ bug")
+                    // for some reason, sometimes it says this first
+                    // instead (when the previous instance was killed
+                    // by way of Xorg being killed?):
+                    || line.contains("emacsclient: connect: Connection refused")
+                    // this is new as of Feb 2023
+                    || line.contains("Should XDG_RUNTIME_DIR=")
+            }
+            Editor::VsCode | Editor::Nvim => false,
+        }
+    }
+}
+
+/// How the client is told the line (and column) to go to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PositionStyle {
+    /// `+LINE:COLUMN -- path` (emacsclient).
+    Plus,
+    /// `+LINE -- path`, the column is dropped (vim style).
+    PlusLine,
+    /// `--goto path:LINE:COLUMN` (VS Code).
+    Goto,
+}
+
+impl FromStr for PositionStyle {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "plus" => Ok(PositionStyle::Plus),
+            "plus_line" => Ok(PositionStyle::PlusLine),
+            "goto" => Ok(PositionStyle::Goto),
+            _ => bail!(
+                "unknown position style {s:?}, expecting plus, plus_line \
+                 or goto"
+            ),
+        }
+    }
+}
+
+impl PositionStyle {
+    /// The client arguments to open `path` at `pos` (`LINE` or
+    /// `LINE:COLUMN`).
+    fn args(self, path: &str, pos: &str) -> Result<Vec<CString>> {
+        Ok(match self {
+            PositionStyle::Plus => vec![
+                CString::new(format!("+{pos}"))?,
+                CString::new("--")?,
+                CString::new(path)?,
+            ],
+            PositionStyle::PlusLine => {
+                let line = pos.split(':').next().expect("split gives one");
+                vec![
+                    CString::new(format!("+{line}"))?,
+                    CString::new("--")?,
+                    CString::new(path)?,
+                ]
+            }
+            PositionStyle::Goto => vec![
+                CString::new("--goto")?,
+                CString::new(format!("{path}:{pos}"))?,
+            ],
+        })
+    }
+}
+
+/// The commands used to talk to the editor. Starts from the preset
+/// for the editor chosen via the `editor` key (`emacs`, `vscode` or
+/// `nvim`, the default depends on the frontend), then can be
+/// configured via the `EDITOR_DAEMON`, `EDITOR_CLIENT`,
+/// `EDITOR_CHECK` and `EDITOR_TTY_OPTION` env vars, or the `daemon`,
+/// `client`, `check` and `tty_option` keys in the config file (one
+/// `key: value` per line, lines starting with `#` are ignored). Env
+/// vars take precedence over the config file (`E_EDITOR` for
+/// `editor`). Command strings are split on whitespace (no quoting
+/// supported). The same mechanism configures the log file via the
+/// `log_format` (`plain` or `jsonl`), `log_max_size` (bytes),
+/// `log_max_age_days` and `log_keep` keys, or the env vars
+/// `E_LOG_FORMAT` etc., and the timeouts via the `check_timeout` and
+/// `client_timeout` keys (`E_CHECK_TIMEOUT`, `E_CLIENT_TIMEOUT`;
+/// durations like `10`, `10s`, `2m`, empty for none) and
+/// `restart_daemon` (`E_RESTART_DAEMON`, `yes` or `no`), together
+/// with `kill` (`EDITOR_KILL`). `daemon_detach` (`EDITOR_DAEMON_DETACH`,
+/// `yes` or `no`) and `position_style` (`EDITOR_POSITION_STYLE`,
+/// `plus`, `plus_line` or `goto`) are described below.
+#[derive(Debug)]
+struct EditorConfig {
+    editor: Editor,
+    /// Command to start the editor daemon (it is expected to return
+    /// once the daemon is ready, unless `daemon_detach` is set). None
+    /// (set via the empty string) for editors whose client starts
+    /// the editor itself.
+    daemon: Option<Vec<CString>>,
+    /// Whether `daemon` keeps running in the foreground (it is then
+    /// left running in the background, and the `check` command is
+    /// retried until it reports the daemon as up).
+    daemon_detach: bool,
+    /// Command to open files in a new frame/window of the running
+    /// daemon; `--` and the path are appended.
+    client: Vec<CString>,
+    /// Command that exits with code 0 if the daemon is up. If `None`,
+    /// uses `emacsclient -e '(+ 3 2)'` and checks for the output 5
+    /// for Emacs, considers the daemon up for other editors.
+    check: Option<Vec<CString>>,
+    /// Option added to `client` when running in a terminal, if any.
+    tty_option: Option<CString>,
+    /// How paths with line numbers are passed to `client`.
+    position_style: PositionStyle,
+    /// How long to wait for the `check` command (or the default
+    /// check) before considering the daemon hung.
+    check_timeout: Option<Duration>,
+    /// How long to let each `client` invocation run before killing
+    /// it. Note that clients like `emacsclient -c` only return when
+    /// the user closes the frame, thus this is unset by default.
+    /// (There's no timeout for clients running in the terminal, as
+    /// those are exec'ed.)
+    client_timeout: Option<Duration>,
+    /// Whether to kill and restart a hung daemon (instead of
+    /// reporting an error); also enabled by the `--restart-daemon`
+    /// option.
+    restart_daemon: bool,
+    /// Command to kill a hung daemon.
+    kill: Vec<CString>,
+    log_format: LogFormat,
+    log_rotation: Rotation,
+}
+
+fn split_command(s: &str) -> Result<Vec<CString>> {
+    let cmd = s
+        .split_ascii_whitespace()
+        .map(CString::new)
+        .collect::<Result<Vec<_>, _>>()?;
+    if cmd.is_empty() {
+        bail!("empty command string")
+    }
+    Ok(cmd)
+}
+
+fn cstrings(strs: &[&str]) -> Vec<CString> {
+    strs.iter()
+        .map(|s| CString::new(*s).expect("no \0 in literals"))
+        .collect()
+}
+
+/// The empty string means no timeout.
+fn parse_optional_duration(s: &str) -> Result<Option<Duration>> {
+    if s.is_empty() {
+        Ok(None)
+    } else {
+        Ok(Some(parse_duration(s)?))
+    }
+}
+
+fn parse_yes_no(s: &str) -> Result<bool> {
+    match s {
+        "yes" => Ok(true),
+        "no" => Ok(false),
+        _ => bail!("expecting yes or no, got {s:?}"),
+    }
+}
+
+/// The empty string means no limit.
+fn parse_optional_number(s: &str) -> Result<Option<u64>> {
+    if s.is_empty() {
+        Ok(None)
+    } else {
+        Ok(Some(s.parse().with_context(|| anyhow!("number {s:?}"))?))
+    }
+}
+
+/// The socket the Neovim daemon of the `nvim` preset listens on.
+fn nvim_socket() -> String {
+    match env::var("XDG_RUNTIME_DIR") {
+        Ok(dir) => format!("{dir}/e-nvim.sock"),
+        Err(_) => format!("/tmp/e-nvim-{}.sock", getuid()),
+    }
+}
+
+impl EditorConfig {
+    fn preset(editor: Editor) -> Self {
+        let uid = getuid().as_raw().to_string();
+        let emacs = EditorConfig {
+            editor,
+            daemon: Some(cstrings(&["emacs", "--daemon"])),
+            daemon_detach: false,
+            client: cstrings(&["emacsclient", "-c"]),
+            check: None,
+            tty_option: Some(CString::new("-nw").unwrap()),
+            position_style: PositionStyle::Plus,
+            check_timeout: Some(Duration::from_secs(10)),
+            client_timeout: None,
+            restart_daemon: false,
+            kill: cstrings(&["pkill", "-u", &uid, "-x", "emacs"]),
+            log_format: LogFormat::Plain,
+            log_rotation: Rotation::default(),
+        };
+        match editor {
+            Editor::Emacs => emacs,
+            Editor::VsCode => EditorConfig {
+                daemon: None,
+                client: cstrings(&["code", "--new-window", "--wait"]),
+                tty_option: None,
+                position_style: PositionStyle::Goto,
+                kill: cstrings(&["pkill", "-u", &uid, "-x", "code"]),
+                ..emacs
+            },
+            Editor::Nvim => {
+                let socket = nvim_socket();
+                EditorConfig {
+                    daemon: Some(cstrings(&[
+                        "nvim",
+                        "--headless",
+                        "--listen",
+                        &socket,
+                    ])),
+                    daemon_detach: true,
+                    client: cstrings(&[
+                        "nvim", "--server", &socket, "--remote",
+                    ]),
+                    check: Some(cstrings(&[
+                        "nvim",
+                        "--server",
+                        &socket,
+                        "--remote-expr",
+                        "1",
+                    ])),
+                    tty_option: None,
+                    position_style: PositionStyle::PlusLine,
+                    kill: cstrings(&[
+                        "pkill",
+                        "-u",
+                        &uid,
+                        "-f",
+                        &format!("nvim --headless --listen {socket}"),
+                    ]),
+                    ..emacs
+                }
+            }
+        }
+    }
+
+    fn set(&mut self, key: &str, val: &str) -> Result<()> {
+        match key {
+            "daemon" => {
+                self.daemon = if val.is_empty() {
+                    None
+                } else {
+                    Some(split_command(val)?)
+                }
+            }
+            "daemon_detach" => self.daemon_detach = parse_yes_no(val)?,
+            "client" => self.client = split_command(val)?,
+            "check" => self.check = Some(split_command(val)?),
+            "tty_option" => {
+                self.tty_option = if val.is_empty() {
+                    None
+                } else {
+                    Some(CString::new(val)?)
+                }
+            }
+            "position_style" => self.position_style = val.parse()?,
+            "check_timeout" => {
+                self.check_timeout = parse_optional_duration(val)?
+            }
+            "client_timeout" => {
+                self.client_timeout = parse_optional_duration(val)?
+            }
+            "restart_daemon" => self.restart_daemon = parse_yes_no(val)?,
+            "kill" => self.kill = split_command(val)?,
+            "log_format" => self.log_format = val.parse()?,
+            "log_max_size" => {
+                self.log_rotation.max_size = parse_optional_number(val)?
+            }
+            "log_max_age_days" => {
+                self.log_rotation.max_age = parse_optional_number(val)?
+                    .map(|days| Duration::from_secs(days * 24 * 3600))
+            }
+            "log_keep" => self.log_rotation.keep = val.parse()?,
+            _ => bail!("unknown key {key:?}"),
+        }
+        Ok(())
+    }
+
+    /// Call `f` with the settings from the config file at `path` (if
+    /// it exists).
+    fn read_settings(
+        path: &Path,
+        mut f: impl FnMut(&str, &str) -> Result<()>,
+    ) -> Result<()> {
+        if path.exists() {
+            let mut inp = ReadWithContext::open_path(path)?;
+            inp.set_preprocessing(Preprocessing {
+                strip_comments: true,
+                skip_blank_lines: true,
+                ..Default::default()
+            });
+            let mut line = String::new();
+            while inp.easy_read_line(&mut line)? {
+                if let Some((key, val)) = key_val(&line) {
+                    inp.context(f(cleanwhite(key), cleanwhite(val)))?;
+                } else {
+                    inp.err_with_context(anyhow!(
+                        "line does not match `key: val` pattern"
+                    ))?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Start with the preset for the `editor` setting (from the
+    /// `E_EDITOR` env var, the config file at `path`, or
+    /// `default_editor`), then apply the other settings from the
+    /// config file, then from the env vars.
+    fn load(path: &Path, default_editor: Editor) -> Result<Self> {
+        let mut editor = default_editor;
+        Self::read_settings(path, |key, val| {
+            if key == "editor" {
+                editor = val.parse()?;
+            }
+            Ok(())
+        })?;
+        if let Some(val) = env::var_os("E_EDITOR") {
+            editor = val
+                .to_string_lossy()
+                .parse()
+                .with_context(|| anyhow!("env var E_EDITOR"))?;
+        }
+        let mut config = Self::preset(editor);
+        Self::read_settings(path, |key, val| {
+            if key == "editor" {
+                Ok(())
+            } else {
+                config.set(key, val)
+            }
+        })?;
+        for (var, key) in [
+            ("EDITOR_DAEMON", "daemon"),
+            ("EDITOR_DAEMON_DETACH", "daemon_detach"),
+            ("EDITOR_CLIENT", "client"),
+            ("EDITOR_CHECK", "check"),
+            ("EDITOR_TTY_OPTION", "tty_option"),
+            ("EDITOR_POSITION_STYLE", "position_style"),
+            ("EDITOR_KILL", "kill"),
+            ("E_CHECK_TIMEOUT", "check_timeout"),
+            ("E_CLIENT_TIMEOUT", "client_timeout"),
+            ("E_RESTART_DAEMON", "restart_daemon"),
+            ("E_LOG_FORMAT", "log_format"),
+            ("E_LOG_MAX_SIZE", "log_max_size"),
+            ("E_LOG_MAX_AGE_DAYS", "log_max_age_days"),
+            ("E_LOG_KEEP", "log_keep"),
+        ] {
+            if let Some(val) = env::var_os(var) {
+                let val = val.into_string().map_err(|val| {
+                    anyhow!("env var {var} is not valid UTF-8: {val:?}")
+                })?;
+                config
+                    .set(key, cleanwhite(&val))
+                    .with_context(|| anyhow!("env var {var}"))?;
+            }
+        }
+        Ok(config)
+    }
+
+    fn daemon_status(&self) -> Result<DaemonStatus> {
+        if let Some(check) = &self.check {
+            Ok(match run_quietly(check, self.check_timeout)? {
+                None => DaemonStatus::Hung,
+                Some(Status::Normalexit(0)) => DaemonStatus::Up,
+                Some(_) => DaemonStatus::Down,
+            })
+        } else if self.editor == Editor::Emacs {
+            let res: Result<Option<i32>> = backtick(
+                &cstrings(&["emacsclient", "-e", "(+ 3 2)"]),
+                true,
+                self.check_timeout,
+            );
+            match res {
+                Err(_) => Ok(DaemonStatus::Down),
+                Ok(None) => Ok(DaemonStatus::Hung),
+                Ok(Some(val)) => Ok(if val == 5 {
+                    DaemonStatus::Up
+                } else {
+                    DaemonStatus::Down
+                }),
+            }
+        } else {
+            Ok(DaemonStatus::Up)
+        }
+    }
+
+    /// Retry the daemon check until the daemon is up, for at most
+    /// `check_timeout` (or 10 seconds if unset).
+    fn wait_daemon_up(&self) -> Result<()> {
+        let timeout = self.check_timeout.unwrap_or(Duration::from_secs(10));
+        let deadline = Instant::now() + timeout;
+        loop {
+            if let DaemonStatus::Up = self.daemon_status()? {
+                return Ok(());
+            }
+            if Instant::now() >= deadline {
+                bail!("the editor daemon did not come up within {timeout:?}")
+            }
+            std::thread::sleep(Duration::from_millis(100));
+        }
+    }
+}
+
+enum DaemonStatus {
+    Up,
+    Down,
+    /// The check did not finish within `check_timeout`.
+    Hung,
+}
+
+// Run cmd, waiting for its exit and logging its output. If it runs
+// longer than `timeout`, it is killed and exit code 124 is returned
+// (like timeout(1) does).
+fn run_cmd_with_log(
+    cmd: &[CString],
+    logpath: &Path,
+    config: &EditorConfig,
+    timeout: Option<Duration>,
+) -> Result<Status> {
+    // (Close-on-exec, thus only the fds the client gets duped to are
+    // inherited.)
+    let (streamr, streamw) = pipe2(OFlag::O_CLOEXEC)?;
+    let spawned =
+        spawnp(cmd, &SpawnFds::new().dup_to(streamw, 1).dup_to(streamw, 2));
+    close(streamw)?;
+    let pid = match spawned {
+        Ok(pid) => pid,
+        Err(e) => {
+            close(streamr)?;
+            return Err(e);
+        }
+    };
+    let deadline = timeout.map(|t| Instant::now() + t);
+    let mut log =
+        LogFile::open(logpath, config.log_format, &config.log_rotation)?;
+    let command: Vec<String> = cmd
+        .iter()
+        .map(|s| s.to_string_lossy().into_owned())
+        .collect();
+    let mut log_event = |event: LogEvent<'_>| {
+        log.write_entry(&LogEntry {
+            time: SystemTime::now(),
+            pid: getpid().as_raw(),
+            child_pid: Some(pid.as_raw()),
+            command: &command,
+            event,
+        })
+    };
+    let mut timed_out = false;
+    {
+        let mut have_written = false;
+        let mut pass_through = false; // print message to stdout
+        let mut handle_line = |line: &str| -> Result<()> {
+            let line = match config.editor.output_noise() {
+                Some(noise) => string_remove_start(line, noise),
+                None => line,
+            };
+            if !line.is_empty() {
+                log_event(LogEvent::Output(line))?;
+                if !have_written {
+                    if config.editor.is_startup_message(line) {
+                        eprintln!(
+                            "{}: starting {} instance",
+                            program_name(),
+                            config.editor.name()
+                        );
+                    } else {
+                        pass_through = true;
+                    }
+                    have_written = true;
+                }
+                if pass_through {
+                    let mut buf = Vec::new();
+                    writeln!(&mut buf, "{}", line)?;
+                    stderr().write_all(&buf)?;
+                }
+            }
+            Ok(())
+        };
+        // With the deadline set, the reader polls before each read
+        // system call, to be able to stop at the deadline.
+        let mut inp = unsafe { RawFdReader::from_raw_fd(streamr) };
+        inp.set_deadline(deadline);
+        let mut lines = inp.lines_lossy();
+        for line in lines.by_ref() {
+            match line {
+                Ok(line) => handle_line(&line)?,
+                Err(e) if e.kind() == ErrorKind::TimedOut => {
+                    timed_out = true;
+                    break;
+                }
+                Err(e) => Err(e)?,
+            }
+        }
+        if let Some(line) = lines.take_incomplete() {
+            handle_line(&line)?;
+        }
+        close(streamr)?;
+    }
+
+    let status = if timed_out {
+        let msg = format!(
+            "{}: timeout after {:?}, killing {:?}",
+            program_name(),
+            timeout.expect("only timing out if given"),
+            command
+        );
+        eprintln!("{msg}");
+        log_event(LogEvent::Output(&msg))?;
+        kill_until_gone(pid)?
+    } else {
+        waitpid_until_gone(pid)?
+    };
+    match status {
+        Status::Normalexit(code) => log_event(LogEvent::Exit(code))?,
+        Status::Signalexit(signal) => {
+            log_event(LogEvent::Signal(signal.as_str()))?
+        }
+    }
+    Ok(if timed_out {
+        Status::Normalexit(124)
+    } else {
+        status
+    })
+}
+
+fn is_num(s: &str) -> bool {
+    (!s.is_empty()) && s.chars().all(|c| c.is_ascii_digit())
+}
+
+// "Garbage" is a ':' and any following string that is empty or
+// contains non-digit characters (except for ':').
+fn remove_trailing_garbage(s: &str) -> &str {
+    if let Some((i, c)) = s
+        .char_indices()
+        .rev()
+        .find(|(_i, c)| *c == ':' || *c == '/')
+    {
+        if c == '/' {
+            return s;
+        }
+        let rest = &s[i + 1..];
+        if rest.is_empty() || rest.contains(|c: char| !c.is_ascii_digit()) {
+            &s[0..i]
+        } else {
+            s
+        }
+    } else {
+        s
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn t_remove_trailing_garbage() {
+    let t = remove_trailing_garbage;
+    assert_eq!(t("foo"), "foo");
+    assert_eq!(t("foo:"), "foo");
+    assert_eq!(t("foo:53"), "foo:53");
+    assert_eq!(t("foo:bar"), "foo");
+    assert_eq!(t("foo:b53"), "foo");
+    assert_eq!(t("foo:5b3"), "foo");
+    assert_eq!(t("foo:5b3:"), "foo:5b3");
+    assert_eq!(t("a:1:1/foo:5b3"), "a:1:1/foo");
+    assert_eq!(t("a:1:1/foo:5b3:"), "a:1:1/foo:5b3");
+    assert_eq!(t("a:1:1/fwef"), "a:1:1/fwef");
+}
+
+/// If `s` ends with ":" and some digits for line, and optionally
+/// another ":" and more digits for column, then split off this part
+/// and return the digits (and optionally ":" and more digits) as
+/// second value. Also, as the first step, any trailing ":" along with
+/// any non-digit string after it is removed, same for a leading
+/// "file://".
+fn parse_file_description(s: &str) -> (&str, Option<&str>) {
+    let s = remove_trailing_garbage(s);
+    let s = s.strip_prefix("file://").unwrap_or(s);
+    if let Some((pos, _)) = s.char_indices().rev().find(|(_, c)| *c == ':') {
+        let (path, num) = (&s[0..pos], &s[pos + 1..]);
+        if is_num(num) {
+            // stupid nested copy
+            if let Some((pos, _)) =
+                path.char_indices().rev().find(|(_, c)| *c == ':')
+            {
+                let (path2, num2) = (&path[0..pos], &path[pos + 1..]);
+                if is_num(num2) {
+                    (path2, Some(&s[pos + 1..]))
+                } else {
+                    (path, Some(num))
+                }
+            } else {
+                (path, Some(num))
+            }
+        } else {
+            (s, None)
+        }
+    } else {
+        (s, None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn t_parse_file_description() {
+        let t = parse_file_description;
+        assert_eq!(t("/foo/bar"), ("/foo/bar", None));
+        assert_eq!(t("/foo/bar:"), ("/foo/bar", None));
+        assert_eq!(t("/foo/bar::"), ("/foo/bar:", None));
+        assert_eq!(t("/foo/ba:r"), ("/foo/ba", None));
+        assert_eq!(t(""), ("", None));
+        assert_eq!(t(":"), ("", None));
+        assert_eq!(t("foo:123"), ("foo", Some("123")));
+        assert_eq!(t("foo:123:"), ("foo", Some("123")));
+        assert_eq!(t("foo:123:abc"), ("foo", Some("123")));
+        assert_eq!(t("foo:abc:"), ("foo:abc", None));
+        assert_eq!(t("foo:abc:def"), ("foo:abc", None));
+        assert_eq!(t("file:foo:123:"), ("file:foo", Some("123")));
+        assert_eq!(t("file://foo:123:"), ("foo", Some("123")));
+        assert_eq!(t("file:///foo:123:"), ("/foo", Some("123")));
+        assert_eq!(t("file://123:"), ("123", None));
+        assert_eq!(t("foo:"), ("foo", None));
+        assert_eq!(t(":123"), ("", Some("123")));
+        assert_eq!(t("foo:12a3"), ("foo", None));
+        assert_eq!(t("foo:12:a3"), ("foo", Some("12")));
+        assert_eq!(t("foo:12a:3"), ("foo:12a", Some("3")));
+        assert_eq!(t("foo::3"), ("foo:", Some("3")));
+        assert_eq!(t("foo:3:"), ("foo", Some("3")));
+        assert_eq!(t("foo:12:3"), ("foo", Some("12:3")));
+        assert_eq!(t("foo:12:3:"), ("foo", Some("12:3")));
+        assert_eq!(t("foo:12:3:abc"), ("foo", Some("12:3")));
+    }
+}
+
+// Returns `(prefixed_dir, path)` if `s` is a path coming from git
+// diff.
+fn starts_with_a_b(s: &str) -> Option<(&str, &str)> {
+    match s.as_bytes() {
+        &[b'a' | b'b', b'/', r, ..] if r != b'/' => Some((&s[0..1], &s[2..])),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests2 {
+    use super::*;
+
+    #[test]
+    fn t_starts_with_a_b() {
+        let t = starts_with_a_b;
+        assert_eq!(t(""), None);
+        assert_eq!(t("a"), None);
+        assert_eq!(t("a/"), None);
+        assert_eq!(t("a//be"), None);
+        assert_eq!(t("a/be"), Some(("a", "be")));
+        assert_eq!(t("a/c/d.x"), Some(("a", "c/d.x")));
+        assert_eq!(t("c/c/d.x"), None);
+        assert_eq!(t("b/."), Some(("b", ".")));
+    }
+}
+
+/// Tries to decode `s` as UTF-8 string (if not successful, returns
+/// None).  If the string starts with `a/` or `b/` and a non-'/'
+/// character afterwards, check if a directory of the same name
+/// exists, if not, strip it (it's then assumed to be left-overs from
+/// ). Then process (what remains) via `parse_file_description`. (This
+/// means that line numbering etc. is only detected for paths that are
+/// UTF-8, which is probably OK, at least on Linux.)
+fn parse_file_description_from_cstring(
+    s: &CStr,
+) -> Option<(&str, Option<&str>)> {
+    let s = s.to_str().ok()?;
+    if let Some((prefix, rest)) = starts_with_a_b(s) {
+        match std::fs::metadata(prefix) {
+            Ok(m) => {
+                if m.is_dir() {
+                    Some(parse_file_description(s))
+                } else {
+                    Some(parse_file_description(rest))
+                }
+            }
+            Err(_) => Some(parse_file_description(rest)),
+        }
+    } else {
+        Some(parse_file_description(s))
+    }
+}
+
+fn is_hr(s: &[u8]) -> bool {
+    s.len() >= 3 && s.iter().all(|b| *b == b'-')
+}
+
+/// The settings that differ between the frontend binaries.
+pub struct Frontend {
+    /// The editor to use if not configured via the `editor` key.
+    pub default_editor: Editor,
+    /// The config file, in the home directory.
+    pub config_file: &'static str,
+    /// The log file, in the home directory.
+    pub log_file: &'static str,
+}
+
+/// Run the frontend, with the program arguments.
+pub fn main(frontend: &Frontend) -> Result<()> {
+    let prog = program_name();
+    // If `args_is_all_files` then `args` is all file descriptions
+    // (which can be path, path:linenumber, path:linenumber:colnumber,
+    // or the same with :garbage appended).
+    // Our own option, not to be passed on to the client
+    let mut opt_restart_daemon = false;
+    let (_args, args_is_all_files, opt_nw): (Vec<CString>, bool, bool) =
+        (|| -> Result<_> {
+            let mut args =
+                cstrings_from_osstrings(&mut env::args_os().skip(1))?;
+            while let Some(i) = args
+                .iter()
+                .take_while(|a| a.to_bytes() != b"--")
+                .position(|a| a.to_bytes() == b"--restart-daemon")
+            {
+                args.remove(i);
+                opt_restart_daemon = true;
+            }
+            let mut opt_nw = false;
+            let mut files: Vec<CString> = Vec::new();
+            let mut iargs = args.clone().into_iter();
+            for arg in &mut iargs {
+                let a = arg.to_bytes();
+                if a == b"--" {
+                    // (Idea: mark files from after "--" as such, and
+                    // don't do some magic then?)
+                    files.extend(&mut iargs);
+                    // Return args_is_all_files=`true` since otherwise we
+                    // returned earlier already.
+                    return Ok((files, true, opt_nw));
+                } else if a == b"-nw" || a == b"-t" || a == b"--tty" {
+                    opt_nw = true;
+                } else if a.starts_with(b"-") && !is_hr(a) {
+                    eprintln!(
+                        "{prog}: can't currently deal with options, falling \
+                           back to single client call (not opening \
+                           a separate frame per file)"
+                    );
+                    return Ok((args, false, opt_nw));
+                } else if a.starts_with(b"+") {
+                    // XX todo: now that we support "path:123" style
+                    // positions, either remove this or implement it too.
+                    eprintln!("{prog}: can't currently deal with '+' style positions, falling \
+                           back to single client call (not opening \
+                           a separate frame per file); note that 'file:123' style positions \
+                           are supported.");
+                    return Ok((args, false, opt_nw));
+                } else if a.ends_with(b"~") {
+                    // Simply always ignore such arguments (for now? But
+                    // I'm not sure I've ever opened backup files via `e`)
+                } else {
+                    files.push(arg);
+                }
+            }
+            Ok((files, true, opt_nw))
+        })()?;
+
+    // Drop superfluous `e` arguments from accidentally running
+    // e.g. `e e foo`, and file paths consisting of 3 or more `-`
+    // characters (copy pastes from gitk).
+    let args = if args_is_all_files {
+        let mut e_exists = None;
+        _args
+            .into_iter()
+            .filter(|a| {
+                if a.as_bytes() == b"e" {
+                    *e_exists.get_or_insert_with(|| PathBuf::from("e").exists())
+                } else if is_hr(a.as_bytes()) {
+                    path_is_normal(a)
+                } else {
+                    true
+                }
+            })
+            .collect()
+    } else {
+        _args
+    };
+
+    let (is_running_in_terminal, add_nw_option) =
+        if env::var_os("DISPLAY").is_none() {
+            (true, false)
+        } else if args_is_all_files {
+            // We stripped the "-nw" or similar options if they were
+            // present.
+            (opt_nw, opt_nw)
+        } else {
+            // We did retain the "-nw" or similar options; only need
+            // to add_nw_option if not already seen it.
+            (opt_nw, !opt_nw)
+        };
+
+    let home = PathBuf::from(
+        env::var_os("HOME").ok_or_else(|| anyhow!("missing HOME env var"))?,
+    );
+    let logpath = home.join(frontend.log_file);
+    let config = EditorConfig::load(
+        &home.join(frontend.config_file),
+        frontend.default_editor,
+    )?;
+    if do_debug() {
+        eprintln!("{prog}: {config:?}");
+    }
+
+    if config.editor == Editor::Emacs {
+        verify_env()?;
+        if env::var_os("ALTERNATE_EDITOR").is_none() {
+            // Make sure emacsclient will not try to exec the file
+            // argument (from PATH)! (Genuine bug?)
+            env::set_var("ALTERNATE_EDITOR", "/usr/bin/false");
+        }
+    }
+
+    if args.len() > 8
+        && !ask_yn(&format!(
+            "{prog}: got {} arguments, do you really want to open \
+                              so many windows?",
+            args.len()
+        ))?
+    {
+        eprintln!("{prog}: cancelled.");
+        return Ok(());
+    }
+
+    // Check if the editor daemon is up, if not, start it. Then open
+    // each file (args is just files here) with a separate client
+    // call, so that each is opened in a separate frame.
+
+    if let Some(daemon) = &config.daemon {
+        let start_daemon = || -> Result<()> {
+            let run_daemon = || {
+                if do_debug() {
+                    eprintln!("{prog}: child {} {:?}", getpid(), daemon)
+                }
+                run_cmd_with_log(daemon, &logpath, &config, None)
+            };
+            if config.daemon_detach {
+                // Left running (and logging its output) after we
+                // exit
+                fork_session_proc(run_daemon)?;
+                config.wait_daemon_up()
+            } else {
+                xcheck_status(run_session_proc(run_daemon)?, daemon)
+            }
+        };
+        match config.daemon_status()? {
+            DaemonStatus::Up => {}
+            DaemonStatus::Down => start_daemon()?,
+            DaemonStatus::Hung => {
+                if !(opt_restart_daemon || config.restart_daemon) {
+                    bail!(
+                        "the editor daemon did not respond within {:?}, \
+                         pass --restart-daemon to kill and restart it",
+                        config.check_timeout.expect("only hung with a timeout")
+                    )
+                }
+                eprintln!(
+                    "{prog}: the editor daemon is not responding, \
+                     restarting it"
+                );
+                // (The kill command failing may just mean that the
+                // daemon has exited in the mean time.)
+                run_quietly(&config.kill, config.check_timeout)?;
+                // Give it time to clean up its socket
+                std::thread::sleep(Duration::from_secs(1));
+                start_daemon()?;
+            }
+        }
+    }
+
+    let client_cmd_base = || {
+        let mut cmd = config.client.clone();
+        if add_nw_option {
+            if let Some(tty_option) = &config.tty_option {
+                cmd.push(tty_option.clone());
+            }
+        }
+        cmd
+    };
+    if args_is_all_files && !is_running_in_terminal {
+        // Open each file separately, collecting the pids that
+        // we then wait on.
+        let mut pids: HashMap<Pid, (Vec<CString>, SessionProc)> =
+            HashMap::new();
+        for file in args {
+            let cmd = {
+                let mut cmd = client_cmd_base();
+                let mut append_unchanged = || -> Result<()> {
+                    cmd.append(&mut vec![CString::new("--")?, file.clone()]);
+                    Ok(())
+                };
+                if path_is_normal(&file) {
+                    append_unchanged()?;
+                } else if let Some((path, pos)) =
+                    parse_file_description_from_cstring(&file)
+                {
+                    let path_cstr = CString::new(path.as_bytes()).expect(
+                        "`file` came from CStr thus no problem with \0 possible");
+                    if path_is_normal(&path_cstr) {
+                        if let Some(pos) = pos {
+                            cmd.append(
+                                &mut config.position_style.args(path, pos)?,
+                            );
+                        } else {
+                            // use `path`, not `file`, to get trailing ":"s dropped
+                            cmd.append(&mut vec![
+                                CString::new("--")?,
+                                CString::new(path)?,
+                            ]);
+                        }
+                    } else {
+                        // There's no reason a non-existing path would
+                        // have line/column numbers added, thus assume
+                        // the user wants to edit the original path.
+                        append_unchanged()?;
+                    }
+                } else {
+                    append_unchanged()?;
+                }
+                cmd
+            };
+            let proc = fork_session_proc(|| {
+                if do_debug() {
+                    eprintln!("{prog}: child {} {:?}", getpid(), cmd)
+                }
+                let status = run_cmd_with_log(
+                    &cmd,
+                    &logpath,
+                    &config,
+                    config.client_timeout,
+                )?;
+                // Exit codes of the clients are ignored here, only
+                // signal exits are propagated.
+                Ok(match status {
+                    Status::Normalexit(_) => Status::Normalexit(0),
+                    Status::Signalexit(_) => status,
+                })
+            })?;
+            if let Some((oldcmd, _)) = pids.insert(proc.pid, (cmd, proc)) {
+                bail!("bug?: got same pid again, previously cmd {:?}", oldcmd)
+            }
+        }
+        // The first signal that ended a client, to end with after all
+        // clients have ended
+        let mut signal_exit = None;
+        while !pids.is_empty() {
+            let (pid, status) = wait_until_gone()?;
+            if let Some((cmd, proc)) = pids.remove(&pid) {
+                match proc.reported_status(status)? {
+                    Status::Signalexit(signal) => {
+                        signal_exit.get_or_insert(signal);
+                    }
+                    status => xcheck_status(status, &cmd)?,
+                }
+            } else {
+                // (E.g. a detached daemon that ended.)
+                eprintln!("{prog}: ignoring unknown pid {}", pid);
+            }
+        }
+        if let Some(signal) = signal_exit {
+            exit_by_signal(signal)
+        }
+    } else {
+        let mut cmd = client_cmd_base();
+        if args_is_all_files {
+            cmd.push(CString::new("--").unwrap());
+        }
+        cmd.append(&mut args.to_owned());
+
+        if is_running_in_terminal {
+            // Need to run direcly, can't redirect log
+            execvp(&cmd[0], &cmd)?;
+        } else {
+            let status = run_session_proc(|| {
+                run_cmd_with_log(&cmd, &logpath, &config, config.client_timeout)
+            })?;
+            if let Status::Signalexit(signal) = status {
+                exit_by_signal(signal)
+            }
+            xcheck_status(status, &cmd)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests3 {
+    use super::*;
+
+    #[test]
+    fn t_editor_config_set() {
+        let mut c = EditorConfig::preset(Editor::Emacs);
+        c.set("client", " nvr  --remote-wait ").unwrap();
+        assert_eq!(c.client, cstrings(&["nvr", "--remote-wait"]));
+        c.set("tty_option", "").unwrap();
+        assert_eq!(c.tty_option, None);
+        c.set("check_timeout", "2m").unwrap();
+        assert_eq!(c.check_timeout, Some(Duration::from_secs(120)));
+        c.set("check_timeout", "").unwrap();
+        assert_eq!(c.check_timeout, None);
+        c.set("restart_daemon", "yes").unwrap();
+        assert!(c.restart_daemon);
+        assert!(c.set("restart_daemon", "1").is_err());
+        assert!(c.set("client", "  ").is_err());
+        c.set("daemon", "").unwrap();
+        assert_eq!(c.daemon, None);
+        c.set("position_style", "goto").unwrap();
+        assert_eq!(c.position_style, PositionStyle::Goto);
+        assert!(c.set("foo", "bar").is_err());
+    }
+
+    #[test]
+    fn t_position_style() {
+        let t = |style: &str, pos| {
+            style
+                .parse::<PositionStyle>()
+                .unwrap()
+                .args("a b", pos)
+                .unwrap()
+        };
+        assert_eq!(t("plus", "12:3"), cstrings(&["+12:3", "--", "a b"]));
+        assert_eq!(t("plus_line", "12:3"), cstrings(&["+12", "--", "a b"]));
+        assert_eq!(t("plus_line", "12"), cstrings(&["+12", "--", "a b"]));
+        assert_eq!(t("goto", "12:3"), cstrings(&["--goto", "a b:12:3"]));
+        assert_eq!(
+            EditorConfig::preset(Editor::VsCode).position_style,
+            PositionStyle::Goto
+        );
+        assert!("vim".parse::<Editor>().is_err());
+    }
+}
@@ -0,0 +1,186 @@
+//! Categorized errors, so that `cli::main` can exit with a status
+//! telling scripts wrapping the binaries what kind of error happened
+//! (e.g. a bad input file vs. a failing subprocess), without parsing
+//! the message on stderr.
+//!
+//! The binaries still return `anyhow::Result`; errors are
+//! categorized by wrapping them via `ErrorKind::wrap` or the `Categorize`
+//! methods, which keeps them `anyhow::Error`s (and their messages
+//! unchanged). Errors carrying a `std::io::Error` count as `Io`
+//! unless categorized explicitly; all others exit with status 1.
+
+use std::fmt;
+
+/// The kinds of errors, with the exit codes they map to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// Invalid options or arguments (2, like clap uses for parse
+    /// errors of the command line).
+    Usage,
+    /// Failing to read or write files (3).
+    Io,
+    /// Invalid input data (4).
+    Parse,
+    /// A subprocess couldn't be started or failed (5).
+    Subprocess,
+}
+
+impl ErrorKind {
+    /// `err` categorized as this kind, as an `anyhow::Error` again.
+    pub fn wrap(self, err: impl Into<anyhow::Error>) -> anyhow::Error {
+        let err = err.into();
+        match self {
+            ErrorKind::Usage => Error::Usage(err),
+            ErrorKind::Io => Error::Io(err),
+            ErrorKind::Parse => Error::Parse(err),
+            ErrorKind::Subprocess => Error::Subprocess(err),
+        }
+        .into()
+    }
+
+    /// Like `wrap`, but leaves `err` unchanged if it already has a
+    /// kind (see `error_kind`).
+    pub fn wrap_default(self, err: impl Into<anyhow::Error>) -> anyhow::Error {
+        let err = err.into();
+        if error_kind(&err).is_some() {
+            err
+        } else {
+            self.wrap(err)
+        }
+    }
+
+    pub fn exit_code(self) -> i32 {
+        match self {
+            ErrorKind::Usage => 2,
+            ErrorKind::Io => 3,
+            ErrorKind::Parse => 4,
+            ErrorKind::Subprocess => 5,
+        }
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ErrorKind::Usage => "usage",
+            ErrorKind::Io => "IO",
+            ErrorKind::Parse => "parse",
+            ErrorKind::Subprocess => "subprocess",
+        })
+    }
+}
+
+/// An error of a given kind; shows (and has the sources of) the
+/// wrapped error only.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error(transparent)]
+    Usage(anyhow::Error),
+    #[error(transparent)]
+    Io(anyhow::Error),
+    #[error(transparent)]
+    Parse(anyhow::Error),
+    #[error(transparent)]
+    Subprocess(anyhow::Error),
+}
+
+impl Error {
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            Error::Usage(_) => ErrorKind::Usage,
+            Error::Io(_) => ErrorKind::Io,
+            Error::Parse(_) => ErrorKind::Parse,
+            Error::Subprocess(_) => ErrorKind::Subprocess,
+        }
+    }
+}
+
+/// The kind of `err`: that of the outermost `Error` in its chain, or
+/// `Io` if the chain contains a `std::io::Error`, or None.
+pub fn error_kind(err: &anyhow::Error) -> Option<ErrorKind> {
+    err.chain()
+        .find_map(|e| e.downcast_ref::<Error>().map(Error::kind))
+        .or_else(|| {
+            err.chain()
+                .any(|e| e.is::<std::io::Error>())
+                .then_some(ErrorKind::Io)
+        })
+}
+
+/// The exit code `cli::main` uses for `err`. (Binaries using exit
+/// status 1 for a negative result, like a failed check, should make
+/// sure all their errors have a kind, see
+/// `cli::check_main_with_matches`.)
+pub fn exit_code(err: &anyhow::Error) -> i32 {
+    error_kind(err).map_or(1, ErrorKind::exit_code)
+}
+
+/// Categorize the error of a `Result`.
+pub trait Categorize<T> {
+    fn categorize(self, kind: ErrorKind) -> anyhow::Result<T>;
+
+    fn usage_error(self) -> anyhow::Result<T>
+    where
+        Self: Sized,
+    {
+        self.categorize(ErrorKind::Usage)
+    }
+
+    fn io_error(self) -> anyhow::Result<T>
+    where
+        Self: Sized,
+    {
+        self.categorize(ErrorKind::Io)
+    }
+
+    fn parse_error(self) -> anyhow::Result<T>
+    where
+        Self: Sized,
+    {
+        self.categorize(ErrorKind::Parse)
+    }
+
+    fn subprocess_error(self) -> anyhow::Result<T>
+    where
+        Self: Sized,
+    {
+        self.categorize(ErrorKind::Subprocess)
+    }
+}
+
+impl<T, E: Into<anyhow::Error>> Categorize<T> for Result<T, E> {
+    fn categorize(self, kind: ErrorKind) -> anyhow::Result<T> {
+        self.map_err(|e| kind.wrap(e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::{anyhow, Context};
+
+    #[test]
+    fn t_error_kind() {
+        let e = ErrorKind::Parse
+            .wrap(anyhow!("bad number"))
+            .context("line 3");
+        assert_eq!(format!("{e:#}"), "line 3: bad number");
+        assert_eq!(error_kind(&e), Some(ErrorKind::Parse));
+        assert_eq!(exit_code(&e), 4);
+
+        // The outermost kind wins, unless with wrap_default
+        let e = ErrorKind::Usage.wrap(e);
+        assert_eq!(exit_code(&e), 2);
+        let e = ErrorKind::Subprocess.wrap_default(e);
+        assert_eq!(error_kind(&e), Some(ErrorKind::Usage));
+
+        let io = std::fs::read("/nonexistent/chj-rustbin")
+            .context("reading input")
+            .unwrap_err();
+        assert_eq!(error_kind(&io), Some(ErrorKind::Io));
+        let e: anyhow::Result<()> = Err(io).subprocess_error();
+        assert_eq!(exit_code(&e.unwrap_err()), 5);
+
+        assert_eq!(exit_code(&anyhow!("other")), 1);
+    }
+}
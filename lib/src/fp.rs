@@ -0,0 +1,148 @@
+use std::{cell::RefCell, collections::HashMap, hash::Hash};
+
+/// Invert the boolean result of a function.
+pub fn complement<T>(f: impl Fn(T) -> bool) -> impl Fn(T) -> bool {
+    move |c: T| -> bool { !f(c) }
+}
+
+pub fn on<T, K, R>(
+    access: impl Fn(&T) -> K,
+    cmp: impl Fn(K, K) -> R,
+) -> impl Fn(&T, &T) -> R {
+    move |a: &T, b: &T| cmp(access(a), access(b))
+}
+
+/// Like `on`, for accessors that can fail: gives the error from
+/// accessing `a`, else the one from `b`, else the comparison.
+pub fn on_try<T, K, R, E>(
+    access: impl Fn(&T) -> Result<K, E>,
+    cmp: impl Fn(K, K) -> R,
+) -> impl Fn(&T, &T) -> Result<R, E> {
+    move |a: &T, b: &T| Ok(cmp(access(a)?, access(b)?))
+}
+
+/// Whether `pred` holds for all items, stopping at the first item
+/// for which it returns false or an error (which is returned).
+pub fn try_all<T, E>(
+    items: impl IntoIterator<Item = T>,
+    mut pred: impl FnMut(T) -> Result<bool, E>,
+) -> Result<bool, E> {
+    for item in items {
+        if !pred(item)? {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// Apply `f` to all items, collecting the results, or stopping at
+/// the first error.
+pub fn and_then_all<T, U, E>(
+    items: impl IntoIterator<Item = T>,
+    f: impl FnMut(T) -> Result<U, E>,
+) -> Result<Vec<U>, E> {
+    items.into_iter().map(f).collect()
+}
+
+/// Note: applies `f` first, then `g` (i.e. `compose(f, g)(x) ==
+/// g(f(x))`). See `pipe!` for chaining more than two functions.
+pub fn compose<A, B, C>(
+    f: impl Fn(A) -> B,
+    g: impl Fn(B) -> C,
+) -> impl Fn(A) -> C {
+    move |x| g(f(x))
+}
+
+/// Chain any number of functions, applied left to right: `pipe!(f,
+/// g, h)` is `|x| h(g(f(x)))`.
+#[macro_export]
+macro_rules! pipe {
+    ( $f:expr $(,)? ) => {
+        $f
+    };
+    ( $f:expr, $( $rest:expr ),+ $(,)? ) => {
+        $crate::fp::compose($f, $crate::pipe!($( $rest ),+))
+    };
+}
+
+/// A function that ignores its argument and always returns (a clone
+/// of) `v`.
+pub fn constantly<A, V: Clone>(v: V) -> impl Fn(A) -> V {
+    move |_| v.clone()
+}
+
+/// Cache the results of `f` for each key. The cache is never
+/// evicted, and uses a `RefCell`, thus the returned function is not
+/// `Sync`, and `f` must not call it recursively. `V` is cloned for
+/// each call.
+pub fn memoize1<K, V>(f: impl Fn(&K) -> V) -> impl Fn(&K) -> V
+where
+    K: Hash + Eq + Clone,
+    V: Clone,
+{
+    let cache: RefCell<HashMap<K, V>> = RefCell::new(HashMap::new());
+    move |k: &K| {
+        if let Some(v) = cache.borrow().get(k) {
+            return v.clone();
+        }
+        let v = f(k);
+        cache.borrow_mut().insert(k.clone(), v.clone());
+        v
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use super::*;
+
+    #[test]
+    fn t_pipe() {
+        let f =
+            crate::pipe!(|x: i32| x + 1, |x| x * 10, |x: i32| x.to_string());
+        assert_eq!(f(2), "30");
+        let g = crate::pipe!(|x: i32| x + 1);
+        assert_eq!(g(2), 3);
+    }
+
+    #[test]
+    fn t_on_try() {
+        let cmp = on_try(|s: &&str| s.parse::<i32>(), |a, b| a.cmp(&b));
+        assert_eq!(cmp(&"10", &"9"), Ok(std::cmp::Ordering::Greater));
+        assert!(cmp(&"10", &"x").is_err());
+    }
+
+    #[test]
+    fn t_try_all() {
+        let parses_positive = |s: &str| s.parse::<i32>().map(|n| n > 0);
+        assert_eq!(try_all(["1", "2"], parses_positive), Ok(true));
+        assert_eq!(try_all(["1", "-2", "x"], parses_positive), Ok(false));
+        assert!(try_all(["1", "x", "-2"], parses_positive).is_err());
+        assert_eq!(
+            and_then_all(["1", "2"], |s| s.parse::<i32>()),
+            Ok(vec![1, 2])
+        );
+        assert!(and_then_all(["1", "x"], |s| s.parse::<i32>()).is_err());
+    }
+
+    #[test]
+    fn t_constantly() {
+        let f = constantly("a");
+        assert_eq!(f(1), "a");
+        assert_eq!(f(2), "a");
+    }
+
+    #[test]
+    fn t_memoize1() {
+        let calls = Cell::new(0);
+        let f = memoize1(|x: &u32| {
+            calls.set(calls.get() + 1);
+            x * 2
+        });
+        assert_eq!(f(&3), 6);
+        assert_eq!(f(&3), 6);
+        assert_eq!(f(&4), 8);
+        assert_eq!(calls.get(), 2);
+    }
+}
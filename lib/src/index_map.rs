@@ -3,14 +3,17 @@
 
 // Also offer a variant that requires T to implement Default?
 
-use std::{
-    mem::replace,
-    ops::{Index, IndexMut},
-};
+use std::ops::{Index, IndexMut};
 
 #[derive(Debug)]
 pub struct IndexMap<T>(Vec<Option<T>>);
 
+impl<T> Default for IndexMap<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl<T> IndexMap<T> {
     pub fn new() -> Self {
         Self(Vec::new())
@@ -25,7 +28,7 @@ impl<T> IndexMap<T> {
             self.0.push(Some(val));
             None
         } else {
-            replace(&mut self.0[key], Some(val))
+            self.0[key].replace(val)
         }
     }
 
@@ -93,7 +96,7 @@ mod tests {
     #[should_panic]
     fn t_inaccessible() {
         let m = IndexMap::<u32>::new();
-        m[0];
+        let _ = m[0];
     }
 
     #[test]
@@ -101,6 +104,6 @@ mod tests {
     fn t_inaccessible_1() {
         let mut m = IndexMap::new();
         m.insert(10, 10);
-        m[3];
+        let _ = m[3];
     }
 }
@@ -1,5 +1,10 @@
+pub mod child_fds;
+pub mod dirscan;
 pub mod excludes;
 pub mod file_path_type;
+pub mod item;
+pub mod logfile;
 pub mod rawfdreader;
 pub mod readwithcontext;
+pub mod records;
 pub mod unix_fs;
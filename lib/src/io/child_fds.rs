@@ -0,0 +1,194 @@
+//! Declaring which file descriptors a child process gets, instead of
+//! sequences of `dup2` and `close` calls after `fork`. Build a
+//! `ChildFds` (in the parent or the child), then call `apply` in the
+//! child before `exec`.
+
+use std::os::unix::io::RawFd;
+
+use nix::dir::Dir;
+use nix::errno::Errno;
+use nix::fcntl::{fcntl, FcntlArg, FdFlag, OFlag};
+use nix::sys::stat::Mode;
+use nix::unistd::{close, dup2};
+
+#[derive(Debug, Clone)]
+pub struct ChildFds {
+    /// (from, to)
+    mappings: Vec<(RawFd, RawFd)>,
+    keep: Vec<RawFd>,
+    close_others: bool,
+}
+
+impl Default for ChildFds {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ChildFds {
+    /// Keep fds 0, 1, 2 and close all others.
+    pub fn new() -> Self {
+        ChildFds {
+            mappings: Vec::new(),
+            keep: vec![0, 1, 2],
+            close_others: true,
+        }
+    }
+
+    /// Make `from` available as `to` in the child (`from` itself is
+    /// closed unless kept or mapped to itself).
+    pub fn dup_to(mut self, from: RawFd, to: RawFd) -> Self {
+        self.mappings.push((from, to));
+        self
+    }
+
+    /// Keep `fd` under its number (also clears its close-on-exec
+    /// flag).
+    pub fn keep(mut self, fd: RawFd) -> Self {
+        self.keep.push(fd);
+        self
+    }
+
+    /// Don't close the fds that are neither kept nor mapped (they are
+    /// still closed on exec if they have the close-on-exec flag).
+    pub fn inherit_others(mut self) -> Self {
+        self.close_others = false;
+        self
+    }
+
+    fn is_wanted(&self, fd: RawFd) -> bool {
+        self.keep.contains(&fd) || self.mappings.iter().any(|(_, to)| *to == fd)
+    }
+
+    /// Set up the fds of the current process as declared. Meant to
+    /// be called in the child after `fork`. Mappings may overlap (e.g.
+    /// swapping two fds), since all sources are duplicated to fresh
+    /// fds before any target is overwritten.
+    pub fn apply(&self) -> nix::Result<()> {
+        let min_temp = self
+            .mappings
+            .iter()
+            .flat_map(|(from, to)| [*from, *to])
+            .chain(self.keep.iter().copied())
+            .max()
+            .unwrap_or(0)
+            + 1;
+        let mut temps = Vec::with_capacity(self.mappings.len());
+        for (from, _) in &self.mappings {
+            temps.push(fcntl(*from, FcntlArg::F_DUPFD_CLOEXEC(min_temp))?);
+        }
+        for (temp, (_, to)) in temps.iter().zip(&self.mappings) {
+            // (the new fd does not have the close-on-exec flag)
+            dup2(*temp, *to)?;
+        }
+        for fd in &self.keep {
+            if self.mappings.iter().any(|(_, to)| to == fd) {
+                continue;
+            }
+            match fcntl(*fd, FcntlArg::F_SETFD(FdFlag::empty())) {
+                // Kept fds don't need to be open
+                Ok(_) | Err(Errno::EBADF) => {}
+                Err(e) => return Err(e),
+            }
+        }
+        if self.close_others {
+            for fd in open_fds()? {
+                if !self.is_wanted(fd) {
+                    let _ = close(fd);
+                }
+            }
+        } else {
+            for temp in temps {
+                close(temp)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The open fds of the current process, via /proc if possible,
+/// otherwise all possible fd numbers up to the limit.
+fn open_fds() -> nix::Result<Vec<RawFd>> {
+    if let Ok(mut dir) = Dir::open(
+        "/proc/self/fd",
+        OFlag::O_RDONLY | OFlag::O_DIRECTORY | OFlag::O_CLOEXEC,
+        Mode::empty(),
+    ) {
+        use std::os::unix::io::AsRawFd;
+        let dirfd = dir.as_raw_fd();
+        let mut fds = Vec::new();
+        for entry in dir.iter() {
+            let entry = entry?;
+            if let Ok(s) = entry.file_name().to_str() {
+                if let Ok(fd) = s.parse::<RawFd>() {
+                    if fd != dirfd {
+                        fds.push(fd);
+                    }
+                }
+            }
+        }
+        return Ok(fds);
+    }
+    let max = unsafe { libc::sysconf(libc::_SC_OPEN_MAX) };
+    let max = if max <= 0 {
+        1024
+    } else {
+        max.min(65536) as RawFd
+    };
+    Ok((0..max).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::io::rawfdreader::RawFdReader;
+    use nix::sys::wait::{waitpid, WaitStatus};
+    use nix::unistd::{execvp, fork, pipe, ForkResult};
+    use std::ffi::CString;
+    use std::io::Read;
+    use std::os::unix::io::FromRawFd;
+
+    #[test]
+    fn t_apply() {
+        let (r, w) = pipe().unwrap();
+        let (a, b) = pipe().unwrap();
+        dup2(a, 100).unwrap();
+        dup2(b, 101).unwrap();
+        // (high numbers, to not clash with fds that `ls` opens)
+        dup2(w, 102).unwrap();
+        match unsafe { fork() }.unwrap() {
+            ForkResult::Child => {
+                let res = ChildFds::new().dup_to(102, 1).keep(101).apply();
+                if res.is_ok() {
+                    let cmd = [
+                        CString::new("sh").unwrap(),
+                        CString::new("-c").unwrap(),
+                        CString::new("ls /proc/self/fd").unwrap(),
+                    ];
+                    let _ = execvp(&cmd[0], &cmd);
+                }
+                unsafe { libc::_exit(1) }
+            }
+            ForkResult::Parent { child } => {
+                close(w).unwrap();
+                for fd in [a, b, 100, 101, 102] {
+                    close(fd).unwrap();
+                }
+                let mut out = String::new();
+                unsafe { RawFdReader::from_raw_fd(r) }
+                    .read_to_string(&mut out)
+                    .unwrap();
+                close(r).unwrap();
+                assert_eq!(
+                    waitpid(child, None),
+                    Ok(WaitStatus::Exited(child, 0))
+                );
+                let fds: Vec<&str> = out.lines().collect();
+                assert!(fds.contains(&"1"));
+                assert!(fds.contains(&"101"));
+                assert!(!fds.contains(&"100"));
+                assert!(!fds.contains(&"102"));
+            }
+        }
+    }
+}
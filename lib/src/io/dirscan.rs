@@ -0,0 +1,326 @@
+//! Parallel (rayon based) directory scanning, with the metadata of
+//! the entries, for tools like lastitem and findnewer.
+
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context, Result};
+use log::warn;
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+
+use crate::io::excludes::Excludes;
+use crate::io::file_path_type::{
+    file_path_types_vec, FilePathType, ItemOptions,
+};
+use crate::io::item::Item;
+use crate::io::unix_fs::{mount_id, MountId};
+use crate::region::Region;
+
+/// Which levels of the directory tree to report entries from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Recursion {
+    /// Only the entries of the directory itself.
+    None,
+    /// Only the entries `n` levels of directories below the
+    /// directory (`AtDepth(0)` is the same as `None`).
+    AtDepth(u8),
+    /// The entries at all levels.
+    All,
+}
+
+/// The configuration of a scan: `opt` selects the kinds of entries
+/// that are reported (dirs are descended into regardless, as
+/// `recursion` requires), and whether symlinks are followed and
+/// mounts crossed. With `follow_symlinks` and `Recursion::All`,
+/// symlink loops are skipped with a warning.
+#[derive(Debug, Clone, Copy)]
+pub struct DirScan<'t> {
+    pub opt: ItemOptions,
+    pub excludes: &'t Excludes,
+    pub recursion: Recursion,
+}
+
+/// Per-scan state passed down while descending.
+struct Level<'a> {
+    depth: u8,
+    /// (dev, ino) of the current dir and its ancestors, only
+    /// maintained with follow_symlinks and `Recursion::All`.
+    ancestors: &'a [(u64, u64)],
+    root_mount: Option<MountId>,
+}
+
+fn dev_ino(path: &Path) -> Result<(u64, u64)> {
+    let m =
+        fs::metadata(path).with_context(|| anyhow!("stat on {:?}", path))?;
+    Ok((m.dev(), m.ino()))
+}
+
+impl<'t> DirScan<'t> {
+    fn reports_at(&self, depth: u8) -> bool {
+        match self.recursion {
+            Recursion::None => depth == 0,
+            Recursion::AtDepth(n) => depth == n,
+            Recursion::All => true,
+        }
+    }
+
+    fn descends_from(&self, depth: u8) -> bool {
+        match self.recursion {
+            Recursion::None => false,
+            Recursion::AtDepth(n) => depth < n,
+            Recursion::All => true,
+        }
+    }
+
+    /// Fold the entries below `dir_path` (with their path and
+    /// metadata) in parallel: `fold` is applied to the entries in
+    /// unspecified order, starting from values created by `init`,
+    /// and the partial results are combined with `reduce`.
+    pub fn fold<A, I, F, R>(
+        &self,
+        dir_path: &Path,
+        init: I,
+        fold: F,
+        reduce: R,
+    ) -> Result<A>
+    where
+        A: Send,
+        I: Fn() -> A + Sync,
+        F: Fn(A, Item<PathBuf>) -> Result<A> + Sync,
+        R: Fn(A, A) -> A + Sync,
+    {
+        let root_mount = if self.opt.one_file_system {
+            Some(mount_id(dir_path, true)?)
+        } else {
+            None
+        };
+        let ancestors =
+            if self.opt.follow_symlinks && self.recursion == Recursion::All {
+                vec![dev_ino(dir_path)?]
+            } else {
+                Vec::new()
+            };
+        self.fold_dir(
+            dir_path,
+            &Level {
+                depth: 0,
+                ancestors: &ancestors,
+                root_mount,
+            },
+            &init,
+            &fold,
+            &reduce,
+        )
+    }
+
+    /// All entries, in unspecified order.
+    pub fn collect(&self, dir_path: &Path) -> Result<Vec<Item<PathBuf>>> {
+        self.fold(
+            dir_path,
+            Vec::new,
+            |mut v, item| {
+                v.push(item);
+                Ok(v)
+            },
+            |mut a, b| {
+                a.extend(b);
+                a
+            },
+        )
+    }
+
+    fn fold_dir<A, I, F, R>(
+        &self,
+        dir_path: &Path,
+        level: &Level,
+        init: &I,
+        fold: &F,
+        reduce: &R,
+    ) -> Result<A>
+    where
+        A: Send,
+        I: Fn() -> A + Sync,
+        F: Fn(A, Item<PathBuf>) -> Result<A> + Sync,
+        R: Fn(A, A) -> A + Sync,
+    {
+        let report = self.reports_at(level.depth);
+        let descend = self.descends_from(level.depth);
+        let list_opt = if report {
+            ItemOptions {
+                dirs: self.opt.dirs || descend,
+                ..self.opt
+            }
+        } else {
+            ItemOptions {
+                dirs: true,
+                files: false,
+                other: false,
+                ..self.opt
+            }
+        };
+        let region = Region::new();
+        let dir_path_id = region.store(dir_path.to_path_buf());
+        let entries = file_path_types_vec(
+            &region,
+            dir_path_id,
+            list_opt,
+            self.excludes,
+            false,
+        )?;
+        entries
+            .into_par_iter()
+            .try_fold(
+                init,
+                |mut a,
+                 FilePathType {
+                     file_name,
+                     file_type,
+                     ..
+                 }|
+                 -> Result<A> {
+                    let is_dir = file_type.is_dir();
+                    let path = dir_path.join(&file_name);
+                    if report && (self.opt.dirs || !is_dir) {
+                        let item = Item::from_dir_entry(
+                            dir_path,
+                            file_name,
+                            self.opt.follow_symlinks,
+                        )?;
+                        a = fold(a, item.with_parent(dir_path))?;
+                    }
+                    if descend && is_dir {
+                        if let Some(b) =
+                            self.fold_subdir(&path, level, init, fold, reduce)?
+                        {
+                            a = reduce(a, b);
+                        }
+                    }
+                    Ok(a)
+                },
+            )
+            .try_reduce(init, |a, b| Ok(reduce(a, b)))
+    }
+
+    /// None if not descending into `dir_path` because of a mount
+    /// boundary or symlink loop.
+    fn fold_subdir<A, I, F, R>(
+        &self,
+        dir_path: &Path,
+        level: &Level,
+        init: &I,
+        fold: &F,
+        reduce: &R,
+    ) -> Result<Option<A>>
+    where
+        A: Send,
+        I: Fn() -> A + Sync,
+        F: Fn(A, Item<PathBuf>) -> Result<A> + Sync,
+        R: Fn(A, A) -> A + Sync,
+    {
+        if let Some(root_mount) = level.root_mount {
+            if mount_id(dir_path, true)? != root_mount {
+                return Ok(None);
+            }
+        }
+        let mut ancestors = Vec::new();
+        if !level.ancestors.is_empty() {
+            let id = dev_ino(dir_path)?;
+            if level.ancestors.contains(&id) {
+                warn!("skipping symlink loop at {:?}", dir_path);
+                return Ok(None);
+            }
+            ancestors.extend_from_slice(level.ancestors);
+            ancestors.push(id);
+        }
+        self.fold_dir(
+            dir_path,
+            &Level {
+                depth: level.depth + 1,
+                ancestors: &ancestors,
+                root_mount: level.root_mount,
+            },
+            init,
+            fold,
+            reduce,
+        )
+        .map(Some)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::io::excludes::empty_excludes;
+
+    fn t(
+        dirs: bool,
+        files: bool,
+        recursion: Recursion,
+    ) -> Result<Vec<PathBuf>> {
+        let excludes = empty_excludes(true);
+        let scan = DirScan {
+            opt: ItemOptions {
+                dirs,
+                files,
+                other: true,
+                follow_symlinks: false,
+                one_file_system: false,
+            },
+            excludes: &excludes,
+            recursion,
+        };
+        let mut v: Vec<PathBuf> = scan
+            .collect(Path::new("test/file_path_type"))?
+            .iter()
+            .map(|item| item.path())
+            .collect();
+        v.sort();
+        Ok(v)
+    }
+
+    #[test]
+    fn t_dirscan() -> Result<()> {
+        let p = |ps: &[&str]| -> Vec<PathBuf> {
+            ps.iter()
+                .map(|p| Path::new("test/file_path_type").join(p))
+                .collect()
+        };
+        assert_eq!(t(true, true, Recursion::None)?, p(&["bar", "foo"]));
+        assert_eq!(t(false, true, Recursion::None)?, p(&[]));
+        assert_eq!(
+            t(true, true, Recursion::AtDepth(1))?,
+            p(&["bar/c", "foo/a", "foo/b"])
+        );
+        assert_eq!(t(true, true, Recursion::AtDepth(2))?, p(&[]));
+        assert_eq!(
+            t(true, true, Recursion::All)?,
+            p(&["bar", "bar/c", "foo", "foo/a", "foo/b"])
+        );
+        assert_eq!(
+            t(false, true, Recursion::All)?,
+            p(&["bar/c", "foo/a", "foo/b"])
+        );
+
+        let excludes = empty_excludes(true);
+        let scan = DirScan {
+            opt: ItemOptions {
+                dirs: false,
+                files: true,
+                other: false,
+                follow_symlinks: false,
+                one_file_system: false,
+            },
+            excludes: &excludes,
+            recursion: Recursion::All,
+        };
+        let count = scan.fold(
+            Path::new("test/file_path_type"),
+            || 0,
+            |n, _| Ok(n + 1),
+            |a, b| a + b,
+        )?;
+        assert_eq!(count, 3);
+        Ok(())
+    }
+}
@@ -0,0 +1,537 @@
+/// General basis for filesystem path exclusion logic.
+use std::{
+    collections::HashSet,
+    ffi::{OsStr, OsString},
+    os::unix::prelude::{OsStrExt, OsStringExt},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use anyhow::{bail, Context, Result};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RuleKind {
+    Exclude,
+    Include,
+}
+
+/// A glob pattern (see `glob_match`) matched against file names
+/// (not paths). If the pattern ends in `/`, it only matches dirs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rule {
+    pub kind: RuleKind,
+    pub pattern: OsString,
+}
+
+impl Rule {
+    pub fn matches(&self, file_name: &OsStr, is_dir: bool) -> bool {
+        let pattern = self.pattern.as_bytes();
+        if let Some(pattern) = pattern.strip_suffix(b"/") {
+            is_dir && glob_match(pattern, file_name.as_bytes())
+        } else {
+            glob_match(pattern, file_name.as_bytes())
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Excludes {
+    pub exclude_dot_files: bool,
+    pub exclude_emacs_backups: bool,
+    pub files: HashSet<OsString>,
+    pub dirs: HashSet<OsString>,
+    /// Applied after the above: the last matching rule decides
+    /// (like in .gitignore files), thus `Include` rules can override
+    /// all other exclusions.
+    pub rules: Vec<Rule>,
+}
+
+impl Excludes {
+    pub fn filename_is_excluded(
+        &self,
+        file_name: &OsStr,
+        is_dir: bool,
+    ) -> bool {
+        if let Some(rule) = self
+            .rules
+            .iter()
+            .rev()
+            .find(|r| r.matches(file_name, is_dir))
+        {
+            return rule.kind == RuleKind::Exclude;
+        }
+        (self.exclude_dot_files && filename_is_dot(file_name))
+            || (self.exclude_emacs_backups
+                && filename_is_emacs_backup(file_name))
+            || (if is_dir { &self.dirs } else { &self.files })
+                .contains(file_name)
+    }
+}
+
+/// Shell-style glob matching of a whole name: `*` matches any
+/// sequence of bytes, `?` any single byte, `[abc]`, `[a-z]` and
+/// `[!a-z]` (or `[^a-z]`) match (or exclude) a set of bytes, `\`
+/// escapes the next byte. An unterminated `[` matches literally.
+pub fn glob_match(pattern: &[u8], name: &[u8]) -> bool {
+    // Position to backtrack to for the last `*` seen: (pattern
+    // position after the `*`, name position it was tried at)
+    let mut star: Option<(usize, usize)> = None;
+    let (mut p, mut n) = (0, 0);
+    while n < name.len() {
+        if p < pattern.len() {
+            match pattern[p] {
+                b'*' => {
+                    star = Some((p + 1, n));
+                    p += 1;
+                    continue;
+                }
+                b'?' => {
+                    p += 1;
+                    n += 1;
+                    continue;
+                }
+                b'[' => {
+                    if let Some((matched, len)) =
+                        match_bracket(&pattern[p..], name[n])
+                    {
+                        if matched {
+                            p += len;
+                            n += 1;
+                            continue;
+                        }
+                    } else if name[n] == b'[' {
+                        p += 1;
+                        n += 1;
+                        continue;
+                    }
+                }
+                b'\\' if p + 1 < pattern.len() => {
+                    if pattern[p + 1] == name[n] {
+                        p += 2;
+                        n += 1;
+                        continue;
+                    }
+                }
+                c => {
+                    if c == name[n] {
+                        p += 1;
+                        n += 1;
+                        continue;
+                    }
+                }
+            }
+        }
+        // Mismatch: let the last `*` eat one more byte, if any
+        if let Some((sp, sn)) = star {
+            star = Some((sp, sn + 1));
+            p = sp;
+            n = sn + 1;
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|c| *c == b'*')
+}
+
+/// Match `c` against the bracket expression at the start of
+/// `pattern`; returns whether it matched and the length of the
+/// expression, or None if it is unterminated.
+fn match_bracket(pattern: &[u8], c: u8) -> Option<(bool, usize)> {
+    let mut i = 1;
+    let negated = matches!(pattern.get(i), Some(b'!' | b'^'));
+    if negated {
+        i += 1;
+    }
+    let mut matched = false;
+    let mut first = true;
+    loop {
+        let start = *pattern.get(i)?;
+        if start == b']' && !first {
+            return Some((matched != negated, i + 1));
+        }
+        first = false;
+        if pattern.get(i + 1) == Some(&b'-')
+            && pattern.get(i + 2).is_some_and(|end| *end != b']')
+        {
+            let end = pattern[i + 2];
+            matched |= start <= c && c <= end;
+            i += 3;
+        } else {
+            matched |= start == c;
+            i += 1;
+        }
+    }
+}
+
+/// A rule from a pattern in a file or env var: `!` at the start
+/// makes it an `Include` rule.
+fn rule_from_pattern(pattern: &[u8]) -> Rule {
+    if let Some(pattern) = pattern.strip_prefix(b"!") {
+        Rule {
+            kind: RuleKind::Include,
+            pattern: OsString::from_vec(pattern.to_vec()),
+        }
+    } else {
+        Rule {
+            kind: RuleKind::Exclude,
+            pattern: OsString::from_vec(pattern.to_vec()),
+        }
+    }
+}
+
+/// Parse rules from the contents of an exclusion file: one pattern
+/// per line, empty lines and lines starting with `#` are ignored,
+/// `!` at the start of a pattern makes it an `Include` rule (like in
+/// .gitignore files, but patterns still match file names only).
+pub fn rules_from_bytes(s: &[u8]) -> Vec<Rule> {
+    s.split(|b| *b == b'\n')
+        .filter(|line| !line.is_empty() && !line.starts_with(b"#"))
+        .map(rule_from_pattern)
+        .collect()
+}
+
+/// Read rules from an exclusion file, see `rules_from_bytes`.
+pub fn rules_from_file(path: &Path) -> Result<Vec<Rule>> {
+    let s = std::fs::read(path)
+        .with_context(|| format!("reading exclusion file {:?}", path))?;
+    Ok(rules_from_bytes(&s))
+}
+
+/// Parse rules from a colon-separated list of patterns (empty
+/// entries are ignored, `!` makes an `Include` rule).
+pub fn rules_from_colon_list(s: &OsStr) -> Vec<Rule> {
+    s.as_bytes()
+        .split(|b| *b == b':')
+        .filter(|pattern| !pattern.is_empty())
+        .map(rule_from_pattern)
+        .collect()
+}
+
+/// Rules from the env var with the given name (see
+/// `rules_from_colon_list`), none if it is not set.
+pub fn rules_from_env(var: &str) -> Vec<Rule> {
+    std::env::var_os(var)
+        .map(|s| rules_from_colon_list(&s))
+        .unwrap_or_default()
+}
+
+/// Merge rule lists from multiple sources, given in the order of
+/// increasing precedence (since the last matching rule wins, the
+/// rules of later sources override those of earlier ones).
+pub fn merge_rules(sources: impl IntoIterator<Item = Vec<Rule>>) -> Vec<Rule> {
+    sources.into_iter().flatten().collect()
+}
+
+/// Built-in sets of exclude patterns, for the kinds of files that
+/// are usually not of interest when looking for changes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExcludeProfile {
+    /// Version control metadata dirs
+    Vcs,
+    /// Build output and dependency dirs
+    Build,
+    /// Editor backup, lock and swap files
+    Editor,
+}
+
+impl ExcludeProfile {
+    pub const ALL: [ExcludeProfile; 3] = [
+        ExcludeProfile::Vcs,
+        ExcludeProfile::Build,
+        ExcludeProfile::Editor,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ExcludeProfile::Vcs => "vcs",
+            ExcludeProfile::Build => "build",
+            ExcludeProfile::Editor => "editor",
+        }
+    }
+
+    /// The patterns, in the syntax of `rules_from_bytes`.
+    pub fn patterns(self) -> &'static [&'static str] {
+        match self {
+            ExcludeProfile::Vcs => {
+                &[".git/", ".hg/", ".svn/", ".bzr/", "CVS/", "_darcs/"]
+            }
+            ExcludeProfile::Build => {
+                &["target/", "node_modules/", "__pycache__/", "_build/"]
+            }
+            ExcludeProfile::Editor => &["*~", ".#*", "#*#", "*.swp"],
+        }
+    }
+
+    pub fn rules(self) -> Vec<Rule> {
+        self.patterns()
+            .iter()
+            .map(|pattern| rule_from_pattern(pattern.as_bytes()))
+            .collect()
+    }
+}
+
+impl FromStr for ExcludeProfile {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if let Some(profile) =
+            ExcludeProfile::ALL.iter().find(|p| p.name() == s)
+        {
+            Ok(*profile)
+        } else {
+            bail!(
+                "unknown exclude profile {s:?}, expecting one of: {}",
+                ExcludeProfile::ALL
+                    .iter()
+                    .map(|p| p.name())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )
+        }
+    }
+}
+
+/// Command line options for exclusion rules, to be `flatten`ed
+/// into a binary's options. Since the order of the rules matters,
+/// use `rules` with the `ArgMatches` to get them.
+#[derive(clap::Args, Debug)]
+pub struct ExcludeArgs {
+    /// exclude items whose name matches the given glob pattern
+    /// (`*`, `?`, `[..]`; a trailing `/` means dirs only); can be
+    /// given multiple times; of the --exclude, --include and
+    /// --exclude-from options, the last matching one wins
+    #[clap(long, multiple_occurrences = true)]
+    exclude: Vec<OsString>,
+
+    /// include items whose name matches the given glob pattern, even
+    /// if they are excluded otherwise (by default ignores, --all,
+    /// or an earlier --exclude)
+    #[clap(long, multiple_occurrences = true)]
+    include: Vec<OsString>,
+
+    /// read exclude patterns from the given file, one per line
+    /// (empty lines and lines starting with `#` are ignored, a
+    /// pattern starting with `!` is an include pattern); can be
+    /// given multiple times
+    #[clap(long, multiple_occurrences = true, parse(from_os_str))]
+    exclude_from: Vec<PathBuf>,
+
+    /// exclude the items matched by a built-in set of patterns:
+    /// `vcs` (.git/, .hg/, .svn/, .bzr/, CVS/, _darcs/), `build`
+    /// (target/, node_modules/, __pycache__/, _build/) or `editor`
+    /// (*~, .#*, #*#, *.swp); can be given multiple times, and
+    /// ordered with the other options like --exclude-from
+    #[clap(long, multiple_occurrences = true)]
+    exclude_profile: Vec<ExcludeProfile>,
+}
+
+impl ExcludeArgs {
+    /// The rules in the order in which they were given on the
+    /// command line (with the rules from each --exclude-from file and
+    /// --exclude-profile at the position of the option).
+    pub fn rules(&self, matches: &clap::ArgMatches) -> Result<Vec<Rule>> {
+        let with_indices = |name, kind, patterns: &[OsString]| {
+            matches
+                .indices_of(name)
+                .into_iter()
+                .flatten()
+                .zip(patterns.iter())
+                .map(move |(i, pattern)| {
+                    (
+                        i,
+                        vec![Rule {
+                            kind,
+                            pattern: pattern.clone(),
+                        }],
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+        let mut rules =
+            with_indices("exclude", RuleKind::Exclude, &self.exclude);
+        rules.extend(with_indices("include", RuleKind::Include, &self.include));
+        for (i, path) in matches
+            .indices_of("exclude-from")
+            .into_iter()
+            .flatten()
+            .zip(self.exclude_from.iter())
+        {
+            rules.push((i, rules_from_file(path)?));
+        }
+        rules.extend(
+            matches
+                .indices_of("exclude-profile")
+                .into_iter()
+                .flatten()
+                .zip(self.exclude_profile.iter())
+                .map(|(i, profile)| (i, profile.rules())),
+        );
+        rules.sort_by_key(|(i, _)| *i);
+        Ok(merge_rules(rules.into_iter().map(|(_, rules)| rules)))
+    }
+}
+
+pub fn hashset_from(strs: &[&str]) -> HashSet<OsString> {
+    let mut h: HashSet<OsString> = HashSet::new();
+    for s in strs {
+        h.insert(OsString::from(s));
+    }
+    h
+}
+
+/// If `all` is true, does not ignore exclude dot and emacs backup files.
+pub fn default_excludes(all: bool) -> Excludes {
+    Excludes {
+        exclude_dot_files: !all,
+        exclude_emacs_backups: !all,
+        files: hashset_from(&["HEUTE", "CALENDAR"]),
+        dirs: hashset_from(&[".git", ".METADATA-v2"]),
+        rules: Vec::new(),
+    }
+}
+
+/// If `all` is true, does not ignore exclude dot and emacs backup files.
+pub fn empty_excludes(all: bool) -> Excludes {
+    Excludes {
+        exclude_dot_files: !all,
+        exclude_emacs_backups: !all,
+        files: HashSet::new(),
+        dirs: HashSet::new(),
+        rules: Vec::new(),
+    }
+}
+
+pub fn filename_is_dot(filename: &OsStr) -> bool {
+    let bs = filename.as_bytes();
+    let len = bs.len();
+    len >= 1 && (bs[0] == b'.')
+}
+
+pub fn filename_is_emacs_backup(filename: &OsStr) -> bool {
+    let bs = filename.as_bytes();
+    let len = bs.len();
+    len >= 2 && (bs[len - 1] == b'~')
+}
+
+pub fn generic_ignore_filename(filename: &OsStr) -> bool {
+    filename_is_dot(filename) || filename_is_emacs_backup(filename)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn t_glob_match() {
+        let t = |p: &str, n: &str| glob_match(p.as_bytes(), n.as_bytes());
+        assert!(t("foo", "foo"));
+        assert!(!t("foo", "fo"));
+        assert!(!t("fo", "foo"));
+        assert!(t("*", ""));
+        assert!(t("*.log", "a.log"));
+        assert!(t("*.log", ".log"));
+        assert!(!t("*.log", "a.log.1"));
+        assert!(t("*.log*", "a.log.1"));
+        assert!(t("a*b*c", "axxbyybc"));
+        assert!(!t("a*b*c", "axxbyyb"));
+        assert!(t("?.txt", "a.txt"));
+        assert!(!t("?.txt", "ab.txt"));
+        assert!(t("[ab].txt", "b.txt"));
+        assert!(!t("[ab].txt", "c.txt"));
+        assert!(t("[a-c]x", "bx"));
+        assert!(!t("[!a-c]x", "bx"));
+        assert!(t("[!a-c]x", "dx"));
+        assert!(t("[]]", "]"));
+        assert!(t("x[", "x["));
+        assert!(t("\\*", "*"));
+        assert!(!t("\\*", "a"));
+    }
+
+    #[test]
+    fn t_rules_last_match_wins() {
+        let mut excludes = default_excludes(false);
+        let rule = |kind, pattern: &str| Rule {
+            kind,
+            pattern: pattern.into(),
+        };
+        excludes.rules = vec![
+            rule(RuleKind::Exclude, "*.log"),
+            rule(RuleKind::Include, "important.log"),
+            rule(RuleKind::Include, ".git/"),
+        ];
+        let t = |name: &str, is_dir| {
+            excludes.filename_is_excluded(OsStr::new(name), is_dir)
+        };
+        assert!(t("a.log", false));
+        assert!(!t("important.log", false));
+        assert!(!t("a.txt", false));
+        assert!(t(".hidden", false));
+        assert!(!t(".git", true));
+        assert!(t(".git", false));
+    }
+
+    #[test]
+    fn t_rules_from_sources() {
+        let rule = |kind, pattern: &str| Rule {
+            kind,
+            pattern: pattern.into(),
+        };
+        assert_eq!(
+            rules_from_bytes(b"# build output\n*.o\n\n!keep.o\ntarget/\n"),
+            [
+                rule(RuleKind::Exclude, "*.o"),
+                rule(RuleKind::Include, "keep.o"),
+                rule(RuleKind::Exclude, "target/"),
+            ]
+        );
+        let env_rules = rules_from_colon_list(OsStr::new("*.tmp::!a.tmp"));
+        assert_eq!(
+            env_rules,
+            [
+                rule(RuleKind::Exclude, "*.tmp"),
+                rule(RuleKind::Include, "a.tmp"),
+            ]
+        );
+        let mut excludes = empty_excludes(true);
+        excludes.rules =
+            merge_rules(vec![env_rules, vec![rule(RuleKind::Exclude, "a*")]]);
+        assert!(excludes.filename_is_excluded(OsStr::new("a.tmp"), false));
+        assert!(excludes.filename_is_excluded(OsStr::new("b.tmp"), false));
+        assert!(!excludes.filename_is_excluded(OsStr::new("b"), false));
+    }
+
+    #[test]
+    fn t_exclude_profiles() {
+        use clap::{FromArgMatches, IntoApp};
+
+        #[derive(clap::Parser, Debug)]
+        struct Opt {
+            #[clap(flatten)]
+            exclude_args: ExcludeArgs,
+        }
+
+        let matches = Opt::command().get_matches_from([
+            "test",
+            "--exclude-profile",
+            "vcs",
+            "--include",
+            ".hg/",
+            "--exclude-profile=editor",
+        ]);
+        let opt = Opt::from_arg_matches(&matches).unwrap();
+        let mut excludes = empty_excludes(true);
+        excludes.rules = opt.exclude_args.rules(&matches).unwrap();
+        let t = |name: &str, is_dir| {
+            excludes.filename_is_excluded(OsStr::new(name), is_dir)
+        };
+        assert!(t(".git", true));
+        assert!(!t(".git", false));
+        assert!(!t(".hg", true));
+        assert!(t("foo.rs~", false));
+        assert!(t(".#foo.rs", false));
+        assert!(!t("target", true));
+        assert!("build".parse::<ExcludeProfile>().unwrap().rules()[0]
+            .matches(OsStr::new("target"), true));
+        assert!("nope".parse::<ExcludeProfile>().is_err());
+    }
+}
@@ -1,12 +1,14 @@
 use std::fs;
+use std::os::unix::fs::MetadataExt;
 use std::path::Path;
 use std::{ffi::OsString, fmt::Debug, path::PathBuf};
 
 use anyhow::{anyhow, Context, Result};
 use genawaiter::rc::Gen;
-use log::trace;
+use log::{trace, warn};
 
 use crate::io::excludes::Excludes;
+use crate::io::unix_fs::{mount_id, MountId};
 use crate::region::{Region, RegionId};
 use crate::scope;
 
@@ -17,11 +19,20 @@ pub struct ItemOptions {
     pub dirs: bool,
     pub files: bool,
     pub other: bool,
+    /// Classify symlinks by the type of their target (broken
+    /// symlinks count as `other`). The recursive iterator then also
+    /// descends into symlinked directories, skipping loops.
+    pub follow_symlinks: bool,
+    /// The recursive iterator doesn't descend into directories on
+    /// other mounts than the starting directory (the directories
+    /// themselves are still reported).
+    pub one_file_system: bool,
 }
 
 /// Implement conversion from the type `$from` to
 /// `ItemOptions`. Assumes the `dirs`, `files`, and `other` fields are
-/// present on `$from` as `bool`.
+/// present on `$from` as `bool`. `follow_symlinks` and
+/// `one_file_system` are set to false.
 #[macro_export]
 macro_rules! impl_item_options_from {
     { $from:ty } => {
@@ -30,7 +41,9 @@ macro_rules! impl_item_options_from {
                 $crate::io::file_path_type::ItemOptions {
                     dirs: o.dirs,
                     files: o.files,
-                    other: o.other
+                    other: o.other,
+                    follow_symlinks: false,
+                    one_file_system: false,
                 }
             }
         }
@@ -48,17 +61,11 @@ pub enum FileType {
 
 impl FileType {
     pub fn is_dir(self) -> bool {
-        match self {
-            FileType::Dir => true,
-            _ => false,
-        }
+        matches!(self, FileType::Dir)
     }
     /// Does not include symlinks
     pub fn is_file(self) -> bool {
-        match self {
-            FileType::File => true,
-            _ => false,
-        }
+        matches!(self, FileType::File)
     }
 }
 
@@ -152,6 +159,13 @@ pub fn file_path_types_iter<'region, 't, P: FileParent<'t>>(
                 Ok(entry) => {
                     let ft = entry.file_type()
                         .expect("does this fail on OSes needing stat?");
+                    let ft = if opt.follow_symlinks && ft.is_symlink() {
+                        // Broken links stay symlinks
+                        fs::metadata(entry.path())
+                            .map_or(ft, |m| m.file_type())
+                    } else {
+                        ft
+                    };
                     let file_name = entry.file_name();
                     let handle_as_dir = ft.is_dir()
                         && opt.dirs
@@ -210,12 +224,18 @@ pub fn file_path_types_sortable_iter<'region, 't, P: FileParent<'t>>(
     if sorted {
         let vec =
             file_path_types_vec(region, file_parent, opt, excludes, true)?;
-        Ok(Box::new(vec.into_iter().map(|v| Ok(v))))
+        Ok(Box::new(vec.into_iter().map(Ok)))
     } else {
         file_path_types_iter(region, file_parent, opt, excludes)
     }
 }
 
+fn dev_ino(path: &Path) -> Result<(u64, u64)> {
+    let m = fs::metadata(path)
+        .with_context(|| anyhow!("stat on {:?}", path))?;
+    Ok((m.dev(), m.ino()))
+}
+
 /// Descends into subdirs. You'll want to use `PathBuf` for `P` unless
 /// you have a need to store additional data in the parent nodes. When
 /// `sorted == true`, sorts every directory level individually,
@@ -249,11 +269,76 @@ pub fn recursive_file_path_types_iter<'region, 't, P: FileParent<'t>>(
                 }
             };
             let mut stack = vec![];
+            // (dev, ino) of the current dir and its ancestors, to
+            // detect symlink loops; only maintained if following
+            // symlinks.
+            let mut ancestors: Vec<(u64, u64)> = vec![];
+            if opt.follow_symlinks {
+                match dev_ino(region.get(file_parent).path()) {
+                    Ok(id) => ancestors.push(id),
+                    Err(e) => {
+                        co.yield_(Err(e)).await;
+                        return;
+                    }
+                }
+            }
+            let root_mount: Option<MountId> = if opt.one_file_system {
+                match mount_id(region.get(file_parent).path(), true) {
+                    Ok(id) => Some(id),
+                    Err(e) => {
+                        co.yield_(Err(e)).await;
+                        return;
+                    }
+                }
+            } else {
+                None
+            };
             loop {
                 while let Some(item) = iter.next() {
                     match item {
                         Ok(item) => {
                             if item.is_dir() {
+                                if let Some(root_mount) = root_mount {
+                                    let id = match mount_id(
+                                        &item.to_path_buf(region),
+                                        true,
+                                    ) {
+                                        Ok(id) => id,
+                                        Err(e) => {
+                                            co.yield_(Err(e)).await;
+                                            return;
+                                        }
+                                    };
+                                    if id != root_mount {
+                                        trace!(
+                                            "not descending into mount {:?}",
+                                            item.to_path_buf(region)
+                                        );
+                                        if orig_opt.dirs {
+                                            co.yield_(Ok(item)).await;
+                                        }
+                                        continue;
+                                    }
+                                }
+                                if opt.follow_symlinks {
+                                    let id = match dev_ino(
+                                        &item.to_path_buf(region),
+                                    ) {
+                                        Ok(id) => id,
+                                        Err(e) => {
+                                            co.yield_(Err(e)).await;
+                                            return;
+                                        }
+                                    };
+                                    if ancestors.contains(&id) {
+                                        warn!(
+                                            "skipping symlink loop at {:?}",
+                                            item.to_path_buf(region)
+                                        );
+                                        continue;
+                                    }
+                                    ancestors.push(id);
+                                }
                                 let file_parent =
                                     region.store(P::new(region, &item));
                                 if orig_opt.dirs {
@@ -285,6 +370,7 @@ pub fn recursive_file_path_types_iter<'region, 't, P: FileParent<'t>>(
                 }
                 if let Some(old_iter) = stack.pop() {
                     iter = old_iter;
+                    ancestors.pop();
                 } else {
                     return;
                 }
@@ -316,7 +402,7 @@ mod tests {
             let mut v = iter
                 .map(|r| r.map(|s| s.to_path_buf(&region)))
                 .collect::<Result<Vec<_>, _>>()?;
-            v.sort_by(|a, b| a.cmp(&b));
+            v.sort();
             Ok(v)
         };
 
@@ -325,6 +411,8 @@ mod tests {
                 dirs: true,
                 files: false,
                 other: true,
+                follow_symlinks: false,
+                one_file_system: false,
             })
             .unwrap(),
             &["test/file_path_type/bar", "test/file_path_type/foo"]
@@ -336,6 +424,8 @@ mod tests {
                 dirs: true,
                 files: true,
                 other: true,
+                follow_symlinks: false,
+                one_file_system: false,
             })
             .unwrap(),
             &[
@@ -353,6 +443,8 @@ mod tests {
                 dirs: false,
                 files: true,
                 other: true,
+                follow_symlinks: false,
+                one_file_system: false,
             })
             .unwrap(),
             &[
@@ -363,4 +455,42 @@ mod tests {
             .map(PathBuf::from)
         );
     }
+
+    #[test]
+    fn t_recursive_follow_symlinks_loop() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!(
+            "chj-rustbin-file_path_type-test-{}",
+            std::process::id()
+        ));
+        fs::create_dir_all(dir.join("a"))?;
+        fs::write(dir.join("a/f"), "")?;
+        std::os::unix::fs::symlink("..", dir.join("a/up"))?;
+        std::os::unix::fs::symlink("a/f", dir.join("g"))?;
+
+        let region: Region<PathBuf> = Region::new();
+        let excludes = empty_excludes(true);
+        let t = |follow_symlinks| -> Result<Vec<PathBuf>> {
+            let mut v = recursive_file_path_types_iter(
+                &region,
+                region.store(dir.clone()),
+                ItemOptions {
+                    dirs: false,
+                    files: true,
+                    other: false,
+                    follow_symlinks,
+                    one_file_system: false,
+                },
+                &excludes,
+                true,
+            )
+            .map(|r| r.map(|s| s.to_path_buf(&region)))
+            .collect::<Result<Vec<_>, _>>()?;
+            v.sort();
+            Ok(v)
+        };
+        assert_eq!(t(false)?, [dir.join("a/f")]);
+        assert_eq!(t(true)?, [dir.join("a/f"), dir.join("g")]);
+        fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
 }
@@ -0,0 +1,71 @@
+//! Directory entries with the metadata needed for selecting them by
+//! modification time (lastitem, findnewer).
+
+use std::ffi::OsString;
+use std::fmt::Debug;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use anyhow::{anyhow, Context, Result};
+
+/// Placeholder for the parent directory of `Item`s while they are
+/// collected from a single directory.
+#[derive(Debug)]
+pub struct NoPath;
+
+#[derive(Debug)]
+pub struct Item<P: Debug> {
+    pub parentdir: P,
+    pub filename: OsString,
+    pub mtime: SystemTime,
+    pub size: u64,
+}
+
+impl Item<NoPath> {
+    /// Get the metadata for the entry `file_name` in `dir_path`. If
+    /// `follow_symlinks` is true, takes the metadata of the target of
+    /// symlinks (falling back to the link itself if it's broken).
+    pub fn from_dir_entry(
+        dir_path: &Path,
+        file_name: OsString,
+        follow_symlinks: bool,
+    ) -> Result<Self> {
+        let path = dir_path.join(&file_name);
+        let md = if follow_symlinks {
+            fs::metadata(&path).or_else(|_| fs::symlink_metadata(&path))
+        } else {
+            fs::symlink_metadata(&path)
+        }
+        .with_context(|| anyhow!("getting metadata of {file_name:?}"))?;
+        let mtime = md
+            .modified()
+            .with_context(|| anyhow!("modified on {file_name:?}"))?;
+        Ok(Item {
+            parentdir: NoPath,
+            filename: file_name,
+            mtime,
+            size: md.len(),
+        })
+    }
+
+    pub fn with_parent(self, parentdir: &Path) -> Item<PathBuf> {
+        Item {
+            parentdir: parentdir.to_path_buf(),
+            filename: self.filename,
+            mtime: self.mtime,
+            size: self.size,
+        }
+    }
+}
+
+impl Item<PathBuf> {
+    /// The path of the item, without a leading `./`.
+    pub fn path(&self) -> PathBuf {
+        // todo: it is offering `join`, yet then we use the
+        // archaic "./" stripping.
+        let clean_parentdir: &Path =
+            self.parentdir.strip_prefix("./").unwrap_or(&self.parentdir);
+        clean_parentdir.join(&self.filename)
+    }
+}
@@ -0,0 +1,345 @@
+//! Append-only log files with optional rotation, writing either plain
+//! tab-separated lines or structured JSONL entries.
+
+use std::{
+    fmt::Write as _,
+    fs::{self, OpenOptions},
+    io::{ErrorKind, Write},
+    os::unix::{fs::OpenOptionsExt, io::AsRawFd},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Local};
+use nix::fcntl::{flock, FlockArg};
+
+use crate::text::json::push_json_string;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    /// `unixtime\t(pid)\tline`, only output lines are logged.
+    Plain,
+    /// One JSON object per line, including exit statuses.
+    Jsonl,
+}
+
+impl std::str::FromStr for LogFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "plain" => Ok(LogFormat::Plain),
+            "jsonl" => Ok(LogFormat::Jsonl),
+            _ => bail!("unknown log format {s:?}, expecting plain or jsonl"),
+        }
+    }
+}
+
+/// When to rotate a log file: if it is at least `max_size` bytes
+/// large, or if it was created more than `max_age` ago (only works
+/// on file systems that report creation times). Rotated files get
+/// the suffixes `.1` (most recent) to `.{keep}`, older ones are
+/// deleted.
+#[derive(Debug, Clone)]
+pub struct Rotation {
+    pub max_size: Option<u64>,
+    pub max_age: Option<Duration>,
+    pub keep: u32,
+}
+
+impl Default for Rotation {
+    fn default() -> Self {
+        Rotation {
+            max_size: Some(10_000_000),
+            max_age: None,
+            keep: 3,
+        }
+    }
+}
+
+pub enum LogEvent<'t> {
+    /// A line of output from the command.
+    Output(&'t str),
+    /// The command exited normally with the given code.
+    Exit(i32),
+    /// The command was terminated by the given signal.
+    Signal(&'t str),
+}
+
+pub struct LogEntry<'t> {
+    pub time: SystemTime,
+    /// The pid of the logging process.
+    pub pid: i32,
+    /// The pid of the command, if known.
+    pub child_pid: Option<i32>,
+    pub command: &'t [String],
+    pub event: LogEvent<'t>,
+}
+
+impl<'t> LogEntry<'t> {
+    fn unixtime(&self) -> u64 {
+        self.time
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0)
+    }
+
+    /// The plain format only records output lines, thus returns None
+    /// for other events.
+    pub fn to_plain(&self) -> Option<String> {
+        match self.event {
+            LogEvent::Output(line) => {
+                Some(format!("{}\t({})\t{}\n", self.unixtime(), self.pid, line))
+            }
+            LogEvent::Exit(_) | LogEvent::Signal(_) => None,
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        let mut out = String::new();
+        let dt: DateTime<Local> = DateTime::from(self.time);
+        out.push_str("{\"time\":");
+        push_json_string(&mut out, &dt.to_rfc3339());
+        write!(
+            out,
+            ",\"unixtime\":{},\"pid\":{}",
+            self.unixtime(),
+            self.pid
+        )
+        .unwrap();
+        if let Some(child_pid) = self.child_pid {
+            write!(out, ",\"child_pid\":{}", child_pid).unwrap();
+        }
+        out.push_str(",\"command\":[");
+        for (i, arg) in self.command.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            push_json_string(&mut out, arg);
+        }
+        out.push(']');
+        match self.event {
+            LogEvent::Output(line) => {
+                out.push_str(",\"output\":");
+                push_json_string(&mut out, line);
+            }
+            LogEvent::Exit(code) => {
+                write!(out, ",\"exit_status\":{}", code).unwrap();
+            }
+            LogEvent::Signal(signal) => {
+                out.push_str(",\"signal\":");
+                push_json_string(&mut out, signal);
+            }
+        }
+        out.push_str("}\n");
+        out
+    }
+}
+
+fn path_with_suffix(path: &Path, i: u32) -> PathBuf {
+    let mut s = path.as_os_str().to_owned();
+    s.push(format!(".{}", i));
+    s.into()
+}
+
+/// The file locked while checking and rotating the log file at
+/// `path` (the log file itself can't be used since it gets renamed).
+fn lock_path(path: &Path) -> PathBuf {
+    let mut s = path.as_os_str().to_owned();
+    s.push(".lock");
+    s.into()
+}
+
+pub struct LogFile {
+    path: PathBuf,
+    format: LogFormat,
+    file: fs::File,
+}
+
+impl LogFile {
+    /// Open `path` for appending (creating it with mode 0600 if
+    /// necessary), rotating it first if `rotation` says so. The check
+    /// and rotation happen while holding an exclusive `flock` on
+    /// `path` with `.lock` appended, so that concurrent processes
+    /// opening the same log don't both rotate it.
+    pub fn open(
+        path: &Path,
+        format: LogFormat,
+        rotation: &Rotation,
+    ) -> Result<Self> {
+        let lock_path = lock_path(path);
+        let lock = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .mode(0o600)
+            .open(&lock_path)
+            .with_context(|| format!("opening lock file {:?}", lock_path))?;
+        flock(lock.as_raw_fd(), FlockArg::LockExclusive)
+            .with_context(|| format!("locking {:?}", lock_path))?;
+        rotate_if_needed(path, rotation)
+            .with_context(|| format!("rotating log file {:?}", path))?;
+        // The lock is released when `lock` is closed, after opening.
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .mode(0o600)
+            .open(path)
+            .with_context(|| format!("opening log file {:?}", path))?;
+        Ok(LogFile {
+            path: path.to_owned(),
+            format,
+            file,
+        })
+    }
+
+    pub fn format(&self) -> LogFormat {
+        self.format
+    }
+
+    /// Write the entry with a single write call (so that concurrent
+    /// writers appending to the same file don't interleave within
+    /// lines).
+    pub fn write_entry(&mut self, entry: &LogEntry) -> Result<()> {
+        let s = match self.format {
+            LogFormat::Plain => entry.to_plain(),
+            LogFormat::Jsonl => Some(entry.to_json()),
+        };
+        if let Some(s) = s {
+            self.file
+                .write_all(s.as_bytes())
+                .with_context(|| format!("writing to {:?}", self.path))?;
+        }
+        Ok(())
+    }
+}
+
+/// Rotate the file at `path` if it exists and matches the criteria
+/// in `rotation`.
+pub fn rotate_if_needed(path: &Path, rotation: &Rotation) -> Result<()> {
+    let m = match fs::metadata(path) {
+        Ok(m) => m,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e.into()),
+    };
+    let too_big = rotation.max_size.is_some_and(|max| m.len() >= max);
+    let too_old = rotation.max_age.is_some_and(|max_age| {
+        m.created()
+            .ok()
+            .and_then(|created| created.elapsed().ok())
+            .is_some_and(|age| age > max_age)
+    });
+    if too_big || too_old {
+        rotate(path, rotation.keep)?;
+    }
+    Ok(())
+}
+
+/// Unconditionally rotate the file at `path`, keeping `keep` old
+/// versions (if `keep` is 0, the file is simply deleted).
+pub fn rotate(path: &Path, keep: u32) -> Result<()> {
+    if keep == 0 {
+        return ignore_not_found(fs::remove_file(path));
+    }
+    ignore_not_found(fs::remove_file(path_with_suffix(path, keep)))?;
+    for i in (1..keep).rev() {
+        ignore_not_found(fs::rename(
+            path_with_suffix(path, i),
+            path_with_suffix(path, i + 1),
+        ))?;
+    }
+    ignore_not_found(fs::rename(path, path_with_suffix(path, 1)))
+}
+
+fn ignore_not_found(res: std::io::Result<()>) -> Result<()> {
+    match res {
+        Err(e) if e.kind() != ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn t_to_json() {
+        let command = vec!["emacsclient".to_string(), "a \"b\"".to_string()];
+        let mut e = LogEntry {
+            time: UNIX_EPOCH + Duration::from_secs(10),
+            pid: 12,
+            child_pid: Some(13),
+            command: &command,
+            event: LogEvent::Output("x\ty"),
+        };
+        let j = e.to_json();
+        assert!(j.contains(
+            ",\"unixtime\":10,\"pid\":12,\"child_pid\":13,\
+             \"command\":[\"emacsclient\",\"a \\\"b\\\"\"],\
+             \"output\":\"x\\ty\"}\n"
+        ));
+        assert_eq!(e.to_plain(), Some("10\t(12)\tx\ty\n".into()));
+        e.event = LogEvent::Exit(1);
+        assert!(e.to_json().ends_with(",\"exit_status\":1}\n"));
+        assert_eq!(e.to_plain(), None);
+    }
+
+    #[test]
+    fn t_rotate() -> Result<()> {
+        let dir = std::env::temp_dir()
+            .join(format!("chj-rustbin-logfile-test-{}", std::process::id()));
+        fs::create_dir_all(&dir)?;
+        let path = dir.join("log");
+        let rotation = Rotation {
+            max_size: Some(5),
+            max_age: None,
+            keep: 2,
+        };
+        for i in 0..4 {
+            fs::write(&path, format!("{}23456", i))?;
+            rotate_if_needed(&path, &rotation)?;
+        }
+        assert!(!path.exists());
+        assert_eq!(fs::read_to_string(path_with_suffix(&path, 1))?, "323456");
+        assert_eq!(fs::read_to_string(path_with_suffix(&path, 2))?, "223456");
+        assert!(!path_with_suffix(&path, 3).exists());
+        fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn t_open_concurrently() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!(
+            "chj-rustbin-logfile-open-test-{}",
+            std::process::id()
+        ));
+        fs::create_dir_all(&dir)?;
+        let path = dir.join("log");
+        fs::write(&path, "123456")?;
+        let rotation = Rotation {
+            max_size: Some(5),
+            max_age: None,
+            keep: 3,
+        };
+        let threads: Vec<_> = (0..8)
+            .map(|_| {
+                let (path, rotation) = (path.clone(), rotation.clone());
+                std::thread::spawn(move || {
+                    LogFile::open(&path, LogFormat::Plain, &rotation)
+                        .map(|_| ())
+                })
+            })
+            .collect();
+        for t in threads {
+            t.join().expect("no panic")?;
+        }
+        // Only the first opener rotated, the others saw the new,
+        // empty file
+        assert_eq!(fs::read_to_string(&path)?, "");
+        assert_eq!(fs::read_to_string(path_with_suffix(&path, 1))?, "123456");
+        assert!(!path_with_suffix(&path, 2).exists());
+        fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
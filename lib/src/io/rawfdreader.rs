@@ -0,0 +1,256 @@
+// Originally a copy from
+// https://stackoverflow.com/questions/55812291/bufreader-from-a-raw-fd,
+// now with its own buffer, so that it can be used as a `BufRead`
+// directly.
+
+use libc;
+use std::io::{BufRead, Error, ErrorKind, Read, Result};
+use std::os::unix::io::{FromRawFd, RawFd};
+use std::time::Instant;
+
+use nix::errno::Errno;
+use nix::poll::{poll, PollFd, PollFlags};
+
+use crate::process::poll_timeout;
+
+/// The buffer size used by `from_raw_fd`.
+pub const DEFAULT_BUF_SIZE: usize = 8 * 1024;
+
+/// Reads from a file descriptor, buffered. The file descriptor is not
+/// closed on drop.
+pub struct RawFdReader {
+    fd: RawFd,
+    buf: Box<[u8]>,
+    pos: usize,
+    filled: usize,
+    deadline: Option<Instant>,
+}
+
+impl FromRawFd for RawFdReader {
+    unsafe fn from_raw_fd(fd: RawFd) -> Self {
+        Self::with_capacity(fd, DEFAULT_BUF_SIZE)
+    }
+}
+
+impl RawFdReader {
+    /// Like `from_raw_fd` but with a buffer of `capacity` bytes (at
+    /// least 1).
+    ///
+    /// # Safety
+    ///
+    /// `fd` must be an open file descriptor for as long as the reader
+    /// is used.
+    pub unsafe fn with_capacity(fd: RawFd, capacity: usize) -> Self {
+        Self {
+            fd,
+            buf: vec![0; capacity.max(1)].into_boxed_slice(),
+            pos: 0,
+            filled: 0,
+            deadline: None,
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.buf.len()
+    }
+
+    /// The data that has been read from the fd but not consumed yet;
+    /// if empty, the next `fill_buf` will do a `read` system call
+    /// (and may block).
+    pub fn buffer(&self) -> &[u8] {
+        &self.buf[self.pos..self.filled]
+    }
+
+    /// If set, poll the fd before each `read` system call, and fail
+    /// with `ErrorKind::TimedOut` if it didn't become readable before
+    /// `deadline`.
+    pub fn set_deadline(&mut self, deadline: Option<Instant>) {
+        self.deadline = deadline;
+    }
+
+    /// The lines of the input, without their `\n`, invalid UTF-8
+    /// replaced with U+FFFD.
+    pub fn lines_lossy(self) -> LinesLossy<Self> {
+        LinesLossy::new(self)
+    }
+
+    fn wait_readable(&self) -> Result<()> {
+        if self.deadline.is_none() {
+            return Ok(());
+        }
+        loop {
+            let mut fds = [PollFd::new(self.fd, PollFlags::POLLIN)];
+            match poll(&mut fds, poll_timeout(self.deadline)) {
+                Ok(0) => return Err(Error::from(ErrorKind::TimedOut)),
+                Ok(_) => return Ok(()),
+                Err(Errno::EINTR) => {}
+                Err(e) => return Err(Error::from_raw_os_error(e as i32)),
+            }
+        }
+    }
+
+    fn read_fd(&mut self, buf: &mut [u8]) -> Result<usize> {
+        assert!(buf.len() <= isize::MAX as usize);
+        self.wait_readable()?;
+        loop {
+            match unsafe {
+                libc::read(self.fd, buf.as_mut_ptr() as _, buf.len())
+            } {
+                x if x < 0 => {
+                    let e = Error::last_os_error();
+                    if e.kind() != ErrorKind::Interrupted {
+                        return Err(e);
+                    }
+                }
+                x => return Ok(x as usize),
+            }
+        }
+    }
+}
+
+impl Read for RawFdReader {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        // Bypass the buffer for large reads if it's empty, to avoid
+        // the extra copy
+        if self.pos == self.filled && buf.len() >= self.buf.len() {
+            return self.read_fd(buf);
+        }
+        let n = {
+            let available = self.fill_buf()?;
+            let n = available.len().min(buf.len());
+            buf[..n].copy_from_slice(&available[..n]);
+            n
+        };
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl BufRead for RawFdReader {
+    fn fill_buf(&mut self) -> Result<&[u8]> {
+        if self.pos == self.filled {
+            let mut buf = std::mem::take(&mut self.buf);
+            let n = self.read_fd(&mut buf);
+            self.buf = buf;
+            self.pos = 0;
+            self.filled = n?;
+        }
+        Ok(self.buffer())
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.pos = (self.pos + amt).min(self.filled);
+    }
+}
+
+/// See `RawFdReader::lines_lossy`; works for any `BufRead`. After an
+/// error (e.g. a timeout, see `RawFdReader::set_deadline`), the part
+/// of the line read so far is kept; the next iteration continues it,
+/// or `take_incomplete` gets it.
+pub struct LinesLossy<R: BufRead> {
+    inp: R,
+    buf: Vec<u8>,
+}
+
+impl<R: BufRead> LinesLossy<R> {
+    pub fn new(inp: R) -> Self {
+        LinesLossy {
+            inp,
+            buf: Vec::new(),
+        }
+    }
+
+    /// The incomplete line left over by an error, if any.
+    pub fn take_incomplete(&mut self) -> Option<String> {
+        if self.buf.is_empty() {
+            None
+        } else {
+            let s = String::from_utf8_lossy(&self.buf).into_owned();
+            self.buf.clear();
+            Some(s)
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for LinesLossy<R> {
+    type Item = Result<String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.inp.read_until(b'\n', &mut self.buf) {
+            Ok(0) if self.buf.is_empty() => None,
+            Ok(_) => {
+                if self.buf.last() == Some(&b'\n') {
+                    self.buf.pop();
+                }
+                let line = String::from_utf8_lossy(&self.buf).into_owned();
+                self.buf.clear();
+                Some(Ok(line))
+            }
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nix::fcntl::OFlag;
+    use nix::unistd::{close, pipe2, write};
+    use std::time::Duration;
+
+    /// A reader for a pipe containing `data`, and the read end of the
+    /// pipe for closing it after use.
+    fn reader_for(data: &[u8], capacity: usize) -> (RawFdReader, RawFd) {
+        let (r, w) = pipe2(OFlag::O_CLOEXEC).unwrap();
+        write(w, data).unwrap();
+        close(w).unwrap();
+        (unsafe { RawFdReader::with_capacity(r, capacity) }, r)
+    }
+
+    #[test]
+    fn t_lines_lossy() -> Result<()> {
+        for capacity in [1, 3, DEFAULT_BUF_SIZE] {
+            let (inp, r) = reader_for(b"foo\n\nb\xffr\nlast", capacity);
+            assert_eq!(inp.capacity(), capacity);
+            let lines: Vec<String> =
+                inp.lines_lossy().collect::<Result<_>>()?;
+            close(r)?;
+            assert_eq!(lines, ["foo", "", "b\u{fffd}r", "last"]);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn t_lines_lossy_deadline() -> Result<()> {
+        let (r, w) = pipe2(OFlag::O_CLOEXEC)?;
+        write(w, b"foo\nba")?;
+        let mut inp = unsafe { RawFdReader::with_capacity(r, 4) };
+        inp.set_deadline(Some(Instant::now() + Duration::from_millis(50)));
+        let mut lines = inp.lines_lossy();
+        assert_eq!(lines.next().unwrap()?, "foo");
+        let e = lines.next().unwrap().unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::TimedOut);
+        assert_eq!(lines.take_incomplete().as_deref(), Some("ba"));
+        assert_eq!(lines.take_incomplete(), None);
+        close(w)?;
+        close(r)?;
+        Ok(())
+    }
+
+    #[test]
+    fn t_read() -> Result<()> {
+        let (mut inp, r) = reader_for(b"hello world", 4);
+        assert_eq!(inp.fill_buf()?, b"hell");
+        inp.consume(1);
+        assert_eq!(inp.buffer(), b"ell");
+        let mut buf = [0; 8];
+        assert_eq!(inp.read(&mut buf)?, 3);
+        assert_eq!(&buf[..3], b"ell");
+        // Empty buffer and large read: directly from the fd
+        assert_eq!(inp.read(&mut buf)?, 7);
+        assert_eq!(&buf[..7], b"o world");
+        assert_eq!(inp.read(&mut buf)?, 0);
+        close(r)?;
+        Ok(())
+    }
+}
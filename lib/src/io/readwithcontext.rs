@@ -0,0 +1,747 @@
+//! File handling utilities that make life simpler for the common
+//! case.
+
+use anyhow::{anyhow, bail, Context, Result};
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, Read, Seek, SeekFrom},
+    os::unix::io::OwnedFd,
+    path::Path,
+    process::{Child, Command, Stdio},
+};
+
+use crate::error::ErrorKind;
+use crate::io::records::{read_record, RecordSeparator};
+use crate::util::progress::Progress;
+
+pub fn trim(line: &mut String) {
+    if line.ends_with("\n") {
+        line.pop().unwrap();
+    }
+}
+
+/// Easy buffered file opening with context on open errors. But see
+/// `ReadWithContext` instead for keeping context across subsequent
+/// calls, too.
+pub fn open_file(path: &Path) -> Result<BufReader<File>> {
+    Ok(BufReader::new(
+        File::open(path).with_context(|| format!("opening file {:?}", path))?,
+    ))
+}
+
+/// Compression formats recognized by `open_decompressed`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    Gzip,
+    Xz,
+    Zstd,
+}
+
+impl Compression {
+    /// Detect the format from the first (up to) 6 bytes of a file.
+    pub fn from_magic(start: &[u8]) -> Option<Self> {
+        if start.starts_with(&[0x1f, 0x8b]) {
+            Some(Compression::Gzip)
+        } else if start.starts_with(&[0xfd, b'7', b'z', b'X', b'Z', 0]) {
+            Some(Compression::Xz)
+        } else if start.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Some(Compression::Zstd)
+        } else {
+            None
+        }
+    }
+
+    /// The program that decompresses from stdin to stdout with the
+    /// `-dc` options.
+    pub fn program(self) -> &'static str {
+        match self {
+            Compression::Gzip => "gzip",
+            Compression::Xz => "xz",
+            Compression::Zstd => "zstd",
+        }
+    }
+}
+
+/// The child process decompressing a file opened by
+/// `open_decompressed`. Killed if dropped before `finish` is called.
+#[derive(Debug)]
+pub struct Decompressor {
+    compression: Compression,
+    child: Option<Child>,
+    /// The compressed file.
+    source: File,
+}
+
+impl Decompressor {
+    pub fn compression(&self) -> Compression {
+        self.compression
+    }
+
+    /// Wait for the process to exit, after its output has been read
+    /// to EOF, and report if it failed (it prints the reason to
+    /// stderr itself).
+    pub fn finish(&mut self) -> Result<()> {
+        if let Some(mut child) = self.child.take() {
+            let program = self.compression.program();
+            let status = child
+                .wait()
+                .with_context(|| anyhow!("waiting for {program}"))?;
+            if !status.success() {
+                bail!("{program} -dc failed: {status}")
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Decompressor {
+    fn drop(&mut self) {
+        if let Some(mut child) = self.child.take() {
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+    }
+}
+
+/// Open `path`, and if it is a file compressed with one of the
+/// `Compression` formats (detected from its first bytes), run the
+/// decompressor on it and return the pipe with its output instead.
+/// Needs the decompression program to be installed (only when it is
+/// needed). Files that aren't regular files (like FIFOs) are returned
+/// as they are, without detection.
+pub fn open_decompressed(path: &Path) -> Result<(File, Option<Decompressor>)> {
+    let mut file =
+        File::open(path).with_context(|| format!("opening file {:?}", path))?;
+    if !file.metadata()?.is_file() {
+        return Ok((file, None));
+    }
+    let mut start = Vec::new();
+    (&mut file)
+        .take(6)
+        .read_to_end(&mut start)
+        .with_context(|| format!("reading file {:?}", path))?;
+    file.seek(SeekFrom::Start(0))?;
+    let compression = match Compression::from_magic(&start) {
+        Some(compression) => compression,
+        None => return Ok((file, None)),
+    };
+    let program = compression.program();
+    let mut child = Command::new(program)
+        .arg("-dc")
+        .stdin(file.try_clone()?)
+        .stdout(Stdio::piped())
+        .spawn()
+        .with_context(|| anyhow!("running {program} to decompress {path:?}"))?;
+    let stdout = child.stdout.take().expect("piped");
+    Ok((
+        File::from(OwnedFd::from(stdout)),
+        Some(Decompressor {
+            compression,
+            child: Some(child),
+            source: file,
+        }),
+    ))
+}
+
+/// "Clean" read_line function: returns true if it did read a line,
+/// false on EOF. Does overwrite `line`, not append to it. Removes
+/// trailing '\n' if present.
+pub fn easy_read_line(
+    inp: &mut BufReader<File>,
+    line: &mut String,
+) -> Result<bool> {
+    line.clear();
+    if inp.read_line(line)? != 0 {
+        trim(line);
+        Ok(true)
+    } else {
+        Ok(false)
+    }
+}
+
+/// Preprocessing of the lines read by `ReadWithContext::easy_read_line`
+/// (and `easy_read_record` for lines), for config files and the like.
+/// The steps are applied in the order of the fields.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Preprocessing {
+    /// Remove comments: from a `#` at the start of a line or after
+    /// whitespace to the end of the line, together with the
+    /// whitespace before it.
+    pub strip_comments: bool,
+    /// Join a line ending in a backslash with the next line (after
+    /// removing the backslash; the next line is appended as is). The
+    /// line number and byte offset are those of the first line, error
+    /// contexts show the range of lines.
+    pub join_continuations: bool,
+    /// Skip lines that are empty or only contain whitespace.
+    pub skip_blank_lines: bool,
+}
+
+impl Preprocessing {
+    /// All steps enabled.
+    pub fn all() -> Self {
+        Preprocessing {
+            strip_comments: true,
+            join_continuations: true,
+            skip_blank_lines: true,
+        }
+    }
+
+    fn is_enabled(&self) -> bool {
+        *self != Preprocessing::default()
+    }
+}
+
+/// Remove a comment (see `Preprocessing::strip_comments`) from `line`.
+fn strip_comment(line: &mut String) {
+    let mut prev_white = true;
+    for (i, c) in line.char_indices() {
+        if c == '#' && prev_white {
+            line.truncate(line[..i].trim_end().len());
+            return;
+        }
+        prev_white = c.is_whitespace();
+    }
+}
+
+/// Automatically count lines and report them, the byte offset and
+/// the path in error messages, plus an optional label set by the
+/// caller (e.g. to point at the start of the block being parsed).
+pub struct ReadWithContext<'p> {
+    path: &'p Path,
+    linenumber: i64,
+    /// The line breaks within the last record read by
+    /// `easy_read_record`, or the continuation lines joined with the
+    /// last line, to add to `linenumber` on the next read.
+    record_lines: i64,
+    /// The number of continuation lines joined with the last line.
+    joined_lines: i64,
+    /// Offset of the start of the last line read.
+    byte_offset: u64,
+    /// Offset of the start of the next line.
+    next_byte_offset: u64,
+    label: Option<String>,
+    reader: BufReader<File>,
+    /// An incomplete last line read by `read_appended_line`.
+    pending: String,
+    /// Set if opened via `open_path_auto` on a compressed file.
+    decompressor: Option<Decompressor>,
+    /// Set by `enable_progress`.
+    progress: Option<Progress>,
+    preprocessing: Preprocessing,
+}
+
+impl<'p> ReadWithContext<'p> {
+    pub fn open_path(path: &'p Path) -> Result<ReadWithContext<'p>> {
+        Ok(ReadWithContext {
+            path,
+            linenumber: 0,
+            record_lines: 0,
+            joined_lines: 0,
+            byte_offset: 0,
+            next_byte_offset: 0,
+            label: None,
+            reader: open_file(path)?,
+            pending: String::new(),
+            decompressor: None,
+            progress: None,
+            preprocessing: Preprocessing::default(),
+        })
+    }
+
+    /// Like `open_path` but decompresses the file if it is
+    /// compressed (see `open_decompressed`). Line numbers and byte
+    /// offsets then refer to the decompressed data.
+    pub fn open_path_auto(path: &'p Path) -> Result<ReadWithContext<'p>> {
+        let (file, decompressor) = open_decompressed(path)?;
+        Ok(ReadWithContext {
+            path,
+            linenumber: 0,
+            record_lines: 0,
+            joined_lines: 0,
+            byte_offset: 0,
+            next_byte_offset: 0,
+            label: None,
+            reader: BufReader::new(file),
+            pending: String::new(),
+            decompressor,
+            progress: None,
+            preprocessing: Preprocessing::default(),
+        })
+    }
+
+    /// The open file (e.g. to check whether the path still refers to
+    /// it); the compressed file when decompressing.
+    pub fn file(&self) -> &File {
+        match &self.decompressor {
+            Some(decompressor) => &decompressor.source,
+            None => self.reader.get_ref(),
+        }
+    }
+
+    /// Report the progress of reading on stderr (see
+    /// `util::progress`), by `easy_read_line` and
+    /// `easy_read_record`. Without a percentage and ETA when
+    /// decompressing.
+    pub fn enable_progress(&mut self) -> Result<()> {
+        let total = if self.decompressor.is_some() {
+            None
+        } else {
+            Some(
+                self.file()
+                    .metadata()
+                    .with_context(|| anyhow!("stat on file {:?}", self.path))?
+                    .len(),
+            )
+        };
+        self.progress = Some(Progress::new(self.path.to_string_lossy(), total));
+        Ok(())
+    }
+
+    /// Preprocess the lines read by `easy_read_line` from now on (not
+    /// those read by `read_appended_line`).
+    pub fn set_preprocessing(&mut self, preprocessing: Preprocessing) {
+        self.preprocessing = preprocessing;
+    }
+
+    /// The compression format if decompressing.
+    pub fn compression(&self) -> Option<Compression> {
+        self.decompressor.as_ref().map(|d| d.compression())
+    }
+
+    /// The number of the last line read (starting at 1); the first
+    /// one if continuation lines were joined.
+    pub fn linenumber(&self) -> i64 {
+        self.linenumber
+    }
+
+    /// The offset in bytes of the start of the last line read.
+    pub fn byte_offset(&self) -> u64 {
+        self.byte_offset
+    }
+
+    /// The offset in bytes of the start of the next line to be read.
+    pub fn next_byte_offset(&self) -> u64 {
+        self.next_byte_offset
+    }
+
+    /// Continue reading at `offset`, which must be the start of a
+    /// line, as obtained from `next_byte_offset` (e.g. in a previous
+    /// run), with `linenumber` as the number of the line before it.
+    /// When decompressing, the data up to `offset` is decompressed and
+    /// skipped, which only works once, before reading anything.
+    pub fn seek_to(&mut self, offset: u64, linenumber: i64) -> Result<()> {
+        if self.decompressor.is_some() {
+            if self.next_byte_offset != 0 {
+                bail!("can only seek once, at the start, when decompressing")
+            }
+            let skipped = std::io::copy(
+                &mut (&mut self.reader).take(offset),
+                &mut std::io::sink(),
+            )
+            .with_context(|| anyhow!("{}", self.context_message()))?;
+            if skipped != offset {
+                bail!(
+                    "{}: decompressed data is shorter than the offset {offset}",
+                    self.context_message()
+                )
+            }
+        } else {
+            self.reader
+                .seek(SeekFrom::Start(offset))
+                .with_context(|| anyhow!("{}", self.context_message()))?;
+        }
+        self.linenumber = linenumber;
+        self.record_lines = 0;
+        self.joined_lines = 0;
+        self.byte_offset = offset;
+        self.next_byte_offset = offset;
+        self.pending.clear();
+        Ok(())
+    }
+
+    /// Set a label that is appended to the context of subsequent
+    /// errors, until replaced or cleared.
+    pub fn set_label(&mut self, label: impl Into<String>) {
+        self.label = Some(label.into());
+    }
+
+    pub fn clear_label(&mut self) {
+        self.label = None;
+    }
+
+    fn context_message(&self) -> String {
+        let mut s = if self.joined_lines > 0 {
+            format!(
+                "file {:?} lines {}-{} (byte offset {})",
+                self.path,
+                self.linenumber,
+                self.linenumber + self.joined_lines,
+                self.byte_offset
+            )
+        } else {
+            format!(
+                "file {:?} line {} (byte offset {})",
+                self.path, self.linenumber, self.byte_offset
+            )
+        };
+        if let Some(label) = &self.label {
+            s.push_str(", ");
+            s.push_str(label);
+        }
+        s
+    }
+
+    /// "Clean" read_line function: returns true if it did read a line,
+    /// false on EOF. Does overwrite `line`, not append to it. Removes
+    /// trailing '\n' if present. Applies the preprocessing set via
+    /// `set_preprocessing`, if any.
+    pub fn easy_read_line(&mut self, line: &mut String) -> Result<bool> {
+        if self.preprocessing.is_enabled() {
+            self.read_preprocessed_line(line)
+        } else {
+            self.read_physical_line(line)
+        }
+    }
+
+    fn read_preprocessed_line(&mut self, line: &mut String) -> Result<bool> {
+        let Preprocessing {
+            strip_comments,
+            join_continuations,
+            skip_blank_lines,
+        } = self.preprocessing;
+        let mut continuation = String::new();
+        loop {
+            if !self.read_physical_line(line)? {
+                return Ok(false);
+            }
+            if strip_comments {
+                strip_comment(line);
+            }
+            let (linenumber, byte_offset) = (self.linenumber, self.byte_offset);
+            let mut joined_lines = 0;
+            while join_continuations && line.ends_with('\\') {
+                line.pop();
+                if !self.read_physical_line(&mut continuation)? {
+                    break;
+                }
+                if strip_comments {
+                    strip_comment(&mut continuation);
+                }
+                line.push_str(&continuation);
+                joined_lines += 1;
+            }
+            self.linenumber = linenumber;
+            self.byte_offset = byte_offset;
+            self.record_lines = joined_lines;
+            self.joined_lines = joined_lines;
+            if !(skip_blank_lines && line.trim().is_empty()) {
+                return Ok(true);
+            }
+        }
+    }
+
+    fn read_physical_line(&mut self, line: &mut String) -> Result<bool> {
+        self.linenumber += 1 + std::mem::take(&mut self.record_lines);
+        self.joined_lines = 0;
+        self.byte_offset = self.next_byte_offset;
+        line.clear();
+        let n = self
+            .reader
+            .read_line(line)
+            .with_context(|| anyhow!("{}", self.context_message()))?;
+        self.next_byte_offset += n as u64;
+        trim(line);
+        if n == 0 {
+            self.finish()?;
+        } else if let Some(progress) = &mut self.progress {
+            progress.tick(self.next_byte_offset);
+        }
+        Ok(n != 0)
+    }
+
+    /// Like `easy_read_line`, but reads a record as delimited by
+    /// `separator` (see `records::read_record`). The line number and
+    /// byte offset are those of the start of the record.
+    pub fn easy_read_record(
+        &mut self,
+        separator: RecordSeparator,
+        record: &mut String,
+    ) -> Result<bool> {
+        if separator.is_line() {
+            return self.easy_read_line(record);
+        }
+        self.linenumber += std::mem::take(&mut self.record_lines);
+        self.joined_lines = 0;
+        self.byte_offset = self.next_byte_offset;
+        let span = match read_record(&mut self.reader, separator, record) {
+            Ok(span) => span,
+            Err(e) => return self.err_with_context(e),
+        };
+        match span {
+            Some(span) => {
+                self.linenumber += span.skipped as i64 + 1;
+                self.byte_offset += span.skipped as u64;
+                self.next_byte_offset = self.byte_offset + span.len as u64;
+                self.record_lines = record.matches('\n').count() as i64
+                    + (separator == RecordSeparator::Paragraph) as i64;
+                if let Some(progress) = &mut self.progress {
+                    progress.tick(self.next_byte_offset);
+                }
+                Ok(true)
+            }
+            None => {
+                self.finish()?;
+                Ok(false)
+            }
+        }
+    }
+
+    /// At EOF of `easy_read_line` or `easy_read_record`.
+    fn finish(&mut self) -> Result<()> {
+        if let Some(progress) = &mut self.progress {
+            progress.finish();
+        }
+        self.finish_decompressor()
+    }
+
+    /// At EOF: report a failure of the decompressor, if any.
+    fn finish_decompressor(&mut self) -> Result<()> {
+        if let Some(decompressor) = &mut self.decompressor {
+            let res = decompressor.finish();
+            self.context(res)?;
+        }
+        Ok(())
+    }
+
+    /// Like `easy_read_line`, but for a file that is being appended
+    /// to: returns false at EOF, also if only an incomplete line
+    /// could be read, which is then kept and completed by later
+    /// calls, once the rest of it has been appended.
+    pub fn read_appended_line(&mut self, line: &mut String) -> Result<bool> {
+        let n = self
+            .reader
+            .read_line(&mut self.pending)
+            .with_context(|| anyhow!("{}", self.context_message()))?;
+        if !self.pending.ends_with('\n') {
+            if n == 0 {
+                self.finish_decompressor()?;
+            }
+            return Ok(false);
+        }
+        self.linenumber += 1 + std::mem::take(&mut self.record_lines);
+        self.joined_lines = 0;
+        self.byte_offset = self.next_byte_offset;
+        self.next_byte_offset += self.pending.len() as u64;
+        line.clear();
+        std::mem::swap(line, &mut self.pending);
+        trim(line);
+        Ok(true)
+    }
+
+    /// Report an error in the context of this file and position
+    /// (as a parse error unless already categorized, see
+    /// `error::ErrorKind::wrap_default`)
+    #[allow(unused)]
+    pub fn err_with_context<T>(
+        &self,
+        err: anyhow::Error,
+    ) -> Result<T, anyhow::Error> {
+        Err(ErrorKind::Parse
+            .wrap_default(err)
+            .context(self.context_message()))
+    }
+
+    /// A Result in the context of this file and position
+    #[allow(unused)]
+    pub fn context<T>(
+        &self,
+        res: Result<T, anyhow::Error>,
+    ) -> Result<T, anyhow::Error> {
+        match res {
+            Ok(v) => Ok(v),
+            Err(e) => self.err_with_context(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::io::Write;
+    use std::process::Command;
+
+    #[test]
+    fn t_context() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!(
+            "chj-rustbin-readwithcontext-test-{}",
+            std::process::id()
+        ));
+        fs::create_dir_all(&dir)?;
+        let path = dir.join("input");
+        fs::write(&path, "ab\n\ncde\nf")?;
+        let mut inp = ReadWithContext::open_path(&path)?;
+        let mut line = String::new();
+        let mut offsets = Vec::new();
+        while inp.easy_read_line(&mut line)? {
+            offsets.push((inp.linenumber(), inp.byte_offset(), line.clone()));
+            if line == "cde" {
+                inp.set_label("in block");
+            }
+        }
+        assert_eq!(
+            offsets,
+            [
+                (1, 0, "ab".into()),
+                (2, 3, "".into()),
+                (3, 4, "cde".into()),
+                (4, 8, "f".into())
+            ]
+        );
+        let err = inp.err_with_context::<()>(anyhow!("bad")).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!("file {:?} line 5 (byte offset 9), in block", path)
+        );
+
+        let path = dir.join("appended");
+        fs::write(&path, "ab\nc")?;
+        let mut inp = ReadWithContext::open_path(&path)?;
+        assert!(inp.read_appended_line(&mut line)?);
+        assert_eq!(line, "ab");
+        assert!(!inp.read_appended_line(&mut line)?);
+        fs::OpenOptions::new()
+            .append(true)
+            .open(&path)?
+            .write_all(b"d\n")?;
+        assert!(inp.read_appended_line(&mut line)?);
+        assert_eq!(
+            (line.as_str(), inp.linenumber(), inp.byte_offset()),
+            ("cd", 2, 3)
+        );
+        assert!(!inp.read_appended_line(&mut line)?);
+        assert_eq!(inp.next_byte_offset(), 6);
+
+        let mut inp = ReadWithContext::open_path(&path)?;
+        inp.seek_to(3, 1)?;
+        assert!(inp.easy_read_line(&mut line)?);
+        assert_eq!(
+            (line.as_str(), inp.linenumber(), inp.byte_offset()),
+            ("cd", 2, 3)
+        );
+
+        let path = dir.join("paragraphs");
+        fs::write(&path, "\na\nb\n\n\nc\n\nd\n")?;
+        let mut inp = ReadWithContext::open_path(&path)?;
+        let mut records = Vec::new();
+        while inp.easy_read_record(RecordSeparator::Paragraph, &mut line)? {
+            records.push((inp.linenumber(), inp.byte_offset(), line.clone()));
+        }
+        assert_eq!(
+            records,
+            [
+                (2, 1, "a\nb".into()),
+                (6, 7, "c".into()),
+                (8, 10, "d".into())
+            ]
+        );
+        fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn t_preprocessing() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!(
+            "chj-rustbin-readwithcontext-pre-test-{}",
+            std::process::id()
+        ));
+        fs::create_dir_all(&dir)?;
+        let path = dir.join("input");
+        fs::write(
+            &path,
+            "# comment\na = 1  # one\n\n  \nb = 2,\\\n  3 \\\n # none\n\
+             c = x#y\nd = \\\n4",
+        )?;
+        let read_all = |preprocessing| -> Result<Vec<(i64, u64, String)>> {
+            let mut inp = ReadWithContext::open_path(&path)?;
+            inp.set_preprocessing(preprocessing);
+            let mut line = String::new();
+            let mut lines = Vec::new();
+            while inp.easy_read_line(&mut line)? {
+                lines.push((inp.linenumber(), inp.byte_offset(), line.clone()));
+            }
+            Ok(lines)
+        };
+        assert_eq!(
+            read_all(Preprocessing::all())?,
+            [
+                (2, 10, "a = 1".into()),
+                (5, 27, "b = 2,  3 ".into()),
+                (8, 49, "c = x#y".into()),
+                (9, 57, "d = 4".into()),
+            ]
+        );
+        assert_eq!(read_all(Preprocessing::default())?.len(), 10);
+        assert_eq!(
+            read_all(Preprocessing {
+                skip_blank_lines: true,
+                ..Default::default()
+            })?[..2],
+            [(1, 0, "# comment".into()), (2, 10, "a = 1  # one".into())]
+        );
+
+        let mut inp = ReadWithContext::open_path(&path)?;
+        inp.set_preprocessing(Preprocessing::all());
+        let mut line = String::new();
+        while inp.easy_read_line(&mut line)? && !line.starts_with('b') {}
+        let err = inp.err_with_context::<()>(anyhow!("bad")).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!("file {:?} lines 5-7 (byte offset 27)", path)
+        );
+        assert!(inp.easy_read_line(&mut line)?);
+        assert_eq!((inp.linenumber(), line.as_str()), (8, "c = x#y"));
+        fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn t_decompressed() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!(
+            "chj-rustbin-readwithcontext-gz-test-{}",
+            std::process::id()
+        ));
+        fs::create_dir_all(&dir)?;
+        let path = dir.join("input");
+        fs::write(&path, "ab\ncde\nf\n")?;
+        let uncompressed = ReadWithContext::open_path_auto(&path)?;
+        assert_eq!(uncompressed.compression(), None);
+        let status = Command::new("gzip").arg(&path).status()?;
+        assert!(status.success());
+        let path = dir.join("input.gz");
+        let mut inp = ReadWithContext::open_path_auto(&path)?;
+        assert_eq!(inp.compression(), Some(Compression::Gzip));
+        inp.seek_to(3, 1)?;
+        let mut line = String::new();
+        let mut lines = Vec::new();
+        while inp.easy_read_line(&mut line)? {
+            lines.push((inp.linenumber(), inp.byte_offset(), line.clone()));
+        }
+        assert_eq!(lines, [(2, 3, "cde".into()), (3, 7, "f".into())]);
+        assert!(inp.seek_to(0, 0).is_err());
+
+        // Truncated data
+        let data = fs::read(&path)?;
+        fs::write(&path, &data[..data.len() - 4])?;
+        let mut inp = ReadWithContext::open_path_auto(&path)?;
+        let res = (|| -> Result<()> {
+            while inp.easy_read_line(&mut line)? {}
+            Ok(())
+        })();
+        let err = format!("{:#}", res.unwrap_err());
+        assert!(err.contains("gzip -dc failed"), "{}", err);
+        fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,198 @@
+//! Reading records that are not necessarily lines: terminated by
+//! another byte (e.g. NUL, as from `find -print0`), or paragraphs
+//! separated by empty lines.
+
+use std::io::{BufRead, Write};
+use std::str::FromStr;
+
+use anyhow::{bail, Context, Error, Result};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecordSeparator {
+    /// Each record is terminated by this byte (the last one may be
+    /// unterminated); `b'\n'` for lines.
+    Byte(u8),
+    /// Records are paragraphs: lines separated by one or more empty
+    /// lines (like Perl's `$/ = ""`); the records don't include the
+    /// line break of their last line.
+    Paragraph,
+}
+
+impl Default for RecordSeparator {
+    fn default() -> Self {
+        RecordSeparator::Byte(b'\n')
+    }
+}
+
+impl FromStr for RecordSeparator {
+    type Err = Error;
+
+    /// `\n` for lines, `\0` or `nul`, `paragraph` (or the empty
+    /// string), or any other ASCII character.
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "\\n" => Ok(RecordSeparator::Byte(b'\n')),
+            "\\0" | "nul" => Ok(RecordSeparator::Byte(0)),
+            "" | "paragraph" => Ok(RecordSeparator::Paragraph),
+            _ if s.len() == 1 && s.is_ascii() => {
+                Ok(RecordSeparator::Byte(s.as_bytes()[0]))
+            }
+            _ => bail!(
+                "invalid record separator {s:?}, expecting a single ASCII \
+                 character, `\\n`, `\\0`, `nul` or `paragraph`"
+            ),
+        }
+    }
+}
+
+impl RecordSeparator {
+    pub fn is_line(self) -> bool {
+        self == RecordSeparator::Byte(b'\n')
+    }
+
+    /// Write what follows a record on output (for `Paragraph`, an
+    /// empty line after the record's last line).
+    pub fn write_terminator(self, out: &mut impl Write) -> Result<()> {
+        match self {
+            RecordSeparator::Byte(b) => out.write_all(&[b])?,
+            RecordSeparator::Paragraph => out.write_all(b"\n\n")?,
+        }
+        Ok(())
+    }
+}
+
+/// Where a record read by `read_record` was in the input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecordSpan {
+    /// The number of bytes skipped before the record: the empty lines
+    /// (thus also their number) in `Paragraph` mode, 0 otherwise.
+    pub skipped: usize,
+    /// The number of bytes of the record, including its terminator.
+    pub len: usize,
+}
+
+fn push_utf8(record: &mut String, bytes: &[u8]) -> Result<()> {
+    record.push_str(
+        std::str::from_utf8(bytes).context("record is not valid UTF-8")?,
+    );
+    Ok(())
+}
+
+/// Read the next record into `record` (overwriting it), without its
+/// terminator. Returns `None` at EOF.
+pub fn read_record(
+    inp: &mut impl BufRead,
+    separator: RecordSeparator,
+    record: &mut String,
+) -> Result<Option<RecordSpan>> {
+    record.clear();
+    let mut buf = Vec::new();
+    match separator {
+        RecordSeparator::Byte(b) => {
+            let len = inp.read_until(b, &mut buf)?;
+            if len == 0 {
+                return Ok(None);
+            }
+            if buf.last() == Some(&b) {
+                buf.pop();
+            }
+            push_utf8(record, &buf)?;
+            Ok(Some(RecordSpan { skipped: 0, len }))
+        }
+        RecordSeparator::Paragraph => {
+            let mut skipped = 0;
+            loop {
+                buf.clear();
+                if inp.read_until(b'\n', &mut buf)? == 0 {
+                    return Ok(None);
+                }
+                if buf != b"\n" {
+                    break;
+                }
+                skipped += 1;
+            }
+            let mut len = 0;
+            loop {
+                len += buf.len();
+                if buf == b"\n" {
+                    break;
+                }
+                push_utf8(record, &buf)?;
+                buf.clear();
+                if inp.read_until(b'\n', &mut buf)? == 0 {
+                    break;
+                }
+            }
+            if record.ends_with('\n') {
+                record.pop();
+            }
+            Ok(Some(RecordSpan { skipped, len }))
+        }
+    }
+}
+
+/// An iterator over the records of `inp`, see `read_record`.
+pub struct Records<R: BufRead> {
+    inp: R,
+    separator: RecordSeparator,
+}
+
+impl<R: BufRead> Records<R> {
+    pub fn new(inp: R, separator: RecordSeparator) -> Self {
+        Records { inp, separator }
+    }
+}
+
+impl<R: BufRead> Iterator for Records<R> {
+    type Item = Result<String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut record = String::new();
+        match read_record(&mut self.inp, self.separator, &mut record) {
+            Ok(Some(_)) => Some(Ok(record)),
+            Ok(None) => None,
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn t_read_record() -> Result<()> {
+        let t = |separator: &str, input: &str| -> Result<Vec<String>> {
+            Records::new(input.as_bytes(), separator.parse()?).collect()
+        };
+        assert_eq!(t("\\n", "a\n\nb\n")?, ["a", "", "b"]);
+        assert_eq!(t("\\0", "a\nb\0c\0\0d")?, ["a\nb", "c", "", "d"]);
+        assert_eq!(
+            t("paragraph", "\n\na\nb\n\n\n\nc\n\nd")?,
+            ["a\nb", "c", "d"]
+        );
+        assert_eq!(t("paragraph", "a\n\n\n")?, ["a"]);
+        assert!(t("paragraph", "")?.is_empty());
+        assert!(Records::new(&b"\xff"[..], RecordSeparator::Paragraph)
+            .next()
+            .unwrap()
+            .is_err());
+        assert!("ab".parse::<RecordSeparator>().is_err());
+
+        let mut inp = "\n\na\nb\n\n\nc\n".as_bytes();
+        let mut record = String::new();
+        let mut spans = Vec::new();
+        while let Some(span) =
+            read_record(&mut inp, RecordSeparator::Paragraph, &mut record)?
+        {
+            spans.push((span.skipped, span.len, record.clone()));
+        }
+        assert_eq!(spans, [(2, 5, "a\nb".into()), (1, 2, "c".into())]);
+
+        let mut out = Vec::new();
+        RecordSeparator::Paragraph.write_terminator(&mut out)?;
+        RecordSeparator::Byte(0).write_terminator(&mut out)?;
+        assert_eq!(out, b"\n\n\0");
+        Ok(())
+    }
+}
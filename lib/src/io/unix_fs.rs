@@ -0,0 +1,924 @@
+//! Unix FS easy functions.
+
+//! Why not use std ones? Because those expect Path, and CString is not representable as Path.
+
+use anyhow::{anyhow, bail, Context, Result};
+use enumn::N;
+use log::warn;
+use nix::dir::{Dir, Entry, Type};
+use nix::fcntl::{open, OFlag};
+use nix::sys::stat::{FileStat, Mode};
+use nix::unistd::{close, fsync, mkstemp, unlink};
+use nix::NixPath;
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use std::collections::HashSet;
+use std::ffi::{CStr, CString, OsStr};
+use std::fmt::Debug;
+use std::fs::{remove_dir_all, rename, File, Permissions};
+use std::io::{BufWriter, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+#[derive(N, Eq, PartialEq, Debug, Clone, Copy)]
+#[repr(u8)]
+pub enum FileType {
+    // XX are these Linux-specific, use C constants?
+    Pipe = 1,
+    CharDevice = 2,
+    Dir = 4,
+    BlockDevice = 6,
+    File = 8,
+    Link = 10,
+    Socket = 12,
+}
+
+pub trait EasyFileStat {
+    fn filetype(&self) -> FileType;
+}
+
+impl EasyFileStat for FileStat {
+    fn filetype(&self) -> FileType {
+        FileType::n(stat_filetype(self))
+            .expect("OS using one of the known constants")
+    }
+}
+
+fn stat_filetype(st: &FileStat) -> u8 {
+    ((st.st_mode & 0o0170000) >> 12) as u8
+}
+
+/// Test whether stat on `path` succeeds and yields the given file
+/// type. If permissions deny access or there are disk errors, the
+/// result is simply `false`.
+pub fn path_is_type(
+    path: &CStr,
+    ftypes: &[FileType],
+    follow_links: bool,
+) -> bool {
+    let statfunction: fn(&CStr) -> _ = if follow_links {
+        nix::sys::stat::stat
+    } else {
+        nix::sys::stat::lstat
+    };
+    match statfunction(path) {
+        Ok(m) => ftypes.iter().any(|ftype| m.filetype() == *ftype),
+        Err(_) => false,
+    }
+}
+
+pub fn path_is_file(path: &CStr) -> bool {
+    path_is_type(path, &[FileType::File], false)
+}
+pub fn path_is_dir(path: &CStr) -> bool {
+    path_is_type(path, &[FileType::Dir], false)
+}
+pub fn path_is_link(path: &CStr) -> bool {
+    path_is_type(path, &[FileType::Link], false)
+}
+pub fn path_is_blockdevice(path: &CStr) -> bool {
+    path_is_type(path, &[FileType::BlockDevice], false)
+}
+pub fn path_is_pipe(path: &CStr) -> bool {
+    path_is_type(path, &[FileType::Pipe], false)
+}
+pub fn path_is_socket(path: &CStr) -> bool {
+    path_is_type(path, &[FileType::Socket], false)
+}
+pub fn path_is_chardevice(path: &CStr) -> bool {
+    path_is_type(path, &[FileType::CharDevice], false)
+}
+
+pub fn path_is_normal(path: &CStr) -> bool {
+    path_is_type(path, &[FileType::File, FileType::Dir], true)
+}
+
+/// The commonly needed parts of `stat` results, in easy to use
+/// types.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EasyMetadata {
+    pub filetype: FileType,
+    pub size: u64,
+    /// The number of 512-byte blocks allocated.
+    pub blocks: u64,
+    pub mtime: SystemTime,
+    pub ctime: SystemTime,
+    pub atime: SystemTime,
+    pub uid: u32,
+    pub gid: u32,
+    /// The permission bits (including setuid, setgid and sticky),
+    /// i.e. `st_mode & 0o7777`.
+    pub permissions: u32,
+    pub dev: u64,
+    pub ino: u64,
+    pub nlink: u64,
+}
+
+fn system_time_from_timespec(secs: i64, nsecs: i64) -> SystemTime {
+    let t = if secs >= 0 {
+        UNIX_EPOCH + Duration::from_secs(secs as u64)
+    } else {
+        UNIX_EPOCH - Duration::from_secs(secs.unsigned_abs())
+    };
+    t + Duration::from_nanos(nsecs as u64)
+}
+
+impl From<&FileStat> for EasyMetadata {
+    // The field types of `FileStat` vary between platforms
+    #[allow(clippy::unnecessary_cast)]
+    fn from(st: &FileStat) -> Self {
+        EasyMetadata {
+            filetype: st.filetype(),
+            size: st.st_size as u64,
+            blocks: st.st_blocks as u64,
+            mtime: system_time_from_timespec(st.st_mtime, st.st_mtime_nsec),
+            ctime: system_time_from_timespec(st.st_ctime, st.st_ctime_nsec),
+            atime: system_time_from_timespec(st.st_atime, st.st_atime_nsec),
+            uid: st.st_uid,
+            gid: st.st_gid,
+            permissions: (st.st_mode & 0o7777) as u32,
+            dev: st.st_dev,
+            ino: st.st_ino,
+            nlink: st.st_nlink as u64,
+        }
+    }
+}
+
+/// `stat` (if `follow_links` is true) or `lstat` on `path`, which can
+/// be a `CStr`, `OsStr`, `Path` or `str`.
+pub fn easy_stat<P: ?Sized + NixPath + Debug>(
+    path: &P,
+    follow_links: bool,
+) -> Result<EasyMetadata> {
+    let st = if follow_links {
+        nix::sys::stat::stat(path)
+    } else {
+        nix::sys::stat::lstat(path)
+    }
+    .with_context(|| {
+        anyhow!(
+            "{} on {:?}",
+            if follow_links { "stat" } else { "lstat" },
+            path
+        )
+    })?;
+    Ok(EasyMetadata::from(&st))
+}
+
+/// Identifies the mount a path is on. Paths on the same mount have
+/// equal `MountId`s.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MountId {
+    /// The mount id from `statx` (Linux >= 5.8), which also
+    /// distinguishes bind mounts of the same file system.
+    Mount(u64),
+    /// The device id (`st_dev`), when the mount id is not available.
+    Device(u64),
+}
+
+#[cfg(all(target_os = "linux", target_env = "gnu"))]
+fn statx_mount_id(path: &CStr, follow_links: bool) -> Option<u64> {
+    let mut stx = std::mem::MaybeUninit::<libc::statx>::zeroed();
+    let flags = if follow_links {
+        0
+    } else {
+        libc::AT_SYMLINK_NOFOLLOW
+    };
+    let res = unsafe {
+        libc::statx(
+            libc::AT_FDCWD,
+            path.as_ptr(),
+            flags,
+            libc::STATX_MNT_ID,
+            stx.as_mut_ptr(),
+        )
+    };
+    if res != 0 {
+        return None;
+    }
+    let stx = unsafe { stx.assume_init() };
+    if stx.stx_mask & libc::STATX_MNT_ID != 0 {
+        Some(stx.stx_mnt_id)
+    } else {
+        None
+    }
+}
+
+#[cfg(not(all(target_os = "linux", target_env = "gnu")))]
+fn statx_mount_id(_path: &CStr, _follow_links: bool) -> Option<u64> {
+    None
+}
+
+/// The `MountId` of the mount `path` is on.
+pub fn mount_id(path: &Path, follow_links: bool) -> Result<MountId> {
+    let cpath = cstring_from_path(path)?;
+    if let Some(id) = statx_mount_id(&cpath, follow_links) {
+        return Ok(MountId::Mount(id));
+    }
+    Ok(MountId::Device(easy_stat(cpath.as_c_str(), follow_links)?.dev))
+}
+
+/// Whether `path` (following symlinks) is the root of a mount (bind
+/// mounts of a file system onto itself are only detected when the
+/// mount id is available).
+pub fn is_mount_point(path: &Path) -> Result<bool> {
+    let parent = path.join("..");
+    if mount_id(path, true)? != mount_id(&parent, true)? {
+        return Ok(true);
+    }
+    // The root directory is its own parent
+    let m = easy_stat(path, true)?;
+    let pm = easy_stat(&parent, true)?;
+    Ok(m.dev == pm.dev && m.ino == pm.ino)
+}
+
+/// An entry from `read_dir_cstr` (never `.` or `..`).
+pub struct CDirEntry(Entry);
+
+impl CDirEntry {
+    pub fn file_name(&self) -> &CStr {
+        self.0.file_name()
+    }
+
+    /// The type as reported by readdir, without doing a stat call;
+    /// `None` if the file system doesn't provide it (then use
+    /// `easy_stat` or `path_is_type`).
+    pub fn filetype(&self) -> Option<FileType> {
+        self.0.file_type().map(|t| match t {
+            Type::Fifo => FileType::Pipe,
+            Type::CharacterDevice => FileType::CharDevice,
+            Type::Directory => FileType::Dir,
+            Type::BlockDevice => FileType::BlockDevice,
+            Type::File => FileType::File,
+            Type::Symlink => FileType::Link,
+            Type::Socket => FileType::Socket,
+        })
+    }
+
+    pub fn ino(&self) -> u64 {
+        self.0.ino()
+    }
+}
+
+impl Debug for CDirEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CDirEntry")
+            .field("file_name", &self.file_name())
+            .field("filetype", &self.filetype())
+            .field("ino", &self.ino())
+            .finish()
+    }
+}
+
+fn is_dot_or_dotdot(name: &CStr) -> bool {
+    matches!(name.to_bytes(), b"." | b"..")
+}
+
+/// Iterate over the entries of the directory at `path` via
+/// opendir/readdir, without allocating per entry. If `sorted` is
+/// true, the entries are collected and sorted by name, and then all
+/// errors are reported directly from the call (items then always
+/// being Ok).
+pub fn read_dir_cstr(
+    path: &CStr,
+    sorted: bool,
+) -> Result<Box<dyn Iterator<Item = Result<CDirEntry>>>> {
+    let dir = Dir::open(
+        path,
+        OFlag::O_RDONLY | OFlag::O_DIRECTORY | OFlag::O_CLOEXEC,
+        Mode::empty(),
+    )
+    .with_context(|| anyhow!("opening directory {:?} for reading", path))?;
+    let path = path.to_owned();
+    let iter = dir.into_iter().filter_map(move |entry| match entry {
+        Ok(entry) => {
+            if is_dot_or_dotdot(entry.file_name()) {
+                None
+            } else {
+                Some(Ok(CDirEntry(entry)))
+            }
+        }
+        Err(e) => {
+            Some(Err(e).with_context(|| anyhow!("readdir on {:?}", path)))
+        }
+    });
+    if sorted {
+        let mut vec = iter.collect::<Result<Vec<_>>>()?;
+        vec.sort_by(|a, b| a.file_name().cmp(b.file_name()));
+        Ok(Box::new(vec.into_iter().map(Ok)))
+    } else {
+        Ok(Box::new(iter))
+    }
+}
+
+pub fn cstr_as_path(path: &CStr) -> &Path {
+    Path::new(OsStr::from_bytes(path.to_bytes()))
+}
+
+fn cstring_from_path(path: &Path) -> Result<CString> {
+    Ok(CString::new(path.as_os_str().as_bytes())?)
+}
+
+/// The directory part of `path` (`.` if there is none).
+fn cstr_dirname(path: &CStr) -> CString {
+    let bytes = path.to_bytes();
+    match bytes.iter().rposition(|b| *b == b'/') {
+        Some(0) => CString::new("/").unwrap(),
+        Some(i) => CString::new(&bytes[..i]).unwrap(),
+        None => CString::new(".").unwrap(),
+    }
+}
+
+/// Make the directory entries in `dir` durable.
+pub fn fsync_dir(dir: &CStr) -> Result<()> {
+    let fd = open(
+        dir,
+        OFlag::O_RDONLY | OFlag::O_DIRECTORY | OFlag::O_CLOEXEC,
+        Mode::empty(),
+    )
+    .with_context(|| anyhow!("opening dir {:?}", dir))?;
+    let res = fsync(fd).with_context(|| anyhow!("fsync on dir {:?}", dir));
+    close(fd)?;
+    res
+}
+
+/// Create a new file with a unique name starting with `prefix` in
+/// `dir`, with permissions 0600, like mkstemp(3).
+pub fn mkstemp_in(dir: &CStr, prefix: &str) -> Result<(File, CString)> {
+    let template = cstr_as_path(dir).join(format!("{prefix}XXXXXX"));
+    let (fd, path) = mkstemp(&template)
+        .with_context(|| anyhow!("mkstemp {:?}", template))?;
+    Ok((unsafe { File::from_raw_fd(fd) }, cstring_from_path(&path)?))
+}
+
+/// fsync `file`, then rename `from` to `to` and fsync the directory
+/// containing `to`, so that after returning, `to` durably refers to
+/// the complete contents written to `file`.
+pub fn fsync_and_rename(file: File, from: &CStr, to: &CStr) -> Result<()> {
+    file.sync_all()
+        .with_context(|| anyhow!("fsync on {:?}", from))?;
+    drop(file);
+    rename(cstr_as_path(from), cstr_as_path(to))
+        .with_context(|| anyhow!("renaming {:?} to {:?}", from, to))?;
+    fsync_dir(&cstr_dirname(to))
+}
+
+/// A file created via `mkstemp_in` that is deleted again when
+/// dropped, unless `commit` is called.
+#[derive(Debug)]
+pub struct TempFile {
+    file: Option<File>,
+    path: CString,
+}
+
+impl TempFile {
+    pub fn new_in(dir: &CStr, prefix: &str) -> Result<Self> {
+        let (file, path) = mkstemp_in(dir, prefix)?;
+        Ok(TempFile {
+            file: Some(file),
+            path,
+        })
+    }
+
+    /// Create a temporary file in the same directory as `target`
+    /// (which is necessary for `commit` to be atomic).
+    pub fn for_target(target: &CStr) -> Result<Self> {
+        let name = cstr_as_path(target)
+            .file_name()
+            .map(|s| s.to_string_lossy())
+            .unwrap_or_default();
+        Self::new_in(&cstr_dirname(target), &format!(".{name}.tmp-"))
+    }
+
+    pub fn path(&self) -> &CStr {
+        &self.path
+    }
+
+    pub fn file(&mut self) -> &mut File {
+        self.file.as_mut().expect("only taken by commit")
+    }
+
+    /// Durably (and atomically) move the file to `to`. If that
+    /// fails, the file is deleted (as when dropped).
+    pub fn commit(mut self, to: &CStr) -> Result<()> {
+        let file = self.file.take().expect("only taken by commit");
+        let res = fsync_and_rename(file, &self.path, to);
+        if res.is_err() {
+            // (fails if the rename happened and only the fsync of
+            // the directory failed)
+            let _ = unlink(self.path.as_c_str());
+        }
+        res
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        if self.file.take().is_some() {
+            let _ = unlink(self.path.as_c_str());
+        }
+    }
+}
+
+/// Replace the file at `path` with `bytes`, atomically and durably:
+/// readers (and the file system after a crash) see either the old
+/// or the complete new contents. `mode` gives the permissions (e.g.
+/// 0o644; not affected by the umask).
+pub fn write_file_atomically(
+    path: &Path,
+    bytes: &[u8],
+    mode: u32,
+) -> Result<()> {
+    write_file_atomically_with(path, mode, |out| Ok(out.write_all(bytes)?))
+}
+
+/// Like `write_file_atomically`, with the contents written by
+/// `write` (buffered). If it fails, the file at `path` is left
+/// unchanged.
+pub fn write_file_atomically_with<T>(
+    path: &Path,
+    mode: u32,
+    write: impl FnOnce(&mut BufWriter<&mut File>) -> Result<T>,
+) -> Result<T> {
+    let target = cstring_from_path(path)?;
+    let mut tmp = TempFile::for_target(&target)?;
+    let tmp_path = tmp.path().to_owned();
+    let res = (|| -> Result<T> {
+        tmp.file().set_permissions(Permissions::from_mode(mode))?;
+        let mut out = BufWriter::new(tmp.file());
+        let res = write(&mut out)?;
+        out.flush()?;
+        Ok(res)
+    })()
+    .with_context(|| anyhow!("writing to {:?}", tmp_path))?;
+    tmp.commit(&target)?;
+    Ok(res)
+}
+
+/// A file whose contents replace the file at a path atomically (see
+/// `write_file_atomically`) once `commit` is called; until then they
+/// are written (buffered) to a temporary file next to it, which is
+/// deleted if the `AtomicFile` is dropped without committing.
+#[derive(Debug)]
+pub struct AtomicFile {
+    out: BufWriter<File>,
+    tmp: TempFile,
+    target: CString,
+}
+
+impl AtomicFile {
+    /// Start replacing `path`, with the permissions `mode` (not
+    /// affected by the umask).
+    pub fn create(path: &Path, mode: u32) -> Result<Self> {
+        let target = cstring_from_path(path)?;
+        let mut tmp = TempFile::for_target(&target)?;
+        let file = (|| -> Result<File> {
+            tmp.file().set_permissions(Permissions::from_mode(mode))?;
+            Ok(tmp.file().try_clone()?)
+        })()
+        .with_context(|| anyhow!("preparing {:?}", tmp.path()))?;
+        Ok(AtomicFile {
+            out: BufWriter::new(file),
+            tmp,
+            target,
+        })
+    }
+
+    /// Durably (and atomically) replace the file at the path.
+    pub fn commit(self) -> Result<()> {
+        let AtomicFile { out, tmp, target } = self;
+        out.into_inner()
+            .map_err(|e| e.into_error())
+            .with_context(|| anyhow!("writing to {:?}", tmp.path()))?;
+        tmp.commit(&target)
+    }
+}
+
+impl Write for AtomicFile {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.out.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.out.flush()
+    }
+}
+
+impl AsRawFd for AtomicFile {
+    fn as_raw_fd(&self) -> RawFd {
+        self.out.get_ref().as_raw_fd()
+    }
+}
+/// A directory created with a unique name, like mkdtemp(3), that is
+/// deleted recursively when dropped, unless `keep` is called.
+#[derive(Debug)]
+pub struct TempDir {
+    path: Option<CString>,
+}
+
+impl TempDir {
+    pub fn new_in(dir: &CStr, prefix: &str) -> Result<Self> {
+        let template = cstring_from_path(
+            &cstr_as_path(dir).join(format!("{prefix}XXXXXX")),
+        )?;
+        let mut buf = template.into_bytes_with_nul();
+        // (nix 0.24 doesn't offer mkdtemp)
+        let res =
+            unsafe { libc::mkdtemp(buf.as_mut_ptr() as *mut libc::c_char) };
+        if res.is_null() {
+            return Err(std::io::Error::last_os_error()).with_context(|| {
+                anyhow!("mkdtemp {:?}", String::from_utf8_lossy(&buf))
+            });
+        }
+        buf.pop();
+        Ok(TempDir {
+            path: Some(CString::new(buf)?),
+        })
+    }
+
+    pub fn path(&self) -> &CStr {
+        self.path.as_ref().expect("only taken by keep")
+    }
+
+    /// Don't delete the directory, return its path instead.
+    pub fn keep(mut self) -> CString {
+        self.path.take().expect("only taken by keep")
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        if let Some(path) = self.path.take() {
+            let _ = remove_dir_all(cstr_as_path(&path));
+        }
+    }
+}
+
+/// How `disk_usage` counts the sizes of files and dirs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SizeMode {
+    /// The allocated blocks (`st_blocks` * 512), like du(1).
+    Blocks,
+    /// The sizes (`st_size`), like `du --apparent-size`.
+    Apparent,
+}
+
+impl SizeMode {
+    pub fn size_of(self, m: &EasyMetadata) -> u64 {
+        match self {
+            SizeMode::Blocks => m.blocks * 512,
+            SizeMode::Apparent => m.size,
+        }
+    }
+}
+
+/// The disk usage of a directory tree, see `disk_usage`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DirUsage {
+    pub path: PathBuf,
+    /// The size of the dir itself and of everything below it.
+    pub bytes: u64,
+    /// The number of non-dir entries below it.
+    pub files: u64,
+    /// The number of entries below it that could not be read.
+    pub errors: u64,
+    /// Sorted by name.
+    pub subdirs: Vec<DirUsage>,
+}
+
+/// The disk usage of the directory tree at `path` (following
+/// symlinks only for `path` itself), with the subdirs processed in
+/// parallel. Files with multiple hard links are counted once, in the
+/// first dir they are encountered in (which one that is is
+/// unspecified). With `one_file_system`, dirs on other mounts than
+/// `path` are left out. Failures to read entries below `path` are
+/// logged as warnings and counted in `DirUsage::errors`.
+pub fn disk_usage(
+    path: &Path,
+    size_mode: SizeMode,
+    one_file_system: bool,
+) -> Result<DirUsage> {
+    let m = easy_stat(path, true)?;
+    if m.filetype != FileType::Dir {
+        bail!("not a directory: {:?}", path)
+    }
+    let root_mount = if one_file_system {
+        Some(mount_id(path, true)?)
+    } else {
+        None
+    };
+    let usage = DiskUsage {
+        size_mode,
+        root_mount,
+        seen_inodes: Mutex::new(HashSet::new()),
+    };
+    Ok(usage.dir_usage(path, &m))
+}
+
+/// The state of a `disk_usage` run.
+struct DiskUsage {
+    size_mode: SizeMode,
+    root_mount: Option<MountId>,
+    /// (dev, ino) of the files with multiple links counted already.
+    seen_inodes: Mutex<HashSet<(u64, u64)>>,
+}
+
+enum EntryUsage {
+    Dir(DirUsage),
+    File(u64),
+    /// A hard link to a file counted already, or a dir on another
+    /// mount.
+    Skipped,
+}
+
+impl DiskUsage {
+    fn dir_usage(&self, path: &Path, m: &EasyMetadata) -> DirUsage {
+        let mut usage = DirUsage {
+            path: path.to_path_buf(),
+            bytes: self.size_mode.size_of(m),
+            files: 0,
+            errors: 0,
+            subdirs: Vec::new(),
+        };
+        let names = match cstring_from_path(path).and_then(|cpath| {
+            read_dir_cstr(&cpath, true)?
+                .map(|entry| {
+                    Ok(OsStr::from_bytes(entry?.file_name().to_bytes())
+                        .to_owned())
+                })
+                .collect::<Result<Vec<_>>>()
+        }) {
+            Ok(names) => names,
+            Err(e) => {
+                warn!("{:#}", e);
+                usage.errors += 1;
+                return usage;
+            }
+        };
+        let entries: Vec<Result<EntryUsage>> = names
+            .into_par_iter()
+            .map(|name| self.entry_usage(&path.join(name)))
+            .collect();
+        for entry in entries {
+            match entry {
+                Ok(EntryUsage::Dir(subdir)) => {
+                    usage.bytes += subdir.bytes;
+                    usage.files += subdir.files;
+                    usage.errors += subdir.errors;
+                    usage.subdirs.push(subdir);
+                }
+                Ok(EntryUsage::File(bytes)) => {
+                    usage.bytes += bytes;
+                    usage.files += 1;
+                }
+                Ok(EntryUsage::Skipped) => (),
+                Err(e) => {
+                    warn!("{:#}", e);
+                    usage.errors += 1;
+                }
+            }
+        }
+        usage
+    }
+
+    fn entry_usage(&self, path: &Path) -> Result<EntryUsage> {
+        let m = easy_stat(path, false)?;
+        if m.filetype == FileType::Dir {
+            if let Some(root_mount) = self.root_mount {
+                if mount_id(path, false)? != root_mount {
+                    return Ok(EntryUsage::Skipped);
+                }
+            }
+            return Ok(EntryUsage::Dir(self.dir_usage(path, &m)));
+        }
+        if m.nlink > 1
+            && !self
+                .seen_inodes
+                .lock()
+                .expect("no panics while locked")
+                .insert((m.dev, m.ino))
+        {
+            return Ok(EntryUsage::Skipped);
+        }
+        Ok(EntryUsage::File(self.size_mode.size_of(&m)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::fs::PermissionsExt;
+
+    use super::*;
+
+    #[test]
+    fn t_filetype() {
+        fn t(f: fn(&CStr) -> bool, s: &str, expected: bool) {
+            assert_eq!(f(&CString::new(s).unwrap()), expected);
+        }
+        t(path_is_dir, ".", true);
+        t(path_is_file, ".", false);
+        // Non-existing:
+        t(path_is_dir, "8hbrr2kz8kmztb4dqh4", false);
+        t(path_is_file, "8hbrr2kz8kmztb4dqh4", false);
+        // Looking at hopefully existing paths (XX evil):
+        t(path_is_file, "/etc/fstab", true);
+        t(path_is_chardevice, "/dev/null", true);
+        t(path_is_chardevice, "/dev/loop0", false);
+        t(path_is_blockdevice, "/dev/loop0", true);
+        t(path_is_normal, "/dev/loop0", false);
+        t(path_is_normal, "8hbrr2kz8kmztb4dqh4", false);
+        t(path_is_normal, "/etc/fstab", true);
+        t(path_is_link, "/etc/localtime", true);
+        t(path_is_normal, "/etc/localtime", true);
+    }
+
+    #[test]
+    fn t_easy_stat() -> Result<()> {
+        let dir = TempDir::new_in(
+            &CString::new(std::env::temp_dir().as_os_str().as_bytes())?,
+            "chj-rustbin-test-",
+        )?;
+        let dirpath = cstr_as_path(dir.path());
+        std::fs::write(dirpath.join("f"), "hello")?;
+        std::os::unix::fs::symlink("f", dirpath.join("l"))?;
+
+        let m = easy_stat(&dirpath.join("f"), false)?;
+        let std_m = std::fs::metadata(dirpath.join("f"))?;
+        assert_eq!(m.filetype, FileType::File);
+        assert_eq!(m.size, 5);
+        assert_eq!(m.mtime, std_m.modified()?);
+        assert_eq!(m.permissions, std_m.permissions().mode() & 0o7777);
+        assert_eq!(m.nlink, 1);
+        let l = CString::new(dirpath.join("l").as_os_str().as_bytes())?;
+        assert_eq!(easy_stat(l.as_c_str(), false)?.filetype, FileType::Link);
+        assert_eq!(easy_stat(l.as_c_str(), true)?, m);
+        assert!(easy_stat(OsStr::new("8hbrr2kz8kmztb4dqh4"), true).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn t_read_dir_cstr() -> Result<()> {
+        let dir = TempDir::new_in(
+            &CString::new(std::env::temp_dir().as_os_str().as_bytes())?,
+            "chj-rustbin-test-",
+        )?;
+        let dirpath = cstr_as_path(dir.path());
+        for name in ["b", "a", "c"] {
+            std::fs::write(dirpath.join(name), "")?;
+        }
+        std::fs::create_dir(dirpath.join("d"))?;
+        let entries = read_dir_cstr(dir.path(), true)?
+            .map(|e| {
+                e.map(|e| {
+                    (e.file_name().to_str().unwrap().to_owned(), e.filetype())
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        assert_eq!(
+            entries,
+            [
+                ("a".into(), Some(FileType::File)),
+                ("b".into(), Some(FileType::File)),
+                ("c".into(), Some(FileType::File)),
+                ("d".into(), Some(FileType::Dir)),
+            ]
+        );
+        assert_eq!(read_dir_cstr(dir.path(), false)?.count(), 4);
+        assert!(read_dir_cstr(&CString::new("8hbrr2kz8kmztb4dqh4")?, false)
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn t_tempfile_tempdir() -> Result<()> {
+        use std::io::Write;
+
+        let tmp = CString::new(std::env::temp_dir().as_os_str().as_bytes())?;
+        let dir = TempDir::new_in(&tmp, "chj-rustbin-test-")?;
+        assert!(path_is_dir(dir.path()));
+        let target = CString::new(
+            cstr_as_path(dir.path()).join("out").as_os_str().as_bytes(),
+        )?;
+        {
+            let mut f = TempFile::for_target(&target)?;
+            f.file().write_all(b"abandoned")?;
+            assert!(path_is_file(f.path()));
+        }
+        assert!(!path_is_file(&target));
+        let mut f = TempFile::for_target(&target)?;
+        let tmppath = f.path().to_owned();
+        f.file().write_all(b"hello")?;
+        f.commit(&target)?;
+        assert!(!path_is_file(&tmppath));
+        assert_eq!(std::fs::read(cstr_as_path(&target))?, b"hello");
+        assert_eq!(std::fs::read_dir(cstr_as_path(dir.path()))?.count(), 1);
+        // A failing rename (onto a non-empty directory) doesn't leave
+        // the temporary file behind
+        std::fs::create_dir(cstr_as_path(dir.path()).join("sub"))?;
+        std::fs::write(cstr_as_path(dir.path()).join("sub/x"), "")?;
+        let subdir = CString::new(
+            cstr_as_path(dir.path()).join("sub").as_os_str().as_bytes(),
+        )?;
+        let f = TempFile::for_target(&target)?;
+        let tmppath = f.path().to_owned();
+        assert!(f.commit(&subdir).is_err());
+        assert!(!path_is_file(&tmppath));
+        assert_eq!(std::fs::read_dir(cstr_as_path(dir.path()))?.count(), 2);
+        let dirpath = dir.path().to_owned();
+        drop(dir);
+        assert!(!path_is_dir(&dirpath));
+        Ok(())
+    }
+
+    #[test]
+    fn t_write_file_atomically() -> Result<()> {
+        let tmp = CString::new(std::env::temp_dir().as_os_str().as_bytes())?;
+        let dir = TempDir::new_in(&tmp, "chj-rustbin-test-")?;
+        let path = cstr_as_path(dir.path()).join("out");
+        write_file_atomically(&path, b"one", 0o640)?;
+        assert_eq!(std::fs::read(&path)?, b"one");
+        let mode = std::fs::metadata(&path)?.permissions().mode();
+        assert_eq!(mode & 0o777, 0o640);
+        let res =
+            write_file_atomically_with(&path, 0o644, |out| -> Result<()> {
+                out.write_all(b"partial")?;
+                anyhow::bail!("failing")
+            });
+        assert!(res.is_err());
+        assert_eq!(std::fs::read(&path)?, b"one");
+        let n = write_file_atomically_with(&path, 0o644, |out| {
+            writeln!(out, "two")?;
+            Ok(2)
+        })?;
+        assert_eq!(n, 2);
+        assert_eq!(std::fs::read(&path)?, b"two\n");
+        assert_eq!(std::fs::read_dir(cstr_as_path(dir.path()))?.count(), 1);
+
+        let mut out = AtomicFile::create(&path, 0o600)?;
+        write!(out, "three")?;
+        drop(out);
+        assert_eq!(std::fs::read(&path)?, b"two\n");
+        let mut out = AtomicFile::create(&path, 0o600)?;
+        write!(out, "four")?;
+        assert_eq!(std::fs::read_dir(cstr_as_path(dir.path()))?.count(), 2);
+        out.commit()?;
+        assert_eq!(std::fs::read(&path)?, b"four");
+        let mode = std::fs::metadata(&path)?.permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        assert_eq!(std::fs::read_dir(cstr_as_path(dir.path()))?.count(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn t_disk_usage() -> Result<()> {
+        let tmp = CString::new(std::env::temp_dir().as_os_str().as_bytes())?;
+        let dir = TempDir::new_in(&tmp, "chj-rustbin-test-")?;
+        let dirpath = cstr_as_path(dir.path());
+        std::fs::create_dir_all(dirpath.join("a/b"))?;
+        std::fs::create_dir(dirpath.join("c"))?;
+        std::fs::write(dirpath.join("f"), "12345")?;
+        std::fs::write(dirpath.join("a/g"), "123")?;
+        std::fs::write(dirpath.join("a/b/h"), vec![0; 10000])?;
+        std::fs::hard_link(dirpath.join("a/b/h"), dirpath.join("c/h2"))?;
+        std::fs::hard_link(dirpath.join("a/b/h"), dirpath.join("c/h3"))?;
+
+        let mut dir_sizes = 0;
+        for d in ["", "a", "a/b", "c"] {
+            dir_sizes += std::fs::metadata(dirpath.join(d))?.len();
+        }
+        let usage = disk_usage(dirpath, SizeMode::Apparent, false)?;
+        assert_eq!(usage.files, 3);
+        assert_eq!(usage.bytes, dir_sizes + 5 + 3 + 10000);
+        assert_eq!(usage.errors, 0);
+        let names: Vec<_> =
+            usage.subdirs.iter().map(|d| d.path.clone()).collect();
+        assert_eq!(names, [dirpath.join("a"), dirpath.join("c")]);
+        assert_eq!(usage.subdirs[0].subdirs[0].path, dirpath.join("a/b"));
+        // The hard links are counted in one of the dirs only
+        let a = &usage.subdirs[0];
+        let c = &usage.subdirs[1];
+        assert_eq!(a.files + c.files, 2);
+
+        let usage = disk_usage(dirpath, SizeMode::Blocks, true)?;
+        assert_eq!(usage.files, 3);
+        assert_eq!(usage.bytes % 512, 0);
+        assert!(disk_usage(&dirpath.join("f"), SizeMode::Blocks, false)
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn t_is_mount_point() -> Result<()> {
+        assert!(is_mount_point(Path::new("/"))?);
+        assert!(is_mount_point(Path::new("/proc"))?);
+        assert!(!is_mount_point(Path::new("src"))?);
+        assert_eq!(
+            mount_id(Path::new("src"), true)?,
+            mount_id(Path::new("src/io"), true)?
+        );
+        Ok(())
+    }
+}
@@ -9,10 +9,17 @@ pub mod util;
 
 pub mod alist;
 pub mod alternatively;
+pub mod bloom;
 pub mod checked_mutex;
+pub mod cli;
 pub mod conslist;
+pub mod editor_frontend;
+pub mod error;
 pub mod fp;
 pub mod index_map;
+pub mod netcounters;
 pub mod numbers;
+pub mod pipeline;
+pub mod process;
 pub mod region;
 pub mod sequences;
@@ -0,0 +1,1304 @@
+//! Processing of periodic snapshots of network interface traffic
+//! counters (as from `wg` or `ip -s link` run in a loop with a
+//! daemontools log), into hourly TSV (or CSV) tables and monthly summaries
+//! per interface. The binaries (parse-wg-log, parse-ip-link-log) only
+//! parse their input format into `Datapoint`s.
+
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::CString;
+use std::fs::{OpenOptions, Permissions};
+use std::io::{BufWriter, Write};
+use std::ops::Add;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, UNIX_EPOCH};
+use std::{fmt::Display, fmt::Formatter};
+
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::{DateTime, Datelike, NaiveDate, Timelike, Utc};
+use genawaiter::rc::Gen;
+use tai64::Tai64N;
+
+use crate::alist::AListBuf;
+use crate::fp::on;
+use crate::io::unix_fs::{
+    write_file_atomically, write_file_atomically_with, TempFile,
+};
+use crate::numbers::{max_f64, nandropping_add, numbers_within};
+use crate::sequences::{try_group_owned, try_keep_run_ends};
+use crate::text::humanize::{self, ByteUnits};
+use crate::text::svgchart::{LineChart, Series};
+use crate::text::table::{print_table, TableOptions};
+use crate::time::excel::{CsvSeparator, ExcelCsvWriter};
+use crate::time::tai::{Tai64Format, Tai64NLabel, TimeFormatting};
+use crate::util::div::{hashmap_add, hashmap_get_mut_vivify};
+use crate::util::signals::{check_termination, Terminated};
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Transfer {
+    /// bytes total since interface was activated
+    pub received: usize,
+    /// bytes total since interface was activated
+    pub sent: usize,
+}
+impl Transfer {
+    pub fn total(&self) -> usize {
+        self.received + self.sent
+    }
+    pub fn sub(&self, old: &Transfer) -> Result<Transfer> {
+        let received =
+            self.received.checked_sub(old.received).ok_or_else(|| {
+                anyhow!("old has higher received transfers than new")
+            })?;
+        let sent = self
+            .sent
+            .checked_sub(old.sent)
+            .ok_or_else(|| anyhow!("old has higher sent transfers than new"))?;
+        Ok(Transfer { received, sent })
+    }
+}
+impl Add for Transfer {
+    type Output = Transfer;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        Transfer {
+            received: self.received + rhs.received,
+            sent: self.sent + rhs.sent,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct DateHourUtc {
+    pub hour: u8,
+    pub date: NaiveDate,
+}
+impl DateHourUtc {
+    pub fn from_timestamp(timestamp: &Tai64N) -> Self {
+        let dt = timestamp.to_datetime_utc();
+        DateHourUtc {
+            date: dt.date_naive(),
+            hour: dt.hour() as u8,
+        }
+    }
+}
+
+/// The counters of one interface at one point in time.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Datapoint {
+    /// Index of the interface; the parser decides about the mapping
+    /// to interface names. The memory needed per `Timepoint` is
+    /// proportional to the highest index, so indices should be
+    /// small.
+    pub interface: u16,
+    pub timestamp: Tai64N,
+    pub date_and_hour: DateHourUtc, // cache, derived from timestamp
+    pub transfer: Transfer,
+}
+impl Datapoint {
+    pub fn new(interface: u16, timestamp: Tai64N, transfer: Transfer) -> Self {
+        Datapoint {
+            interface,
+            timestamp,
+            date_and_hour: DateHourUtc::from_timestamp(&timestamp),
+            transfer,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash, PartialOrd, Ord)]
+struct YearMonth {
+    year: i32,
+    month: u8,
+}
+impl YearMonth {
+    fn from_naivedate(nd: NaiveDate) -> YearMonth {
+        YearMonth {
+            year: nd.year(),
+            month: nd.month() as u8,
+        }
+    }
+}
+impl Display for YearMonth {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_fmt(format_args!("{}/{:02}", self.year, self.month))
+    }
+}
+
+/// The datapoints of all interfaces from the same snapshot, indexed
+/// by interface.
+#[derive(Debug)]
+struct Timepoint(Vec<Option<Datapoint>>);
+impl Timepoint {
+    fn insert(&mut self, dp: Datapoint) {
+        let i = dp.interface as usize;
+        if self.0.len() <= i {
+            self.0.resize_with(i + 1, || None);
+        }
+        self.0[i] = Some(dp);
+    }
+    pub fn get(&self, i: usize) -> Option<&Datapoint> {
+        self.0.get(i).and_then(|v| v.as_ref())
+    }
+    pub fn num_interfaces(&self) -> usize {
+        self.0.len()
+    }
+    pub fn from_iter(points: impl Iterator<Item = Datapoint>) -> Result<Self> {
+        let mut ps = Self(Vec::new());
+        let mut ok = false;
+        for p in points {
+            ps.insert(p);
+            ok = true;
+        }
+        if ok {
+            Ok(ps)
+        } else {
+            bail!("trying to construct Datapoints with empty input iterator")
+        }
+    }
+    /// The first timestamp from the left
+    pub fn timestamp(&self) -> &Tai64N {
+        if let Some(dp) = self.0.iter().flatten().next() {
+            return &dp.timestamp;
+        }
+        panic!("always having at least one entry")
+    }
+    pub fn timestamp_seconds(&self) -> u64 {
+        // broken up just because rust-analyzer has some issue with .0.0
+        let a = self.timestamp().0;
+        a.0
+    }
+    pub fn date_and_hour(&self) -> DateHourUtc {
+        if let Some(dp) = self.0.iter().flatten().next() {
+            return dp.date_and_hour;
+        }
+        panic!("always having at least one entry")
+    }
+    /// Whether both are from the same hour and have the same counters
+    /// for the same interfaces. Only the first and last of a run of
+    /// such timepoints are relevant for the hourly aggregates.
+    pub fn is_unchanged_from(&self, other: &Timepoint) -> bool {
+        self.date_and_hour() == other.date_and_hour()
+            && (0..self.num_interfaces().max(other.num_interfaces())).all(|i| {
+                match (self.get(i), other.get(i)) {
+                    (Some(a), Some(b)) => a.transfer == b.transfer,
+                    (None, None) => true,
+                    _ => false,
+                }
+            })
+    }
+}
+
+struct Group(pub Vec<Timepoint>);
+impl Group {
+    fn first_timepoint(&self) -> &Timepoint {
+        self.0
+            .first()
+            .expect("Group always has at least 1 Timepoint")
+    }
+    fn last_timepoint(&self) -> &Timepoint {
+        self.0
+            .last()
+            .expect("Group always has at least 1 Timepoint")
+    }
+    fn first_datapoint(&self, i: usize) -> Option<&Datapoint> {
+        for tp in &self.0 {
+            if let Some(dp) = tp.get(i) {
+                return Some(dp);
+            }
+        }
+        None
+    }
+    fn last_datapoint(&self, i: usize) -> Option<&Datapoint> {
+        for tp in self.0.iter().rev() {
+            if let Some(dp) = tp.get(i) {
+                return Some(dp);
+            }
+        }
+        None
+    }
+    fn num_interfaces(&self) -> usize {
+        self.0
+            .iter()
+            .map(|tp| tp.num_interfaces())
+            .max()
+            .unwrap_or(0)
+    }
+    fn into_datapoints(self) -> impl Iterator<Item = Datapoint> {
+        self.0.into_iter().flat_map(|tp| tp.0.into_iter().flatten())
+    }
+    pub fn transfer_diffs<'a>(
+        &'a self,
+        previous: Option<&'a Self>,
+    ) -> impl Iterator<Item = (u16, Transfer)> + 'a {
+        Gen::new(|co| async move {
+            for i in 0..self.num_interfaces() {
+                // Get the last Datapoint for `i` from `previous` if
+                // that's from the preceding hour and a Datapoint for i
+                // is present, or the first from self if present. If
+                // present, also get the last Datapoint from self if
+                // present, and calculate and yield the transfer diff.
+
+                if let Some(dp1) = previous
+                    .and_then(|group| {
+                        let l = group.last_timepoint();
+                        let f = self.first_timepoint();
+                        if let Some(timediff) = f
+                            .timestamp_seconds()
+                            .checked_sub(l.timestamp_seconds())
+                        {
+                            if timediff < 3600 {
+                                // adjacent hours
+                                group.last_datapoint(i)
+                            } else {
+                                None
+                            }
+                        } else {
+                            eprintln!(
+                                "WARNING: unexpected non-increasing time \
+                                           in subsequent groups: {} to {}",
+                                l.timestamp().to_rfc2822_local(),
+                                f.timestamp().to_rfc2822_local()
+                            );
+                            None
+                        }
+                    })
+                    .or_else(|| self.first_datapoint(i))
+                {
+                    if let Some(dp2) = self.last_datapoint(i) {
+                        match dp2.transfer.sub(&dp1.transfer) {
+                            Ok(d) => co.yield_((i as u16, d)).await,
+                            Err(e) => eprintln!(
+                                "can't calculate diff({:?}, {:?}): {e}",
+                                dp1.transfer, dp2.transfer
+                            ),
+                        }
+                    }
+                }
+            }
+        })
+        .into_iter()
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+struct BilledCost {
+    billed_cost: f64,
+    your_cost: f64,
+}
+impl Add for BilledCost {
+    type Output = BilledCost;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        // evil to drop NaN?
+        let billed_cost = nandropping_add(self.billed_cost, rhs.billed_cost);
+        let your_cost = nandropping_add(self.your_cost, rhs.your_cost);
+        BilledCost {
+            billed_cost,
+            your_cost,
+        }
+    }
+}
+
+struct RowShared {
+    time: Tai64N,
+    total_all_ifaces_hour: usize,
+    num_servers_running: u32,
+}
+impl RowShared {
+    fn timestamp_seconds_unix(&self) -> f64 {
+        self.time
+            .to_system_time()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs_f64())
+            .unwrap_or(0.)
+    }
+}
+struct RowUser {
+    received_cum: usize,
+    sent_cum: usize,
+    received_hour: usize,
+    sent_hour: usize,
+}
+
+struct Row<'a> {
+    shared: &'a RowShared,
+    user: &'a RowUser,
+}
+/// A row for an hour without data: only the time columns are filled
+/// in, so that spreadsheets show a gap.
+fn write_gap_row(
+    outp: &mut impl Write,
+    time: Tai64N,
+    time_format: &TimeFormatting,
+) -> std::io::Result<()> {
+    writeln!(
+        outp,
+        "{}\t{}{}",
+        time_format.format(&time),
+        time.to_exceldays(1.),
+        "\t".repeat(12)
+    )
+}
+
+impl<'a> Row<'a> {
+    #[allow(clippy::write_literal)]
+    fn write_header(outp: &mut impl Write) -> Result<(), std::io::Error> {
+        writeln!(
+            outp,
+            "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
+            "time window start",
+            "time excel",
+            "received B",
+            "sent B",
+            "received B/hour",
+            "sent B/hour",
+            "total B/hour",
+            "all interfaces B/hour",
+            "fraction of all traffic",
+            "num servers running",
+            "free traffic B/hour",
+            "billed traffic B",
+            "billed cost EUR",
+            "your cost EUR"
+        )
+    }
+    fn write(
+        &self,
+        outp: &mut impl Write,
+        time_format: &TimeFormatting,
+    ) -> Result<BilledCost, std::io::Error> {
+        let total = self.user.received_hour + self.user.sent_hour;
+        let part = total as f64 / (self.shared.total_all_ifaces_hour as f64);
+        let included_traffic = self.shared.num_servers_running as f64 * 1.42e9;
+        let billed_traffic = max_f64(
+            0.,
+            self.shared.total_all_ifaces_hour as f64 - included_traffic,
+        );
+        let billed_cost = billed_traffic * (0.02000000 / 1e9);
+        let your_cost = part * billed_cost;
+
+        writeln!(
+            outp,
+            "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
+            time_format.format(&self.shared.time),
+            // XX Hard coding +01:00 for central europe, since
+            // daylight savings time is the fake one, thus this
+            // is closest without introducing discontinuities
+            // (because this is easier than switching for DST,
+            // introducing wrong time points while at it, and
+            // discontinuities which might matter e.g. for
+            // plots):
+            self.shared.time.to_exceldays(1.),
+            self.user.received_cum,
+            self.user.sent_cum,
+            self.user.received_hour,
+            self.user.sent_hour,
+            total,
+            self.shared.total_all_ifaces_hour,
+            part, // * 100. ? use Excel formatting for that
+            self.shared.num_servers_running,
+            included_traffic,
+            billed_traffic,
+            billed_cost,
+            your_cost
+        )?;
+
+        // Hack: return calculated values for summary
+        Ok(BilledCost {
+            billed_cost,
+            your_cost,
+        })
+    }
+}
+
+/// The paths of the files in the given log directories (as written
+/// by daemontools' multilog: `current` and the rotated `@...` files),
+/// sorted so that they are in chronological order.
+pub fn log_files_in_dirs(dir_paths: &[PathBuf]) -> Result<Vec<PathBuf>> {
+    let mut file_paths: Vec<PathBuf> = Vec::new();
+
+    for dir_path in dir_paths {
+        let mut items: Vec<PathBuf> =
+            std::fs::read_dir(dir_path).with_context(
+                || anyhow!("can't open dir {dir_path:?} for reading"))?
+            .filter_map(
+                |entry_result: Result<std::fs::DirEntry, std::io::Error>|
+                                      -> Option<Result<PathBuf,
+                                                       std::io::Error>>
+                {
+                    match entry_result {
+                        Ok(entry) => {
+                            let ft = entry.file_type()
+                                .expect("does this fail on OSes needing stat?");
+                            if ft.is_file() {
+                                Some(Ok(entry.path()))
+                            } else {
+                                None
+                            }
+                        }
+                        Err(e) =>
+                            Some(Err(e))
+                    }
+                })
+            .collect::<Result<_,_>>()?;
+        file_paths.append(&mut items);
+    }
+    file_paths.sort(); // Not ideal, should sort on filenames only.
+    Ok(file_paths)
+}
+
+pub struct HourlyOptions<'t> {
+    /// Write the hourly tables and monthly summary tables to TSV
+    /// files, with paths made by appending `$interfacename.tsv`
+    /// respectively `$interfacename-summary.tsv` to this.
+    pub basepath: Option<&'t str>,
+    /// Write the tables as CSV for Excel instead (with `.csv` instead
+    /// of `.tsv` extensions).
+    pub csv: Option<CsvSeparator>,
+    /// Drop the samples in the middle of runs of unchanged counters
+    /// (within the same hour) before grouping (doesn't change the
+    /// output, just saves work).
+    pub dedup: bool,
+    /// Datapoints whose timestamps are at most this many seconds
+    /// apart belong to the same snapshot.
+    pub max_snapshot_seconds: u64,
+    /// Also write an SVG chart of the total traffic per hour of each
+    /// interface to this path.
+    pub chart: Option<&'t Path>,
+    /// Write rows with only the time columns for the hours without
+    /// data between the first and last hour with data of an
+    /// interface.
+    pub fill_gaps: bool,
+    /// Flush the hourly tables after each hour's rows (for input that
+    /// is being followed, so that the rows show up as they are
+    /// completed).
+    pub flush_rows: bool,
+    /// How to write the "time window start" column.
+    pub time_format: TimeFormatting,
+}
+
+/// `outp`, with the TSV written to it converted to CSV if `csv` is
+/// given (`appending`: to a file that already has records).
+fn table_writer<'w>(
+    outp: impl Write + 'w,
+    csv: Option<CsvSeparator>,
+    appending: bool,
+) -> Box<dyn Write + 'w> {
+    match csv {
+        None => Box::new(outp),
+        Some(separator) if appending => {
+            Box::new(ExcelCsvWriter::appending(outp, separator))
+        }
+        Some(separator) => Box::new(ExcelCsvWriter::new(outp, separator)),
+    }
+}
+
+/// "1.5 GB" etc. (decimal units)
+fn format_bytes(n: f64) -> String {
+    humanize::format_bytes(n, ByteUnits::Decimal, None)
+}
+
+fn write_chart(
+    path: &Path,
+    series: &HashMap<u16, Vec<(f64, f64)>>,
+    interface_name: impl Fn(u16) -> String,
+) -> Result<()> {
+    let mut ifaces: Vec<_> = series.keys().copied().collect();
+    ifaces.sort();
+    let series: Vec<Series> = ifaces
+        .into_iter()
+        .map(|i| Series {
+            name: interface_name(i),
+            points: series[&i].clone(),
+        })
+        .collect();
+    let chart = LineChart {
+        title: "Total traffic per hour",
+        width: 1000,
+        height: 400,
+        format_x: &|x| {
+            DateTime::<Utc>::from(UNIX_EPOCH + Duration::from_secs_f64(x))
+                .format("%Y-%m-%d %H:%M UTC")
+                .to_string()
+        },
+        format_y: &|y| format!("{}/h", format_bytes(y)),
+    };
+    write_file_atomically_with(path, 0o644, |outp| {
+        Ok(chart.write(outp, &series)?)
+    })
+}
+
+/// Statistics over the whole processed range for one interface.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InterfaceSummary {
+    /// The time of the first and last datapoint.
+    pub first: Tai64N,
+    pub last: Tai64N,
+    /// B, total over all hours
+    pub received: usize,
+    pub sent: usize,
+    /// The number of hours with data.
+    pub hours: usize,
+    /// The start of the hour with the most traffic, and its traffic
+    /// (B).
+    pub busiest_hour: Tai64N,
+    pub busiest_hour_total: usize,
+}
+
+impl InterfaceSummary {
+    /// Average traffic per hour with data, B.
+    pub fn average_per_hour(&self) -> f64 {
+        (self.received + self.sent) as f64 / self.hours as f64
+    }
+}
+
+pub struct HourlyOutcome {
+    /// Set if processing was stopped early because of a termination
+    /// signal.
+    pub terminated: Option<Terminated>,
+    pub summaries: BTreeMap<u16, InterfaceSummary>,
+    /// The state to continue from in the next run, if a state was
+    /// passed to `process_hourly`.
+    pub state: Option<HourlyState>,
+}
+
+/// What `process_hourly` needs to continue where a previous run
+/// stopped, so that it only needs to be given the new datapoints,
+/// appends to the hourly tables without duplicating rows, and keeps
+/// the monthly summaries complete.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HourlyState {
+    /// The start of the last hour that was written to the hourly
+    /// tables; hours up to it are not written again.
+    written_until: Option<Tai64N>,
+    /// The datapoints of the last hour written (to calculate the
+    /// transfers of the next hour) and of the hours after it (not
+    /// written yet since they may still have been incomplete), to be
+    /// processed again before the new datapoints.
+    datapoints: Vec<Datapoint>,
+    by_user_month: HashMap<u16, HashMap<YearMonth, BilledCost>>,
+}
+
+impl HourlyState {
+    pub fn datapoints(&self) -> &[Datapoint] {
+        &self.datapoints
+    }
+
+    /// Use the datapoints to process again from `old` (the state the
+    /// run was started with), for when not all of the input was
+    /// processed (e.g. because of termination), and the next run is
+    /// going to read it again from where `old` stopped.
+    pub fn restart_from(&mut self, old: HourlyState) {
+        self.datapoints = old.datapoints;
+    }
+
+    /// Read the state from the entries with keys starting with
+    /// `prefix` (other entries are ignored, so that callers can keep
+    /// their own state in the same alist).
+    pub fn from_alist(
+        alist: &AListBuf<String, String>,
+        prefix: &str,
+    ) -> Result<Self> {
+        let mut state = HourlyState::default();
+        let mut datapoints = Vec::new();
+        for (key, val) in &alist.0 {
+            let key = match key.strip_prefix(prefix) {
+                Some(key) => key,
+                None => continue,
+            };
+            (|| -> Result<()> {
+                let fields: Vec<&str> = val.split(' ').collect();
+                if key == "written_until" {
+                    let t: Tai64NLabel = val.parse()?;
+                    state.written_until = Some(t.into());
+                } else if let Some(n) = key.strip_prefix("datapoint.") {
+                    let n: usize = n.parse()?;
+                    let (interface, timestamp, received, sent) =
+                        match fields[..] {
+                            [a, b, c, d] => (a, b, c, d),
+                            _ => bail!("expecting 4 fields"),
+                        };
+                    let timestamp: Tai64NLabel = timestamp.parse()?;
+                    let transfer = Transfer {
+                        received: received.parse()?,
+                        sent: sent.parse()?,
+                    };
+                    datapoints.push((
+                        n,
+                        Datapoint::new(
+                            interface.parse()?,
+                            timestamp.into(),
+                            transfer,
+                        ),
+                    ));
+                } else if let Some(rest) = key.strip_prefix("month.") {
+                    let (interface, ym) = rest
+                        .split_once('.')
+                        .ok_or_else(|| anyhow!("missing month"))?;
+                    let (year, month) = ym
+                        .split_once('/')
+                        .ok_or_else(|| anyhow!("invalid month {ym:?}"))?;
+                    let ym = YearMonth {
+                        year: year.parse()?,
+                        month: month.parse()?,
+                    };
+                    let (billed_cost, your_cost) = match fields[..] {
+                        [a, b] => (a, b),
+                        _ => bail!("expecting 2 fields"),
+                    };
+                    let cost = BilledCost {
+                        billed_cost: billed_cost.parse()?,
+                        your_cost: your_cost.parse()?,
+                    };
+                    state
+                        .by_user_month
+                        .entry(interface.parse()?)
+                        .or_default()
+                        .insert(ym, cost);
+                } else {
+                    bail!("unknown key")
+                }
+                Ok(())
+            })()
+            .with_context(|| anyhow!("state entry {prefix}{key} = {val}"))?;
+        }
+        datapoints.sort_by_key(|(n, _)| *n);
+        state.datapoints = datapoints.into_iter().map(|(_, dp)| dp).collect();
+        Ok(state)
+    }
+
+    /// Add the state to `alist`, with keys starting with `prefix`.
+    pub fn add_to_alist(
+        &self,
+        alist: &mut AListBuf<String, String>,
+        prefix: &str,
+    ) {
+        if let Some(t) = self.written_until {
+            alist.set(
+                format!("{prefix}written_until"),
+                Tai64NLabel(t).to_string(),
+            );
+        }
+        for (n, dp) in self.datapoints.iter().enumerate() {
+            alist.set(
+                format!("{prefix}datapoint.{n}"),
+                format!(
+                    "{} {} {} {}",
+                    dp.interface,
+                    Tai64NLabel(dp.timestamp),
+                    dp.transfer.received,
+                    dp.transfer.sent
+                ),
+            );
+        }
+        let mut interfaces: Vec<_> = self.by_user_month.iter().collect();
+        interfaces.sort_by_key(|(i, _)| **i);
+        for (i, by_month) in interfaces {
+            let mut months: Vec<_> = by_month.iter().collect();
+            months.sort_by_key(|(ym, _)| **ym);
+            for (ym, cost) in months {
+                // Display for f64 round-trips exactly
+                alist.set(
+                    format!("{prefix}month.{i}.{ym}"),
+                    format!("{} {}", cost.billed_cost, cost.your_cost),
+                );
+            }
+        }
+    }
+}
+
+/// Print `summaries` as a table for terminals.
+pub fn write_summary_table(
+    outp: &mut impl Write,
+    summaries: &BTreeMap<u16, InterfaceSummary>,
+    interface_name: impl Fn(u16) -> String,
+    time_format: &TimeFormatting,
+) -> Result<()> {
+    let mut rows = vec![[
+        "interface",
+        "first",
+        "last",
+        "received",
+        "sent",
+        "hours",
+        "average/hour",
+        "busiest hour",
+        "busiest hour traffic",
+    ]
+    .map(String::from)
+    .to_vec()];
+    for (i, s) in summaries {
+        rows.push(vec![
+            interface_name(*i),
+            time_format.format(&s.first),
+            time_format.format(&s.last),
+            format_bytes(s.received as f64),
+            format_bytes(s.sent as f64),
+            s.hours.to_string(),
+            format_bytes(s.average_per_hour()),
+            time_format.format(&s.busiest_hour),
+            format_bytes(s.busiest_hour_total as f64),
+        ]);
+    }
+    print_table(
+        outp,
+        &rows,
+        &TableOptions {
+            header: true,
+            ..Default::default()
+        },
+    )?;
+    Ok(())
+}
+
+/// The last counter values seen per interface (the counters are
+/// cumulative, so this is what monitoring systems want).
+#[derive(Debug, Default)]
+pub struct LatestCounters(pub BTreeMap<u16, (Tai64N, Transfer)>);
+
+impl LatestCounters {
+    pub fn update(&mut self, datapoint: &Datapoint) {
+        self.0.insert(
+            datapoint.interface,
+            (datapoint.timestamp, datapoint.transfer),
+        );
+    }
+
+    /// Format as Prometheus text exposition format, with metric names
+    /// starting with `metric_prefix` (e.g. "wireguard").
+    pub fn to_prometheus_text(
+        &self,
+        metric_prefix: &str,
+        interface_name: impl Fn(u16) -> String,
+    ) -> String {
+        let labels: Vec<String> = self
+            .0
+            .keys()
+            .map(|i| {
+                let mut name = String::new();
+                for c in interface_name(*i).chars() {
+                    match c {
+                        '\\' => name.push_str("\\\\"),
+                        '"' => name.push_str("\\\""),
+                        '\n' => name.push_str("\\n"),
+                        _ => name.push(c),
+                    }
+                }
+                format!("{{interface=\"{name}\"}}")
+            })
+            .collect();
+        let mut out = String::new();
+        let mut metric =
+            |name: &str,
+             typ: &str,
+             help: &str,
+             value: &dyn Fn(&(Tai64N, Transfer)) -> String| {
+                let name = format!("{metric_prefix}_{name}");
+                out.push_str(&format!("# HELP {name} {help}\n"));
+                out.push_str(&format!("# TYPE {name} {typ}\n"));
+                for (v, label) in self.0.values().zip(&labels) {
+                    out.push_str(&format!("{name}{label} {}\n", value(v)));
+                }
+            };
+        metric(
+            "received_bytes_total",
+            "counter",
+            "Bytes received, as last logged.",
+            &|(_, t)| t.received.to_string(),
+        );
+        metric(
+            "sent_bytes_total",
+            "counter",
+            "Bytes sent, as last logged.",
+            &|(_, t)| t.sent.to_string(),
+        );
+        metric(
+            "last_sample_timestamp_seconds",
+            "gauge",
+            "Unix time of the last logged sample.",
+            &|(timestamp, _)| {
+                timestamp
+                    .to_system_time()
+                    .duration_since(UNIX_EPOCH)
+                    .map(|d| d.as_secs())
+                    .unwrap_or(0)
+                    .to_string()
+            },
+        );
+        out
+    }
+
+    /// Write `to_prometheus_text` to `path`, atomically as required
+    /// by the node_exporter textfile collector.
+    pub fn write_prometheus_textfile(
+        &self,
+        path: &Path,
+        metric_prefix: &str,
+        interface_name: impl Fn(u16) -> String,
+    ) -> Result<()> {
+        // The exporter may run as another user
+        write_file_atomically(
+            path,
+            self.to_prometheus_text(metric_prefix, interface_name)
+                .as_bytes(),
+            0o644,
+        )
+    }
+}
+
+/// Group `datapoints` into snapshots and hours, and write the hourly
+/// tables and monthly summaries if `opts.basepath` is given. The
+/// files for an interface are created when its first row is written;
+/// `interface_name` is called then to get the name for the file.
+/// Stops reading input when termination is requested via
+/// `util::signals` (the caller needs to install the handler), but
+/// still writes out the data processed so far. The output files are
+/// replaced atomically once complete (except for the hourly tables
+/// with `flush_rows` or `state`, which are written as they go).
+///
+/// If `state` is given (from `HourlyOutcome::state` of the previous
+/// run, or the default for the first run), `datapoints` continues the
+/// input of the previous run: the hourly tables are appended to, and
+/// the last hour is not written but kept in the returned state, since
+/// more data for it may follow.
+pub fn process_hourly(
+    datapoints: impl Iterator<Item = Result<Datapoint>>,
+    interface_name: impl Fn(u16) -> String,
+    opts: &HourlyOptions,
+    state: Option<HourlyState>,
+) -> Result<HourlyOutcome> {
+    let HourlyOptions {
+        basepath,
+        csv,
+        dedup,
+        max_snapshot_seconds,
+        chart,
+        fill_gaps,
+        flush_rows,
+        time_format,
+    } = *opts;
+
+    // rust-analyzer can't handle this (rustc can):
+    // |datapoint: &Datapoint| -> u64 { datapoint.timestamp.0.0 }
+    // so:
+    fn timestamp_second(datapoint: &Datapoint) -> u64 {
+        datapoint.timestamp.0 .0
+    }
+
+    let ext = if csv.is_some() { "csv" } else { "tsv" };
+    let mut outputs: Vec<Option<Box<dyn Write>>> = Vec::new();
+    // The temporary files behind `outputs` to move into place at the
+    // end, with their targets
+    let mut temp_files: Vec<(TempFile, CString)> = Vec::new();
+
+    let incremental = state.is_some();
+    let HourlyState {
+        written_until,
+        datapoints: old_datapoints,
+        by_user_month,
+    } = state.unwrap_or_default();
+    let mut new_written_until = written_until;
+
+    let timepoints = try_group_owned(
+        old_datapoints.into_iter().map(Ok).chain(datapoints),
+        on(timestamp_second, numbers_within(max_snapshot_seconds)),
+        |points| {
+            Timepoint::from_iter(points.into_iter())
+                .expect("groups are guaranteed to be non-empty")
+        },
+    );
+
+    let timepoints: Box<dyn Iterator<Item = Result<Timepoint>>> = if dedup {
+        Box::new(try_keep_run_ends(timepoints, |a, b| b.is_unchanged_from(a)))
+    } else {
+        Box::new(timepoints)
+    };
+
+    let mut groups = try_group_owned(
+        timepoints,
+        on(|tp: &Timepoint| tp.date_and_hour(), |a, b| a == b),
+        Group,
+    )
+    .peekable();
+
+    let mut by_user_month = by_user_month;
+
+    let mut terminated = None;
+
+    let mut last_group: Option<Group> = None;
+    let mut rows: HashMap<u16, RowUser> = Default::default();
+    // (unixtime, total B/hour) per interface, for the chart
+    let mut chart_series: HashMap<u16, Vec<(f64, f64)>> = Default::default();
+    // The hour (since the epoch) of the last row written per interface
+    let mut last_hour: HashMap<u16, u64> = Default::default();
+    let mut summaries: BTreeMap<u16, InterfaceSummary> = Default::default();
+    // The last hour, held back in incremental mode
+    let mut unwritten_group: Option<Group> = None;
+    while let Some(group) = groups.next() {
+        if let Err(e) = check_termination() {
+            terminated = Some(e);
+            break;
+        }
+        let group = group?;
+        if incremental && groups.peek().is_none() {
+            unwritten_group = Some(group);
+            break;
+        }
+
+        rows.clear();
+        let mut total_all_ifaces_hour = 0; // B
+        for (iface, transferdiff) in group.transfer_diffs(last_group.as_ref()) {
+            total_all_ifaces_hour += transferdiff.total();
+            let f = group
+                .first_datapoint(iface as usize)
+                .expect("exists because we have a transferdiff");
+            let row = RowUser {
+                received_cum: f.transfer.received,
+                sent_cum: f.transfer.sent,
+                received_hour: transferdiff.received,
+                sent_hour: transferdiff.sent,
+            };
+            rows.insert(iface, row);
+
+            let l = group
+                .last_datapoint(iface as usize)
+                .expect("exists because we have a transferdiff");
+            let total = transferdiff.total();
+            let hour_start = group.first_timepoint().timestamp();
+            summaries
+                .entry(iface)
+                .and_modify(|s| {
+                    s.last = l.timestamp;
+                    s.received += transferdiff.received;
+                    s.sent += transferdiff.sent;
+                    s.hours += 1;
+                    if total > s.busiest_hour_total {
+                        s.busiest_hour = *hour_start;
+                        s.busiest_hour_total = total;
+                    }
+                })
+                .or_insert_with(|| InterfaceSummary {
+                    first: f.timestamp,
+                    last: l.timestamp,
+                    received: transferdiff.received,
+                    sent: transferdiff.sent,
+                    hours: 1,
+                    busiest_hour: *hour_start,
+                    busiest_hour_total: total,
+                });
+        }
+
+        let num_servers_running = 3; // configure XX
+        let shared = RowShared {
+            time: *group.first_timepoint().timestamp(),
+            total_all_ifaces_hour,
+            num_servers_running,
+        };
+        let ym = YearMonth::from_naivedate(
+            shared.time.to_datetime_utc().date_naive(),
+        );
+        let hour = shared.timestamp_seconds_unix() as u64 / 3600;
+        if written_until.is_some_and(|t| shared.time <= t) {
+            // Written by a previous run, only needed for the transfer
+            // diffs and gaps of the following hours
+            for i in rows.keys() {
+                last_hour.insert(*i, hour);
+            }
+            last_group = Some(group);
+            continue;
+        }
+        new_written_until = Some(shared.time);
+        let basepath = match basepath {
+            Some(basepath) => basepath,
+            None => {
+                last_group = Some(group);
+                continue;
+            }
+        };
+        for (i, user) in &mut rows {
+            let i = *i as usize;
+            if outputs.len() <= i {
+                outputs.resize_with(i + 1, || None);
+            }
+            if outputs[i].is_none() {
+                let path =
+                    format!("{basepath}{}.{ext}", interface_name(i as u16));
+                let file = if incremental || flush_rows {
+                    OpenOptions::new()
+                        .create(true)
+                        .append(incremental)
+                        .write(true)
+                        .truncate(!incremental)
+                        .open(&path)
+                        .with_context(|| anyhow!("opening {path:?}"))?
+                } else {
+                    // Replace the file once complete
+                    let target = CString::new(path.as_bytes())?;
+                    let mut tmp = TempFile::for_target(&target)?;
+                    tmp.file()
+                        .set_permissions(Permissions::from_mode(0o644))?;
+                    let file = tmp.file().try_clone()?;
+                    temp_files.push((tmp, target));
+                    file
+                };
+                let is_empty = file
+                    .metadata()
+                    .with_context(|| anyhow!("stat of {path:?}"))?
+                    .len()
+                    == 0;
+                let mut outp =
+                    table_writer(BufWriter::new(file), csv, !is_empty);
+                if is_empty {
+                    Row::write_header(&mut outp)?;
+                }
+                outputs[i] = Some(outp);
+            }
+            let outp = outputs[i].as_mut().expect("just created");
+            if fill_gaps {
+                if let Some(last) = last_hour.insert(i as u16, hour) {
+                    for h in last + 1..hour {
+                        write_gap_row(
+                            outp,
+                            Tai64N::from_system_time(
+                                &(UNIX_EPOCH + Duration::from_secs(h * 3600)),
+                            ),
+                            &time_format,
+                        )?;
+                    }
+                }
+            }
+            let row = Row {
+                shared: &shared,
+                user,
+            };
+            let calculated = row.write(outp, &time_format)?;
+            if chart.is_some() {
+                chart_series.entry(i as u16).or_default().push((
+                    shared.timestamp_seconds_unix(),
+                    (user.received_hour + user.sent_hour) as f64,
+                ));
+            }
+            hashmap_add(
+                hashmap_get_mut_vivify(&mut by_user_month, &(i as u16), || {
+                    HashMap::new()
+                }),
+                ym,
+                calculated,
+            );
+        }
+        if flush_rows {
+            for output in outputs.iter_mut().flatten() {
+                output.flush()?;
+            }
+        }
+
+        last_group = Some(group);
+    }
+
+    for (i, by_month) in &by_user_month {
+        let mut summary: Vec<_> = by_month.iter().collect();
+        summary.sort_by(|a, b| a.0.cmp(b.0));
+        let basepath = basepath.expect("only have data if basepath given");
+        let path = format!("{basepath}{}-summary.{ext}", interface_name(*i));
+        write_file_atomically_with(Path::new(&path), 0o644, |outp| {
+            let mut outp = table_writer(outp, csv, false);
+            writeln!(outp, "year/month\tbilled cost EUR\tyour cost EUR")?;
+            for (month, cost) in summary {
+                writeln!(
+                    outp,
+                    "{month}\t{:.2}\t{:.2}",
+                    cost.billed_cost, cost.your_cost
+                )?;
+            }
+            Ok(())
+        })?;
+    }
+    for output in outputs.iter_mut().flatten() {
+        output.flush()?;
+    }
+    drop(outputs);
+    for (tmp, target) in temp_files {
+        tmp.commit(&target)?;
+    }
+    if let Some(path) = chart {
+        write_chart(path, &chart_series, &interface_name)?;
+    }
+
+    let state = if incremental {
+        let datapoints = last_group
+            .into_iter()
+            .chain(unwritten_group)
+            .flat_map(Group::into_datapoints)
+            .collect();
+        Some(HourlyState {
+            written_until: new_written_until,
+            datapoints,
+            by_user_month,
+        })
+    } else {
+        None
+    };
+
+    Ok(HourlyOutcome {
+        terminated,
+        summaries,
+        state,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::time::excel::UTF8_BOM;
+    use tai64::Tai64;
+
+    fn datapoint(seconds: u64, received: usize, sent: usize) -> Datapoint {
+        let timestamp = Tai64N(Tai64::from_unix(seconds as i64), 0);
+        Datapoint::new(0, timestamp, Transfer { received, sent })
+    }
+
+    #[test]
+    fn t_summaries() {
+        let h = 3600;
+        let datapoints = vec![
+            datapoint(10 * h + 5, 100, 10),
+            datapoint(10 * h + 3000, 200, 15),
+            datapoint(11 * h + 1000, 1200, 20),
+            datapoint(12 * h + 500, 1300, 30),
+        ];
+        let outcome = process_hourly(
+            datapoints.into_iter().map(Ok),
+            |i| format!("if{i}"),
+            &HourlyOptions {
+                basepath: None,
+                csv: None,
+                dedup: true,
+                max_snapshot_seconds: 8,
+                chart: None,
+                fill_gaps: false,
+                flush_rows: false,
+                time_format: TimeFormatting::default(),
+            },
+            None,
+        )
+        .unwrap();
+        assert!(outcome.terminated.is_none());
+        let s = &outcome.summaries[&0];
+        assert_eq!((s.received, s.sent, s.hours), (1200, 20, 3));
+        assert_eq!(s.busiest_hour_total, 1005);
+        assert_eq!(s.busiest_hour.0.to_unix(), 11 * h as i64 + 1000);
+        assert_eq!(s.first.0.to_unix(), 10 * h as i64 + 5);
+        assert_eq!(s.last.0.to_unix(), 12 * h as i64 + 500);
+    }
+
+    #[test]
+    fn t_incremental() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!(
+            "chj-rustbin-netcounters-test-{}",
+            std::process::id()
+        ));
+        std::fs::create_dir_all(&dir)?;
+        let h = 3600;
+        let datapoints: Vec<_> = (0..40)
+            .map(|i| datapoint(10 * h + i * 500, i as usize * 1000, 10))
+            .collect();
+        let run = |basepath: &str,
+                   csv: Option<CsvSeparator>,
+                   datapoints: &[Datapoint],
+                   state: Option<HourlyState>|
+         -> Result<Option<HourlyState>> {
+            let basepath = dir.join(basepath);
+            let outcome = process_hourly(
+                datapoints.iter().cloned().map(Ok),
+                |i| format!("if{i}"),
+                &HourlyOptions {
+                    basepath: basepath.to_str(),
+                    csv,
+                    dedup: true,
+                    max_snapshot_seconds: 8,
+                    chart: None,
+                    fill_gaps: false,
+                    flush_rows: false,
+                    time_format: TimeFormatting::default(),
+                },
+                state,
+            )?;
+            Ok(outcome.state)
+        };
+        // The last hour is held back in incremental mode
+        let last_hour = datapoints
+            .iter()
+            .position(|dp| dp.timestamp.0.to_unix() >= 15 * h as i64)
+            .unwrap();
+        for (csv, ext) in
+            [(None, "tsv"), (Some(CsvSeparator::Semicolon), "csv")]
+        {
+            run("full-", csv, &datapoints[..last_hour], None)?;
+            let mut state = Some(HourlyState::default());
+            for part in
+                [&datapoints[..5], &datapoints[5..23], &datapoints[23..]]
+            {
+                state = run("incremental-", csv, part, state)?;
+                // Round-trip through the text format
+                let mut alist = AListBuf::default();
+                state.as_ref().unwrap().add_to_alist(&mut alist, "h.");
+                let text = alist.to_text()?;
+                let restored = HourlyState::from_alist(
+                    &AListBuf::from_text(&text)?,
+                    "h.",
+                )?;
+                assert_eq!(restored, *state.as_ref().unwrap());
+                state = Some(restored);
+            }
+            run("incremental-", csv, &[], state)?;
+            for suffix in ["if0", "if0-summary"] {
+                assert_eq!(
+                    std::fs::read_to_string(
+                        dir.join(format!("full-{suffix}.{ext}"))
+                    )?,
+                    std::fs::read_to_string(
+                        dir.join(format!("incremental-{suffix}.{ext}"))
+                    )?
+                );
+            }
+            if csv.is_some() {
+                let s =
+                    std::fs::read_to_string(dir.join("incremental-if0.csv"))?;
+                assert!(s.starts_with(UTF8_BOM));
+                assert_eq!(s.matches(UTF8_BOM).count(), 1);
+            }
+        }
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn t_to_prometheus_text() {
+        let mut latest = LatestCounters::default();
+        latest.update(&datapoint(1000, 1, 2));
+        latest.update(&datapoint(2000, 10, 20));
+        let mut dp = datapoint(1500, 30, 40);
+        dp.interface = 1;
+        latest.update(&dp);
+        let names = ["wg0", "a\"b"];
+        assert_eq!(
+            latest.to_prometheus_text("wireguard", |i| names[i as usize]
+                .to_string()),
+            "\
+# HELP wireguard_received_bytes_total Bytes received, as last logged.
+# TYPE wireguard_received_bytes_total counter
+wireguard_received_bytes_total{interface=\"wg0\"} 10
+wireguard_received_bytes_total{interface=\"a\\\"b\"} 30
+# HELP wireguard_sent_bytes_total Bytes sent, as last logged.
+# TYPE wireguard_sent_bytes_total counter
+wireguard_sent_bytes_total{interface=\"wg0\"} 20
+wireguard_sent_bytes_total{interface=\"a\\\"b\"} 40
+# HELP wireguard_last_sample_timestamp_seconds Unix time of the last logged sample.
+# TYPE wireguard_last_sample_timestamp_seconds gauge
+wireguard_last_sample_timestamp_seconds{interface=\"wg0\"} 2000
+wireguard_last_sample_timestamp_seconds{interface=\"a\\\"b\"} 1500
+"
+        );
+    }
+}
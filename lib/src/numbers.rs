@@ -0,0 +1,515 @@
+use std::cmp::Ordering;
+use std::convert::TryFrom;
+use std::iter::FromIterator;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Result};
+use num::{CheckedSub, Num};
+
+/// Get a function that reports whether two numbers are within maxdiff
+/// of each other (either direction).
+pub fn numbers_within<N>(maxdiff: N) -> impl Fn(N, N) -> bool
+where
+    N: CheckedSub + Num + Ord,
+{
+    move |a: N, b: N| -> bool {
+        if a > b {
+            a.checked_sub(&b).expect("should always work, am I wrong?")
+                < maxdiff
+        } else {
+            b.checked_sub(&a).expect("should always work, am I wrong?")
+                < maxdiff
+        }
+    }
+}
+
+/// std::cmp::max doesn't work on floating point
+pub fn max_f64(a: f64, b: f64) -> f64 {
+    if a < b {
+        b
+    } else {
+        a
+    }
+}
+
+/// Comparison between two values with only partial ordering
+/// (e.g. floating point numbers) that provides a fake full ordering
+/// by falling back to Ordering::Equal if non-comparable. Warning:
+/// this may not be what you want, but perhaps panic instead?
+pub fn forced_cmp<T: PartialOrd>(a: T, b: T) -> Ordering {
+    a.partial_cmp(&b).unwrap_or(Ordering::Equal)
+}
+
+/// An addition that ignores NaN numbers if possible (the result
+/// should only be NaN if both arguments are NaN). In other words, NaN
+/// is treated the same as 0, as long as at least one argument isn't
+/// NaN. (Warning: this might not be what you want.)
+pub fn nandropping_add(a: f64, b: f64) -> f64 {
+    if a.is_nan() {
+        b
+    } else if b.is_nan() {
+        a
+    } else {
+        a + b
+    }
+}
+
+/// How to round when converting to an integer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rounding {
+    /// Towards negative infinity (the same as truncation, i.e. `as`,
+    /// for the non-negative numbers accepted here).
+    Down,
+    /// To the nearest integer, halfway cases away from zero.
+    Nearest,
+    Up,
+}
+
+impl Rounding {
+    pub fn round(self, x: f64) -> f64 {
+        match self {
+            Rounding::Down => x.floor(),
+            Rounding::Nearest => x.round(),
+            Rounding::Up => x.ceil(),
+        }
+    }
+}
+
+/// 2^64, the smallest f64 that doesn't fit u64.
+const U64_LIMIT_F64: f64 = 18446744073709551616.0;
+
+/// Convert to u64 after rounding, failing for NaN, negative numbers,
+/// and numbers that don't fit (unlike `as`, which saturates silently
+/// and maps NaN to 0).
+pub fn f64_to_u64(x: f64, rounding: Rounding) -> Result<u64> {
+    let r = rounding.round(x);
+    if !(0. ..U64_LIMIT_F64).contains(&r) {
+        bail!("number can't be represented as u64: {x}")
+    }
+    Ok(r as u64)
+}
+
+/// Like `f64_to_u64`, but also fails if the number doesn't fit usize
+/// (on 32-bit platforms).
+pub fn f64_to_usize(x: f64, rounding: Rounding) -> Result<usize> {
+    let n = f64_to_u64(x, rounding)?;
+    usize::try_from(n)
+        .map_err(|_| anyhow!("number can't be represented as usize: {x}"))
+}
+
+/// Convert to u64 after rounding, clamping to the range of u64, with
+/// NaN mapped to 0 (this is what `as` does, but explicit).
+pub fn saturating_f64_to_u64(x: f64, rounding: Rounding) -> u64 {
+    rounding.round(x) as u64
+}
+
+/// `n / d`, rounded.
+fn div_rounded(n: u128, d: u128, rounding: Rounding) -> u128 {
+    let (q, r) = (n / d, n % d);
+    let round_up = match rounding {
+        Rounding::Down => false,
+        Rounding::Nearest => r >= d - r,
+        Rounding::Up => r > 0,
+    };
+    if round_up {
+        q + 1
+    } else {
+        q
+    }
+}
+
+/// The rate of `amount` (e.g. bytes) per second over `duration`,
+/// rounded, computed exactly in integer arithmetic. Fails for a zero
+/// duration or if the result doesn't fit u64 (very short durations).
+pub fn rate_per_second(
+    amount: u64,
+    duration: Duration,
+    rounding: Rounding,
+) -> Result<u64> {
+    let nanos = duration.as_nanos();
+    if nanos == 0 {
+        bail!("can't compute a rate over a zero duration")
+    }
+    let rate = div_rounded(amount as u128 * 1_000_000_000, nanos, rounding);
+    u64::try_from(rate).map_err(|_| {
+        anyhow!("rate of {amount} over {duration:?} does not fit u64")
+    })
+}
+
+/// Like `rate_per_second`, but clamps to u64::MAX instead of failing
+/// (an amount of 0 over a zero duration gives 0).
+pub fn saturating_rate_per_second(
+    amount: u64,
+    duration: Duration,
+    rounding: Rounding,
+) -> u64 {
+    if amount == 0 {
+        0
+    } else {
+        rate_per_second(amount, duration, rounding).unwrap_or(u64::MAX)
+    }
+}
+
+/// Statistics over numbers fed one at a time, in constant memory
+/// (using Welford's algorithm for the variance). NaN values are only
+/// counted (see `num_nan`), and otherwise ignored.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OnlineStats {
+    count: u64,
+    num_nan: u64,
+    mean: f64,
+    /// The sum of the squared differences from the mean.
+    m2: f64,
+    min: f64,
+    max: f64,
+}
+
+impl Default for OnlineStats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl OnlineStats {
+    pub fn new() -> Self {
+        OnlineStats {
+            count: 0,
+            num_nan: 0,
+            mean: 0.,
+            m2: 0.,
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+        }
+    }
+
+    pub fn add(&mut self, x: f64) {
+        if x.is_nan() {
+            self.num_nan += 1;
+            return;
+        }
+        self.count += 1;
+        let delta = x - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (x - self.mean);
+        self.min = self.min.min(x);
+        self.max = self.max.max(x);
+    }
+
+    /// Combine with the statistics over other numbers (e.g. computed
+    /// in parallel).
+    pub fn merge(&mut self, other: &Self) {
+        if other.count > 0 {
+            let count = self.count + other.count;
+            let delta = other.mean - self.mean;
+            self.mean += delta * other.count as f64 / count as f64;
+            self.m2 += other.m2
+                + delta * delta * (self.count as f64 * other.count as f64)
+                    / count as f64;
+            self.count = count;
+            self.min = self.min.min(other.min);
+            self.max = self.max.max(other.max);
+        }
+        self.num_nan += other.num_nan;
+    }
+
+    /// The number of (non-NaN) numbers added.
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// The number of NaN values added.
+    pub fn num_nan(&self) -> u64 {
+        self.num_nan
+    }
+
+    pub fn mean(&self) -> Option<f64> {
+        (self.count > 0).then_some(self.mean)
+    }
+
+    /// The population variance (dividing by the count, not count - 1).
+    pub fn variance(&self) -> Option<f64> {
+        (self.count > 0).then(|| self.m2 / self.count as f64)
+    }
+
+    /// The population standard deviation.
+    pub fn stddev(&self) -> Option<f64> {
+        self.variance().map(f64::sqrt)
+    }
+
+    pub fn min(&self) -> Option<f64> {
+        (self.count > 0).then_some(self.min)
+    }
+
+    pub fn max(&self) -> Option<f64> {
+        (self.count > 0).then_some(self.max)
+    }
+}
+
+impl Extend<f64> for OnlineStats {
+    fn extend<I: IntoIterator<Item = f64>>(&mut self, iter: I) {
+        for x in iter {
+            self.add(x)
+        }
+    }
+}
+
+impl FromIterator<f64> for OnlineStats {
+    fn from_iter<I: IntoIterator<Item = f64>>(iter: I) -> Self {
+        let mut stats = Self::new();
+        stats.extend(iter);
+        stats
+    }
+}
+
+/// The mean of `values` (ignoring NaN), None if there are none.
+pub fn mean(values: impl IntoIterator<Item = f64>) -> Option<f64> {
+    values.into_iter().collect::<OnlineStats>().mean()
+}
+
+/// The population standard deviation of `values` (ignoring NaN),
+/// None if there are none.
+pub fn stddev(values: impl IntoIterator<Item = f64>) -> Option<f64> {
+    values.into_iter().collect::<OnlineStats>().stddev()
+}
+
+/// Numbers kept sorted for percentile queries (NaN values are
+/// dropped).
+#[derive(Debug, Clone, PartialEq)]
+pub struct SortedNumbers {
+    values: Vec<f64>,
+}
+
+impl FromIterator<f64> for SortedNumbers {
+    fn from_iter<I: IntoIterator<Item = f64>>(iter: I) -> Self {
+        let mut values: Vec<f64> =
+            iter.into_iter().filter(|x| !x.is_nan()).collect();
+        values.sort_by(f64::total_cmp);
+        SortedNumbers { values }
+    }
+}
+
+impl SortedNumbers {
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    pub fn as_slice(&self) -> &[f64] {
+        &self.values
+    }
+
+    /// The `p`th percentile (`p` from 0 to 100), interpolating
+    /// linearly between the closest ranks (like Excel's
+    /// `PERCENTILE.INC`). None if empty. Panics if `p` is out of
+    /// range.
+    pub fn percentile(&self, p: f64) -> Option<f64> {
+        assert!((0. ..=100.).contains(&p), "percentile out of range: {}", p);
+        let last = self.values.len().checked_sub(1)?;
+        let rank = p / 100. * last as f64;
+        let (i, frac) = (rank.floor() as usize, rank.fract());
+        let lower = self.values[i];
+        Some(match self.values.get(i + 1) {
+            Some(upper) if frac > 0. => lower + (upper - lower) * frac,
+            _ => lower,
+        })
+    }
+
+    pub fn median(&self) -> Option<f64> {
+        self.percentile(50.)
+    }
+}
+
+/// The `p`th percentile of `values`, see `SortedNumbers::percentile`
+/// (collect a `SortedNumbers` to get several percentiles).
+pub fn percentile(
+    values: impl IntoIterator<Item = f64>,
+    p: f64,
+) -> Option<f64> {
+    values.into_iter().collect::<SortedNumbers>().percentile(p)
+}
+
+fn digits_len(s: &[u8]) -> usize {
+    s.iter().take_while(|b| b.is_ascii_digit()).count()
+}
+
+/// The length of the file name suffix of `s` (like `.tar.gz`), as
+/// matched by `(\.[A-Za-z~][A-Za-z0-9~]*)*$`.
+fn file_suffix_len(s: &[u8]) -> usize {
+    let mut start = None;
+    let mut after_dot = false;
+    for (i, c) in s.iter().enumerate() {
+        if after_dot {
+            after_dot = false;
+            if !(c.is_ascii_alphabetic() || *c == b'~') {
+                start = None;
+            }
+        } else if *c == b'.' {
+            after_dot = true;
+            start = start.or(Some(i));
+        } else if !(c.is_ascii_alphanumeric() || *c == b'~') {
+            start = None;
+        }
+    }
+    match start {
+        Some(start) if !after_dot => s.len() - start,
+        _ => 0,
+    }
+}
+
+/// "Natural" comparison of strings with embedded numbers, as needed
+/// for version numbers: runs of ASCII digits are compared by their
+/// numeric value (of arbitrary length), everything else bytewise. So
+/// `foo-1.2.10.tar.gz` sorts after `foo-1.2.9.tar.gz`. If two numbers
+/// have the same value, the one with more leading zeros sorts first
+/// (so that the ordering stays total). Like `sort -V`, file name
+/// suffixes (like `.tar.gz`) are only compared if the parts before
+/// them are equal, so that `foo-1.2.tar.gz` sorts before
+/// `foo-1.2.9.tar.gz`.
+pub fn natural_cmp(a: &[u8], b: &[u8]) -> Ordering {
+    let a_base = &a[..a.len() - file_suffix_len(a)];
+    let b_base = &b[..b.len() - file_suffix_len(b)];
+    natural_cmp_parts(a_base, b_base).then_with(|| natural_cmp_parts(a, b))
+}
+
+fn natural_cmp_parts(a: &[u8], b: &[u8]) -> Ordering {
+    let (mut a, mut b) = (a, b);
+    loop {
+        match (a.first(), b.first()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(ca), Some(cb)) => {
+                if ca.is_ascii_digit() && cb.is_ascii_digit() {
+                    let (na, ra) = a.split_at(digits_len(a));
+                    let (nb, rb) = b.split_at(digits_len(b));
+                    let za = na.iter().take_while(|b| **b == b'0').count();
+                    let zb = nb.iter().take_while(|b| **b == b'0').count();
+                    let (va, vb) = (&na[za..], &nb[zb..]);
+                    let ord = va
+                        .len()
+                        .cmp(&vb.len())
+                        .then_with(|| va.cmp(vb))
+                        .then_with(|| zb.cmp(&za));
+                    if ord != Ordering::Equal {
+                        return ord;
+                    }
+                    a = ra;
+                    b = rb;
+                } else {
+                    let ord = ca.cmp(cb);
+                    if ord != Ordering::Equal {
+                        return ord;
+                    }
+                    a = &a[1..];
+                    b = &b[1..];
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn t_natural_cmp() {
+        let t = |a: &str, b: &str| natural_cmp(a.as_bytes(), b.as_bytes());
+        use Ordering::*;
+        assert_eq!(t("foo-1.2.10.tar.gz", "foo-1.2.9.tar.gz"), Greater);
+        assert_eq!(t("foo-1.2.9.tar.gz", "foo-1.2.10.tar.gz"), Less);
+        assert_eq!(t("foo-1.2.9", "foo-1.2.9"), Equal);
+        assert_eq!(t("foo-1.2", "foo-1.2.1"), Less);
+        assert_eq!(t("foo-1.2.tar.gz", "foo-1.2.9.tar.gz"), Less);
+        assert_eq!(t("foo-1.2.9.tar.gz", "foo-1.2.tar.gz"), Greater);
+        assert_eq!(t("foo-1.2.tar.gz", "foo-1.2.tar.xz"), Less);
+        assert_eq!(t("foo-1.2.tar.gz", "foo-1.2.tgz"), Less);
+        assert_eq!(t("a.", "a.b"), Greater);
+        assert_eq!(t("a", "b"), Less);
+        assert_eq!(t("", "a"), Less);
+        assert_eq!(t("x10", "x010"), Greater);
+        assert_eq!(t("x010", "x9"), Greater);
+        assert_eq!(
+            t("v123456789012345678901234567890", "v99999999999999999999"),
+            Greater
+        );
+    }
+
+    #[test]
+    fn t_online_stats() {
+        let values = [2., 4., 4., 4., 5., 5., 7., 9.];
+        let stats: OnlineStats = values.iter().copied().collect();
+        assert_eq!(stats.count(), 8);
+        assert_eq!(stats.mean(), Some(5.));
+        assert_eq!(stats.stddev(), Some(2.));
+        assert_eq!((stats.min(), stats.max()), (Some(2.), Some(9.)));
+        let mut merged: OnlineStats = values[..3].iter().copied().collect();
+        merged.merge(&values[3..].iter().copied().chain([f64::NAN]).collect());
+        assert!((merged.variance().unwrap() - 4.).abs() < 1e-12);
+        assert_eq!(merged.num_nan(), 1);
+        assert_eq!(mean([1., f64::NAN, 2.]), Some(1.5));
+        assert_eq!(stddev(std::iter::empty()), None);
+        assert_eq!(OnlineStats::new().min(), None);
+    }
+
+    #[test]
+    fn t_percentile() {
+        let sorted: SortedNumbers = [15., 20., f64::NAN, 35., 40., 50.]
+            .iter()
+            .copied()
+            .collect();
+        assert_eq!(sorted.len(), 5);
+        assert_eq!(sorted.percentile(0.), Some(15.));
+        assert_eq!(sorted.percentile(25.), Some(20.));
+        assert_eq!(sorted.median(), Some(35.));
+        assert_eq!(sorted.percentile(90.), Some(46.));
+        assert_eq!(sorted.percentile(100.), Some(50.));
+        assert_eq!(percentile([3., 1., 2.], 50.), Some(2.));
+        assert_eq!(percentile([7.], 99.), Some(7.));
+        assert_eq!(percentile(std::iter::empty(), 50.), None);
+    }
+
+    #[test]
+    fn t_conversions() {
+        use Rounding::*;
+        assert_eq!(f64_to_u64(2.5, Down).unwrap(), 2);
+        assert_eq!(f64_to_u64(2.5, Nearest).unwrap(), 3);
+        assert_eq!(f64_to_u64(2.1, Up).unwrap(), 3);
+        assert_eq!(f64_to_usize(20958418452.48, Down).unwrap(), 20958418452);
+        assert!(f64_to_u64(-0.6, Nearest).is_err());
+        assert_eq!(f64_to_u64(-0.4, Nearest).unwrap(), 0);
+        assert!(f64_to_u64(f64::NAN, Down).is_err());
+        assert!(f64_to_u64(U64_LIMIT_F64, Down).is_err());
+        assert!(f64_to_u64(f64::INFINITY, Down).is_err());
+        assert_eq!(saturating_f64_to_u64(1e30, Down), u64::MAX);
+        assert_eq!(saturating_f64_to_u64(-5., Up), 0);
+        assert_eq!(saturating_f64_to_u64(f64::NAN, Up), 0);
+    }
+
+    #[test]
+    fn t_rate_per_second() {
+        use Rounding::*;
+        let secs = Duration::from_secs;
+        assert_eq!(rate_per_second(3600, secs(3600), Down).unwrap(), 1);
+        assert_eq!(rate_per_second(10, secs(4), Down).unwrap(), 2);
+        assert_eq!(rate_per_second(10, secs(4), Nearest).unwrap(), 3);
+        assert_eq!(rate_per_second(9, secs(4), Nearest).unwrap(), 2);
+        assert_eq!(rate_per_second(9, secs(4), Up).unwrap(), 3);
+        assert_eq!(
+            rate_per_second(1, Duration::from_millis(1), Down).unwrap(),
+            1000
+        );
+        assert!(rate_per_second(1, Duration::ZERO, Down).is_err());
+        assert!(
+            rate_per_second(u64::MAX, Duration::from_millis(1), Down).is_err()
+        );
+        assert_eq!(
+            saturating_rate_per_second(u64::MAX, Duration::from_millis(1), Up),
+            u64::MAX
+        );
+        assert_eq!(saturating_rate_per_second(0, Duration::ZERO, Up), 0);
+    }
+}
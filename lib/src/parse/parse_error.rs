@@ -1,3 +1,5 @@
+use std::fmt::Display;
+
 #[derive(Debug)]
 pub struct ParseError {
     pub message: String,
@@ -56,9 +58,9 @@ pub struct FileLocation {
     pub column: u32,
 }
 
-impl FileLocation {
-    pub fn to_string(&self) -> String {
-        format!("{}:{}:{}", self.file, self.line, self.column)
+impl Display for FileLocation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}:{}", self.file, self.line, self.column)
     }
 }
 
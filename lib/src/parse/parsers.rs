@@ -247,14 +247,11 @@ impl<'t> ParseableStr<'t> {
     /// remainder after it.
     pub fn drop_str(self, beginning: &str) -> Option<ParseableStr<'t>> {
         let ParseableStr { position, s } = self;
-        if s.starts_with(beginning) {
-            Some(ParseableStr {
-                position: position + beginning.len(),
-                s: &s[beginning.len()..],
-            })
-        } else {
-            None
-        }
+        let rest = s.strip_prefix(beginning)?;
+        Some(ParseableStr {
+            position: position + beginning.len(),
+            s: rest,
+        })
     }
 
     /// Same as `drop_str` but returns an error mentioning
@@ -383,7 +380,7 @@ impl<'t> ParseableStr<'t> {
         if !pred(c) {
             return err();
         }
-        let (pos, _) = cs.next().unwrap_or_else(|| (s.len(), ' '));
+        let (pos, _) = cs.next().unwrap_or((s.len(), ' '));
         Ok(ParseableStr {
             position: position + pos,
             s: &s[pos..],
@@ -461,10 +458,10 @@ impl<'t> ParseableStr<'t> {
                 .into());
             }
         }
-        let (pos, _) = cs.next().unwrap_or_else(|| (s.len(), ' '));
+        let (pos, _) = cs.next().unwrap_or((s.len(), ' '));
         Ok((
             ParseableStr {
-                position: position,
+                position,
                 s: &s[0..pos],
             },
             ParseableStr {
@@ -486,7 +483,7 @@ impl<'t> ParseableStr<'t> {
         self,
         separator: &'n str,
         omit_empty_last_item: bool,
-    ) -> Box<dyn Iterator<Item = ParseableStr> + 'n>
+    ) -> Box<dyn Iterator<Item = ParseableStr<'n>> + 'n>
     where
         't: 'n,
     {
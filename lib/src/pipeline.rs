@@ -0,0 +1,141 @@
+//! Building blocks for streaming pipelines of fallible items
+//! (`Iterator<Item = Result<T, E>>`), as used by the log parsers:
+//! generator-backed sources where `?` works, and adaptors that pass
+//! errors through. (For generators via `genawaiter::rc::Gen`
+//! directly, there's also the `gen_try_result!` macro.)
+
+use std::future::Future;
+
+use genawaiter::rc::{Co, Gen};
+use genawaiter::GeneratorState;
+
+/// Iterator returned by `try_gen`.
+pub struct TryGen<T, E, F: Future<Output = Result<(), E>>> {
+    gen: Option<Gen<Result<T, E>, (), F>>,
+}
+
+impl<T, E, F: Future<Output = Result<(), E>>> Iterator for TryGen<T, E, F> {
+    type Item = Result<T, E>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.gen.as_mut()?.resume() {
+            GeneratorState::Yielded(item) => Some(item),
+            GeneratorState::Complete(result) => {
+                self.gen = None;
+                result.err().map(Err)
+            }
+        }
+    }
+}
+
+/// Like `Gen::new(producer).into_iter()`, but `producer`'s future
+/// returns a `Result`, so that `?` can be used in it: an error
+/// returned from it becomes the last item of the sequence.
+/// `producer` can also yield errors itself (via `co.yield_(Err(e))`)
+/// and continue.
+pub fn try_gen<T, E, F>(
+    producer: impl FnOnce(Co<Result<T, E>>) -> F,
+) -> TryGen<T, E, F>
+where
+    F: Future<Output = Result<(), E>>,
+{
+    TryGen {
+        gen: Some(Gen::new(producer)),
+    }
+}
+
+/// Map the `Ok` items via a fallible function.
+pub fn try_map<T, U, E>(
+    inp: impl Iterator<Item = Result<T, E>>,
+    mut f: impl FnMut(T) -> Result<U, E>,
+) -> impl Iterator<Item = Result<U, E>> {
+    inp.map(move |item| item.and_then(&mut f))
+}
+
+/// Keep the `Ok` items for which `pred` returns `Ok(true)`; errors
+/// from `pred` become items.
+pub fn try_filter<T, E>(
+    inp: impl Iterator<Item = Result<T, E>>,
+    mut pred: impl FnMut(&T) -> Result<bool, E>,
+) -> impl Iterator<Item = Result<T, E>> {
+    inp.filter_map(move |item| match item {
+        Ok(v) => match pred(&v) {
+            Ok(true) => Some(Ok(v)),
+            Ok(false) => None,
+            Err(e) => Some(Err(e)),
+        },
+        Err(e) => Some(Err(e)),
+    })
+}
+
+/// Map the `Ok` items via a fallible function that can also drop
+/// them (by returning `Ok(None)`).
+pub fn try_filter_map<T, U, E>(
+    inp: impl Iterator<Item = Result<T, E>>,
+    mut f: impl FnMut(T) -> Result<Option<U>, E>,
+) -> impl Iterator<Item = Result<U, E>> {
+    inp.filter_map(move |item| item.and_then(&mut f).transpose())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_all(
+        inp: &'static [&'static str],
+    ) -> impl Iterator<Item = Result<i32, String>> {
+        try_gen(|co| async move {
+            for s in inp {
+                if s.is_empty() {
+                    co.yield_(Err("empty".into())).await;
+                    continue;
+                }
+                let n: i32 = s.parse().map_err(|_| format!("bad {s:?}"))?;
+                co.yield_(Ok(n)).await;
+            }
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn t_try_gen() {
+        assert_eq!(
+            parse_all(&["1", "", "2"]).collect::<Vec<_>>(),
+            vec![Ok(1), Err("empty".into()), Ok(2)]
+        );
+        let mut it = parse_all(&["1", "x", "2"]);
+        assert_eq!(it.next(), Some(Ok(1)));
+        assert_eq!(it.next(), Some(Err("bad \"x\"".into())));
+        assert_eq!(it.next(), None);
+        assert_eq!(it.next(), None);
+    }
+
+    #[test]
+    fn t_adaptors() {
+        let inp = || parse_all(&["1", "", "2", "3", "4"]);
+        assert_eq!(
+            try_map(inp(), |n| if n < 4 {
+                Ok(n * 10)
+            } else {
+                Err("big".into())
+            })
+            .collect::<Vec<_>>(),
+            vec![
+                Ok(10),
+                Err("empty".into()),
+                Ok(20),
+                Ok(30),
+                Err("big".into())
+            ]
+        );
+        assert_eq!(
+            try_filter(inp(), |n| Ok(n % 2 == 0)).collect::<Vec<_>>(),
+            vec![Err("empty".into()), Ok(2), Ok(4)]
+        );
+        assert_eq!(
+            try_filter_map(inp(), |n| Ok(if n > 2 { Some(-n) } else { None }))
+                .collect::<Vec<_>>(),
+            vec![Err("empty".into()), Ok(-3), Ok(-4)]
+        );
+    }
+}
@@ -0,0 +1,1103 @@
+//! Waiting for child processes (with timeouts), running commands
+//! while capturing their output, spawning commands with redirections,
+//! and forking (into new sessions).
+
+use std::convert::TryFrom;
+use std::ffi::{CStr, CString, OsStr};
+use std::fs::File;
+use std::io::Write;
+use std::os::unix::io::{FromRawFd, RawFd};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, bail, Context, Result};
+use nix::errno::Errno;
+use nix::fcntl::OFlag;
+use nix::poll::{poll, PollFd, PollFlags};
+use nix::sys::signal::{kill, SigHandler, SigSet, Signal};
+use nix::sys::stat::Mode;
+use nix::sys::wait::{wait, waitpid, WaitPidFlag, WaitStatus};
+use nix::unistd::{
+    close, dup, fork, getpid, pipe2, read, setsid, write, ForkResult, Pid,
+};
+
+use crate::cli::program_name;
+use crate::error::Categorize;
+use crate::io::unix_fs::write_file_atomically;
+
+/// How a process ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Normalexit(i32),
+    Signalexit(Signal),
+}
+
+impl Status {
+    fn from_wait_status(st: WaitStatus) -> Option<(Pid, Status)> {
+        match st {
+            WaitStatus::Exited(pid, exitcode) => {
+                Some((pid, Status::Normalexit(exitcode)))
+            }
+            WaitStatus::Signaled(pid, signal, _bool) => {
+                Some((pid, Status::Signalexit(signal)))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Really wait until the given process has ended (i.e. not just
+/// stopped), and return a simpler enum.
+pub fn waitpid_until_gone(pid: Pid) -> Result<Status> {
+    loop {
+        if let Some((_, status)) = Status::from_wait_status(waitpid(pid, None)?)
+        {
+            return Ok(status);
+        }
+    }
+}
+
+/// Wait until any child process has ended.
+pub fn wait_until_gone() -> Result<(Pid, Status)> {
+    loop {
+        if let Some(pid_status) = Status::from_wait_status(wait()?) {
+            return Ok(pid_status);
+        }
+    }
+}
+
+/// The status of the process if it has ended, without blocking.
+pub fn try_waitpid(pid: Pid) -> Result<Option<Status>> {
+    Ok(
+        Status::from_wait_status(waitpid(pid, Some(WaitPidFlag::WNOHANG))?)
+            .map(|(_, status)| status),
+    )
+}
+
+/// Milliseconds until `deadline` for poll(2), -1 (infinite) if none.
+pub(crate) fn poll_timeout(deadline: Option<Instant>) -> i32 {
+    match deadline {
+        None => -1,
+        Some(deadline) => i32::try_from(
+            deadline
+                .saturating_duration_since(Instant::now())
+                .as_millis(),
+        )
+        .unwrap_or(i32::MAX),
+    }
+}
+
+/// Wait until `fd` is readable (or at EOF); returns false if
+/// `deadline` passed first.
+pub fn wait_readable(fd: RawFd, deadline: Option<Instant>) -> Result<bool> {
+    loop {
+        let mut fds = [PollFd::new(fd, PollFlags::POLLIN)];
+        match poll(&mut fds, poll_timeout(deadline)) {
+            Ok(0) => return Ok(false),
+            Ok(_) => return Ok(true),
+            Err(Errno::EINTR) => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+}
+
+/// Terminate a child (e.g. one that ran into a timeout): SIGTERM,
+/// then SIGKILL if it's still there after a second.
+pub fn kill_until_gone(pid: Pid) -> Result<Status> {
+    kill(pid, Signal::SIGTERM)?;
+    let deadline = Instant::now() + Duration::from_secs(1);
+    loop {
+        if let Some(status) = try_waitpid(pid)? {
+            return Ok(status);
+        }
+        if Instant::now() >= deadline {
+            kill(pid, Signal::SIGKILL)?;
+            return waitpid_until_gone(pid);
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+}
+
+/// Like `waitpid_until_gone`, but kills the process if it hasn't
+/// ended by `deadline`, returning None then.
+pub fn waitpid_until_gone_or_deadline(
+    pid: Pid,
+    deadline: Option<Instant>,
+) -> Result<Option<Status>> {
+    let deadline = match deadline {
+        Some(deadline) => deadline,
+        None => return waitpid_until_gone(pid).map(Some),
+    };
+    loop {
+        if let Some(status) = try_waitpid(pid)? {
+            return Ok(Some(status));
+        }
+        if Instant::now() >= deadline {
+            kill_until_gone(pid)?;
+            return Ok(None);
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+}
+
+/// Whether a process with the given pid exists (which includes
+/// zombies, and processes of other users).
+pub fn pid_exists(pid: Pid) -> Result<bool> {
+    match kill(pid, None) {
+        Ok(()) | Err(Errno::EPERM) => Ok(true),
+        Err(Errno::ESRCH) => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Read the pid from a pidfile (decimal, surrounding whitespace is
+/// ignored).
+pub fn read_pidfile(path: &Path) -> Result<Pid> {
+    let s = std::fs::read_to_string(path)
+        .with_context(|| anyhow!("reading pidfile {:?}", path))?;
+    let pid: i32 = s
+        .trim()
+        .parse()
+        .with_context(|| anyhow!("invalid pid in pidfile {:?}", path))?;
+    if pid <= 0 {
+        bail!("invalid pid {} in pidfile {:?}", pid, path)
+    }
+    Ok(Pid::from_raw(pid))
+}
+
+/// Write `pid` to the pidfile at `path` (atomically, so that readers
+/// never see a partial pid).
+pub fn write_pidfile(path: &Path, pid: Pid) -> Result<()> {
+    write_file_atomically(path, format!("{}\n", pid).as_bytes(), 0o644)
+        .with_context(|| anyhow!("writing pidfile {:?}", path))
+}
+
+/// A pidfd for `pid`, None if not supported (or the process is
+/// already gone).
+#[cfg(target_os = "linux")]
+fn pidfd_open(pid: Pid) -> Option<RawFd> {
+    let fd = unsafe { libc::syscall(libc::SYS_pidfd_open, pid.as_raw(), 0) };
+    RawFd::try_from(fd).ok().filter(|fd| *fd >= 0)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn pidfd_open(_pid: Pid) -> Option<RawFd> {
+    None
+}
+
+/// Wait until the process `pid`, which does not need to be a child,
+/// is gone (for children, this includes being reaped, so use
+/// `waitpid_until_gone` for those). Returns false if `deadline`
+/// passed first. Uses a pidfd on Linux, otherwise polls, with the
+/// interval growing up to `max_interval`.
+pub fn wait_pid_gone(
+    pid: Pid,
+    deadline: Option<Instant>,
+    max_interval: Duration,
+) -> Result<bool> {
+    if let Some(fd) = pidfd_open(pid) {
+        let res = wait_readable(fd, deadline);
+        close(fd)?;
+        return res;
+    }
+    let mut interval = Duration::from_millis(10);
+    loop {
+        if !pid_exists(pid)? {
+            return Ok(true);
+        }
+        let now = Instant::now();
+        let sleep = match deadline {
+            Some(deadline) => {
+                if now >= deadline {
+                    return Ok(false);
+                }
+                interval.min(deadline - now)
+            }
+            None => interval,
+        };
+        std::thread::sleep(sleep);
+        interval = (interval * 2).min(max_interval);
+    }
+}
+
+/// `fork`, returning the pid of the child in the parent, None in the
+/// child.
+///
+/// # Safety
+///
+/// The child of a multi-threaded process may only use
+/// async-signal-safe functions (see fork(2)); allocation is fine in
+/// single-threaded programs.
+pub unsafe fn easy_fork() -> Result<Option<Pid>> {
+    match fork()? {
+        ForkResult::Parent { child, .. } => Ok(Some(child)),
+        ForkResult::Child => Ok(None),
+    }
+}
+
+/// Run `proc` in a child process, which exits with the exit code
+/// returned by `proc` (or 1 after printing the error if it fails).
+/// Only for single-threaded programs (see `easy_fork`).
+pub fn fork_proc(proc: impl FnOnce() -> Result<i32>) -> Result<Pid> {
+    if let Some(pid) = unsafe { easy_fork() }? {
+        Ok(pid)
+    } else {
+        match proc() {
+            Ok(exitcode) => unsafe { libc::_exit(exitcode) },
+            Err(err) => {
+                let _ = std::io::stderr().write_all(
+                    format!(
+                        "{}: fork_proc: error in child {}: {:#}\n",
+                        program_name(),
+                        getpid(),
+                        err
+                    )
+                    .as_bytes(),
+                );
+                unsafe { libc::_exit(1) }
+            }
+        }
+    }
+}
+
+/// A child process running in a new session, which reports how the
+/// command it ran ended through a pipe (since its own exit code can't
+/// represent a signal exit of the command).
+pub struct SessionProc {
+    pub pid: Pid,
+    status_r: RawFd,
+}
+
+impl SessionProc {
+    /// The status of the command run by the child, if it was
+    /// reported as a signal exit, otherwise `status`, the status of
+    /// the child itself.
+    pub fn reported_status(&self, status: Status) -> Result<Status> {
+        let mut buf = [0; 32];
+        let len = read(self.status_r, &mut buf)?;
+        close(self.status_r)?;
+        if len == 0 {
+            return Ok(status);
+        }
+        let signum: i32 = std::str::from_utf8(&buf[0..len])?
+            .strip_prefix("signal ")
+            .ok_or_else(|| anyhow!("invalid status report {:?}", &buf[..len]))?
+            .parse()?;
+        Ok(Status::Signalexit(Signal::try_from(signum)?))
+    }
+
+    pub fn wait(self) -> Result<Status> {
+        let status = waitpid_until_gone(self.pid)?;
+        self.reported_status(status)
+    }
+}
+
+/// Fork `proc` in a new session (calls `setsid` in the child), to
+/// prevent signals from crossing over (stop ctl-c). The status
+/// returned by `proc` is reported back (see `SessionProc`), for
+/// signal exits via the pipe, otherwise as the child's exit code.
+pub fn fork_session_proc(
+    proc: impl FnOnce() -> Result<Status>,
+) -> Result<SessionProc> {
+    // (Close-on-exec, so that commands spawned by `proc` don't keep
+    // it open.)
+    let (status_r, status_w) = pipe2(OFlag::O_CLOEXEC)?;
+    let pid = fork_proc(|| {
+        close(status_r)?;
+        setsid()?;
+        match proc()? {
+            Status::Normalexit(code) => Ok(code),
+            Status::Signalexit(signal) => {
+                write(
+                    status_w,
+                    format!("signal {}", signal as i32).as_bytes(),
+                )?;
+                Ok(13)
+            }
+        }
+    })?;
+    close(status_w)?;
+    Ok(SessionProc { pid, status_r })
+}
+
+/// Run `proc` in a new session and wait for it, see
+/// `fork_session_proc`.
+pub fn run_session_proc(
+    proc: impl FnOnce() -> Result<Status>,
+) -> Result<Status> {
+    fork_session_proc(proc)?.wait()
+}
+
+/// Run `proc` in a grandchild process in a new session, detached
+/// from the calling process, which gets the grandchild's pid: the
+/// child calls `setsid`, forks the grandchild and exits, thus the
+/// grandchild is reparented to init, and, not being a session
+/// leader, can't acquire a controlling terminal. The grandchild exits
+/// with the code returned by `proc` (see `fork_proc`).
+pub fn double_fork(proc: impl FnOnce() -> Result<i32>) -> Result<Pid> {
+    let (pid_r, pid_w) = pipe2(OFlag::O_CLOEXEC)?;
+    let child = fork_proc(|| {
+        close(pid_r)?;
+        setsid()?;
+        let grandchild = fork_proc(|| {
+            close(pid_w)?;
+            proc()
+        })?;
+        write(pid_w, grandchild.as_raw().to_string().as_bytes())?;
+        Ok(0)
+    });
+    close(pid_w)?;
+    let child_status = child.and_then(waitpid_until_gone);
+    let mut buf = [0; 32];
+    let len = read(pid_r, &mut buf);
+    close(pid_r)?;
+    match child_status? {
+        Status::Normalexit(0) => {}
+        status => bail!("double_fork: child failed with {:?}", status),
+    }
+    let pid: i32 = std::str::from_utf8(&buf[..len?])?
+        .parse()
+        .context("double_fork: invalid pid report")?;
+    Ok(Pid::from_raw(pid))
+}
+
+/// Terminate the current process by `signal`, so that the caller
+/// (e.g. shell job control) sees the true termination reason of a
+/// command run on its behalf. Falls back to exiting with 128 + the
+/// signal number if the signal does not terminate the process.
+pub fn exit_by_signal(signal: Signal) -> ! {
+    let _ = unsafe { nix::sys::signal::signal(signal, SigHandler::SigDfl) };
+    let mut sigset = SigSet::empty();
+    sigset.add(signal);
+    let _ = sigset.thread_unblock();
+    let _ = kill(getpid(), signal);
+    std::process::exit(128 + signal as i32)
+}
+
+/// Run `cmd` (looked up in PATH) with stdout and stderr redirected
+/// to /dev/null, waiting for its exit, or killing it and returning
+/// None after `timeout`.
+pub fn run_quietly(
+    cmd: &[CString],
+    timeout: Option<Duration>,
+) -> Result<Option<Status>> {
+    let deadline = timeout.map(|t| Instant::now() + t);
+    let devnull = CString::new("/dev/null").expect("no \0");
+    let pid = spawnp(
+        cmd,
+        &SpawnFds::new()
+            .open(1, &devnull, OFlag::O_WRONLY, Mode::empty())
+            .dup_to(1, 2),
+    )?;
+    waitpid_until_gone_or_deadline(pid, deadline)
+}
+
+#[derive(Debug, Clone)]
+enum FdAction {
+    Dup {
+        from: RawFd,
+        to: RawFd,
+    },
+    Open {
+        fd: RawFd,
+        path: CString,
+        oflag: OFlag,
+        mode: Mode,
+    },
+    Close(RawFd),
+}
+
+/// The file descriptor setup of a child started via `spawnp`, as
+/// posix_spawn file actions, which are carried out in the order
+/// given. Unlike with `ChildFds`, other fds are only closed if they
+/// have the close-on-exec flag, thus create the pipes etc. involved
+/// with `O_CLOEXEC`.
+#[derive(Debug, Clone, Default)]
+pub struct SpawnFds {
+    actions: Vec<FdAction>,
+}
+
+impl SpawnFds {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Make `from` available as `to` in the child (without the
+    /// close-on-exec flag).
+    pub fn dup_to(mut self, from: RawFd, to: RawFd) -> Self {
+        self.actions.push(FdAction::Dup { from, to });
+        self
+    }
+
+    /// Open `path` as `fd` in the child.
+    pub fn open(
+        mut self,
+        fd: RawFd,
+        path: &CStr,
+        oflag: OFlag,
+        mode: Mode,
+    ) -> Self {
+        self.actions.push(FdAction::Open {
+            fd,
+            path: path.to_owned(),
+            oflag,
+            mode,
+        });
+        self
+    }
+
+    /// Close `fd` in the child.
+    pub fn close(mut self, fd: RawFd) -> Self {
+        self.actions.push(FdAction::Close(fd));
+        self
+    }
+
+    /// Carry out the actions in the current process (in the child,
+    /// for the fork fallback of `spawnp`).
+    #[cfg(not(target_os = "linux"))]
+    fn apply(&self) -> Result<()> {
+        use nix::fcntl::{fcntl, open, FcntlArg, FdFlag};
+        use nix::unistd::dup2;
+
+        for action in &self.actions {
+            match action {
+                FdAction::Dup { from, to } => {
+                    if from == to {
+                        fcntl(*to, FcntlArg::F_SETFD(FdFlag::empty()))?;
+                    } else {
+                        dup2(*from, *to)?;
+                    }
+                }
+                FdAction::Open {
+                    fd,
+                    path,
+                    oflag,
+                    mode,
+                } => {
+                    let opened = open(path.as_c_str(), *oflag, *mode)?;
+                    if opened != *fd {
+                        dup2(opened, *fd)?;
+                        close(opened)?;
+                    }
+                }
+                FdAction::Close(fd) => close(*fd)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Turn the return value of a posix_spawn function into a Result.
+#[cfg(target_os = "linux")]
+fn check_spawn_ret(ret: libc::c_int) -> Result<()> {
+    if ret == 0 {
+        Ok(())
+    } else {
+        Err(Errno::from_i32(ret).into())
+    }
+}
+
+/// Owns initialized posix_spawn file actions.
+#[cfg(target_os = "linux")]
+struct FileActions(libc::posix_spawn_file_actions_t);
+
+#[cfg(target_os = "linux")]
+impl Drop for FileActions {
+    fn drop(&mut self) {
+        unsafe { libc::posix_spawn_file_actions_destroy(&mut self.0) };
+    }
+}
+
+/// Start `cmd` (program and arguments, looked up in PATH) with the
+/// fds set up as given, returning its pid. Uses posix_spawnp on
+/// Linux, thus errors from exec (like a missing program) are
+/// returned here; on other systems falls back to fork and exec (where
+/// the child then exits with code 1 after reporting such an error).
+#[cfg(target_os = "linux")]
+pub fn spawnp(cmd: &[CString], fds: &SpawnFds) -> Result<Pid> {
+    use std::os::unix::ffi::OsStrExt;
+
+    if cmd.is_empty() {
+        bail!("spawnp: empty command")
+    }
+    let mut actions = unsafe {
+        let mut actions = std::mem::MaybeUninit::uninit();
+        check_spawn_ret(libc::posix_spawn_file_actions_init(
+            actions.as_mut_ptr(),
+        ))?;
+        FileActions(actions.assume_init())
+    };
+    for action in &fds.actions {
+        check_spawn_ret(unsafe {
+            match action {
+                FdAction::Dup { from, to } => {
+                    libc::posix_spawn_file_actions_adddup2(
+                        &mut actions.0,
+                        *from,
+                        *to,
+                    )
+                }
+                FdAction::Open {
+                    fd,
+                    path,
+                    oflag,
+                    mode,
+                } => libc::posix_spawn_file_actions_addopen(
+                    &mut actions.0,
+                    *fd,
+                    path.as_ptr(),
+                    oflag.bits(),
+                    mode.bits(),
+                ),
+                FdAction::Close(fd) => {
+                    libc::posix_spawn_file_actions_addclose(&mut actions.0, *fd)
+                }
+            }
+        })?;
+    }
+    let env = std::env::vars_os()
+        .map(|(key, val)| {
+            let mut var = key.as_bytes().to_vec();
+            var.push(b'=');
+            var.extend_from_slice(val.as_bytes());
+            CString::new(var)
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let null_terminated = |strs: &[CString]| -> Vec<*mut libc::c_char> {
+        strs.iter()
+            .map(|s| s.as_ptr() as *mut libc::c_char)
+            .chain(std::iter::once(std::ptr::null_mut()))
+            .collect()
+    };
+    let (argv, envp) = (null_terminated(cmd), null_terminated(&env));
+    let mut pid: libc::pid_t = 0;
+    check_spawn_ret(unsafe {
+        libc::posix_spawnp(
+            &mut pid,
+            cmd[0].as_ptr(),
+            &actions.0,
+            std::ptr::null(),
+            argv.as_ptr(),
+            envp.as_ptr(),
+        )
+    })
+    .with_context(|| anyhow!("spawning {:?}", cmd))?;
+    Ok(Pid::from_raw(pid))
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn spawnp(cmd: &[CString], fds: &SpawnFds) -> Result<Pid> {
+    if cmd.is_empty() {
+        bail!("spawnp: empty command")
+    }
+    fork_proc(|| {
+        fds.apply()?;
+        nix::unistd::execvp(&cmd[0], cmd)?;
+        Ok(0) // in child, never reached, just to satisfy type system
+    })
+}
+
+/// Run `cmd` (program and arguments, looked up in PATH), passing its
+/// stdout (and stderr, too, if `with_stderr` is true) to `on_output`
+/// in chunks as it arrives, and wait for it to end. If it doesn't
+/// end by `timeout`, it is killed and None is returned. Stops
+/// reading with the error if `on_output` returns one (the command is
+/// still waited for).
+pub fn capture_streaming<S: AsRef<OsStr>>(
+    cmd: &[S],
+    with_stderr: bool,
+    timeout: Option<Duration>,
+    mut on_output: impl FnMut(&[u8]) -> Result<()>,
+) -> Result<Option<Status>> {
+    let (program, args) = cmd
+        .split_first()
+        .ok_or_else(|| anyhow!("capture: empty command"))?;
+    let (streamr, streamw) = pipe2(OFlag::O_CLOEXEC)?;
+    let child = {
+        let mut command = Command::new(program);
+        command
+            .args(args)
+            .stdout(Stdio::from(unsafe { File::from_raw_fd(streamw) }));
+        if with_stderr {
+            command.stderr(Stdio::from(unsafe {
+                File::from_raw_fd(dup(streamw)?)
+            }));
+        }
+        // Dropping `command` closes our copies of the write end
+        command.spawn()
+    };
+    let child = match child {
+        Ok(child) => child,
+        Err(e) => {
+            close(streamr)?;
+            return Err(e)
+                .with_context(|| anyhow!("running {:?}", program.as_ref()))
+                .subprocess_error();
+        }
+    };
+    let pid = Pid::from_raw(i32::try_from(child.id()).expect("pids fit i32"));
+    let deadline = timeout.map(|t| Instant::now() + t);
+    let mut buf = [0; 8192];
+    let res = (|| -> Result<bool> {
+        loop {
+            if !wait_readable(streamr, deadline)? {
+                return Ok(false);
+            }
+            let n = match read(streamr, &mut buf) {
+                Ok(n) => n,
+                Err(Errno::EINTR) => continue,
+                Err(e) => return Err(e.into()),
+            };
+            if n == 0 {
+                return Ok(true);
+            }
+            on_output(&buf[..n])?;
+        }
+    })();
+    close(streamr)?;
+    match res {
+        Ok(true) => waitpid_until_gone_or_deadline(pid, deadline),
+        Ok(false) => {
+            kill_until_gone(pid)?;
+            Ok(None)
+        }
+        Err(e) => {
+            waitpid_until_gone_or_deadline(pid, deadline)?;
+            Err(e)
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CaptureOptions {
+    /// Keep at most this many bytes of output (the rest is read and
+    /// dropped, so that the command doesn't block); unlimited if
+    /// None. For large outputs, consider `capture_streaming`.
+    pub max_bytes: Option<usize>,
+    /// Capture stderr, too (interleaved with stdout).
+    pub with_stderr: bool,
+    pub timeout: Option<Duration>,
+}
+
+#[derive(Debug)]
+pub struct Captured {
+    pub output: Vec<u8>,
+    /// Whether output was dropped because of `max_bytes`.
+    pub truncated: bool,
+    pub status: Status,
+}
+
+/// An error (of kind `Subprocess`) unless `status` is exit code 0.
+fn check_exit_status(status: Status) -> Result<()> {
+    match status {
+        Status::Normalexit(0) => Ok(()),
+        Status::Normalexit(code) => {
+            Err(anyhow!("command ended with error exit code {code}"))
+        }
+        Status::Signalexit(signal) => {
+            Err(anyhow!("command ended with signal {signal}"))
+        }
+    }
+    .subprocess_error()
+}
+
+impl Captured {
+    /// An error (of kind `Subprocess`) unless the command exited with
+    /// code 0.
+    pub fn check_status(&self) -> Result<()> {
+        check_exit_status(self.status)
+    }
+}
+
+/// Append as much of `chunk` to `output` as `max_bytes` allows,
+/// setting `truncated` if some of it is dropped.
+fn push_limited(
+    output: &mut Vec<u8>,
+    truncated: &mut bool,
+    max_bytes: Option<usize>,
+    chunk: &[u8],
+) {
+    let room =
+        max_bytes.map_or(chunk.len(), |max| max.saturating_sub(output.len()));
+    if room < chunk.len() {
+        *truncated = true;
+    }
+    output.extend_from_slice(&chunk[..room.min(chunk.len())]);
+}
+
+/// Run `cmd` and collect its output, see `capture_streaming`.
+/// Returns None if it ran into the timeout.
+pub fn capture<S: AsRef<OsStr>>(
+    cmd: &[S],
+    opts: &CaptureOptions,
+) -> Result<Option<Captured>> {
+    let mut output = Vec::new();
+    let mut truncated = false;
+    let status =
+        capture_streaming(cmd, opts.with_stderr, opts.timeout, |chunk| {
+            push_limited(&mut output, &mut truncated, opts.max_bytes, chunk);
+            Ok(())
+        })?;
+    Ok(status.map(|status| Captured {
+        output,
+        truncated,
+        status,
+    }))
+}
+
+/// The output stream of a command a chunk came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputStream {
+    Stdout,
+    Stderr,
+}
+
+/// Like `capture_streaming`, but reads stdout and stderr from
+/// separate pipes, passing each chunk to `on_output` with the stream
+/// it came from (the order between the streams is only as exact as
+/// the reads allow).
+pub fn capture_streaming_separately<S: AsRef<OsStr>>(
+    cmd: &[S],
+    timeout: Option<Duration>,
+    mut on_output: impl FnMut(OutputStream, &[u8]) -> Result<()>,
+) -> Result<Option<Status>> {
+    let (program, args) = cmd
+        .split_first()
+        .ok_or_else(|| anyhow!("capture: empty command"))?;
+    let (outr, outw) = pipe2(OFlag::O_CLOEXEC)?;
+    let (errr, errw) = match pipe2(OFlag::O_CLOEXEC) {
+        Ok(fds) => fds,
+        Err(e) => {
+            close(outr)?;
+            close(outw)?;
+            return Err(e.into());
+        }
+    };
+    // Dropping the `Command` closes our copies of the write ends
+    let child = Command::new(program)
+        .args(args)
+        .stdout(Stdio::from(unsafe { File::from_raw_fd(outw) }))
+        .stderr(Stdio::from(unsafe { File::from_raw_fd(errw) }))
+        .spawn();
+    let child = match child {
+        Ok(child) => child,
+        Err(e) => {
+            close(outr)?;
+            close(errr)?;
+            return Err(e)
+                .with_context(|| anyhow!("running {:?}", program.as_ref()))
+                .subprocess_error();
+        }
+    };
+    let pid = Pid::from_raw(i32::try_from(child.id()).expect("pids fit i32"));
+    let deadline = timeout.map(|t| Instant::now() + t);
+    let mut open =
+        vec![(OutputStream::Stdout, outr), (OutputStream::Stderr, errr)];
+    let mut buf = [0; 8192];
+    let res = (|| -> Result<bool> {
+        while !open.is_empty() {
+            let mut fds: Vec<PollFd> = open
+                .iter()
+                .map(|(_, fd)| PollFd::new(*fd, PollFlags::POLLIN))
+                .collect();
+            match poll(&mut fds, poll_timeout(deadline)) {
+                Ok(0) => return Ok(false),
+                Ok(_) => {}
+                Err(Errno::EINTR) => continue,
+                Err(e) => return Err(e.into()),
+            }
+            let mut at_eof = Vec::new();
+            for (pollfd, &(stream, fd)) in fds.iter().zip(&open) {
+                if pollfd.revents().is_none_or(|r| r.is_empty()) {
+                    continue;
+                }
+                match read(fd, &mut buf) {
+                    Ok(0) => at_eof.push(fd),
+                    Ok(n) => on_output(stream, &buf[..n])?,
+                    Err(Errno::EINTR) => {}
+                    Err(e) => return Err(e.into()),
+                }
+            }
+            for fd in at_eof {
+                close(fd)?;
+                open.retain(|(_, open_fd)| *open_fd != fd);
+            }
+        }
+        Ok(true)
+    })();
+    for (_, fd) in &open {
+        close(*fd)?;
+    }
+    match res {
+        Ok(true) => waitpid_until_gone_or_deadline(pid, deadline),
+        Ok(false) => {
+            kill_until_gone(pid)?;
+            Ok(None)
+        }
+        Err(e) => {
+            waitpid_until_gone_or_deadline(pid, deadline)?;
+            Err(e)
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SeparateCaptureOptions {
+    /// Keep at most this many bytes of stdout (see
+    /// `CaptureOptions::max_bytes`); unlimited if None.
+    pub max_stdout_bytes: Option<usize>,
+    /// The same for stderr.
+    pub max_stderr_bytes: Option<usize>,
+    pub timeout: Option<Duration>,
+}
+
+/// The collected output of one stream.
+#[derive(Debug, Default)]
+pub struct CapturedStream {
+    pub output: Vec<u8>,
+    /// Whether output was dropped because of the limit.
+    pub truncated: bool,
+}
+
+#[derive(Debug)]
+pub struct CapturedSeparately {
+    pub stdout: CapturedStream,
+    pub stderr: CapturedStream,
+    pub status: Status,
+}
+
+impl CapturedSeparately {
+    /// An error (of kind `Subprocess`) unless the command exited with
+    /// code 0.
+    pub fn check_status(&self) -> Result<()> {
+        check_exit_status(self.status)
+    }
+}
+
+/// Receives all output of `capture_separately`.
+pub type OutputTee<'t> = &'t mut dyn FnMut(OutputStream, &[u8]) -> Result<()>;
+
+/// Run `cmd` and collect its stdout and stderr separately, see
+/// `capture_streaming_separately`. If `tee` is given, all output is
+/// passed to it as well, as it arrives and regardless of the limits
+/// (e.g. to write it to a log). Returns None if it ran into the
+/// timeout.
+pub fn capture_separately<S: AsRef<OsStr>>(
+    cmd: &[S],
+    opts: &SeparateCaptureOptions,
+    mut tee: Option<OutputTee>,
+) -> Result<Option<CapturedSeparately>> {
+    let mut stdout = CapturedStream::default();
+    let mut stderr = CapturedStream::default();
+    let status =
+        capture_streaming_separately(cmd, opts.timeout, |stream, chunk| {
+            if let Some(tee) = &mut tee {
+                tee(stream, chunk)?;
+            }
+            let (captured, max_bytes) = match stream {
+                OutputStream::Stdout => (&mut stdout, opts.max_stdout_bytes),
+                OutputStream::Stderr => (&mut stderr, opts.max_stderr_bytes),
+            };
+            push_limited(
+                &mut captured.output,
+                &mut captured.truncated,
+                max_bytes,
+                chunk,
+            );
+            Ok(())
+        })?;
+    Ok(status.map(|status| CapturedSeparately {
+        stdout,
+        stderr,
+        status,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use std::os::unix::ffi::OsStrExt;
+
+    #[test]
+    fn t_capture() -> Result<()> {
+        let sh = |script: &str| ["sh", "-c", script].map(String::from);
+        let c = capture(
+            &sh("echo hi; echo err >&2; exit 3"),
+            &CaptureOptions {
+                with_stderr: true,
+                ..Default::default()
+            },
+        )?
+        .unwrap();
+        assert_eq!(c.output, b"hi\nerr\n");
+        assert_eq!(c.status, Status::Normalexit(3));
+        assert!(!c.truncated);
+        assert!(c.check_status().is_err());
+
+        let c = capture(
+            &sh("seq 100000"),
+            &CaptureOptions {
+                max_bytes: Some(4),
+                ..Default::default()
+            },
+        )?
+        .unwrap();
+        assert_eq!(c.output, b"1\n2\n");
+        assert!(c.truncated);
+        c.check_status()?;
+
+        let mut n = 0;
+        let status = capture_streaming(&sh("seq 100000"), false, None, |c| {
+            n += c.len();
+            Ok(())
+        })?;
+        assert_eq!(status, Some(Status::Normalexit(0)));
+        assert_eq!(n, 588895);
+
+        let c = capture(
+            &sh("sleep 10"),
+            &CaptureOptions {
+                timeout: Some(Duration::from_millis(50)),
+                ..Default::default()
+            },
+        )?;
+        assert!(c.is_none());
+        assert!(capture(&["/nonexistent/foo"], &Default::default()).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn t_capture_separately() -> Result<()> {
+        let sh = |script: &str| ["sh", "-c", script].map(String::from);
+        let mut teed = Vec::new();
+        let mut tee = |stream, chunk: &[u8]| -> Result<()> {
+            teed.push((stream, chunk.to_vec()));
+            Ok(())
+        };
+        let c = capture_separately(
+            &sh("echo data; echo warning >&2; seq 1000 >&2; exit 3"),
+            &SeparateCaptureOptions {
+                max_stderr_bytes: Some(10),
+                ..Default::default()
+            },
+            Some(&mut tee),
+        )?
+        .unwrap();
+        assert_eq!(c.stdout.output, b"data\n");
+        assert!(!c.stdout.truncated);
+        assert_eq!(c.stderr.output, b"warning\n1\n");
+        assert!(c.stderr.truncated);
+        assert_eq!(c.status, Status::Normalexit(3));
+        assert!(c.check_status().is_err());
+        let teed_stderr: Vec<u8> = teed
+            .iter()
+            .filter(|(stream, _)| *stream == OutputStream::Stderr)
+            .flat_map(|(_, chunk)| chunk.iter().copied())
+            .collect();
+        assert_eq!(teed_stderr.len(), 8 + 3893);
+
+        // Output on stderr after stdout is closed is still collected
+        let c = capture_separately(
+            &sh("exec >&-; sleep 0.1; echo late >&2"),
+            &Default::default(),
+            None,
+        )?
+        .unwrap();
+        assert_eq!(c.stderr.output, b"late\n");
+        c.check_status()?;
+
+        let c = capture_separately(
+            &sh("sleep 10"),
+            &SeparateCaptureOptions {
+                timeout: Some(Duration::from_millis(50)),
+                ..Default::default()
+            },
+            None,
+        )?;
+        assert!(c.is_none());
+        assert!(capture_separately(
+            &["/nonexistent/foo"],
+            &Default::default(),
+            None
+        )
+        .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn t_spawnp() -> Result<()> {
+        let sh = |script: &str| -> Vec<CString> {
+            ["sh", "-c", script]
+                .iter()
+                .map(|s| CString::new(*s).unwrap())
+                .collect()
+        };
+        let (r, w) = pipe2(OFlag::O_CLOEXEC)?;
+        let pid = spawnp(
+            &sh("echo out; echo err >&2; exit 2"),
+            &SpawnFds::new().dup_to(w, 1).dup_to(w, 2),
+        )?;
+        close(w)?;
+        let mut output = Vec::new();
+        unsafe { File::from_raw_fd(r) }.read_to_end(&mut output)?;
+        assert_eq!(output, b"out\nerr\n");
+        assert_eq!(waitpid_until_gone(pid)?, Status::Normalexit(2));
+
+        assert_eq!(
+            run_quietly(&sh("echo ignored; exit 0"), None)?,
+            Some(Status::Normalexit(0))
+        );
+        assert!(spawnp(
+            &[CString::new("/nonexistent/foo").unwrap()],
+            &SpawnFds::new()
+        )
+        .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn t_double_fork() -> Result<()> {
+        use crate::io::unix_fs::{cstr_as_path, TempDir};
+
+        let tmp = CString::new(std::env::temp_dir().as_os_str().as_bytes())?;
+        let dir = TempDir::new_in(&tmp, "chj-rustbin-test-")?;
+        let pidfile = cstr_as_path(dir.path()).join("pid");
+        let pid = double_fork(|| {
+            write_pidfile(&pidfile, getpid())?;
+            std::thread::sleep(Duration::from_millis(100));
+            Ok(0)
+        })?;
+        // Not our child
+        assert_eq!(
+            try_waitpid(pid).err().and_then(|e| e.downcast().ok()),
+            Some(Errno::ECHILD)
+        );
+        let deadline = Instant::now() + Duration::from_secs(5);
+        assert!(wait_pid_gone(
+            pid,
+            Some(deadline),
+            Duration::from_millis(10)
+        )?);
+        assert_eq!(read_pidfile(&pidfile)?, pid);
+        Ok(())
+    }
+
+    #[test]
+    fn t_wait_pid_gone() -> Result<()> {
+        let mut child = Command::new("sleep").arg("0.3").spawn()?;
+        let pid = Pid::from_raw(child.id() as i32);
+        assert!(pid_exists(pid)?);
+        let max_interval = Duration::from_millis(20);
+        let soon = Instant::now() + Duration::from_millis(20);
+        assert!(!wait_pid_gone(pid, Some(soon), max_interval)?);
+        // Reap it as soon as it exits (we are its parent)
+        let reaper = std::thread::spawn(move || child.wait());
+        let later = Instant::now() + Duration::from_secs(5);
+        assert!(wait_pid_gone(pid, Some(later), max_interval)?);
+        reaper.join().unwrap()?;
+        assert!(!pid_exists(pid)?);
+        Ok(())
+    }
+}
@@ -99,6 +99,12 @@ impl<'m, T: 'm, R, M: for<'g> Fn(&'g mut T) -> &'g mut R> DerefMut
     }
 }
 
+impl<'region, T> Default for Region<'region, T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl<'region, T> Region<'region, T> {
     pub fn new() -> Self {
         Self {
@@ -156,6 +162,25 @@ impl<'region, T> Region<'region, T> {
     }
 }
 
+// -----------------------------------------------------------------------------
+// Course-grained locking for better performance for batch accesses
+
+pub struct RegionGuard<'m, T> {
+    region_guard: MutexGuard<'m, Vec<T>>,
+}
+
+impl<'m, T> RegionGuard<'m, T> {
+    /// May panic on invalid ids.
+    pub fn get(&'m self, id: RegionId<'m, T>) -> &'m T {
+        &(*self.region_guard)[id.as_index()]
+    }
+
+    /// May panic on invalid ids.
+    pub fn get_mut(&'m mut self, id: RegionId<'m, T>) -> &'m mut T {
+        &mut (*self.region_guard)[id.as_index()]
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -249,22 +274,3 @@ mod tests {
         );
     }
 }
-
-// -----------------------------------------------------------------------------
-// Course-grained locking for better performance for batch accesses
-
-pub struct RegionGuard<'m, T> {
-    region_guard: MutexGuard<'m, Vec<T>>,
-}
-
-impl<'m, T> RegionGuard<'m, T> {
-    /// May panic on invalid ids.
-    pub fn get(&'m self, id: RegionId<'m, T>) -> &'m T {
-        &(*self.region_guard)[id.as_index()]
-    }
-
-    /// May panic on invalid ids.
-    pub fn get_mut(&'m mut self, id: RegionId<'m, T>) -> &'m mut T {
-        &mut (*self.region_guard)[id.as_index()]
-    }
-}
@@ -0,0 +1,578 @@
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, HashMap};
+use std::hash::Hash;
+
+use genawaiter::rc::Gen;
+use num::{CheckedSub, Num};
+
+/// Build groups of items from the input stream. A group finishes when
+/// `belong`, being passed the previous and new item, returns
+/// false. Each group is first collected in a Vec, then passed to the
+/// `construct` function as a mutable reference via an `Option` (which
+/// is always `Some`), and the return value becomes the item in the
+/// resulting sequence. The reason the vector is passed via `Option`
+/// is so that `construct` can take it out via `.take().unwrap()` if
+/// it wishes; if it does, `group` creates a new Vec for the next
+/// group, otherwise it reuses the old one for efficiency.
+///
+/// The resulting iterator is empty (no group is reported) if the
+/// input is empty.
+pub fn group<T, G>(
+    mut inp: impl Iterator<Item = T>,
+    belong: impl Fn(&T, &T) -> bool,
+    construct: impl Fn(&mut Option<Vec<T>>) -> G,
+) -> impl Iterator<Item = G> {
+    Gen::new(|co| async move {
+        let mut v = Some(Vec::new());
+        let mut last_item = None;
+        for item in inp.by_ref() {
+            if let Some(last) = last_item.take() {
+                let same = belong(&last, &item);
+                v.as_mut().unwrap().push(last);
+                if !same {
+                    co.yield_(construct(&mut v)).await;
+                    if let Some(vr) = v.as_mut() {
+                        vr.clear();
+                    } else {
+                        v = Some(Vec::new());
+                    }
+                }
+            }
+            last_item = Some(item);
+        }
+        if let Some(last) = last_item.take() {
+            v.as_mut().unwrap().push(last);
+            co.yield_(construct(&mut v)).await;
+        }
+    })
+    .into_iter()
+}
+
+/// Same as `group` but handles errors in the input stream, making it
+/// easier to use (and more efficient?) than group in that case. Also,
+/// continues building the group, so in case the receiver of the
+/// output stream continues to read past errors, they will still
+/// receive groups, and erros do not break up groups (this would not
+/// be possible to achieve via `group`).
+pub fn try_group<T, G, E>(
+    mut inp: impl Iterator<Item = Result<T, E>>,
+    belong: impl Fn(&T, &T) -> bool,
+    construct: impl Fn(&mut Option<Vec<T>>) -> G,
+) -> impl Iterator<Item = Result<G, E>> {
+    Gen::new(|co| async move {
+        let mut v = Some(Vec::new());
+        let mut last_item = None;
+        for result_item in inp.by_ref() {
+            match result_item {
+                Ok(item) => {
+                    if let Some(last) = last_item.take() {
+                        let same = belong(&last, &item);
+                        v.as_mut().unwrap().push(last);
+                        if !same {
+                            co.yield_(Ok(construct(&mut v))).await;
+                            if let Some(vr) = v.as_mut() {
+                                vr.clear();
+                            } else {
+                                v = Some(Vec::new());
+                            }
+                        }
+                    }
+                    last_item = Some(item);
+                }
+                Err(e) => co.yield_(Err(e)).await,
+            }
+        }
+        if let Some(last) = last_item.take() {
+            v.as_mut().unwrap().push(last);
+            co.yield_(Ok(construct(&mut v))).await;
+        }
+    })
+    .into_iter()
+}
+
+/// Same as `try_group`, but passes each group to `construct` as an
+/// owned Vec, for when the group is kept or consumed anyway (a new Vec
+/// is allocated for each group).
+pub fn try_group_owned<T, G, E>(
+    inp: impl Iterator<Item = Result<T, E>>,
+    belong: impl Fn(&T, &T) -> bool,
+    construct: impl Fn(Vec<T>) -> G,
+) -> impl Iterator<Item = Result<G, E>> {
+    try_group(inp, belong, move |v| {
+        construct(v.take().expect("try_group always passes Some"))
+    })
+}
+
+/// Like `try_group` followed by a reduction of each group, but
+/// without ever materializing the group: each item of a group is
+/// passed to `fold` together with the accumulator (which starts out
+/// as the value returned by `init` for every group), and the final
+/// accumulator becomes the item in the resulting sequence. Thus uses
+/// constant memory regardless of group sizes. Grouping is decided by
+/// `same_group` as with `belong` in `group`. Like `try_group`, errors
+/// are passed through and do not break up groups.
+pub fn try_fold_grouped<T, A, E>(
+    mut inp: impl Iterator<Item = Result<T, E>>,
+    same_group: impl Fn(&T, &T) -> bool,
+    init: impl Fn() -> A,
+    fold: impl Fn(A, T) -> A,
+) -> impl Iterator<Item = Result<A, E>> {
+    Gen::new(|co| async move {
+        let mut acc = None;
+        let mut last_item = None;
+        for result_item in inp.by_ref() {
+            match result_item {
+                Ok(item) => {
+                    if let Some(last) = last_item.take() {
+                        let same = same_group(&last, &item);
+                        let a = fold(acc.take().unwrap_or_else(&init), last);
+                        if same {
+                            acc = Some(a);
+                        } else {
+                            co.yield_(Ok(a)).await;
+                        }
+                    }
+                    last_item = Some(item);
+                }
+                Err(e) => co.yield_(Err(e)).await,
+            }
+        }
+        if let Some(last) = last_item.take() {
+            co.yield_(Ok(fold(acc.take().unwrap_or_else(&init), last)))
+                .await;
+        }
+    })
+    .into_iter()
+}
+
+/// Reduce each run of consecutive items for which `same` (being
+/// passed the previous and new item) returns true to its first and
+/// last item. Useful to drop redundant samples from a stream of
+/// measurements that often stay unchanged. Errors are passed through
+/// and do not break up runs.
+pub fn try_keep_run_ends<T, E>(
+    mut inp: impl Iterator<Item = Result<T, E>>,
+    same: impl Fn(&T, &T) -> bool,
+) -> impl Iterator<Item = Result<T, E>> {
+    Gen::new(|co| async move {
+        // The previous item, and whether it is the start of a run
+        let mut prev: Option<(T, bool)> = None;
+        for result_item in inp.by_ref() {
+            match result_item {
+                Ok(item) => {
+                    if let Some((p, p_is_start)) = prev.take() {
+                        if same(&p, &item) {
+                            if p_is_start {
+                                co.yield_(Ok(p)).await;
+                            }
+                            prev = Some((item, false));
+                        } else {
+                            co.yield_(Ok(p)).await;
+                            prev = Some((item, true));
+                        }
+                    } else {
+                        prev = Some((item, true));
+                    }
+                }
+                Err(e) => co.yield_(Err(e)).await,
+            }
+        }
+        if let Some((p, _)) = prev {
+            co.yield_(Ok(p)).await;
+        }
+    })
+    .into_iter()
+}
+
+/// Run-length encoding: reduce each run of consecutive items with
+/// equal `key` to its first item and the number of items in the run.
+pub fn runs<T, K: PartialEq>(
+    mut inp: impl Iterator<Item = T>,
+    key: impl Fn(&T) -> K,
+) -> impl Iterator<Item = (T, usize)> {
+    Gen::new(|co| async move {
+        // The first item of the current run, its key, and the count
+        let mut run: Option<(T, K, usize)> = None;
+        for item in inp.by_ref() {
+            let k = key(&item);
+            match run.take() {
+                Some((first, first_k, n)) if first_k == k => {
+                    run = Some((first, first_k, n + 1))
+                }
+                Some((first, _, n)) => {
+                    co.yield_((first, n)).await;
+                    run = Some((item, k, 1));
+                }
+                None => run = Some((item, k, 1)),
+            }
+        }
+        if let Some((first, _, n)) = run {
+            co.yield_((first, n)).await;
+        }
+    })
+    .into_iter()
+}
+
+/// Count the items by `key`, regardless of their order (see `runs`
+/// for counting consecutive ones).
+pub fn tally<T, K: Eq + Hash>(
+    inp: impl IntoIterator<Item = T>,
+    key: impl Fn(&T) -> K,
+) -> HashMap<K, usize> {
+    let mut counts = HashMap::new();
+    for item in inp {
+        *counts.entry(key(&item)).or_insert(0) += 1;
+    }
+    counts
+}
+
+/// How `TallyErrors` handles an error from its input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorClass {
+    /// Drop the error (count it) and continue with the next item.
+    Skip,
+    /// Pass the error on and end the sequence after it.
+    Abort,
+}
+
+/// Iterator returned by `try_tally_errors`.
+pub struct TallyErrors<I, F> {
+    inp: I,
+    classify: F,
+    skipped: usize,
+    aborted: bool,
+}
+
+impl<I, F> TallyErrors<I, F> {
+    /// The number of errors skipped so far.
+    pub fn skipped(&self) -> usize {
+        self.skipped
+    }
+
+    /// Whether the sequence was ended by an error classified as
+    /// `Abort`.
+    pub fn aborted(&self) -> bool {
+        self.aborted
+    }
+}
+
+impl<T, E, I, F> Iterator for TallyErrors<I, F>
+where
+    I: Iterator<Item = Result<T, E>>,
+    F: FnMut(&E) -> ErrorClass,
+{
+    type Item = Result<T, E>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.aborted {
+            return None;
+        }
+        for item in self.inp.by_ref() {
+            match item {
+                Ok(v) => return Some(Ok(v)),
+                Err(e) => match (self.classify)(&e) {
+                    ErrorClass::Skip => self.skipped += 1,
+                    ErrorClass::Abort => {
+                        self.aborted = true;
+                        return Some(Err(e));
+                    }
+                },
+            }
+        }
+        None
+    }
+}
+
+/// Separate soft errors from hard ones: errors for which `classify`
+/// returns `Skip` are dropped from the sequence (`classify` can
+/// report them, e.g. print a warning), the first one for which it
+/// returns `Abort` is passed on and ends the sequence. Iterate via
+/// `by_ref()` to query the counts at the end.
+pub fn try_tally_errors<T, E, I, F>(inp: I, classify: F) -> TallyErrors<I, F>
+where
+    I: Iterator<Item = Result<T, E>>,
+    F: FnMut(&E) -> ErrorClass,
+{
+    TallyErrors {
+        inp,
+        classify,
+        skipped: 0,
+        aborted: false,
+    }
+}
+
+/// How far out of order items may arrive for `try_sort_within`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Window<K> {
+    /// An item may arrive after at most this many items that sort
+    /// after it.
+    Items(usize),
+    /// An item may arrive after items whose key is larger by at most
+    /// this amount (e.g. seconds, for a key in seconds).
+    Distance(K),
+}
+
+/// An item waiting in `SortWithin`; ordered by key, then arrival.
+struct Pending<K, T> {
+    key: K,
+    seq: u64,
+    item: T,
+}
+
+impl<K: Ord, T> PartialEq for Pending<K, T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<K: Ord, T> Eq for Pending<K, T> {}
+
+impl<K: Ord, T> PartialOrd for Pending<K, T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<K: Ord, T> Ord for Pending<K, T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.key
+            .cmp(&other.key)
+            .then_with(|| self.seq.cmp(&other.seq))
+    }
+}
+
+/// Iterator returned by `try_sort_within`.
+pub struct SortWithin<T, K, I, F> {
+    inp: I,
+    key: F,
+    window: Window<K>,
+    pending: BinaryHeap<Reverse<Pending<K, T>>>,
+    seq: u64,
+    max_key: Option<K>,
+    last_key: Option<K>,
+    inp_done: bool,
+    late: usize,
+}
+
+impl<T, K, I, F> SortWithin<T, K, I, F> {
+    /// The number of items so far that arrived later than the window
+    /// allows (and were passed on out of order).
+    pub fn late(&self) -> usize {
+        self.late
+    }
+}
+
+impl<T, K, I, F> SortWithin<T, K, I, F>
+where
+    K: Num + CheckedSub + Ord + Copy,
+{
+    /// The smallest pending item, if no item within the window can
+    /// arrive anymore that sorts before it (or the input is done).
+    fn pop_ready(&mut self) -> Option<T> {
+        let Reverse(first) = self.pending.peek()?;
+        let ready = self.inp_done
+            || match self.window {
+                Window::Items(n) => self.pending.len() > n,
+                Window::Distance(d) => {
+                    let max = self.max_key.expect("set when pushing");
+                    max.checked_sub(&first.key).expect("max is the maximum") > d
+                }
+            };
+        if ready {
+            let Reverse(first) = self.pending.pop().expect("peeked");
+            self.last_key = Some(first.key);
+            Some(first.item)
+        } else {
+            None
+        }
+    }
+}
+
+impl<T, E, K, I, F> Iterator for SortWithin<T, K, I, F>
+where
+    I: Iterator<Item = Result<T, E>>,
+    F: Fn(&T) -> K,
+    K: Num + CheckedSub + Ord + Copy,
+{
+    type Item = Result<T, E>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(item) = self.pop_ready() {
+                return Some(Ok(item));
+            }
+            if self.inp_done {
+                return None;
+            }
+            match self.inp.next() {
+                None => self.inp_done = true,
+                Some(Err(e)) => return Some(Err(e)),
+                Some(Ok(item)) => {
+                    let key = (self.key)(&item);
+                    if self.last_key.is_some_and(|last| key < last) {
+                        self.late += 1;
+                        return Some(Ok(item));
+                    }
+                    if self.max_key.is_none_or(|max| key > max) {
+                        self.max_key = Some(key);
+                    }
+                    self.pending.push(Reverse(Pending {
+                        key,
+                        seq: self.seq,
+                        item,
+                    }));
+                    self.seq += 1;
+                }
+            }
+        }
+    }
+}
+
+/// Restore the order of items by `key` that arrive slightly out of
+/// order, i.e. by at most `window`, by buffering items until no item
+/// within the window can arrive before them anymore. Items with equal
+/// keys keep their order. Items arriving later than the window allows
+/// are passed on right away (out of order), and counted (see
+/// `SortWithin::late`; iterate via `by_ref()` to query the count at
+/// the end). Errors are passed through right away.
+pub fn try_sort_within<T, E, K, I, F>(
+    inp: I,
+    key: F,
+    window: Window<K>,
+) -> SortWithin<T, K, I, F>
+where
+    I: Iterator<Item = Result<T, E>>,
+    F: Fn(&T) -> K,
+    K: Num + CheckedSub + Ord + Copy,
+{
+    SortWithin {
+        inp,
+        key,
+        window,
+        pending: BinaryHeap::new(),
+        seq: 0,
+        max_key: None,
+        last_key: None,
+        inp_done: false,
+        late: 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn t_try_group_owned() {
+        let t = |inp: Vec<Result<i32, &'static str>>| {
+            try_group_owned(inp.into_iter(), |a, b| a / 10 == b / 10, |v| v)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(t(vec![]), vec![]);
+        assert_eq!(
+            t(vec![Ok(1), Ok(2), Ok(11), Err("e"), Ok(12), Ok(3)]),
+            vec![Ok(vec![1, 2]), Err("e"), Ok(vec![11, 12]), Ok(vec![3])]
+        );
+    }
+
+    #[test]
+    fn t_try_fold_grouped() {
+        let t = |inp: Vec<Result<i32, &'static str>>| {
+            try_fold_grouped(
+                inp.into_iter(),
+                |a, b| a / 10 == b / 10,
+                || 0,
+                |acc, x| acc + x,
+            )
+            .collect::<Vec<_>>()
+        };
+        assert_eq!(t(vec![]), vec![]);
+        assert_eq!(t(vec![Ok(1)]), vec![Ok(1)]);
+        assert_eq!(
+            t(vec![Ok(1), Ok(2), Ok(11), Err("e"), Ok(12), Ok(3)]),
+            vec![Ok(3), Err("e"), Ok(23), Ok(3)]
+        );
+    }
+
+    #[test]
+    fn t_try_keep_run_ends() {
+        let t = |inp: &[i32]| {
+            try_keep_run_ends(
+                inp.iter().map(|x| -> Result<i32, ()> { Ok(*x) }),
+                |a, b| a / 10 == b / 10,
+            )
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap()
+        };
+        assert_eq!(t(&[]), [0; 0]);
+        assert_eq!(t(&[1]), [1]);
+        assert_eq!(t(&[1, 2]), [1, 2]);
+        assert_eq!(t(&[1, 2, 3, 4, 11, 21, 22, 23]), [1, 4, 11, 21, 23]);
+    }
+
+    #[test]
+    fn t_runs_tally() {
+        let t = |inp: &[i32]| {
+            runs(inp.iter().copied(), |x| *x / 10).collect::<Vec<_>>()
+        };
+        assert_eq!(t(&[]), []);
+        assert_eq!(t(&[1]), [(1, 1)]);
+        assert_eq!(t(&[1, 2, 11, 3, 4, 5]), [(1, 2), (11, 1), (3, 3)]);
+
+        let counts = tally(vec!["a", "b", "a", "c", "a"], |s| *s);
+        assert_eq!(counts.len(), 3);
+        assert_eq!((counts["a"], counts["b"], counts["c"]), (3, 1, 1));
+    }
+
+    #[test]
+    fn t_try_tally_errors() {
+        let inp: Vec<Result<i32, i32>> =
+            vec![Ok(1), Err(1), Ok(2), Err(2), Ok(3), Err(10), Ok(4)];
+        let mut it = try_tally_errors(inp.clone().into_iter(), |e| {
+            if *e < 10 {
+                ErrorClass::Skip
+            } else {
+                ErrorClass::Abort
+            }
+        });
+        assert_eq!(
+            it.by_ref().collect::<Vec<_>>(),
+            vec![Ok(1), Ok(2), Ok(3), Err(10)]
+        );
+        assert_eq!((it.skipped(), it.aborted()), (2, true));
+
+        let mut it = try_tally_errors(inp.into_iter(), |_| ErrorClass::Skip);
+        assert_eq!(it.by_ref().count(), 4);
+        assert_eq!((it.skipped(), it.aborted()), (3, false));
+    }
+
+    #[test]
+    fn t_try_sort_within() {
+        // (key, tag) pairs, sorted by key
+        let t = |inp: &[(u32, char)], window| {
+            let mut it = try_sort_within(
+                inp.iter().map(|x| -> Result<_, ()> { Ok(*x) }),
+                |(key, _)| *key,
+                window,
+            );
+            let out: Vec<char> = it.by_ref().map(|x| x.unwrap().1).collect();
+            (out.into_iter().collect::<String>(), it.late())
+        };
+        let inp = [(1, 'a'), (3, 'c'), (2, 'b'), (3, 'd'), (6, 'f')];
+        assert_eq!(t(&inp, Window::Items(1)), ("abcdf".into(), 0));
+        assert_eq!(t(&inp, Window::Distance(1)), ("abcdf".into(), 0));
+        assert_eq!(t(&inp, Window::Items(0)), ("acbdf".into(), 1));
+        assert_eq!(t(&[], Window::Items(3)), ("".into(), 0));
+        let inp = [(10, 'b'), (12, 'c'), (15, 'd'), (9, 'a'), (16, 'e')];
+        assert_eq!(t(&inp, Window::Distance(6)), ("abcde".into(), 0));
+        assert_eq!(t(&inp, Window::Distance(3)), ("bacde".into(), 1));
+        assert_eq!(t(&inp, Window::Items(3)), ("abcde".into(), 0));
+
+        let inp = vec![Ok(2), Err("e"), Ok(1), Ok(3)];
+        assert_eq!(
+            try_sort_within(inp.into_iter(), |x| *x, Window::Items(1))
+                .collect::<Vec<_>>(),
+            vec![Err("e"), Ok(1), Ok(2), Ok(3)]
+        );
+    }
+}
@@ -0,0 +1,8 @@
+pub mod delimited;
+pub mod humanize;
+pub mod json;
+pub mod naturallanguagejoin;
+pub mod parseutil;
+pub mod startswith;
+pub mod svgchart;
+pub mod table;
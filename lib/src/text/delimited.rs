@@ -0,0 +1,290 @@
+//! Reading delimited data (TSV, CSV) with optional header rows,
+//! selecting columns by number or header name, and writing rows, for
+//! tools like groupby and tsvcut.
+
+use std::io::{stdin, BufRead, BufReader, Write};
+use std::path::PathBuf;
+
+use anyhow::{anyhow, bail, Context, Result};
+
+use crate::error::Categorize;
+use crate::io::readwithcontext::{open_file, trim};
+use crate::pipeline::try_gen;
+use crate::text::parseutil::{split_fields, FieldSyntax};
+use crate::time::excel::{CsvSeparator, ExcelCsvWriter};
+
+/// Parse a delimiter given on the command line (a single character,
+/// `\t` is accepted for tab).
+pub fn parse_delimiter(s: &str) -> Result<char> {
+    if s == "\\t" {
+        return Ok('\t');
+    }
+    let mut cs = s.chars();
+    match (cs.next(), cs.next()) {
+        (Some(c), None) => Ok(c),
+        _ => bail!("delimiter must be a single character, got {s:?}"),
+    }
+}
+
+/// The options to choose the syntax of delimited input, to be
+/// flattened into a binary's options.
+#[derive(clap::Args, Debug)]
+pub struct DelimiterArgs {
+    /// The field delimiter (a single character). `\t` is accepted
+    /// for tab.
+    #[clap(short, long, default_value = "\\t")]
+    pub delimiter: String,
+
+    /// Read CSV: comma delimited, with `"` quoted fields.
+    #[clap(long, conflicts_with = "delimiter")]
+    pub csv: bool,
+}
+
+impl DelimiterArgs {
+    /// CSV, or fields separated by the delimiter without quoting.
+    pub fn syntax(&self) -> Result<FieldSyntax> {
+        if self.csv {
+            Ok(FieldSyntax::csv())
+        } else {
+            Ok(FieldSyntax {
+                delimiter: parse_delimiter(&self.delimiter).usage_error()?,
+                ..FieldSyntax::tsv()
+            })
+        }
+    }
+}
+
+/// An input with a name for error messages.
+pub type Input = (String, Box<dyn BufRead>);
+
+/// Open the files at `paths`, or stdin if `paths` is empty.
+pub fn open_inputs(paths: &[PathBuf]) -> Result<Vec<Input>> {
+    if paths.is_empty() {
+        return Ok(vec![("stdin".into(), Box::new(BufReader::new(stdin())))]);
+    }
+    paths
+        .iter()
+        .map(|path| -> Result<Input> {
+            Ok((format!("file {:?}", path), Box::new(open_file(path)?)))
+        })
+        .collect()
+}
+
+/// The rows of all inputs, with the header rows (if `header`) of the
+/// inputs after the first one dropped.
+pub fn read_rows(
+    inputs: Vec<Input>,
+    syntax: FieldSyntax,
+    header: bool,
+) -> impl Iterator<Item = Result<Vec<String>>> {
+    try_gen(|co| async move {
+        let mut line = String::new();
+        for (file_i, (name, mut inp)) in inputs.into_iter().enumerate() {
+            let mut linenumber = 0;
+            loop {
+                line.clear();
+                linenumber += 1;
+                let context = || anyhow!("{name} line {linenumber}");
+                if inp.read_line(&mut line).with_context(context)? == 0 {
+                    break;
+                }
+                if header && file_i > 0 && linenumber == 1 {
+                    continue;
+                }
+                trim(&mut line);
+                co.yield_(split_fields(&line, &syntax).with_context(context))
+                    .await;
+            }
+        }
+        Ok(())
+    })
+}
+
+/// Part of a column selection, see `Columns::selection`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Selected {
+    Column(usize),
+    /// Columns from the first up to and including the second, or up to
+    /// the end of the row if None.
+    Range(usize, Option<usize>),
+}
+
+/// Resolves column specifications to indices (0-based), using the
+/// header names if given, and gives the names for output headers.
+pub struct Columns(pub Option<Vec<String>>);
+
+impl Columns {
+    /// A column by number (starting at 1) or header name.
+    pub fn index(&self, spec: &str) -> Result<usize> {
+        if let Ok(n) = spec.parse::<usize>() {
+            if n == 0 {
+                bail!("column numbers start at 1")
+            }
+            return Ok(n - 1);
+        }
+        let names = self.0.as_ref().ok_or_else(|| {
+            anyhow!("column {spec:?} is not a number, and no --header given")
+        })?;
+        names
+            .iter()
+            .position(|name| name == spec)
+            .ok_or_else(|| anyhow!("unknown column {spec:?}"))
+    }
+
+    /// The header name of column `i`, or `column <number>`.
+    pub fn name(&self, i: usize) -> String {
+        self.0
+            .as_ref()
+            .and_then(|names| names.get(i).cloned())
+            .unwrap_or_else(|| format!("column {}", i + 1))
+    }
+
+    /// Parse a comma separated list of columns (as for `index`) and
+    /// ranges `FROM-TO`, `FROM-` or `-TO` (where FROM and TO are as for
+    /// `index`, too). Header names containing `-` are taken as a
+    /// single column if they match exactly.
+    pub fn selection(&self, spec: &str) -> Result<Vec<Selected>> {
+        spec.split(',')
+            .map(|part| -> Result<Selected> {
+                let is_name = self.0.iter().flatten().any(|n| n == part);
+                if is_name {
+                    return Ok(Selected::Column(self.index(part)?));
+                }
+                match part.split_once('-') {
+                    Some((from, to)) => {
+                        let from = if from.is_empty() {
+                            0
+                        } else {
+                            self.index(from)?
+                        };
+                        let to = if to.is_empty() {
+                            None
+                        } else {
+                            Some(self.index(to)?)
+                        };
+                        if let Some(to) = to {
+                            if to < from {
+                                bail!("decreasing range {part:?}")
+                            }
+                        }
+                        Ok(Selected::Range(from, to))
+                    }
+                    None => Ok(Selected::Column(self.index(part)?)),
+                }
+            })
+            .collect()
+    }
+}
+
+/// The column indices selected by `selection` in a row with `len`
+/// columns (for open ranges; explicitly given columns are included
+/// even if out of range).
+pub fn selected_indices(selection: &[Selected], len: usize) -> Vec<usize> {
+    let mut indices = Vec::new();
+    for s in selection {
+        match *s {
+            Selected::Column(i) => indices.push(i),
+            Selected::Range(from, to) => {
+                indices.extend(from..to.map_or(len, |to| to + 1))
+            }
+        }
+    }
+    indices
+}
+
+/// Writes rows with fields separated by a delimiter (without any
+/// quoting), or as CSV for Excel.
+pub enum RowWriter<W: Write> {
+    Delimited { out: W, delimiter: char },
+    ExcelCsv(ExcelCsvWriter<W>),
+}
+
+impl<W: Write> RowWriter<W> {
+    /// CSV for Excel if `excel_csv` is given, `delimiter` otherwise.
+    pub fn new(
+        out: W,
+        delimiter: char,
+        excel_csv: Option<CsvSeparator>,
+    ) -> Self {
+        match excel_csv {
+            Some(separator) => {
+                RowWriter::ExcelCsv(ExcelCsvWriter::new(out, separator))
+            }
+            None => RowWriter::Delimited { out, delimiter },
+        }
+    }
+
+    pub fn write_row<S: AsRef<str>>(&mut self, row: &[S]) -> Result<()> {
+        match self {
+            RowWriter::Delimited { out, delimiter } => {
+                let mut line = String::new();
+                for (i, field) in row.iter().enumerate() {
+                    if i > 0 {
+                        line.push(*delimiter);
+                    }
+                    line.push_str(field.as_ref());
+                }
+                line.push('\n');
+                out.write_all(line.as_bytes())?;
+            }
+            RowWriter::ExcelCsv(w) => w.write_record(row)?,
+        }
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> Result<()> {
+        match self {
+            RowWriter::Delimited { out, .. } => out.flush()?,
+            RowWriter::ExcelCsv(w) => w.flush()?,
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn t_selection() {
+        let columns = Columns(Some(
+            ["time", "received B", "sent B", "a-b"]
+                .map(String::from)
+                .into(),
+        ));
+        assert_eq!(columns.index("2").unwrap(), 1);
+        assert_eq!(columns.index("sent B").unwrap(), 2);
+        assert!(columns.index("foo").is_err());
+        assert!(columns.index("0").is_err());
+        let t = |spec: &str, len: usize| {
+            selected_indices(&columns.selection(spec).unwrap(), len)
+        };
+        assert_eq!(t("sent B,time", 4), [2, 0]);
+        assert_eq!(t("2-3", 4), [1, 2]);
+        assert_eq!(t("received B-", 4), [1, 2, 3]);
+        assert_eq!(t("-2", 4), [0, 1]);
+        assert_eq!(t("a-b,1", 4), [3, 0]);
+        assert_eq!(t("3-", 6), [2, 3, 4, 5]);
+        assert!(columns.selection("3-2").is_err());
+        assert!(Columns(None).selection("time").is_err());
+    }
+
+    #[test]
+    fn t_row_writer() -> Result<()> {
+        let t = |excel_csv| -> Result<String> {
+            let mut w = RowWriter::new(Vec::new(), '\t', excel_csv);
+            w.write_row(&["a", "b,c"])?;
+            w.write_row(&[String::from("1.5")])?;
+            Ok(match w {
+                RowWriter::Delimited { out, .. } => String::from_utf8(out)?,
+                RowWriter::ExcelCsv(w) => String::from_utf8(w.into_inner())?,
+            })
+        };
+        assert_eq!(t(None)?, "a\tb,c\n1.5\n");
+        assert_eq!(
+            t(Some(CsvSeparator::Comma))?,
+            "\u{feff}a,\"b,c\"\r\n1.5\r\n"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,149 @@
+//! Durations and byte sizes formatted for humans, for messages and
+//! summaries: `3 days, 4 hours and 12 minutes`, `1.4 GiB`.
+
+use std::time::Duration;
+
+use crate::text::naturallanguagejoin::natural_language_join_with;
+
+/// Whether `format_bytes` uses powers of 1000 or 1024.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ByteUnits {
+    /// kB, MB, GB, ..
+    Decimal,
+    /// KiB, MiB, GiB, ..
+    Binary,
+}
+
+impl ByteUnits {
+    fn base(self) -> f64 {
+        match self {
+            ByteUnits::Decimal => 1000.,
+            ByteUnits::Binary => 1024.,
+        }
+    }
+
+    fn names(self) -> &'static [&'static str] {
+        match self {
+            ByteUnits::Decimal => &["B", "kB", "MB", "GB", "TB", "PB", "EB"],
+            ByteUnits::Binary => {
+                &["B", "KiB", "MiB", "GiB", "TiB", "PiB", "EiB"]
+            }
+        }
+    }
+}
+
+/// `n` bytes in the largest unit that keeps the number at 1 or more,
+/// e.g. `1.5 GB`, with `decimals` digits after the point, or with
+/// None, 1 digit below 100 and none from 100 on. Plain bytes are
+/// shown without decimals.
+pub fn format_bytes(
+    n: f64,
+    units: ByteUnits,
+    decimals: Option<usize>,
+) -> String {
+    let (base, names) = (units.base(), units.names());
+    let mut n = n;
+    let mut unit = 0;
+    while n.abs() >= base && unit < names.len() - 1 {
+        n /= base;
+        unit += 1;
+    }
+    let decimals = |n: f64, unit: usize| match decimals {
+        _ if unit == 0 => 0,
+        Some(decimals) => decimals,
+        None if n.abs() >= 100. => 0,
+        None => 1,
+    };
+    let mut s = format!("{:.*}", decimals(n, unit), n);
+    // Rounding may have reached the next unit (`1000.0 kB`)
+    if s.trim_start_matches('-').parse::<f64>().unwrap_or(0.) >= base
+        && unit < names.len() - 1
+    {
+        n /= base;
+        unit += 1;
+        s = format!("{:.*}", decimals(n, unit), n);
+    }
+    format!("{s} {}", names[unit])
+}
+
+const DURATION_UNITS: [(&str, u64); 5] = [
+    ("day", 86_400_000),
+    ("hour", 3_600_000),
+    ("minute", 60_000),
+    ("second", 1000),
+    ("millisecond", 1),
+];
+
+/// `d` as e.g. `3 days, 4 hours and 12 minutes`, with at most
+/// `max_units` of the units (days, hours, minutes, seconds,
+/// milliseconds) starting from the largest non-zero one; the rest is
+/// truncated, as are sub-millisecond parts. Zero units within
+/// the range are left out (`1 hour and 5 seconds`); durations below
+/// the smallest shown unit give `0 seconds`.
+pub fn format_duration(d: Duration, max_units: usize) -> String {
+    let mut millis = d.as_millis();
+    let mut parts = Vec::new();
+    let mut units_left = max_units;
+    for (name, unit_millis) in DURATION_UNITS {
+        if units_left == 0 {
+            break;
+        }
+        let unit_millis = u128::from(unit_millis);
+        let n = millis / unit_millis;
+        millis %= unit_millis;
+        if n > 0 || !parts.is_empty() {
+            units_left -= 1;
+        }
+        if n > 0 {
+            let plural = if n == 1 { "" } else { "s" };
+            parts.push(format!("{n} {name}{plural}"));
+        }
+    }
+    if parts.is_empty() {
+        "0 seconds".into()
+    } else {
+        natural_language_join_with(&parts, "and")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn t_format_bytes() {
+        use ByteUnits::*;
+        assert_eq!(format_bytes(0., Decimal, None), "0 B");
+        assert_eq!(format_bytes(999., Decimal, Some(2)), "999 B");
+        assert_eq!(format_bytes(1500., Decimal, None), "1.5 kB");
+        assert_eq!(format_bytes(150_000., Decimal, None), "150 kB");
+        assert_eq!(format_bytes(1.5e9, Decimal, Some(3)), "1.500 GB");
+        assert_eq!(format_bytes(1.5e9, Binary, None), "1.4 GiB");
+        assert_eq!(format_bytes(1536., Binary, Some(0)), "2 KiB");
+        assert_eq!(format_bytes(999_960., Decimal, Some(1)), "1.0 MB");
+        assert_eq!(format_bytes(1023.9 * 1024., Binary, None), "1.0 MiB");
+        assert_eq!(format_bytes(-2048., Binary, None), "-2.0 KiB");
+        assert_eq!(format_bytes(3e21, Decimal, None), "3000 EB");
+    }
+
+    #[test]
+    fn t_format_duration() {
+        let secs = Duration::from_secs;
+        let d = secs(3 * 86400 + 4 * 3600 + 12 * 60 + 30);
+        assert_eq!(format_duration(d, 3), "3 days, 4 hours and 12 minutes");
+        assert_eq!(format_duration(d, 2), "3 days and 4 hours");
+        assert_eq!(
+            format_duration(d, 10),
+            "3 days, 4 hours, 12 minutes and 30 seconds"
+        );
+        assert_eq!(format_duration(secs(3605), 3), "1 hour and 5 seconds");
+        assert_eq!(format_duration(secs(3605), 2), "1 hour");
+        assert_eq!(format_duration(secs(1), 1), "1 second");
+        assert_eq!(
+            format_duration(Duration::from_millis(1500), 2),
+            "1 second and 500 milliseconds"
+        );
+        assert_eq!(format_duration(Duration::from_micros(10), 2), "0 seconds");
+        assert_eq!(format_duration(secs(60), 0), "0 seconds");
+    }
+}
@@ -0,0 +1,317 @@
+//! Minimal helpers for writing JSON without a serialization
+//! framework, and for reading the members of JSON objects (as in
+//! JSONL logs).
+
+use std::fmt::Write;
+
+use anyhow::{anyhow, bail, Result};
+
+/// Append `s` as a JSON string literal (including the quotes) to
+/// `out`.
+pub fn push_json_string(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                write!(out, "\\u{:04x}", c as u32).unwrap()
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+/// A member value of an object parsed by `parse_json_object`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JsonValue {
+    String(String),
+    /// Anything else (a number, `true`, `false`, `null`, an array or
+    /// object), as its JSON text.
+    Other(String),
+}
+
+impl JsonValue {
+    /// The string, or the JSON text of other values.
+    pub fn as_str(&self) -> &str {
+        match self {
+            JsonValue::String(s) | JsonValue::Other(s) => s,
+        }
+    }
+}
+
+/// The maximum nesting of arrays and objects accepted in member
+/// values (like serde_json's default recursion limit), so that deeply
+/// nested input gives an error instead of overflowing the stack.
+const MAX_DEPTH: usize = 128;
+
+struct Parser<'s> {
+    s: &'s str,
+    pos: usize,
+}
+
+impl<'s> Parser<'s> {
+    fn peek(&self) -> Option<char> {
+        self.s[self.pos..].chars().next()
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = &self.s[self.pos..];
+        self.pos +=
+            rest.len() - rest.trim_start_matches([' ', '\t', '\n', '\r']).len();
+    }
+
+    fn error(&self, expected: &str) -> anyhow::Error {
+        match self.peek() {
+            Some(c) => anyhow!(
+                "invalid JSON: expected {expected} at byte {}, got {c:?}",
+                self.pos
+            ),
+            None => anyhow!("invalid JSON: expected {expected}, got the end"),
+        }
+    }
+
+    fn expect(&mut self, c: char) -> Result<()> {
+        if self.peek() != Some(c) {
+            return Err(self.error(&format!("{c:?}")));
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    fn hex4(&mut self) -> Result<u32> {
+        let hex = self
+            .s
+            .get(self.pos..self.pos + 4)
+            .ok_or_else(|| self.error("4 hex digits"))?;
+        let n = u32::from_str_radix(hex, 16)
+            .map_err(|_| self.error("4 hex digits"))?;
+        self.pos += 4;
+        Ok(n)
+    }
+
+    fn string(&mut self) -> Result<String> {
+        self.expect('"')?;
+        let mut out = String::new();
+        loop {
+            let c = self.peek().ok_or_else(|| self.error("'\"'"))?;
+            self.pos += c.len_utf8();
+            match c {
+                '"' => return Ok(out),
+                '\\' => {
+                    let e = self.peek().ok_or_else(|| self.error("escape"))?;
+                    self.pos += e.len_utf8();
+                    match e {
+                        '"' | '\\' | '/' => out.push(e),
+                        'b' => out.push('\u{8}'),
+                        'f' => out.push('\u{c}'),
+                        'n' => out.push('\n'),
+                        'r' => out.push('\r'),
+                        't' => out.push('\t'),
+                        'u' => {
+                            let mut n = self.hex4()?;
+                            if (0xd800..0xdc00).contains(&n)
+                                && self.s[self.pos..].starts_with("\\u")
+                            {
+                                self.pos += 2;
+                                let low = self.hex4()?;
+                                n = 0x10000
+                                    + ((n - 0xd800) << 10)
+                                    + (low.wrapping_sub(0xdc00) & 0x3ff);
+                            }
+                            out.push(
+                                char::from_u32(n)
+                                    .unwrap_or(char::REPLACEMENT_CHARACTER),
+                            );
+                        }
+                        _ => bail!("invalid JSON: unknown escape \\{e}"),
+                    }
+                }
+                c => out.push(c),
+            }
+        }
+    }
+
+    /// Skip over a value, checking its syntax; `depth` is the number
+    /// of arrays and objects it is nested in.
+    fn skip_value(&mut self, depth: usize) -> Result<()> {
+        match self.peek() {
+            Some('"') => {
+                self.string()?;
+            }
+            Some(open @ ('{' | '[')) => {
+                if depth >= MAX_DEPTH {
+                    bail!(
+                        "invalid JSON: nested deeper than {MAX_DEPTH} levels \
+                         at byte {}",
+                        self.pos
+                    )
+                }
+                let close = if open == '{' { '}' } else { ']' };
+                self.pos += 1;
+                self.skip_whitespace();
+                if self.peek() == Some(close) {
+                    self.pos += 1;
+                    return Ok(());
+                }
+                loop {
+                    self.skip_whitespace();
+                    if open == '{' {
+                        self.string()?;
+                        self.skip_whitespace();
+                        self.expect(':')?;
+                        self.skip_whitespace();
+                    }
+                    self.skip_value(depth + 1)?;
+                    self.skip_whitespace();
+                    match self.peek() {
+                        Some(',') => self.pos += 1,
+                        Some(c) if c == close => {
+                            self.pos += 1;
+                            return Ok(());
+                        }
+                        _ => {
+                            return Err(self.error(&format!("',' or {close:?}")))
+                        }
+                    }
+                }
+            }
+            _ => {
+                let rest = &self.s[self.pos..];
+                let len = rest.len()
+                    - rest
+                        .trim_start_matches(|c: char| {
+                            c.is_ascii_alphanumeric() || "+-.".contains(c)
+                        })
+                        .len();
+                let literal = &rest[..len];
+                let is_number = literal
+                    .starts_with(|c: char| c == '-' || c.is_ascii_digit())
+                    && literal.ends_with(|c: char| c.is_ascii_digit())
+                    && literal.parse::<f64>().is_ok();
+                if !(is_number || ["true", "false", "null"].contains(&literal))
+                {
+                    return Err(self.error("a value"));
+                }
+                self.pos += len;
+            }
+        }
+        Ok(())
+    }
+
+    fn value(&mut self) -> Result<JsonValue> {
+        if self.peek() == Some('"') {
+            return Ok(JsonValue::String(self.string()?));
+        }
+        let start = self.pos;
+        self.skip_value(0)?;
+        Ok(JsonValue::Other(self.s[start..self.pos].into()))
+    }
+}
+
+/// Parse a JSON object (e.g. a line of a JSONL file), returning its
+/// members in the order given. Nested arrays and objects are only
+/// checked, and returned as their text.
+pub fn parse_json_object(s: &str) -> Result<Vec<(String, JsonValue)>> {
+    let mut p = Parser { s, pos: 0 };
+    let mut members = Vec::new();
+    p.skip_whitespace();
+    p.expect('{')?;
+    p.skip_whitespace();
+    if p.peek() == Some('}') {
+        p.pos += 1;
+    } else {
+        loop {
+            p.skip_whitespace();
+            let key = p.string()?;
+            p.skip_whitespace();
+            p.expect(':')?;
+            p.skip_whitespace();
+            members.push((key, p.value()?));
+            p.skip_whitespace();
+            match p.peek() {
+                Some(',') => p.pos += 1,
+                Some('}') => {
+                    p.pos += 1;
+                    break;
+                }
+                _ => return Err(p.error("',' or '}'")),
+            }
+        }
+    }
+    p.skip_whitespace();
+    if p.pos < s.len() {
+        return Err(p.error("the end"));
+    }
+    Ok(members)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn t_push_json_string() {
+        let t = |s| {
+            let mut out = String::new();
+            push_json_string(&mut out, s);
+            out
+        };
+        assert_eq!(t(""), "\"\"");
+        assert_eq!(t("a\"b\\c\nd"), "\"a\\\"b\\\\c\\nd\"");
+        assert_eq!(t("\u{1}ä"), "\"\\u0001ä\"");
+    }
+
+    #[test]
+    fn t_parse_json_object() {
+        let t = |s| parse_json_object(s).unwrap();
+        let string = |s: &str| JsonValue::String(s.into());
+        let other = |s: &str| JsonValue::Other(s.into());
+        assert_eq!(t(" { } "), []);
+        assert_eq!(
+            t(r#"{"a": "x\"y\u00e4\ud83d\ude00", "b":-1.5e3,"c" :null}"#),
+            [
+                ("a".into(), string("x\"yä😀")),
+                ("b".into(), other("-1.5e3")),
+                ("c".into(), other("null"))
+            ]
+        );
+        assert_eq!(
+            t(r#"{"a":[1, {"b": "]"}], "c": {}}"#),
+            [
+                ("a".into(), other(r#"[1, {"b": "]"}]"#)),
+                ("c".into(), other("{}"))
+            ]
+        );
+        assert_eq!(t(r#"{"a":true}"#)[0].1.as_str(), "true");
+        for s in [
+            "",
+            "[]",
+            "{",
+            r#"{"a"}"#,
+            r#"{"a":}"#,
+            r#"{"a":1,}"#,
+            r#"{"a":tru}"#,
+            r#"{"a":-inf}"#,
+            r#"{"a":1.}"#,
+            r#"{"a":"x}"#,
+            r#"{"a":[1 2]}"#,
+            r#"{"a":1} x"#,
+            r#"{a:1}"#,
+        ] {
+            assert!(parse_json_object(s).is_err(), "{}", s);
+        }
+        // The original text of other values is kept
+        assert_eq!(t(r#"{"a":[1 , 2]}"#)[0].1.as_str(), "[1 , 2]");
+        let nested = |depth| {
+            format!(r#"{{"a":{}{}}}"#, "[".repeat(depth), "]".repeat(depth))
+        };
+        assert_eq!(t(&nested(MAX_DEPTH))[0].1.as_str().len(), 2 * MAX_DEPTH);
+        assert!(parse_json_object(&nested(MAX_DEPTH + 1)).is_err());
+        assert!(parse_json_object(&nested(200_000)).is_err());
+    }
+}
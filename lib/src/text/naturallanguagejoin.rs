@@ -0,0 +1,29 @@
+pub trait NaturalLanguageJoin {
+    fn natural_language_join(&self) -> String;
+}
+
+impl NaturalLanguageJoin for Vec<&'static str> {
+    fn natural_language_join(&self) -> String {
+        natural_language_join_with(self, "or")
+    }
+}
+
+/// `a, b and c` with `conjunction` "and".
+pub fn natural_language_join_with<S: AsRef<str>>(
+    items: &[S],
+    conjunction: &str,
+) -> String {
+    let len = items.len();
+    let mut out = String::new();
+    for (i, item) in items.iter().enumerate() {
+        out.push_str(item.as_ref());
+        if i + 2 < len {
+            out.push_str(", ");
+        } else if i + 2 == len {
+            out.push(' ');
+            out.push_str(conjunction);
+            out.push(' ');
+        }
+    }
+    out
+}
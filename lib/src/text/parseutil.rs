@@ -0,0 +1,1095 @@
+use std::borrow::Cow;
+use std::convert::TryFrom;
+use std::time::{Duration, SystemTime};
+
+use anyhow::{anyhow, bail, Result};
+use chrono::{
+    DateTime, Datelike, FixedOffset, Local, NaiveDate, NaiveDateTime,
+    NaiveTime, TimeZone,
+};
+
+use crate::time::tai::parse_timestamp;
+
+pub fn is_all(s: &str, pred: impl Fn(char) -> bool) -> bool {
+    s.chars().all(pred)
+}
+
+pub fn is_all_white(s: &str) -> bool {
+    is_all(s, |c| c.is_ascii_whitespace())
+}
+
+// pub fn is_all_alphanum(s: &str) -> bool {
+//     is_all(s, |c| c.is_alphanumeric())
+// }
+
+pub fn key_val(s: &str) -> Option<(&str, &str)> {
+    let i = s.find(':')?;
+    Some((&s[0..i], &s[i + 1..]))
+}
+
+pub fn first_rest(s: &str) -> Option<(char, &str)> {
+    let c = s.chars().next()?;
+    Some((c, &s[c.len_utf8()..]))
+}
+
+pub fn drop_white(s: &str) -> &str {
+    // s.chars().skip_while(|c| c.is_ascii_whitespace())  but now ?  lol
+    let mut p = s;
+    while let Some((c, r)) = first_rest(p) {
+        if !c.is_ascii_whitespace() {
+            return p;
+        }
+        p = r;
+    }
+    p
+}
+
+/// drop whitespace from the end
+pub fn drop_white_end(s: &str) -> &str {
+    for (i, b) in s.bytes().rev().enumerate() {
+        if !(b as char).is_ascii_whitespace() {
+            return &s[0..s.len() - i];
+        }
+    }
+    ""
+}
+
+pub fn after_white(s: &str) -> Option<&str> {
+    let s2 = drop_white(s);
+    if std::ptr::eq(s, s2) {
+        None
+    } else {
+        Some(s2)
+    }
+}
+
+pub fn cleanwhite(s: &str) -> &str {
+    // I forgot the std function, there was one, right? Just:
+    drop_white(drop_white_end(s))
+}
+
+pub fn take_while(s: &str, pred: impl Fn(char) -> bool) -> (&str, &str) {
+    for (i, c) in s.char_indices() {
+        if !pred(c) {
+            return (&s[0..i], &s[i..]);
+        }
+    }
+    (s, "")
+}
+
+pub fn parse_hex_digit(c: char) -> Result<u32> {
+    let n = c as u32;
+    if (n >= '0' as u32) && (n <= '9' as u32) {
+        Ok(n - ('0' as u32))
+    } else if (n >= 'a' as u32) && (n <= 'f' as u32) {
+        Ok(n - ('a' as u32) + 10)
+    } else if (n >= 'A' as u32) && (n <= 'F' as u32) {
+        Ok(n - ('A' as u32) + 10)
+    } else {
+        bail!("invalid hex digit {c:?}")
+    }
+}
+
+pub fn next_hex_digit<I>(cs: &mut I) -> Result<u32>
+where
+    I: Iterator<Item = char>,
+{
+    parse_hex_digit(cs.next().ok_or_else(|| anyhow!("hex string too short"))?)
+}
+
+pub fn parse_hex<const N: usize>(s: &str) -> Result<[u8; N]> {
+    let mut r = [0; N];
+    let mut cs = s.chars();
+    for byte in r.iter_mut() {
+        let a = next_hex_digit(&mut cs)?;
+        let b = next_hex_digit(&mut cs)?;
+        *byte = (a * 16 + b) as u8;
+    }
+    Ok(r)
+}
+
+pub fn char_is_white(c: char) -> bool {
+    c.is_ascii_whitespace()
+}
+
+pub fn drop_n(s: &str, n: usize, f: impl Fn(char) -> bool) -> Result<&str> {
+    let mut p = s;
+    let mut i = 0;
+    while i < n {
+        if let Some((c, r)) = first_rest(p) {
+            if !f(c) {
+                bail!(
+                    "drop_n: non-matching character after {i} \
+                       instead of {n} characters"
+                )
+            }
+            p = r;
+        } else {
+            bail!("drop_n: end of string after {i} instead of {n} characters")
+        }
+        i += 1;
+    }
+    Ok(&s[n..])
+}
+
+pub fn parse_byte_multiplier(s: &str) -> Result<u64> {
+    if s == "B" {
+        Ok(1)
+    } else if s == "KiB" {
+        Ok(1024)
+    } else if s == "MiB" {
+        Ok(1024 * 1024)
+    } else if s == "GiB" {
+        Ok(1024 * 1024 * 1024)
+    } else if s == "TiB" {
+        Ok(1024 * 1024 * 1024 * 1024)
+    } else if s == "PiB" {
+        Ok(1024 * 1024 * 1024 * 1024 * 1024)
+    } else {
+        bail!("unknown multiplier {s:?}")
+    }
+}
+
+/// The unit suffix of a number parsed by `parse_number_with_unit` or
+/// `parse_integer_with_unit`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Unit {
+    /// 1 without a prefix, 1000^n for the decimal prefixes `k` (or
+    /// `K`), `M`, `G`, `T`, `P`, 1024^n for the binary prefixes `Ki`,
+    /// `Mi`, `Gi`, `Ti`, `Pi`.
+    pub multiplier: u64,
+    /// Whether the suffix ended in `B` (bytes).
+    pub bytes: bool,
+}
+
+/// Parse a unit suffix: an optional multiplier prefix followed by an
+/// optional `B`, e.g. `k`, `MiB`, `B`, or the empty string.
+pub fn parse_unit(s: &str) -> Result<Unit> {
+    let (prefix, bytes) = match s.strip_suffix('B') {
+        Some(prefix) => (prefix, true),
+        None => (s, false),
+    };
+    let (prefix, base) = match prefix.strip_suffix('i') {
+        Some(prefix) if !prefix.is_empty() => (prefix, 1024),
+        _ => (prefix, 1000),
+    };
+    let exponent = match prefix {
+        "" => 0,
+        "k" | "K" => 1,
+        "M" => 2,
+        "G" => 3,
+        "T" => 4,
+        "P" => 5,
+        _ => bail!("unknown unit {s:?}"),
+    };
+    Ok(Unit {
+        multiplier: u64::pow(base, exponent),
+        bytes,
+    })
+}
+
+/// A number split up by `split_number`: the value is `digits *
+/// 10^-scale`, negated if `negative`.
+struct NumberParts<'s> {
+    negative: bool,
+    digits: String,
+    scale: i32,
+    suffix: &'s str,
+}
+
+/// Split `s` into a decimal number (with `_` between digits, or `,`
+/// before groups of 3 digits in the integer part, as thousands
+/// separators, and an optional exponent) and the rest, without the
+/// whitespace inbetween.
+fn split_number(s: &str) -> Result<NumberParts<'_>> {
+    let s = cleanwhite(s);
+    let err = || anyhow!("invalid number {s:?}");
+    let (negative, rest) = match first_rest(s) {
+        Some(('-', rest)) => (true, rest),
+        Some(('+', rest)) => (false, rest),
+        _ => (false, s),
+    };
+    let bytes = rest.as_bytes();
+    let mut i = 0;
+    let mut digits = String::new();
+    let mut scale = 0;
+    let mut in_fraction = false;
+    while i < bytes.len() {
+        let b = bytes[i];
+        let prev_is_digit = i > 0 && bytes[i - 1].is_ascii_digit();
+        let next_is_digit = bytes.get(i + 1).is_some_and(u8::is_ascii_digit);
+        let is_separator = (b == b'_' && prev_is_digit && next_is_digit)
+            || (b == b','
+                && prev_is_digit
+                && !in_fraction
+                && bytes.len() >= i + 4
+                && bytes[i + 1..i + 4].iter().all(u8::is_ascii_digit)
+                && !bytes.get(i + 4).is_some_and(u8::is_ascii_digit));
+        if b.is_ascii_digit() {
+            digits.push(b as char);
+            if in_fraction {
+                scale += 1;
+            }
+        } else if b == b'.' && !in_fraction && next_is_digit {
+            in_fraction = true;
+        } else if !is_separator {
+            break;
+        }
+        i += 1;
+    }
+    if digits.is_empty() {
+        bail!(err())
+    }
+    let mut rest = &rest[i..];
+    if let Some(exp) = rest.strip_prefix(['e', 'E']) {
+        let (exp_str, after) =
+            take_while(exp, |c| c.is_ascii_digit() || c == '-' || c == '+');
+        // (Leave it as a suffix if it isn't an exponent)
+        if let Ok(exp) = exp_str.parse::<i32>() {
+            scale -= exp;
+            rest = after;
+        }
+    }
+    Ok(NumberParts {
+        negative,
+        digits,
+        scale,
+        suffix: drop_white(rest),
+    })
+}
+
+/// Parse a number with an optional unit suffix, e.g. `1_500_000`,
+/// `1,500,000`, `-2.5k`, `1e3`, `3 MiB`; returns the value with the
+/// multiplier of the unit applied, and the unit.
+pub fn parse_number_with_unit(s: &str) -> Result<(f64, Unit)> {
+    let parts = split_number(s)?;
+    let unit = parse_unit(parts.suffix)
+        .map_err(|e| anyhow!("invalid number {s:?}: {e}"))?;
+    let x: f64 = format!("{}e{}", parts.digits, -parts.scale)
+        .parse()
+        .map_err(|e| anyhow!("invalid number {s:?}: {e}"))?;
+    let x = x * unit.multiplier as f64;
+    Ok((if parts.negative { -x } else { x }, unit))
+}
+
+/// Like `parse_number_with_unit`, but the value (after applying the
+/// unit's multiplier) must be an integer that fits an i64, e.g.
+/// `2.5k` is fine but `2.5` is an error. Calculated exactly.
+pub fn parse_integer_with_unit(s: &str) -> Result<(i64, Unit)> {
+    let parts = split_number(s)?;
+    let unit = parse_unit(parts.suffix)
+        .map_err(|e| anyhow!("invalid integer {s:?}: {e}"))?;
+    let out_of_range = || anyhow!("integer {s:?} is out of range");
+    let digits = parts.digits.trim_start_matches('0');
+    if digits.len() > 38 {
+        bail!(out_of_range())
+    }
+    let mut x: i128 = if digits.is_empty() {
+        0
+    } else {
+        digits.parse().map_err(|_| out_of_range())?
+    };
+    x = x
+        .checked_mul(unit.multiplier.into())
+        .ok_or_else(out_of_range)?;
+    if parts.scale < 0 {
+        let factor = 10i128
+            .checked_pow((-parts.scale) as u32)
+            .ok_or_else(out_of_range)?;
+        x = x.checked_mul(factor).ok_or_else(out_of_range)?;
+    } else if parts.scale > 0 && x != 0 {
+        let divisor = 10i128
+            .checked_pow(parts.scale as u32)
+            .ok_or_else(|| anyhow!("{s:?} is not an integer"))?;
+        if x % divisor != 0 {
+            bail!("{s:?} is not an integer")
+        }
+        x /= divisor;
+    }
+    if parts.negative {
+        x = -x;
+    }
+    Ok((i64::try_from(x).map_err(|_| out_of_range())?, unit))
+}
+
+/// A line of indented `key: value` style output (as from `wg`, or
+/// `ip -s link`), with the more deeply indented lines following it as
+/// its children.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyValNode {
+    /// The part before the first `:`, or the whole line (without
+    /// surrounding whitespace) if it doesn't contain a `:`.
+    pub key: String,
+    /// The part after the first `:` without surrounding whitespace,
+    /// None if the line doesn't contain a `:`.
+    pub value: Option<String>,
+    pub children: Vec<KeyValNode>,
+}
+
+impl KeyValNode {
+    /// The first child with the given key.
+    pub fn child(&self, key: &str) -> Option<&KeyValNode> {
+        self.children.iter().find(|c| c.key == key)
+    }
+
+    /// The value of the first child with the given key.
+    pub fn child_value(&self, key: &str) -> Option<&str> {
+        self.child(key)?.value.as_deref()
+    }
+}
+
+/// Parse `lines` into a tree of `KeyValNode`s according to their
+/// indentation (the number of leading whitespace characters). Lines
+/// consisting only of whitespace are ignored (they don't end
+/// blocks). Returns the top-level nodes.
+pub fn parse_key_val_blocks<'s>(
+    lines: impl IntoIterator<Item = &'s str>,
+) -> Vec<KeyValNode> {
+    let mut roots = Vec::new();
+    // The chain of currently open nodes, with their indentation.
+    let mut open: Vec<(usize, KeyValNode)> = Vec::new();
+    fn close(open: &mut Vec<(usize, KeyValNode)>, roots: &mut Vec<KeyValNode>) {
+        let (_, node) = open.pop().expect("only called when non-empty");
+        match open.last_mut() {
+            Some((_, parent)) => parent.children.push(node),
+            None => roots.push(node),
+        }
+    }
+    for line in lines {
+        if is_all_white(line) {
+            continue;
+        }
+        let rest = drop_white(line);
+        let indent = line.len() - rest.len();
+        while open.last().is_some_and(|(i, _)| *i >= indent) {
+            close(&mut open, &mut roots);
+        }
+        let (key, value) = match key_val(rest) {
+            Some((k, v)) => (cleanwhite(k), Some(cleanwhite(v).to_string())),
+            None => (cleanwhite(rest), None),
+        };
+        open.push((
+            indent,
+            KeyValNode {
+                key: key.to_string(),
+                value,
+                children: Vec::new(),
+            },
+        ));
+    }
+    while !open.is_empty() {
+        close(&mut open, &mut roots);
+    }
+    roots
+}
+
+/// A byte range within a line, for error messages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    /// The same span within a string in which the tokenized line
+    /// starts at byte `offset`.
+    pub fn shifted(self, offset: usize) -> Span {
+        Span {
+            start: self.start + offset,
+            end: self.end + offset,
+        }
+    }
+}
+
+impl std::fmt::Display for Span {
+    /// 1-based columns (counting bytes).
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "columns {}-{}", self.start + 1, self.end)
+    }
+}
+
+/// A line as split by `LineTokenizer`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LineToken<'s> {
+    /// The nesting depth: 0 for the least indented lines, 1 for lines
+    /// indented more than the last line at level 0, etc.
+    pub level: usize,
+    /// The number of leading whitespace bytes.
+    pub indent: usize,
+    /// The part before the separator (or the whole line if there is
+    /// none), without surrounding whitespace.
+    pub key: &'s str,
+    pub key_span: Span,
+    pub separator: Option<char>,
+    /// The part after the separator without surrounding whitespace
+    /// (empty if there is no separator).
+    pub value: &'s str,
+    pub value_span: Span,
+}
+
+/// Splits lines of indented `key: value` style output (as from `wg`
+/// or `systemctl status`) one at a time, tracking the indentation
+/// levels.
+#[derive(Debug, Clone)]
+pub struct LineTokenizer {
+    separators: Vec<char>,
+    /// The indentations of the currently open levels.
+    indents: Vec<usize>,
+}
+
+impl LineTokenizer {
+    /// A line is split at the first occurrence of any of `separators`.
+    pub fn new(separators: &[char]) -> Self {
+        LineTokenizer {
+            separators: separators.into(),
+            indents: Vec::new(),
+        }
+    }
+
+    /// Split the next line; None for lines consisting only of
+    /// whitespace (which don't affect the levels). Gives an error if
+    /// the line is dedented to an indentation between those of two open
+    /// levels (the levels stay unchanged then).
+    pub fn next_line<'s>(
+        &mut self,
+        line: &'s str,
+    ) -> Result<Option<LineToken<'s>>> {
+        if is_all_white(line) {
+            return Ok(None);
+        }
+        let indent = line.len() - drop_white(line).len();
+        let level = match self.indents.iter().position(|i| *i >= indent) {
+            None => {
+                self.indents.push(indent);
+                self.indents.len() - 1
+            }
+            Some(0) if self.indents[0] > indent => {
+                // Less indented than all lines before: a new outermost
+                // level (e.g. if the input started in the middle of a
+                // block)
+                self.indents = vec![indent];
+                0
+            }
+            Some(level) => {
+                if self.indents[level] != indent {
+                    bail!(
+                        "indentation by {indent} does not match any outer \
+                         level"
+                    )
+                }
+                self.indents.truncate(level + 1);
+                level
+            }
+        };
+        let span_of = |part: &str| {
+            let start = part.as_ptr() as usize - line.as_ptr() as usize;
+            Span {
+                start,
+                end: start + part.len(),
+            }
+        };
+        let (key, separator, value) =
+            match line.find(|c| self.separators.contains(&c)) {
+                Some(i) => {
+                    let sep = line[i..].chars().next().expect("found");
+                    let value = cleanwhite(&line[i + sep.len_utf8()..]);
+                    (cleanwhite(&line[..i]), Some(sep), value)
+                }
+                None => (cleanwhite(line), None, &line[line.len()..]),
+            };
+        Ok(Some(LineToken {
+            level,
+            indent,
+            key,
+            key_span: span_of(key),
+            separator,
+            value,
+            value_span: span_of(value),
+        }))
+    }
+}
+
+/// How lines are normalized before comparing them (e.g. for
+/// deduplication or set operations).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LineNormalization {
+    /// Ignore leading and trailing whitespace.
+    pub trim: bool,
+    /// Compare lower-cased.
+    pub ignore_case: bool,
+}
+
+impl LineNormalization {
+    /// The key to compare `line` by.
+    pub fn normalize<'s>(&self, line: &'s str) -> Cow<'s, str> {
+        let line = if self.trim { cleanwhite(line) } else { line };
+        if self.ignore_case {
+            Cow::Owned(line.to_lowercase())
+        } else {
+            Cow::Borrowed(line)
+        }
+    }
+}
+
+/// How fields are delimited and quoted within a line, for
+/// `split_fields`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FieldSyntax {
+    pub delimiter: char,
+    /// Fields starting with this character extend up to the next
+    /// (non-doubled) occurrence of it, and may contain the
+    /// delimiter. Within them, the quote character is written
+    /// twice.
+    pub quote: Option<char>,
+    /// The character following this one is taken literally (both
+    /// inside and outside of quotes).
+    pub escape: Option<char>,
+}
+
+impl FieldSyntax {
+    /// Comma separated, `"` quoted, no escape character (RFC 4180,
+    /// except that fields can't contain newlines since we work on
+    /// lines).
+    pub fn csv() -> Self {
+        FieldSyntax {
+            delimiter: ',',
+            quote: Some('"'),
+            escape: None,
+        }
+    }
+
+    /// Tab separated, without quoting.
+    pub fn tsv() -> Self {
+        FieldSyntax {
+            delimiter: '\t',
+            quote: None,
+            escape: None,
+        }
+    }
+}
+
+/// Split `line` into fields according to `syntax`. Gives an error
+/// for unterminated quotes, characters between a closing quote and
+/// the next delimiter, and an escape character at the end of the
+/// line.
+pub fn split_fields(line: &str, syntax: &FieldSyntax) -> Result<Vec<String>> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut cs = line.char_indices().peekable();
+    // Whether we are at the start of a field.
+    let mut at_start = true;
+    // Whether the current field was quoted and the closing quote
+    // was seen.
+    let mut after_quote = false;
+    while let Some((i, c)) = cs.next() {
+        if c == syntax.delimiter {
+            fields.push(std::mem::take(&mut field));
+            at_start = true;
+            after_quote = false;
+            continue;
+        }
+        if after_quote {
+            bail!("character {c:?} after closing quote at position {i}")
+        }
+        if Some(c) == syntax.escape {
+            let (_, c2) = cs
+                .next()
+                .ok_or_else(|| anyhow!("escape character at end of line"))?;
+            field.push(c2);
+        } else if at_start && Some(c) == syntax.quote {
+            loop {
+                match cs.next() {
+                    None => {
+                        bail!("unterminated quote starting at position {i}")
+                    }
+                    Some((_, c2)) if Some(c2) == syntax.escape => {
+                        let (_, c3) = cs.next().ok_or_else(|| {
+                            anyhow!("escape character at end of line")
+                        })?;
+                        field.push(c3);
+                    }
+                    Some((_, c2)) if c2 == c => {
+                        if cs.peek().is_some_and(|(_, c3)| *c3 == c) {
+                            cs.next();
+                            field.push(c);
+                        } else {
+                            break;
+                        }
+                    }
+                    Some((_, c2)) => field.push(c2),
+                }
+            }
+            after_quote = true;
+        } else {
+            field.push(c);
+        }
+        at_start = false;
+    }
+    fields.push(field);
+    Ok(fields)
+}
+
+/// The timestamp formats recognized by `detect_and_parse_timestamp`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimestampFormat {
+    /// `@` and 24 hex digits (daemontools)
+    Tai64N,
+    /// `2024-01-02T03:04:05`, optionally with a space instead of the
+    /// `T`, fractional seconds, and a zone (`Z`, `+01:00` or
+    /// `+0100`); local time if without zone.
+    Iso8601,
+    /// `Jan  2 03:04:05`, local time, the year is guessed (the
+    /// current one, or the previous one if that would be in the
+    /// future).
+    Syslog,
+    /// Unix time in seconds, optionally with a fraction.
+    EpochSeconds,
+    /// Unix time in milliseconds (12 or more digits).
+    EpochMillis,
+}
+
+fn digits_at(s: &[u8], pos: usize, n: usize) -> bool {
+    s.len() >= pos + n && s[pos..pos + n].iter().all(|c| c.is_ascii_digit())
+}
+
+/// The length of the fractional seconds part (including the `.`) at
+/// the start of `s`, 0 if there is none.
+fn fraction_len(s: &[u8]) -> usize {
+    if s.first() == Some(&b'.') {
+        let n = s[1..].iter().take_while(|c| c.is_ascii_digit()).count();
+        if n > 0 {
+            return n + 1;
+        }
+    }
+    0
+}
+
+/// After the timestamp, which ends at `end`, there must be whitespace
+/// (one character of which is dropped) or the end of the line.
+fn timestamp_rest(s: &str, end: usize) -> Option<&str> {
+    let rest = &s[end..];
+    match first_rest(rest) {
+        None => Some(rest),
+        Some((c, r)) if char_is_white(c) => Some(r),
+        Some(_) => None,
+    }
+}
+
+fn local_to_system_time(t: NaiveDateTime) -> Result<SystemTime> {
+    let t = Local
+        .from_local_datetime(&t)
+        .earliest()
+        .ok_or_else(|| anyhow!("local time {t} does not exist"))?;
+    Ok(t.into())
+}
+
+fn parse_iso8601(s: &str) -> Option<Result<(SystemTime, &str)>> {
+    let b = s.as_bytes();
+    if !(digits_at(b, 0, 4)
+        && b.get(4) == Some(&b'-')
+        && digits_at(b, 5, 2)
+        && b.get(7) == Some(&b'-')
+        && digits_at(b, 8, 2)
+        && matches!(b.get(10), Some(b'T') | Some(b' '))
+        && digits_at(b, 11, 2)
+        && b.get(13) == Some(&b':')
+        && digits_at(b, 14, 2)
+        && b.get(16) == Some(&b':')
+        && digits_at(b, 17, 2))
+    {
+        return None;
+    }
+    let time_end = 19 + fraction_len(&b[19..]);
+    let (zone_end, offset_seconds) = match b.get(time_end) {
+        Some(b'Z') => (time_end + 1, Some(0)),
+        Some(sign @ (b'+' | b'-')) => {
+            let p = time_end + 1;
+            let (mm, end) = if b.get(p + 2) == Some(&b':') {
+                (p + 3, p + 5)
+            } else {
+                (p + 2, p + 4)
+            };
+            if !(digits_at(b, p, 2) && digits_at(b, mm, 2)) {
+                return None;
+            }
+            let hours: i32 = s[p..p + 2].parse().expect("digits");
+            let minutes: i32 = s[mm..mm + 2].parse().expect("digits");
+            let seconds = (hours * 60 + minutes) * 60;
+            (end, Some(if *sign == b'-' { -seconds } else { seconds }))
+        }
+        _ => (time_end, None),
+    };
+    let rest = timestamp_rest(s, zone_end)?;
+    Some((|| {
+        let t = NaiveDateTime::parse_from_str(
+            &format!("{}T{}", &s[0..10], &s[11..time_end]),
+            "%Y-%m-%dT%H:%M:%S%.f",
+        )?;
+        let st = match offset_seconds {
+            Some(offset) => {
+                let offset = FixedOffset::east_opt(offset)
+                    .ok_or_else(|| anyhow!("invalid zone offset"))?;
+                let t = offset
+                    .from_local_datetime(&t)
+                    .single()
+                    .expect("fixed offsets are unambiguous");
+                t.into()
+            }
+            None => local_to_system_time(t)?,
+        };
+        Ok((st, rest))
+    })())
+}
+
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct",
+    "Nov", "Dec",
+];
+
+fn parse_syslog(
+    s: &str,
+    now: DateTime<Local>,
+) -> Option<Result<(SystemTime, &str)>> {
+    let b = s.as_bytes();
+    let month = MONTHS.iter().position(|m| s.starts_with(m))? as u32 + 1;
+    // "Jan  2" or "Jan 12"
+    if !(b.get(3) == Some(&b' ')
+        && (b.get(4) == Some(&b' ') || digits_at(b, 4, 1))
+        && digits_at(b, 5, 1)
+        && b.get(6) == Some(&b' ')
+        && digits_at(b, 7, 2)
+        && b.get(9) == Some(&b':')
+        && digits_at(b, 10, 2)
+        && b.get(12) == Some(&b':')
+        && digits_at(b, 13, 2))
+    {
+        return None;
+    }
+    let rest = timestamp_rest(s, 15)?;
+    Some((|| {
+        let day: u32 = s[4..6].trim_start().parse()?;
+        let time = NaiveTime::parse_from_str(&s[7..15], "%H:%M:%S")?;
+        let at_year = |year| -> Result<SystemTime> {
+            let date = NaiveDate::from_ymd_opt(year, month, day)
+                .ok_or_else(|| anyhow!("invalid date in {:?}", &s[..15]))?;
+            local_to_system_time(date.and_time(time))
+        };
+        let t = at_year(now.year())?;
+        // Allow for clock differences between machines
+        if t > SystemTime::from(now) + Duration::from_secs(86400) {
+            Ok((at_year(now.year() - 1)?, rest))
+        } else {
+            Ok((t, rest))
+        }
+    })())
+}
+
+fn parse_epoch(s: &str) -> Option<Result<(SystemTime, TimestampFormat, &str)>> {
+    let b = s.as_bytes();
+    let n = b.iter().take_while(|c| c.is_ascii_digit()).count();
+    if n == 0 {
+        return None;
+    }
+    let end = n + fraction_len(&b[n..]);
+    let rest = timestamp_rest(s, end)?;
+    // The first `digits` digits of the fraction, as an integer
+    let frac_digits = if end > n { &s[n + 1..end] } else { "" };
+    let fraction_units = |digits: usize| -> u64 {
+        let mut padded: String = frac_digits.chars().take(digits).collect();
+        while padded.len() < digits {
+            padded.push('0');
+        }
+        padded.parse().unwrap_or(0)
+    };
+    Some((|| {
+        let int: u64 = s[..n].parse()?;
+        let (format, duration) = if n >= 12 {
+            (
+                TimestampFormat::EpochMillis,
+                Duration::from_millis(int)
+                    + Duration::from_nanos(fraction_units(6)),
+            )
+        } else {
+            (
+                TimestampFormat::EpochSeconds,
+                Duration::from_secs(int)
+                    + Duration::from_nanos(fraction_units(9)),
+            )
+        };
+        let t = SystemTime::UNIX_EPOCH
+            .checked_add(duration)
+            .ok_or_else(|| anyhow!("timestamp out of range"))?;
+        Ok((t, format, rest))
+    })())
+}
+
+/// Like `detect_and_parse_timestamp`, but with `now` for guessing the
+/// year of syslog timestamps.
+pub fn detect_and_parse_timestamp_at(
+    s: &str,
+    now: DateTime<Local>,
+) -> Result<(SystemTime, TimestampFormat, &str)> {
+    if s.starts_with('@') {
+        let (t, rest) = parse_timestamp(s)?;
+        return Ok((t.to_system_time(), TimestampFormat::Tai64N, rest));
+    }
+    if let Some(r) = parse_iso8601(s) {
+        let (t, rest) = r?;
+        return Ok((t, TimestampFormat::Iso8601, rest));
+    }
+    if let Some(r) = parse_syslog(s, now) {
+        let (t, rest) = r?;
+        return Ok((t, TimestampFormat::Syslog, rest));
+    }
+    if let Some(r) = parse_epoch(s) {
+        return r;
+    }
+    bail!("no recognized timestamp at the start of the line")
+}
+
+/// Recognize and parse the timestamp at the start of line `s`, in any
+/// of the `TimestampFormat`s. Returns the time, the format, and the
+/// rest of the line after the timestamp and one whitespace
+/// character.
+pub fn detect_and_parse_timestamp(
+    s: &str,
+) -> Result<(SystemTime, TimestampFormat, &str)> {
+    detect_and_parse_timestamp_at(s, Local::now())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn t_drop_white() {
+        fn t(inp: &str, outp: &str) {
+            assert_eq!(drop_white(inp), outp)
+        }
+        t("foo", "foo");
+        t("  foo", "foo");
+        t("foo  ", "foo  ");
+        t(" foo  ", "foo  ");
+        t(" f oo  ", "f oo  ");
+        t("  ", "");
+    }
+
+    #[test]
+    fn t_drop_white_end() {
+        fn t(inp: &str, outp: &str) {
+            assert_eq!(drop_white_end(inp), outp)
+        }
+        t("foo", "foo");
+        t("  foo", "  foo");
+        t("foo  ", "foo");
+        t(" foo  ", " foo");
+        t(" f oo  ", " f oo");
+        t("  ", "");
+    }
+
+    #[test]
+    fn t_cleanwhite() {
+        fn t(inp: &str, outp: &str) {
+            assert_eq!(cleanwhite(inp), outp)
+        }
+        t("foo", "foo");
+        t("  foo", "foo");
+        t("foo  ", "foo");
+        t(" foo  ", "foo");
+        t(" f oo  ", "f oo");
+        t("  ", "");
+    }
+
+    #[test]
+    fn t_parse_number_with_unit() {
+        let unit = |multiplier, bytes| Unit { multiplier, bytes };
+        let f = |s| parse_number_with_unit(s).unwrap();
+        assert_eq!(f("1_500_000"), (1.5e6, unit(1, false)));
+        assert_eq!(f(" 1,500,000.25 "), (1500000.25, unit(1, false)));
+        assert_eq!(f("-2.5k"), (-2500., unit(1000, false)));
+        assert_eq!(f("3 MiB"), (3. * 1048576., unit(1048576, true)));
+        assert_eq!(f("1e3B"), (1000., unit(1, true)));
+        assert_eq!(f(".5"), (0.5, unit(1, false)));
+        let i = |s| parse_integer_with_unit(s).map_err(|e| e.to_string());
+        assert_eq!(i("2.5k"), Ok((2500, unit(1000, false))));
+        assert_eq!(i("+1_024 KiB"), Ok((1048576, unit(1024, true))));
+        assert_eq!(i("-0.000"), Ok((0, unit(1, false))));
+        assert_eq!(i("1.5e2"), Ok((150, unit(1, false))));
+        assert_eq!(i("2.5"), Err("\"2.5\" is not an integer".into()));
+        assert_eq!(
+            i("10000 PiB"),
+            Err("integer \"10000 PiB\" is out of range".into())
+        );
+        for s in ["", "k", "1__0", "1_", "1,50", "1.", "1x", "3 MB/s", "1e"] {
+            assert!(parse_number_with_unit(s).is_err(), "{}", s);
+        }
+        assert_eq!(parse_unit("KiB").unwrap(), unit(1024, true));
+        assert!(parse_unit("i").is_err());
+    }
+
+    #[test]
+    fn t_split_fields() {
+        let t = |line: &str, syntax: &FieldSyntax| {
+            split_fields(line, syntax).map_err(|e| e.to_string())
+        };
+        let csv = FieldSyntax::csv();
+        assert_eq!(t("a,b,,c", &csv).unwrap(), ["a", "b", "", "c"]);
+        assert_eq!(t("", &csv).unwrap(), [""]);
+        assert_eq!(t("a,", &csv).unwrap(), ["a", ""]);
+        assert_eq!(
+            t(r#""a,b",c"d,"e""f""#, &csv).unwrap(),
+            ["a,b", "c\"d", "e\"f"]
+        );
+        assert!(t(r#""a,b"#, &csv).unwrap_err().contains("unterminated"));
+        assert!(t(r#""a"b,c"#, &csv).unwrap_err().contains("after closing"));
+
+        let esc = FieldSyntax {
+            escape: Some('\\'),
+            ..FieldSyntax::tsv()
+        };
+        assert_eq!(t("a\\\tb\tc\\\\", &esc).unwrap(), ["a\tb", "c\\"]);
+        assert!(t("a\\", &esc).is_err());
+        assert_eq!(t("\"a\"\tb", &FieldSyntax::tsv()).unwrap(), ["\"a\"", "b"]);
+    }
+
+    #[test]
+    fn t_parse_key_val_blocks() {
+        let input = "interface: wg0
+  public key: abc=
+  listening port: 51820
+
+peer: def=
+  endpoint: 10.0.0.1:51820
+  transfer: 1.5 KiB received, 2 KiB sent
+2: eth0: <UP>
+    RX: bytes packets
+    100 2
+";
+        let nodes = parse_key_val_blocks(input.lines());
+        assert_eq!(nodes.len(), 3);
+        assert_eq!(nodes[0].key, "interface");
+        assert_eq!(nodes[0].value.as_deref(), Some("wg0"));
+        assert_eq!(nodes[0].child_value("listening port"), Some("51820"));
+        assert_eq!(nodes[1].children.len(), 2);
+        assert_eq!(nodes[1].child_value("endpoint"), Some("10.0.0.1:51820"));
+        assert_eq!(nodes[2].key, "2");
+        assert_eq!(nodes[2].value.as_deref(), Some("eth0: <UP>"));
+        let rx = nodes[2].child("RX").unwrap();
+        assert_eq!(rx.value.as_deref(), Some("bytes packets"));
+        // same indentation as "RX:", thus a sibling
+        assert_eq!(nodes[2].children[1].key, "100 2");
+        assert_eq!(nodes[2].children[1].value, None);
+    }
+
+    #[test]
+    fn t_line_tokenizer() {
+        let mut t = LineTokenizer::new(&[':']);
+        let tok = t.next_line("interface: wg0").unwrap().unwrap();
+        assert_eq!((tok.level, tok.key, tok.value), (0, "interface", "wg0"));
+        assert_eq!(tok.separator, Some(':'));
+        assert_eq!(tok.value_span, Span { start: 11, end: 14 });
+        assert_eq!(t.next_line("  ").unwrap(), None);
+        let tok = t.next_line("  public key: x ").unwrap().unwrap();
+        assert_eq!((tok.level, tok.indent, tok.key), (1, 2, "public key"));
+        assert_eq!(tok.key_span.to_string(), "columns 3-12");
+        assert_eq!(tok.value_span.shifted(10).to_string(), "columns 25-25");
+        let tok = t.next_line("    deeper").unwrap().unwrap();
+        assert_eq!((tok.level, tok.key, tok.value), (2, "deeper", ""));
+        assert_eq!(tok.separator, None);
+        assert_eq!(tok.value_span, Span { start: 10, end: 10 });
+        assert!(t.next_line(" bad").is_err());
+        assert_eq!(t.next_line("  again").unwrap().unwrap().level, 1);
+        assert_eq!(t.next_line("peer: p0").unwrap().unwrap().level, 0);
+        let mut t = LineTokenizer::new(&[':', '=']);
+        assert_eq!(t.next_line("  a = 1").unwrap().unwrap().level, 0);
+        let tok = t.next_line("b").unwrap().unwrap();
+        assert_eq!((tok.level, tok.separator), (0, None));
+        assert_eq!(t.next_line("  c=d:e").unwrap().unwrap().value, "d:e");
+    }
+
+    #[test]
+    fn t_detect_and_parse_timestamp() {
+        use TimestampFormat::*;
+        let unix =
+            |secs: u64| SystemTime::UNIX_EPOCH + Duration::from_secs(secs);
+        let local = |s: &str| {
+            local_to_system_time(
+                NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S").unwrap(),
+            )
+            .unwrap()
+        };
+        let now = Local.with_ymd_and_hms(2024, 3, 10, 12, 0, 0).unwrap();
+        let t = |s| detect_and_parse_timestamp_at(s, now).unwrap();
+
+        assert_eq!(
+            t("@400000006553f10a0000007b x"),
+            (
+                parse_timestamp("@400000006553f10a0000007b x")
+                    .unwrap()
+                    .0
+                    .to_system_time(),
+                Tai64N,
+                "x"
+            )
+        );
+        assert_eq!(
+            t("2023-11-14T22:13:20Z a b"),
+            (unix(1700000000), Iso8601, "a b")
+        );
+        assert_eq!(
+            t("2023-11-14 23:13:20.5+01:00 a"),
+            (unix(1700000000) + Duration::from_millis(500), Iso8601, "a")
+        );
+        assert_eq!(
+            t("2023-11-14T22:13:20-0130"),
+            (unix(1700005400), Iso8601, "")
+        );
+        assert_eq!(
+            t("2023-11-14T22:13:20 a"),
+            (local("2023-11-14 22:13:20"), Iso8601, "a")
+        );
+        assert_eq!(
+            t("Mar  9 08:01:02 host x"),
+            (local("2024-03-09 08:01:02"), Syslog, "host x")
+        );
+        assert_eq!(
+            t("Dec 24 08:01:02 host"),
+            (local("2023-12-24 08:01:02"), Syslog, "host")
+        );
+        assert_eq!(t("1700000000 x"), (unix(1700000000), EpochSeconds, "x"));
+        assert_eq!(
+            t("1700000000.25"),
+            (
+                unix(1700000000) + Duration::from_millis(250),
+                EpochSeconds,
+                ""
+            )
+        );
+        assert_eq!(
+            t("1700000000123 x"),
+            (
+                unix(1700000000) + Duration::from_millis(123),
+                EpochMillis,
+                "x"
+            )
+        );
+
+        let e = |s| detect_and_parse_timestamp_at(s, now).is_err();
+        assert!(e("12ab x"));
+        assert!(e("hello"));
+        assert!(e("2023-13-14T22:13:20Z"));
+        assert!(e("Feb 30 08:01:02 x"));
+        assert!(e("2023-11-14T22:13:20Zx"));
+    }
+
+    #[test]
+    fn t_line_normalization() {
+        let n = LineNormalization::default();
+        assert_eq!(n.normalize(" Ab "), " Ab ");
+        let n = LineNormalization {
+            trim: true,
+            ignore_case: true,
+        };
+        assert_eq!(n.normalize(" Ab\t"), "ab");
+    }
+}
@@ -0,0 +1,99 @@
+pub trait StartsWith<I> {
+    fn starts_with(&mut self, start: &mut I) -> Option<usize>;
+}
+
+impl<T: Eq, I: Iterator<Item = T>> StartsWith<I> for I {
+    fn starts_with(&mut self, start: &mut I) -> Option<usize> {
+        let mut len: usize = 0;
+        loop {
+            if let Some(b) = start.next() {
+                if let Some(a) = self.next() {
+                    if a != b {
+                        return None;
+                    } else {
+                        len += 1;
+                    }
+                } else {
+                    return None;
+                }
+            } else {
+                return Some(len);
+            }
+        }
+    }
+}
+
+/// Whether the byte string `s` starts with `start` (like
+/// `str::starts_with`, for binary-safe processing).
+pub fn bytes_starts_with(s: &[u8], start: &[u8]) -> bool {
+    bytes_strip_start(s, start).is_some()
+}
+
+/// The rest of `s` after `start`, if it starts with it.
+pub fn bytes_strip_start<'s>(s: &'s [u8], start: &[u8]) -> Option<&'s [u8]> {
+    s.strip_prefix(start)
+}
+
+/// Whether `s` starts with `start`, ignoring the case of ASCII
+/// letters (other bytes have to be equal).
+pub fn bytes_starts_with_ignore_ascii_case(s: &[u8], start: &[u8]) -> bool {
+    bytes_strip_start_ignore_ascii_case(s, start).is_some()
+}
+
+/// The rest of `s` after `start`, if it starts with it ignoring the
+/// case of ASCII letters.
+pub fn bytes_strip_start_ignore_ascii_case<'s>(
+    s: &'s [u8],
+    start: &[u8],
+) -> Option<&'s [u8]> {
+    if s.len() >= start.len() && s[..start.len()].eq_ignore_ascii_case(start) {
+        Some(&s[start.len()..])
+    } else {
+        None
+    }
+}
+
+/// Whether `s` starts with `start`, ignoring the case of ASCII
+/// letters.
+pub fn starts_with_ignore_ascii_case(s: &str, start: &str) -> bool {
+    bytes_starts_with_ignore_ascii_case(s.as_bytes(), start.as_bytes())
+}
+
+/// The rest of `s` after `start`, if it starts with it ignoring the
+/// case of ASCII letters.
+pub fn strip_start_ignore_ascii_case<'s>(
+    s: &'s str,
+    start: &str,
+) -> Option<&'s str> {
+    // Non-ASCII bytes have to match exactly, thus the match ends on a
+    // character boundary
+    bytes_strip_start_ignore_ascii_case(s.as_bytes(), start.as_bytes())
+        .map(|rest| &s[s.len() - rest.len()..])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn t_starts_with() {
+        assert_eq!("abc".chars().starts_with(&mut "ab".chars()), Some(2));
+        assert_eq!("abc".chars().starts_with(&mut "ac".chars()), None);
+        assert!(bytes_starts_with(b"ab\xffc", b"ab\xff"));
+        assert!(!bytes_starts_with(b"ab", b"abc"));
+        assert_eq!(bytes_strip_start(b"abc", b"a"), Some(&b"bc"[..]));
+    }
+
+    #[test]
+    fn t_ignore_ascii_case() {
+        assert!(bytes_starts_with_ignore_ascii_case(
+            b"Content-Type",
+            b"content-"
+        ));
+        assert!(!bytes_starts_with_ignore_ascii_case(b"Con", b"content"));
+        assert!(starts_with_ignore_ascii_case("ÄBc", "Äb"));
+        assert!(!starts_with_ignore_ascii_case("äbc", "Äb"));
+        assert_eq!(strip_start_ignore_ascii_case("ÄBc", "Äb"), Some("c"));
+        assert_eq!(strip_start_ignore_ascii_case("x", "xy"), None);
+    }
+}
@@ -0,0 +1,173 @@
+//! Writing simple line charts as SVG, without dependencies.
+
+use std::io::{self, Write};
+
+pub struct Series {
+    pub name: String,
+    /// (x, y) pairs, sorted by x.
+    pub points: Vec<(f64, f64)>,
+}
+
+pub struct LineChart<'t> {
+    pub title: &'t str,
+    pub width: u32,
+    pub height: u32,
+    /// Format x values for the axis labels.
+    pub format_x: &'t dyn Fn(f64) -> String,
+    /// Format y values for the axis labels.
+    pub format_y: &'t dyn Fn(f64) -> String,
+}
+
+const COLORS: &[&str] = &[
+    "#1f77b4", "#ff7f0e", "#2ca02c", "#d62728", "#9467bd", "#8c564b",
+    "#e377c2", "#7f7f7f",
+];
+
+const MARGIN_LEFT: f64 = 80.;
+const MARGIN_RIGHT: f64 = 20.;
+const MARGIN_TOP: f64 = 40.;
+const MARGIN_BOTTOM: f64 = 50.;
+const Y_TICKS: usize = 5;
+
+fn push_xml_escaped(out: &mut String, s: &str) {
+    for c in s.chars() {
+        match c {
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '&' => out.push_str("&amp;"),
+            '"' => out.push_str("&quot;"),
+            _ => out.push(c),
+        }
+    }
+}
+
+impl<'t> LineChart<'t> {
+    /// Write `series` as one chart, one line per series, the y axis
+    /// starting at 0.
+    pub fn write(
+        &self,
+        out: &mut impl Write,
+        series: &[Series],
+    ) -> io::Result<()> {
+        let (w, h) = (self.width as f64, self.height as f64);
+        let plot_w = w - MARGIN_LEFT - MARGIN_RIGHT;
+        let plot_h = h - MARGIN_TOP - MARGIN_BOTTOM;
+        let points = || series.iter().flat_map(|s| s.points.iter());
+        let x_min = points().map(|p| p.0).fold(f64::INFINITY, f64::min);
+        let x_max = points().map(|p| p.0).fold(f64::NEG_INFINITY, f64::max);
+        let y_max = points().map(|p| p.1).fold(0., f64::max);
+        let x_range = if x_max > x_min { x_max - x_min } else { 1. };
+        let y_range = if y_max > 0. { y_max } else { 1. };
+        let sx = |x: f64| MARGIN_LEFT + (x - x_min) / x_range * plot_w;
+        let sy = |y: f64| MARGIN_TOP + plot_h - y / y_range * plot_h;
+
+        let mut s = String::new();
+        s.push_str(&format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" \
+             height=\"{h}\" font-family=\"sans-serif\" font-size=\"12\">\n\
+             <rect width=\"100%\" height=\"100%\" fill=\"white\"/>\n"
+        ));
+        s.push_str(&format!(
+            "<text x=\"{}\" y=\"24\" text-anchor=\"middle\" \
+             font-size=\"16\">",
+            w / 2.
+        ));
+        push_xml_escaped(&mut s, self.title);
+        s.push_str("</text>\n");
+
+        // Axes and y grid
+        s.push_str(&format!(
+            "<polyline fill=\"none\" stroke=\"black\" \
+             points=\"{l},{t} {l},{b} {r},{b}\"/>\n",
+            l = MARGIN_LEFT,
+            t = MARGIN_TOP,
+            b = MARGIN_TOP + plot_h,
+            r = MARGIN_LEFT + plot_w
+        ));
+        for i in 0..=Y_TICKS {
+            let y = y_range * i as f64 / Y_TICKS as f64;
+            let py = sy(y);
+            if i > 0 {
+                s.push_str(&format!(
+                    "<line x1=\"{}\" y1=\"{py}\" x2=\"{}\" y2=\"{py}\" \
+                     stroke=\"#ddd\"/>\n",
+                    MARGIN_LEFT,
+                    MARGIN_LEFT + plot_w
+                ));
+            }
+            s.push_str(&format!(
+                "<text x=\"{}\" y=\"{}\" text-anchor=\"end\">",
+                MARGIN_LEFT - 6.,
+                py + 4.
+            ));
+            push_xml_escaped(&mut s, &(self.format_y)(y));
+            s.push_str("</text>\n");
+        }
+        if x_min.is_finite() {
+            for (x, anchor) in [(x_min, "start"), (x_max, "end")] {
+                s.push_str(&format!(
+                    "<text x=\"{}\" y=\"{}\" text-anchor=\"{anchor}\">",
+                    sx(x),
+                    MARGIN_TOP + plot_h + 20.
+                ));
+                push_xml_escaped(&mut s, &(self.format_x)(x));
+                s.push_str("</text>\n");
+            }
+        }
+
+        // Lines and legend
+        for (i, serie) in series.iter().enumerate() {
+            let color = COLORS[i % COLORS.len()];
+            s.push_str(&format!(
+                "<polyline fill=\"none\" stroke=\"{color}\" \
+                 stroke-width=\"1.5\" points=\""
+            ));
+            for (j, (x, y)) in serie.points.iter().enumerate() {
+                if j > 0 {
+                    s.push(' ');
+                }
+                s.push_str(&format!("{:.1},{:.1}", sx(*x), sy(*y)));
+            }
+            s.push_str("\"/>\n");
+            s.push_str(&format!(
+                "<text x=\"{}\" y=\"{}\" fill=\"{color}\">",
+                MARGIN_LEFT + 10. + 100. * i as f64,
+                h - 10.
+            ));
+            push_xml_escaped(&mut s, &serie.name);
+            s.push_str("</text>\n");
+        }
+        s.push_str("</svg>\n");
+        out.write_all(s.as_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn t_write_line_chart() {
+        let chart = LineChart {
+            title: "a<b",
+            width: 400,
+            height: 300,
+            format_x: &|x| format!("{x}"),
+            format_y: &|y| format!("{y}"),
+        };
+        let series = [Series {
+            name: "wg0".into(),
+            points: vec![(0., 0.), (10., 5.)],
+        }];
+        let mut out = Vec::new();
+        chart.write(&mut out, &series).unwrap();
+        let svg = String::from_utf8(out).unwrap();
+        assert!(svg.starts_with("<svg "));
+        assert!(svg.contains(">a&lt;b</text>"));
+        // plot area is 300x210 starting at (80, 40)
+        assert!(svg.contains("points=\"80.0,250.0 380.0,40.0\""));
+        assert!(svg.ends_with("</svg>\n"));
+        // no data
+        chart.write(&mut Vec::new(), &[]).unwrap();
+    }
+}
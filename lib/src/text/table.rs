@@ -0,0 +1,179 @@
+//! Printing rows of fields as an aligned table for terminals.
+
+use std::borrow::Cow;
+use std::io::{self, Write};
+
+#[derive(Debug, Clone)]
+pub struct TableOptions {
+    /// Treat the first row as a header (emphasized if `color` is
+    /// true, and not considered for numeric alignment).
+    pub header: bool,
+    /// Truncate fields to this many characters (marking truncation
+    /// with `…`).
+    pub max_width: Option<usize>,
+    /// Use ANSI escapes for emphasis.
+    pub color: bool,
+    /// Right-align columns whose (non-header, non-empty) fields are
+    /// all numbers.
+    pub align_numbers: bool,
+    /// Printed between columns.
+    pub separator: String,
+}
+
+impl Default for TableOptions {
+    fn default() -> Self {
+        TableOptions {
+            header: false,
+            max_width: None,
+            color: false,
+            align_numbers: true,
+            separator: "  ".into(),
+        }
+    }
+}
+
+/// The width of `s` on the terminal. Counts chars, which is wrong for
+/// wide (e.g. CJK) and combining characters.
+pub fn display_width(s: &str) -> usize {
+    s.chars().count()
+}
+
+/// `s` truncated to at most `max_width` chars, with the last one
+/// replaced by `…` if truncated.
+pub fn truncate(s: &str, max_width: usize) -> Cow<'_, str> {
+    if display_width(s) <= max_width {
+        s.into()
+    } else if max_width == 0 {
+        "".into()
+    } else {
+        let mut t: String = s.chars().take(max_width - 1).collect();
+        t.push('…');
+        t.into()
+    }
+}
+
+pub fn is_number(s: &str) -> bool {
+    let s = s.trim();
+    !s.is_empty() && s.parse::<f64>().is_ok()
+}
+
+fn cell<'s, S: AsRef<str>>(
+    row: &'s [S],
+    i: usize,
+    opts: &TableOptions,
+) -> Cow<'s, str> {
+    let s = row.get(i).map(|s| s.as_ref()).unwrap_or("");
+    match opts.max_width {
+        Some(w) => truncate(s, w),
+        None => s.into(),
+    }
+}
+
+/// Print `rows` with aligned columns. Rows may have different
+/// numbers of fields; missing fields are treated as empty. Trailing
+/// whitespace is not printed.
+pub fn print_table<S: AsRef<str>>(
+    out: &mut impl Write,
+    rows: &[Vec<S>],
+    opts: &TableOptions,
+) -> io::Result<()> {
+    let num_columns = rows.iter().map(|r| r.len()).max().unwrap_or(0);
+    let body_start = if opts.header { 1 } else { 0 };
+    let mut widths = vec![0; num_columns];
+    let mut numeric = vec![opts.align_numbers; num_columns];
+    for (rowi, row) in rows.iter().enumerate() {
+        for (i, (width, numeric)) in
+            widths.iter_mut().zip(numeric.iter_mut()).enumerate()
+        {
+            let c = cell(row, i, opts);
+            *width = (*width).max(display_width(&c));
+            if rowi >= body_start && !c.is_empty() && !is_number(&c) {
+                *numeric = false;
+            }
+        }
+    }
+
+    for (rowi, row) in rows.iter().enumerate() {
+        let is_header = rowi < body_start;
+        let mut line = String::new();
+        // Collect padding for the last non-empty column lazily, to
+        // avoid trailing whitespace
+        let mut pending_pad = String::new();
+        for i in 0..num_columns {
+            let c = cell(row, i, opts);
+            let pad = widths[i] - display_width(&c);
+            if i > 0 {
+                pending_pad.push_str(&opts.separator);
+            }
+            if c.is_empty() {
+                pending_pad.extend(std::iter::repeat_n(' ', pad));
+                continue;
+            }
+            line.push_str(&pending_pad);
+            pending_pad.clear();
+            let right_align = numeric[i] && !is_header;
+            if right_align {
+                line.extend(std::iter::repeat_n(' ', pad));
+            }
+            if is_header && opts.color {
+                line.push_str("\x1b[1;4m");
+                line.push_str(&c);
+                line.push_str("\x1b[0m");
+            } else {
+                line.push_str(&c);
+            }
+            if !right_align {
+                pending_pad.extend(std::iter::repeat_n(' ', pad));
+            }
+        }
+        line.push('\n');
+        out.write_all(line.as_bytes())?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn t(rows: &[&[&str]], opts: &TableOptions) -> String {
+        let rows: Vec<Vec<&str>> = rows.iter().map(|r| r.to_vec()).collect();
+        let mut out = Vec::new();
+        print_table(&mut out, &rows, opts).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn t_print_table() {
+        let opts = TableOptions {
+            header: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            t(
+                &[&["name", "n", "x"], &["a", "1", ""], &["bbb", "100", "z"]],
+                &opts
+            ),
+            "name  n    x\n\
+             a       1\n\
+             bbb   100  z\n"
+        );
+        // ragged rows, non-numeric column stays left-aligned
+        assert_eq!(
+            t(&[&["a", "1x"], &["bb"], &["c", "2"]], &Default::default()),
+            "a   1x\nbb\nc   2\n"
+        );
+    }
+
+    #[test]
+    fn t_truncate() {
+        assert_eq!(truncate("hello", 5), "hello");
+        assert_eq!(truncate("hello", 4), "hel…");
+        assert_eq!(truncate("hello", 0), "");
+        let opts = TableOptions {
+            max_width: Some(3),
+            ..Default::default()
+        };
+        assert_eq!(t(&[&["abcdef", "x"]], &opts), "ab…  x\n");
+    }
+}
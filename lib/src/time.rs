@@ -1,2 +1,3 @@
 pub mod excel;
+pub mod realtime;
 pub mod tai;
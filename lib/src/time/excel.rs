@@ -0,0 +1,596 @@
+//! Translate between unixtime and Excel date-time values (days since
+//! Excel's epoch), format numbers the way Excel displays them, write
+//! CSV files that Excel opens correctly when double-clicked, and read
+//! files exported from spreadsheets by their header names.
+
+use std::borrow::Cow;
+use std::io::{BufRead, Write};
+use std::str::FromStr;
+
+use anyhow::{anyhow, bail, Context, Error, Result};
+
+use crate::alist::AListBuf;
+use crate::text::parseutil::{split_fields, FieldSyntax};
+
+const DAYS_AT_EPOCH: f64 = 25569.;
+
+/// offset_hours: `1.0` represents `+01:00`.
+pub fn exceldays_from_unixtime(unixtime: f64, offset_hours: f64) -> f64 {
+    unixtime / 86400. + (DAYS_AT_EPOCH + offset_hours / 24.)
+}
+
+/// offset_hours: `1.0` represents `+01:00`.
+pub fn unixtime_from_exceldays(exceldays: f64, offset_hours: f64) -> f64 {
+    (exceldays - (DAYS_AT_EPOCH + offset_hours / 24.)) * 86400.
+}
+
+/// Format `x` like Excel's General number format does in a cell wide
+/// enough for it: rounded to 15 significant digits, without trailing
+/// zeros, in scientific notation (`1.5E+15`, `1E-10`) if the
+/// magnitude is 1e15 or more, or less than 1e-9. NaN and infinities
+/// (which Excel can't represent) give `#NUM!`.
+pub fn format_general(x: f64) -> String {
+    if !x.is_finite() {
+        return "#NUM!".into();
+    }
+    if x == 0. {
+        return "0".into();
+    }
+    // "-d.dddddddddddddde-5"
+    let s = format!("{:.14e}", x);
+    let (mantissa, exponent) = s.split_once('e').expect("has exponent");
+    let exponent: i32 = exponent.parse().expect("valid exponent");
+    let (sign, mantissa) = match mantissa.strip_prefix('-') {
+        Some(m) => ("-", m),
+        None => ("", mantissa),
+    };
+    let digits: String = mantissa.chars().filter(|c| *c != '.').collect();
+    let digits = digits.trim_end_matches('0');
+    if !(-9..15).contains(&exponent) {
+        let (first, rest) = digits.split_at(1);
+        let point = if rest.is_empty() { "" } else { "." };
+        let expsign = if exponent < 0 { '-' } else { '+' };
+        format!("{sign}{first}{point}{rest}E{expsign}{:02}", exponent.abs())
+    } else if exponent < 0 {
+        let zeros = "0".repeat((-exponent - 1) as usize);
+        format!("{sign}0.{zeros}{digits}")
+    } else {
+        let intlen = exponent as usize + 1;
+        if digits.len() <= intlen {
+            let zeros = "0".repeat(intlen - digits.len());
+            format!("{sign}{digits}{zeros}")
+        } else {
+            let (int, frac) = digits.split_at(intlen);
+            format!("{sign}{int}.{frac}")
+        }
+    }
+}
+
+/// The field separator Excel expects in CSV files, which depends on
+/// the locale's list separator: `Comma` for English locales,
+/// `Semicolon` for those with a decimal comma (e.g. German), for
+/// which numbers are also written with a decimal comma.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CsvSeparator {
+    Comma,
+    Semicolon,
+}
+
+impl FromStr for CsvSeparator {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "comma" => Ok(CsvSeparator::Comma),
+            "semicolon" => Ok(CsvSeparator::Semicolon),
+            _ => {
+                bail!("unknown CSV separator {s:?}, expecting comma|semicolon")
+            }
+        }
+    }
+}
+
+impl CsvSeparator {
+    pub fn as_char(self) -> char {
+        match self {
+            CsvSeparator::Comma => ',',
+            CsvSeparator::Semicolon => ';',
+        }
+    }
+}
+
+/// Written at the start of CSV files, without it Excel decodes them
+/// in the system's legacy code page instead of UTF-8.
+pub const UTF8_BOM: &str = "\u{feff}";
+
+fn is_digits(s: &str) -> bool {
+    !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit())
+}
+
+/// Whether `s` is a number as Rust formats them (`-12.5`, `1e-7`).
+fn is_number(s: &str) -> bool {
+    let s = s.strip_prefix('-').unwrap_or(s);
+    let (mantissa, exponent) = match s.split_once(['e', 'E']) {
+        Some((m, e)) => (m, Some(e)),
+        None => (s, None),
+    };
+    let mantissa_ok = match mantissa.split_once('.') {
+        Some((int, frac)) => is_digits(int) && is_digits(frac),
+        None => is_digits(mantissa),
+    };
+    mantissa_ok
+        && exponent
+            .is_none_or(|e| is_digits(e.strip_prefix(['-', '+']).unwrap_or(e)))
+}
+
+/// Append a CSV record with `fields` to `line`, terminated with CRLF.
+/// Fields are quoted (with `"` doubled) if they contain the
+/// separator, `"` or line breaks. `at_file_start`: the record is the
+/// first in the file, `UTF8_BOM` is prepended, and a first field
+/// starting with `ID` is quoted (Excel takes such files to be in the
+/// SYLK format otherwise). Note that quoting doesn't keep Excel from
+/// converting values that look like numbers or dates (`007`, `1-2`).
+pub fn push_csv_record<S: AsRef<str>>(
+    line: &mut String,
+    fields: impl IntoIterator<Item = S>,
+    separator: CsvSeparator,
+    at_file_start: bool,
+) {
+    let sep = separator.as_char();
+    if at_file_start {
+        line.push_str(UTF8_BOM);
+    }
+    for (i, field) in fields.into_iter().enumerate() {
+        let field = field.as_ref();
+        if i > 0 {
+            line.push(sep);
+        }
+        let field: Cow<str> =
+            if separator == CsvSeparator::Semicolon && is_number(field) {
+                Cow::Owned(field.replace('.', ","))
+            } else {
+                Cow::Borrowed(field)
+            };
+        let needs_quotes = field.contains([sep, '"', '\n', '\r'])
+            || (at_file_start && i == 0 && field.starts_with("ID"));
+        if needs_quotes {
+            line.push('"');
+            line.push_str(&field.replace('"', "\"\""));
+            line.push('"');
+        } else {
+            line.push_str(&field);
+        }
+    }
+    line.push_str("\r\n");
+}
+
+/// Writes CSV for Excel (see `push_csv_record`) to `out`. Also
+/// implements `Write`, for code that writes TSV: each line written
+/// is split at tabs and written as a record (an unterminated last
+/// line is dropped).
+pub struct ExcelCsvWriter<W: Write> {
+    out: W,
+    separator: CsvSeparator,
+    at_file_start: bool,
+    line: String,
+    /// The unterminated TSV line written via `Write`.
+    partial: Vec<u8>,
+}
+
+impl<W: Write> ExcelCsvWriter<W> {
+    /// For a new file (starting it with `UTF8_BOM`).
+    pub fn new(out: W, separator: CsvSeparator) -> Self {
+        ExcelCsvWriter {
+            out,
+            separator,
+            at_file_start: true,
+            line: String::new(),
+            partial: Vec::new(),
+        }
+    }
+
+    /// For appending to a file that already contains records.
+    pub fn appending(out: W, separator: CsvSeparator) -> Self {
+        ExcelCsvWriter {
+            at_file_start: false,
+            ..ExcelCsvWriter::new(out, separator)
+        }
+    }
+
+    pub fn write_record<S: AsRef<str>>(
+        &mut self,
+        fields: impl IntoIterator<Item = S>,
+    ) -> std::io::Result<()> {
+        self.line.clear();
+        push_csv_record(
+            &mut self.line,
+            fields,
+            self.separator,
+            self.at_file_start,
+        );
+        self.at_file_start = false;
+        self.out.write_all(self.line.as_bytes())
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+}
+
+impl<W: Write> Write for ExcelCsvWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let mut rest = buf;
+        while let Some(i) = rest.iter().position(|b| *b == b'\n') {
+            self.partial.extend_from_slice(&rest[..i]);
+            let tsv = std::mem::take(&mut self.partial);
+            let tsv = String::from_utf8_lossy(&tsv);
+            self.write_record(
+                tsv.strip_suffix('\r').unwrap_or(&tsv).split('\t'),
+            )?;
+            rest = &rest[i + 1..];
+        }
+        self.partial.extend_from_slice(rest);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.out.flush()
+    }
+}
+
+/// What Excel writes when saving as "Text (Tab delimited)": fields
+/// containing tabs, quotes or line breaks are quoted with `"`.
+pub fn exported_tsv_syntax() -> FieldSyntax {
+    FieldSyntax {
+        quote: Some('"'),
+        ..FieldSyntax::tsv()
+    }
+}
+
+/// A data row read by `HeaderRecords`: the fields by header name, in
+/// the order of the columns.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Record {
+    /// The line number in the input (starting at 1 for the header)
+    /// where the row starts.
+    pub linenumber: usize,
+    pub fields: AListBuf<String, String>,
+}
+
+impl Record {
+    /// The field in the column with the header `column`.
+    pub fn str(&self, column: &str) -> Result<&str> {
+        self.fields
+            .0
+            .iter()
+            .find(|(k, _)| k == column)
+            .map(|(_, v)| v.as_str())
+            .ok_or_else(|| anyhow!("unknown column {column:?}"))
+    }
+
+    /// The field in `column` as a number, also accepting a decimal
+    /// comma (as exported in locales using it) and surrounding
+    /// whitespace.
+    pub fn f64(&self, column: &str) -> Result<f64> {
+        let s = self.str(column)?.trim();
+        let s: Cow<str> = if s.contains(',') && !s.contains('.') {
+            Cow::Owned(s.replacen(',', ".", 1))
+        } else {
+            Cow::Borrowed(s)
+        };
+        s.parse().with_context(|| {
+            anyhow!(
+                "line {}, column {column:?}: invalid number {s:?}",
+                self.linenumber
+            )
+        })
+    }
+
+    /// The field in `column` as an Excel date-time value (see `f64`),
+    /// converted to unixtime; `offset_hours` as for
+    /// `unixtime_from_exceldays`.
+    pub fn excel_date(&self, column: &str, offset_hours: f64) -> Result<f64> {
+        Ok(unixtime_from_exceldays(self.f64(column)?, offset_hours))
+    }
+}
+
+/// Reads delimited data with a header row, e.g. TSV exported from a
+/// spreadsheet (see `exported_tsv_syntax`), yielding the following
+/// rows as `Record`s. A UTF-8 BOM at the start and CRLF line endings
+/// are accepted, empty lines are skipped; rows must have as many
+/// fields as the header. Quoted fields may contain line breaks (a row
+/// then continues on the following lines).
+pub struct HeaderRecords<R: BufRead> {
+    inp: R,
+    /// For error messages, e.g. `file "foo.tsv"`.
+    name: String,
+    syntax: FieldSyntax,
+    headers: Vec<String>,
+    /// The number of lines read.
+    linenumber: usize,
+    /// The line number where the last row read starts.
+    row_linenumber: usize,
+    line: String,
+}
+
+/// Whether `line` ends within a quoted field.
+fn has_open_quote(line: &str, syntax: &FieldSyntax) -> bool {
+    let quote = match syntax.quote {
+        Some(quote) => quote,
+        None => return false,
+    };
+    let mut in_quote = false;
+    let mut at_start = true;
+    let mut cs = line.chars().peekable();
+    while let Some(c) = cs.next() {
+        if Some(c) == syntax.escape {
+            cs.next();
+            at_start = false;
+        } else if in_quote {
+            if c == quote {
+                if cs.peek() == Some(&quote) {
+                    cs.next();
+                } else {
+                    in_quote = false;
+                }
+            }
+        } else if c == syntax.delimiter {
+            at_start = true;
+        } else {
+            in_quote = at_start && c == quote;
+            at_start = false;
+        }
+    }
+    in_quote
+}
+
+impl<R: BufRead> HeaderRecords<R> {
+    /// Reads the header row. Fails if the input is empty, or headers
+    /// are empty or duplicated.
+    pub fn new(
+        inp: R,
+        name: impl Into<String>,
+        syntax: FieldSyntax,
+    ) -> Result<Self> {
+        let mut records = HeaderRecords {
+            inp,
+            name: name.into(),
+            syntax,
+            headers: Vec::new(),
+            linenumber: 0,
+            row_linenumber: 0,
+            line: String::new(),
+        };
+        let headers = records
+            .read_fields()?
+            .ok_or_else(|| anyhow!("{}: missing header row", records.name))?;
+        for (i, header) in headers.iter().enumerate() {
+            let context = || anyhow!("{} line 1", records.name);
+            if header.is_empty() {
+                return Err(anyhow!("column {} has no header", i + 1))
+                    .with_context(context);
+            }
+            if headers[..i].contains(header) {
+                return Err(anyhow!("duplicate header {header:?}"))
+                    .with_context(context);
+            }
+        }
+        records.headers = headers;
+        Ok(records)
+    }
+
+    pub fn headers(&self) -> &[String] {
+        &self.headers
+    }
+
+    /// The fields of the next non-empty row (continuing over
+    /// multiple lines while a quoted field is open), None at EOF.
+    fn read_fields(&mut self) -> Result<Option<Vec<String>>> {
+        loop {
+            self.line.clear();
+            self.row_linenumber = self.linenumber + 1;
+            let (name, linenumber) = (&self.name, self.row_linenumber);
+            let context = || anyhow!("{name} line {linenumber}");
+            let line = loop {
+                self.linenumber += 1;
+                if self.inp.read_line(&mut self.line).with_context(context)?
+                    == 0
+                {
+                    if self.line.is_empty() {
+                        return Ok(None);
+                    }
+                    // (unterminated quote, reported by split_fields)
+                    break self.line.as_str();
+                }
+                let mut line = self.line.as_str();
+                if self.row_linenumber == 1 {
+                    line = line.strip_prefix(UTF8_BOM).unwrap_or(line);
+                }
+                let line = line.strip_suffix('\n').unwrap_or(line);
+                let line = line.strip_suffix('\r').unwrap_or(line);
+                if !has_open_quote(line, &self.syntax) {
+                    break line;
+                }
+            };
+            if line.is_empty() {
+                continue;
+            }
+            return split_fields(line, &self.syntax)
+                .map(Some)
+                .with_context(context);
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for HeaderRecords<R> {
+    type Item = Result<Record>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let fields = match self.read_fields() {
+            Ok(fields) => fields?,
+            Err(e) => return Some(Err(e)),
+        };
+        if fields.len() != self.headers.len() {
+            return Some(Err(anyhow!(
+                "{} line {}: {} fields, but {} headers",
+                self.name,
+                self.row_linenumber,
+                fields.len(),
+                self.headers.len()
+            )));
+        }
+        Some(Ok(Record {
+            linenumber: self.row_linenumber,
+            fields: AListBuf(
+                self.headers.iter().cloned().zip(fields).collect(),
+            ),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_relative_eq;
+
+    #[test]
+    fn t_() {
+        fn t(ut: f64, et: f64) {
+            assert_relative_eq!(exceldays_from_unixtime(ut, 0.), et);
+            assert_relative_eq!(ut, unixtime_from_exceldays(et, 0.));
+            assert_relative_eq!(exceldays_from_unixtime(ut, 1.), et + 1. / 24.);
+            assert_relative_eq!(ut, unixtime_from_exceldays(et + 1. / 24., 1.));
+        }
+        t(1538352000., 43374.);
+    }
+
+    #[test]
+    fn t_format_general() {
+        let t = |x: f64, s: &str| {
+            assert_eq!(format_general(x), s);
+            // Round-trips to 15 significant digits
+            if x.is_finite() && x != 0. {
+                let back: f64 = s.replace('E', "e").parse().unwrap();
+                assert_relative_eq!(back, x, max_relative = 1e-14);
+            }
+        };
+        t(0., "0");
+        t(-0., "0");
+        t(1., "1");
+        t(-1.5, "-1.5");
+        t(0.1 + 0.2, "0.3");
+        t(1. / 3., "0.333333333333333");
+        t(2. / 3., "0.666666666666667");
+        t(43374.5, "43374.5");
+        t(100., "100");
+        t(1234567.125, "1234567.125");
+        t(999999999999999., "999999999999999");
+        t(1e15, "1E+15");
+        t(9.999999999999999e14, "1E+15");
+        t(123456789012345678., "1.23456789012346E+17");
+        t(-2.5e20, "-2.5E+20");
+        t(0.000000001, "0.000000001");
+        t(0.00012345, "0.00012345");
+        t(1e-10, "1E-10");
+        t(1.25e-100, "1.25E-100");
+        t(f64::NAN, "#NUM!");
+        t(f64::INFINITY, "#NUM!");
+    }
+
+    #[test]
+    fn t_csv() -> Result<()> {
+        let t = |separator, fields: &[&str], at_file_start| {
+            let mut line = String::new();
+            push_csv_record(&mut line, fields, separator, at_file_start);
+            line
+        };
+        use CsvSeparator::*;
+        assert_eq!(
+            t(Comma, &["a", "b,c", "say \"hi\"", "x\ny", ""], false),
+            "a,\"b,c\",\"say \"\"hi\"\"\",\"x\ny\",\r\n"
+        );
+        assert_eq!(t(Comma, &["1.5", "a;b"], false), "1.5,a;b\r\n");
+        assert_eq!(
+            t(
+                Semicolon,
+                &["1.5", "-2e-7", "a;b", "1,5", "v1.2", "1."],
+                false
+            ),
+            "1,5;-2e-7;\"a;b\";1,5;v1.2;1.\r\n"
+        );
+        assert_eq!(t(Comma, &["ID", "ID"], true), "\u{feff}\"ID\",ID\r\n");
+        assert_eq!(t(Comma, &["ID"], false), "ID\r\n");
+
+        let mut w = ExcelCsvWriter::new(Vec::new(), Semicolon);
+        w.write_record(["name", "value"])?;
+        write!(w, "a b\t0.25\nc")?;
+        writeln!(w, ";d\t3")?;
+        w.flush()?;
+        assert_eq!(
+            String::from_utf8(w.into_inner())?,
+            "\u{feff}name;value\r\na b;0,25\r\n\"c;d\";3\r\n"
+        );
+        let mut w = ExcelCsvWriter::appending(Vec::new(), Comma);
+        writeln!(w, "1\t2")?;
+        assert_eq!(String::from_utf8(w.into_inner())?, "1,2\r\n");
+
+        assert!("tab".parse::<CsvSeparator>().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn t_header_records() -> Result<()> {
+        let tsv = "\u{feff}name\tprice\tdate\r\n\
+                   \"a \"\"b\"\"\tc\"\t1,5\t43374.5\r\n\
+                   \r\n\
+                   d\t 2.25 \t43374\r\n";
+        let mut records =
+            HeaderRecords::new(tsv.as_bytes(), "test", exported_tsv_syntax())?;
+        assert_eq!(records.headers(), ["name", "price", "date"]);
+        let r = records.next().unwrap()?;
+        assert_eq!(r.linenumber, 2);
+        assert_eq!(r.str("name")?, "a \"b\"\tc");
+        assert_eq!(r.f64("price")?, 1.5);
+        assert_eq!(r.excel_date("date", 0.)?, 1538352000. + 43200.);
+        assert!(r.str("Name").is_err());
+        assert!(r.f64("name").is_err());
+        let r = records.next().unwrap()?;
+        assert_eq!(r.linenumber, 4);
+        assert_eq!(r.f64("price")?, 2.25);
+        assert!(records.next().is_none());
+
+        let read = |s: &'static str| -> Result<Vec<Record>> {
+            HeaderRecords::new(s.as_bytes(), "test", FieldSyntax::tsv())?
+                .collect()
+        };
+        let e = read("a\tb\n1\t2\n3\n").unwrap_err();
+        assert_eq!(e.to_string(), "test line 3: 1 fields, but 2 headers");
+        assert!(read("").is_err());
+        assert!(read("a\t\n").is_err());
+        assert!(read("a\ta\n").is_err());
+        assert_eq!(read("a\n")?, []);
+
+        // Line breaks in quoted fields
+        let tsv = "a\tb\r\n\"x\r\n\"\"y\"\"\n\tz\"\t1\r\n2\t3\r\n";
+        let mut records =
+            HeaderRecords::new(tsv.as_bytes(), "test", exported_tsv_syntax())?;
+        let r = records.next().unwrap()?;
+        assert_eq!(r.linenumber, 2);
+        assert_eq!(r.str("a")?, "x\r\n\"y\"\n\tz");
+        assert_eq!(r.str("b")?, "1");
+        let r = records.next().unwrap()?;
+        assert_eq!(r.linenumber, 5);
+        assert_eq!(r.str("a")?, "2");
+        assert!(records.next().is_none());
+        let e = HeaderRecords::new(
+            "a\n\"x\ny\n".as_bytes(),
+            "test",
+            exported_tsv_syntax(),
+        )?
+        .next()
+        .unwrap()
+        .unwrap_err();
+        assert!(e.to_string().starts_with("test line 2"), "{}", e);
+        Ok(())
+    }
+}
@@ -0,0 +1,216 @@
+//! Parsing points in time and durations, and sleeping until a point
+//! in (wall clock) time.
+
+use std::convert::TryFrom;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, NaiveTime, TimeZone};
+use tai64::Tai64N;
+
+use crate::text::parseutil::parse_hex;
+
+/// Parse a duration like `90`, `90s`, `5m`, `1h`, `2d`, `1w`
+/// (seconds if no unit is given; fractional values like `1.5h` are
+/// allowed).
+pub fn parse_duration(s: &str) -> Result<Duration> {
+    let numlen = s
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(s.len());
+    let (num, unit) = s.split_at(numlen);
+    let num: f64 = num
+        .parse()
+        .with_context(|| anyhow!("invalid number in duration {s:?}"))?;
+    let multiplier = match unit {
+        "" | "s" => 1,
+        "m" | "min" => 60,
+        "h" => 3600,
+        "d" => 24 * 3600,
+        "w" => 7 * 24 * 3600,
+        _ => bail!("unknown unit {unit:?} in duration {s:?}"),
+    };
+    Duration::try_from_secs_f64(num * multiplier as f64)
+        .with_context(|| anyhow!("duration out of range: {s:?}"))
+}
+
+fn local_to_system_time(dt: NaiveDateTime) -> Result<SystemTime> {
+    let t: DateTime<Local> = Local
+        .from_local_datetime(&dt)
+        .earliest()
+        .ok_or_else(|| anyhow!("non-existing local time {dt}"))?;
+    Ok(t.into())
+}
+
+/// Parse a point in time given as:
+///
+///  - Unix time in seconds, e.g. `1700000000` or `1700000000.5`
+///  - TAI64N label, e.g. `@4000000065...` (at least 24 hex digits)
+///  - RFC 3339, e.g. `2024-05-01T12:00:00+02:00`
+///  - RFC 2822, e.g. `Wed, 01 May 2024 12:00:00 +0200` (as in the
+///    TSV files written by `netcounters`)
+///  - local date and time, `2024-05-01 12:00[:00]` or with `T`
+///  - local date, `2024-05-01` (meaning midnight)
+///  - local time of day, `12:00[:00]`, meaning the next such time
+///    after `now`
+///  - a duration (see `parse_duration`) followed by ` ago`, e.g.
+///    `15min ago` or `2 h ago`.
+pub fn parse_time_spec(s: &str, now: SystemTime) -> Result<SystemTime> {
+    if let Some(duration) = s.strip_suffix(" ago") {
+        let duration: String =
+            duration.chars().filter(|c| !c.is_whitespace()).collect();
+        return now
+            .checked_sub(parse_duration(&duration)?)
+            .ok_or_else(|| anyhow!("time out of range: {s:?}"));
+    }
+    if let Some(hex) = s.strip_prefix('@') {
+        if hex.len() < 24 {
+            bail!("TAI64N label is too short: {s:?}")
+        }
+        let bytes: [u8; 12] = parse_hex(hex)?;
+        return Ok(Tai64N::from_slice(&bytes)?.to_system_time());
+    }
+    if !s.is_empty() && s.chars().all(|c| c.is_ascii_digit() || c == '.') {
+        let secs: f64 = s.parse()?;
+        return Duration::try_from_secs_f64(secs)
+            .ok()
+            .and_then(|d| UNIX_EPOCH.checked_add(d))
+            .ok_or_else(|| anyhow!("time out of range: {s:?}"));
+    }
+    if let Ok(t) = DateTime::parse_from_rfc3339(s) {
+        return Ok(t.into());
+    }
+    if let Ok(t) = DateTime::parse_from_rfc2822(s) {
+        return Ok(t.into());
+    }
+    for format in [
+        "%Y-%m-%d %H:%M:%S",
+        "%Y-%m-%dT%H:%M:%S",
+        "%Y-%m-%d %H:%M",
+        "%Y-%m-%dT%H:%M",
+    ] {
+        if let Ok(dt) = NaiveDateTime::parse_from_str(s, format) {
+            return local_to_system_time(dt);
+        }
+    }
+    if let Ok(d) = NaiveDate::parse_from_str(s, "%Y-%m-%d") {
+        return local_to_system_time(d.and_hms_opt(0, 0, 0).unwrap());
+    }
+    for format in ["%H:%M:%S", "%H:%M"] {
+        if let Ok(time) = NaiveTime::parse_from_str(s, format) {
+            let today = DateTime::<Local>::from(now).date_naive();
+            let t = local_to_system_time(today.and_time(time))?;
+            return if t > now {
+                Ok(t)
+            } else {
+                local_to_system_time(
+                    today
+                        .succ_opt()
+                        .expect("not the end of time")
+                        .and_time(time),
+                )
+            };
+        }
+    }
+    bail!("can't parse time specification {s:?}")
+}
+
+/// The next point in time after `now` that is a multiple of
+/// `interval` since the Unix epoch, plus `offset`. (Aligning to an
+/// interval of a day thus means midnight UTC.)
+pub fn next_aligned(
+    now: SystemTime,
+    interval: Duration,
+    offset: Duration,
+) -> Result<SystemTime> {
+    let interval = interval.as_nanos();
+    if interval == 0 {
+        bail!("interval must be greater than zero")
+    }
+    let t = now
+        .duration_since(UNIX_EPOCH)
+        .with_context(|| anyhow!("time before the Unix epoch"))?
+        .as_nanos();
+    let offset = offset.as_nanos() % interval;
+    let next = t + interval - (t + interval - offset) % interval;
+    let next = u64::try_from(next).with_context(|| anyhow!("time overflow"))?;
+    Ok(UNIX_EPOCH + Duration::from_nanos(next))
+}
+
+/// Sleep until the wall clock (CLOCK_REALTIME) reaches `target`. The
+/// sleeps themselves are measured on the monotonic clock, so to
+/// handle changes of the wall clock (and suspend), the sleep is done
+/// in steps of at most `max_step`, re-checking the wall clock in
+/// between.
+pub fn sleep_until(target: SystemTime, max_step: Duration) {
+    loop {
+        match target.duration_since(SystemTime::now()) {
+            Ok(remaining) if !remaining.is_zero() => {
+                std::thread::sleep(remaining.min(max_step))
+            }
+            _ => return,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn t_parse_duration() {
+        let t = |s| parse_duration(s).unwrap().as_secs_f64();
+        assert_eq!(t("90"), 90.);
+        assert_eq!(t("90s"), 90.);
+        assert_eq!(t("5m"), 300.);
+        assert_eq!(t("1.5h"), 5400.);
+        assert_eq!(t("1d"), 86400.);
+        assert!(parse_duration("1y").is_err());
+        assert!(parse_duration("h").is_err());
+        assert!(parse_duration(&format!("{}w", "9".repeat(30))).is_err());
+    }
+
+    #[test]
+    fn t_parse_time_spec() {
+        let now = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let t = |s| {
+            parse_time_spec(s, now)
+                .unwrap()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_secs()
+        };
+        assert_eq!(t("1700000123"), 1_700_000_123);
+        assert_eq!(t("2023-11-14T22:13:20Z"), 1_700_000_000);
+        assert_eq!(t("2023-11-14T23:13:20+01:00"), 1_700_000_000);
+        assert_eq!(t("Tue, 14 Nov 2023 23:13:20 +0100"), 1_700_000_000);
+        assert_eq!(t("@400000006553f10a00000000"), 1_700_000_000);
+        let tod = t("12:00");
+        assert!(tod > 1_700_000_000 && tod <= 1_700_000_000 + 24 * 3600);
+        assert_eq!(t("15min ago"), 1_700_000_000 - 900);
+        assert_eq!(t("2 h ago"), 1_700_000_000 - 7200);
+        assert!(parse_time_spec("foo", now).is_err());
+        assert!(parse_time_spec("foo ago", now).is_err());
+        assert!(parse_time_spec(&"9".repeat(30), now).is_err());
+        assert!(parse_time_spec(&"9".repeat(400), now).is_err());
+    }
+
+    #[test]
+    fn t_next_aligned() {
+        let t = |now: u64, interval: u64, offset: u64| {
+            next_aligned(
+                UNIX_EPOCH + Duration::from_secs(now),
+                Duration::from_secs(interval),
+                Duration::from_secs(offset),
+            )
+            .unwrap()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+        };
+        assert_eq!(t(7100, 3600, 0), 7200);
+        assert_eq!(t(7200, 3600, 0), 10800);
+        assert_eq!(t(7100, 3600, 60), 7260);
+        assert_eq!(t(7250, 3600, 60), 7260);
+        assert_eq!(t(7260, 3600, 60), 10860);
+    }
+}
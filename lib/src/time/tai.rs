@@ -0,0 +1,649 @@
+use std::{
+    fmt,
+    str::FromStr,
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{anyhow, bail, Result};
+use chrono::{DateTime, Local, Offset, SecondsFormat, Utc};
+use tai64::Tai64N;
+
+use crate::{
+    fp::complement,
+    text::parseutil::{
+        char_is_white, drop_n, first_rest, parse_hex, take_while,
+    },
+    time::excel::exceldays_from_unixtime,
+};
+
+pub fn parse_timestamp(s: &str) -> Result<(Tai64N, &str)> {
+    let (c0, r) = first_rest(s)
+        .ok_or_else(|| anyhow!("empty line, missing timestamp"))?;
+    if c0 != '@' {
+        bail!("line does not start with @")
+    }
+    let (stamp, rest) = take_while(r, complement(char_is_white));
+    if stamp.len() < 24 {
+        bail!("timestamp string is too short")
+    }
+    let stamp8: [u8; 12] = parse_hex(stamp)?;
+    let t = Tai64N::from_slice(&stamp8)?;
+    Ok((t, drop_n(rest, 1, char_is_white)?))
+}
+
+/// A log line as classified by `parse_timestamp_tolerant`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimestampedLine<'s> {
+    /// The line starts with a TAI64N label; the rest of the line after
+    /// it and the whitespace following it.
+    Timestamped(Tai64N, &'s str),
+    /// The line has no (valid) TAI64N label, e.g. because the program
+    /// logging wrote a message spanning multiple lines; the whole
+    /// line, to be attached to the last timestamped one.
+    Continuation(&'s str),
+}
+
+/// Like `parse_timestamp`, but lines that don't start with a valid
+/// TAI64N label are returned as `Continuation` instead of being an
+/// error.
+pub fn parse_timestamp_tolerant(s: &str) -> TimestampedLine<'_> {
+    match parse_timestamp(s) {
+        Ok((t, rest)) => TimestampedLine::Timestamped(t, rest),
+        Err(_) => TimestampedLine::Continuation(s),
+    }
+}
+
+/// A `Tai64N` that is displayed and parsed in the external TAI64N
+/// label form used by daemontools (`@` followed by 24 hex digits),
+/// for writing timestamps to and reading them from text formats
+/// (TSV, JSON, state files) without conversion code in every
+/// binary. (No serde impls since serde is not a dependency of this
+/// crate; serialize via `to_string` and `parse` instead.)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Tai64NLabel(pub Tai64N);
+
+impl From<Tai64N> for Tai64NLabel {
+    fn from(t: Tai64N) -> Self {
+        Tai64NLabel(t)
+    }
+}
+
+impl From<Tai64NLabel> for Tai64N {
+    fn from(t: Tai64NLabel) -> Self {
+        t.0
+    }
+}
+
+impl fmt::Display for Tai64NLabel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("@")?;
+        for b in self.0.to_bytes() {
+            write!(f, "{:02x}", b)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Tai64NLabel {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let hex = s
+            .strip_prefix('@')
+            .ok_or_else(|| anyhow!("TAI64N label does not start with @"))?;
+        if hex.len() != 24 {
+            bail!("TAI64N label must have 24 hex digits: {s:?}")
+        }
+        let bytes: [u8; 12] = parse_hex(hex)?;
+        Ok(Tai64NLabel(Tai64N::from_slice(&bytes)?))
+    }
+}
+
+/// How many digits of the fractional seconds to show (truncated,
+/// not rounded), for `Tai64Format::to_rfc3339` and `to_unix_string`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SubsecPrecision {
+    /// As many as needed: none for whole seconds, otherwise 3, 6 or 9
+    /// for RFC 3339, and without trailing zeros for Unix time.
+    #[default]
+    Auto,
+    /// Whole seconds.
+    None,
+    Milli,
+    Micro,
+    Nano,
+}
+
+impl SubsecPrecision {
+    /// The number of digits, None for `Auto`.
+    pub fn digits(self) -> Option<usize> {
+        match self {
+            SubsecPrecision::Auto => None,
+            SubsecPrecision::None => Some(0),
+            SubsecPrecision::Milli => Some(3),
+            SubsecPrecision::Micro => Some(6),
+            SubsecPrecision::Nano => Some(9),
+        }
+    }
+
+    fn seconds_format(self) -> SecondsFormat {
+        match self {
+            SubsecPrecision::Auto => SecondsFormat::AutoSi,
+            SubsecPrecision::None => SecondsFormat::Secs,
+            SubsecPrecision::Milli => SecondsFormat::Millis,
+            SubsecPrecision::Micro => SecondsFormat::Micros,
+            SubsecPrecision::Nano => SecondsFormat::Nanos,
+        }
+    }
+}
+
+impl FromStr for SubsecPrecision {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "auto" => Ok(SubsecPrecision::Auto),
+            "none" => Ok(SubsecPrecision::None),
+            "milli" => Ok(SubsecPrecision::Milli),
+            "micro" => Ok(SubsecPrecision::Micro),
+            "nano" => Ok(SubsecPrecision::Nano),
+            _ => bail!(
+                "unknown sub-second precision {s:?}, expecting auto, none, \
+                 milli, micro or nano"
+            ),
+        }
+    }
+}
+
+pub trait Tai64Format {
+    fn to_rfc2822_local(&self) -> String;
+    fn to_rfc2822_utc(&self) -> String;
+    fn to_datetime_utc(&self) -> DateTime<Utc>;
+    fn to_exceldays(&self, offset_hours: f64) -> f64;
+    /// `2023-11-14T22:13:30.5Z` (UTC) or with the local offset.
+    fn to_rfc3339(&self, utc: bool, precision: SubsecPrecision) -> String;
+    /// Seconds since 1970-01-01 UTC, exact (unlike going via f64).
+    fn to_unix_string(&self, precision: SubsecPrecision) -> String;
+}
+
+impl Tai64Format for Tai64N {
+    fn to_rfc2822_local(&self) -> String {
+        let t = self.to_system_time();
+        let dt: DateTime<Local> = DateTime::from(t);
+        dt.to_rfc2822()
+    }
+
+    fn to_rfc2822_utc(&self) -> String {
+        let t = self.to_system_time();
+        let dt: DateTime<Utc> = DateTime::from(t);
+        dt.to_rfc2822()
+    }
+
+    fn to_datetime_utc(&self) -> DateTime<Utc> {
+        let t = self.to_system_time();
+        DateTime::from(t)
+    }
+
+    /// Convert to Excel's days-since-~1900 values. You need to pass
+    /// the correct zone difference, and adapt it for DST. Panics on
+    /// potential conversion errors.  offset_hours: `1.0` represents
+    /// `+01:00`.
+    fn to_exceldays(&self, offset_hours: f64) -> f64 {
+        let st = self.to_system_time();
+        let t = st
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .expect("no overflows?")
+            .as_secs_f64();
+        exceldays_from_unixtime(t, offset_hours)
+    }
+
+    fn to_rfc3339(&self, utc: bool, precision: SubsecPrecision) -> String {
+        let st = self.to_system_time();
+        let format = precision.seconds_format();
+        if utc {
+            DateTime::<Utc>::from(st).to_rfc3339_opts(format, true)
+        } else {
+            DateTime::<Local>::from(st).to_rfc3339_opts(format, false)
+        }
+    }
+
+    fn to_unix_string(&self, precision: SubsecPrecision) -> String {
+        let (sign, d) = match self
+            .to_system_time()
+            .duration_since(SystemTime::UNIX_EPOCH)
+        {
+            Ok(d) => ("", d),
+            Err(e) => ("-", e.duration()),
+        };
+        let nanos = format!("{:09}", d.subsec_nanos());
+        let digits = match precision.digits() {
+            Some(n) => &nanos[..n],
+            None => nanos.trim_end_matches('0'),
+        };
+        if digits.is_empty() {
+            format!("{sign}{}", d.as_secs())
+        } else {
+            format!("{sign}{}.{digits}", d.as_secs())
+        }
+    }
+}
+
+/// Parse Unix time in seconds with up to 9 fractional digits (e.g.
+/// `1700000000.5` or `-1.25`) exactly, i.e. the inverse of
+/// `Tai64Format::to_unix_string`.
+pub fn parse_unix_time(s: &str) -> Result<Tai64N> {
+    let (negative, unsigned) = match s.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, s),
+    };
+    let (secs, frac) = unsigned.split_once('.').unwrap_or((unsigned, ""));
+    let all_digits = |s: &str| s.bytes().all(|b| b.is_ascii_digit());
+    if secs.is_empty() || !all_digits(secs) || !all_digits(frac) {
+        bail!("invalid Unix time {s:?}")
+    }
+    if frac.len() > 9 {
+        bail!("more than 9 fractional digits in Unix time {s:?}")
+    }
+    let secs: u64 = secs
+        .parse()
+        .map_err(|e| anyhow!("invalid Unix time {s:?}: {e}"))?;
+    let nanos: u32 = if frac.is_empty() {
+        0
+    } else {
+        format!("{frac:0<9}").parse()?
+    };
+    let d = Duration::new(secs, nanos);
+    let st = if negative {
+        SystemTime::UNIX_EPOCH.checked_sub(d)
+    } else {
+        SystemTime::UNIX_EPOCH.checked_add(d)
+    }
+    .ok_or_else(|| anyhow!("Unix time out of range: {s:?}"))?;
+    Ok(Tai64N::from_system_time(&st))
+}
+
+/// The formats for timestamps in the output of tools, see
+/// `TimeFormatting`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TimeFormat {
+    /// `Tue, 14 Nov 2023 22:13:30 +0100`
+    #[default]
+    Rfc2822,
+    /// `2023-11-14T22:13:30+01:00` (sorts chronologically as text
+    /// as long as the offset doesn't change, e.g. in UTC)
+    Rfc3339,
+    /// Seconds since 1970-01-01 UTC, with fractions if any
+    Unix,
+    /// Excel's days since ~1900, see `Tai64Format::to_exceldays`
+    Excel,
+}
+
+impl FromStr for TimeFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "rfc2822" => Ok(TimeFormat::Rfc2822),
+            "rfc3339" => Ok(TimeFormat::Rfc3339),
+            "unix" => Ok(TimeFormat::Unix),
+            "excel" => Ok(TimeFormat::Excel),
+            _ => bail!(
+                "unknown time format {s:?}, expecting rfc2822, rfc3339, \
+                 unix or excel"
+            ),
+        }
+    }
+}
+
+/// A `TimeFormat` and the time zone to use for it (`Unix` is the
+/// same in both).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct TimeFormatting {
+    pub format: TimeFormat,
+    /// UTC instead of the local time zone.
+    pub utc: bool,
+    /// For `Rfc3339` and `Unix` (the others have fixed precisions).
+    pub precision: SubsecPrecision,
+}
+
+impl TimeFormatting {
+    pub fn format(&self, t: &Tai64N) -> String {
+        let st = t.to_system_time();
+        match (self.format, self.utc) {
+            (TimeFormat::Rfc2822, false) => t.to_rfc2822_local(),
+            (TimeFormat::Rfc2822, true) => t.to_rfc2822_utc(),
+            (TimeFormat::Rfc3339, utc) => t.to_rfc3339(utc, self.precision),
+            (TimeFormat::Unix, _) => t.to_unix_string(self.precision),
+            (TimeFormat::Excel, false) => {
+                let offset = DateTime::<Local>::from(st).offset().fix();
+                t.to_exceldays(offset.local_minus_utc() as f64 / 3600.)
+                    .to_string()
+            }
+            (TimeFormat::Excel, true) => t.to_exceldays(0.).to_string(),
+        }
+    }
+
+    /// Parse a timestamp as written by `format` (with any precision,
+    /// and any UTC offset). Excel days are not supported, since they
+    /// are not exact.
+    pub fn parse(&self, s: &str) -> Result<Tai64N> {
+        let dt = match self.format {
+            TimeFormat::Rfc2822 => DateTime::parse_from_rfc2822(s),
+            TimeFormat::Rfc3339 => DateTime::parse_from_rfc3339(s),
+            TimeFormat::Unix => return parse_unix_time(s),
+            TimeFormat::Excel => {
+                bail!("parsing Excel timestamps is not supported")
+            }
+        }
+        .map_err(|e| {
+            anyhow!("invalid {:?} timestamp {s:?}: {e}", self.format)
+        })?;
+        Ok(Tai64N::from_system_time(&dt.into()))
+    }
+}
+
+/// The options to choose a `TimeFormatting`, to be flattened into a
+/// binary's options.
+#[derive(clap::Args, Debug)]
+pub struct TimeFormatArgs {
+    /// How to show timestamps: rfc2822 (the default), rfc3339, unix
+    /// (seconds since 1970), or excel (days since ~1900; with --local,
+    /// using the UTC offset at each time, i.e. jumping at DST
+    /// changes)
+    #[clap(long, default_value = "rfc2822")]
+    pub time_format: TimeFormat,
+
+    /// Show timestamps in UTC (of `--utc` and `--local`, the last one
+    /// given wins)
+    #[clap(long, overrides_with = "local")]
+    pub utc: bool,
+
+    /// Show timestamps in the local time zone (the default)
+    #[clap(long, overrides_with = "utc")]
+    pub local: bool,
+
+    /// The digits of fractional seconds to show with the rfc3339 and
+    /// unix time formats: auto (as many as needed), none, milli,
+    /// micro, or nano (truncated, not rounded)
+    #[clap(long, default_value = "auto")]
+    pub time_precision: SubsecPrecision,
+}
+
+impl TimeFormatArgs {
+    pub fn formatting(&self) -> TimeFormatting {
+        TimeFormatting {
+            format: self.time_format,
+            utc: self.utc,
+            precision: self.time_precision,
+        }
+    }
+}
+
+/// A problem with a timestamp in a sequence that should be
+/// increasing, relative to the preceding one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SequenceIssue {
+    /// Earlier than the preceding timestamp, by `by`.
+    OutOfOrder { previous: Tai64N, by: Duration },
+    /// The same as the preceding timestamp.
+    Duplicate,
+    /// Later than the preceding timestamp by more than the allowed
+    /// gap.
+    Gap { previous: Tai64N, gap: Duration },
+}
+
+impl SequenceIssue {
+    pub fn name(&self) -> &'static str {
+        match self {
+            SequenceIssue::OutOfOrder { .. } => "out_of_order",
+            SequenceIssue::Duplicate => "duplicate",
+            SequenceIssue::Gap { .. } => "gap",
+        }
+    }
+}
+
+/// Checks timestamps fed one by one for being strictly increasing,
+/// and optionally for gaps larger than `max_gap`.
+#[derive(Debug, Clone)]
+pub struct SequenceChecker {
+    max_gap: Option<Duration>,
+    previous: Option<Tai64N>,
+}
+
+impl SequenceChecker {
+    pub fn new(max_gap: Option<Duration>) -> Self {
+        SequenceChecker {
+            max_gap,
+            previous: None,
+        }
+    }
+
+    /// Check `t` against the preceding timestamp.
+    pub fn check(&mut self, t: Tai64N) -> Option<SequenceIssue> {
+        let previous = self.previous.replace(t)?;
+        match t.duration_since(&previous) {
+            Err(by) => Some(SequenceIssue::OutOfOrder { previous, by }),
+            Ok(gap) if gap.is_zero() => Some(SequenceIssue::Duplicate),
+            Ok(gap) if self.max_gap.is_some_and(|max| gap > max) => {
+                Some(SequenceIssue::Gap { previous, gap })
+            }
+            Ok(_) => None,
+        }
+    }
+}
+
+/// The result of `check_sequence`, with the issues found, each with
+/// the position (e.g. line number) and timestamp it was found at.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SequenceReport<P> {
+    pub count: usize,
+    pub first: Option<Tai64N>,
+    pub last: Option<Tai64N>,
+    pub issues: Vec<(P, Tai64N, SequenceIssue)>,
+    pub num_out_of_order: usize,
+    pub num_duplicates: usize,
+    pub num_gaps: usize,
+}
+
+/// Check a sequence of positioned timestamps (see `SequenceChecker`).
+pub fn check_sequence<P>(
+    items: impl IntoIterator<Item = (P, Tai64N)>,
+    max_gap: Option<Duration>,
+) -> SequenceReport<P> {
+    let mut checker = SequenceChecker::new(max_gap);
+    let mut report = SequenceReport {
+        count: 0,
+        first: None,
+        last: None,
+        issues: Vec::new(),
+        num_out_of_order: 0,
+        num_duplicates: 0,
+        num_gaps: 0,
+    };
+    for (position, t) in items {
+        report.count += 1;
+        report.first.get_or_insert(t);
+        report.last = Some(t);
+        if let Some(issue) = checker.check(t) {
+            *match issue {
+                SequenceIssue::OutOfOrder { .. } => {
+                    &mut report.num_out_of_order
+                }
+                SequenceIssue::Duplicate => &mut report.num_duplicates,
+                SequenceIssue::Gap { .. } => &mut report.num_gaps,
+            } += 1;
+            report.issues.push((position, t, issue));
+        }
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn t_tai64n_label() {
+        let s = "@400000006553f10a0000007b";
+        let t: Tai64NLabel = s.parse().unwrap();
+        assert_eq!(t.0 .1, 123);
+        assert_eq!(t.to_string(), s);
+        assert_eq!(t, parse_timestamp(&format!("{s} x")).unwrap().0.into());
+        assert!("400000006553f10a0000007b".parse::<Tai64NLabel>().is_err());
+        assert!("@400000006553f10a".parse::<Tai64NLabel>().is_err());
+        assert!("@400000006553f10a0000007g".parse::<Tai64NLabel>().is_err());
+    }
+
+    #[test]
+    fn t_check_sequence() {
+        let t = |secs| {
+            Tai64N::from_system_time(
+                &(SystemTime::UNIX_EPOCH + Duration::from_secs(secs)),
+            )
+        };
+        let report = check_sequence(
+            [10, 11, 11, 9, 20, 21].iter().map(|s| t(*s)).enumerate(),
+            Some(Duration::from_secs(5)),
+        );
+        assert_eq!(report.count, 6);
+        assert_eq!((report.first, report.last), (Some(t(10)), Some(t(21))));
+        assert_eq!(
+            report.issues,
+            [
+                (2, t(11), SequenceIssue::Duplicate),
+                (
+                    3,
+                    t(9),
+                    SequenceIssue::OutOfOrder {
+                        previous: t(11),
+                        by: Duration::from_secs(2)
+                    }
+                ),
+                (
+                    4,
+                    t(20),
+                    SequenceIssue::Gap {
+                        previous: t(9),
+                        gap: Duration::from_secs(11)
+                    }
+                ),
+            ]
+        );
+        assert_eq!(
+            (
+                report.num_out_of_order,
+                report.num_duplicates,
+                report.num_gaps
+            ),
+            (1, 1, 1)
+        );
+        assert!(check_sequence([(0, t(1)), (1, t(100))], None)
+            .issues
+            .is_empty());
+    }
+
+    #[test]
+    fn t_time_formatting() {
+        let t = Tai64N::from_system_time(
+            &(SystemTime::UNIX_EPOCH
+                + Duration::from_millis(1_700_000_000_500)),
+        );
+        let f = |format: &str| {
+            TimeFormatting {
+                format: format.parse().unwrap(),
+                utc: true,
+                ..Default::default()
+            }
+            .format(&t)
+        };
+        assert_eq!(f("rfc2822"), "Tue, 14 Nov 2023 22:13:20 +0000");
+        assert_eq!(f("rfc3339"), "2023-11-14T22:13:20.500Z");
+        assert_eq!(f("unix"), "1700000000.5");
+        let label = "@400000006553f10a0000007b".parse::<Tai64NLabel>().unwrap();
+        assert_eq!(
+            TimeFormatting {
+                format: TimeFormat::Unix,
+                utc: false,
+                ..Default::default()
+            }
+            .format(&label.0),
+            "1700000000.000000123"
+        );
+        assert_eq!(f("excel").parse::<f64>().unwrap(), t.to_exceldays(0.));
+        assert!("iso".parse::<TimeFormat>().is_err());
+    }
+
+    #[test]
+    fn t_precision() -> Result<()> {
+        // 22:13:20.123456789
+        let label = "@400000006553f10a075bcd15".parse::<Tai64NLabel>()?;
+        let t = label.0;
+        let f = |format, precision: &str| -> Result<String> {
+            Ok(TimeFormatting {
+                format,
+                utc: true,
+                precision: precision.parse()?,
+            }
+            .format(&t))
+        };
+        assert_eq!(f(TimeFormat::Rfc3339, "none")?, "2023-11-14T22:13:20Z");
+        assert_eq!(
+            f(TimeFormat::Rfc3339, "milli")?,
+            "2023-11-14T22:13:20.123Z"
+        );
+        assert_eq!(f(TimeFormat::Unix, "micro")?, "1700000000.123456");
+        assert_eq!(f(TimeFormat::Unix, "nano")?, "1700000000.123456789");
+        assert_eq!(f(TimeFormat::Unix, "none")?, "1700000000");
+        assert!("centi".parse::<SubsecPrecision>().is_err());
+
+        for format in
+            [TimeFormat::Rfc2822, TimeFormat::Rfc3339, TimeFormat::Unix]
+        {
+            for precision in ["auto", "none", "milli", "nano"] {
+                let formatting = TimeFormatting {
+                    format,
+                    utc: false,
+                    precision: precision.parse()?,
+                };
+                let s = formatting.format(&t);
+                let parsed = formatting.parse(&s)?;
+                // The same up to the precision written
+                assert_eq!(formatting.format(&parsed), s);
+            }
+        }
+        assert_eq!(parse_unix_time("1700000000.123456789")?, t);
+        let epoch = Tai64N::from_system_time(&SystemTime::UNIX_EPOCH);
+        assert_eq!(
+            parse_unix_time("-1.5")?,
+            Tai64N::from_system_time(
+                &(SystemTime::UNIX_EPOCH - Duration::from_millis(1500))
+            )
+        );
+        assert_eq!(parse_unix_time("0")?, epoch);
+        assert!(parse_unix_time("1.1234567891").is_err());
+        assert!(parse_unix_time("1e9").is_err());
+        assert!(parse_unix_time(".5").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn t_parse_timestamp_tolerant() {
+        let s = "@400000006553f10a0000007b  x y";
+        let t: Tai64NLabel = s[..25].parse().unwrap();
+        assert_eq!(
+            parse_timestamp_tolerant(s),
+            TimestampedLine::Timestamped(t.0, " x y")
+        );
+        for s in [
+            "  x y",
+            "",
+            "@400000006553f10a x",
+            "@400000006553f10a0000007b",
+        ] {
+            assert_eq!(
+                parse_timestamp_tolerant(s),
+                TimestampedLine::Continuation(s)
+            );
+        }
+    }
+}
@@ -0,0 +1,7 @@
+pub mod cli_output;
+pub mod div;
+pub mod error_policy;
+pub mod map_trait;
+pub mod progress;
+pub mod scope;
+pub mod signals;
@@ -0,0 +1,300 @@
+//! Output conventions shared by the binaries: `--color` (with tty
+//! detection and `NO_COLOR` support) for human-readable output, and
+//! `--output` to choose machine-readable formats instead (TSV with a
+//! header line, CSV for Excel, or JSON, one object per line).
+
+use std::borrow::Cow;
+use std::io::Write;
+use std::os::unix::io::RawFd;
+use std::str::FromStr;
+
+use anyhow::{bail, Error, Result};
+
+use crate::text::json::push_json_string;
+use crate::time::excel::{push_csv_record, CsvSeparator};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorMode {
+    Auto,
+    Always,
+    Never,
+}
+
+impl FromStr for ColorMode {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "auto" => Ok(ColorMode::Auto),
+            "always" => Ok(ColorMode::Always),
+            "never" => Ok(ColorMode::Never),
+            _ => bail!("unknown color mode {s:?}, expecting auto|always|never"),
+        }
+    }
+}
+
+impl ColorMode {
+    /// Whether to use colors for output to `fd`: in `Auto` mode, if
+    /// it is a terminal and the `NO_COLOR` env var is not set (see
+    /// <https://no-color.org/>).
+    pub fn enabled_for(self, fd: RawFd) -> bool {
+        match self {
+            ColorMode::Always => true,
+            ColorMode::Never => false,
+            ColorMode::Auto => {
+                std::env::var_os("NO_COLOR").is_none_or(|v| v.is_empty())
+                    && nix::unistd::isatty(fd).unwrap_or(false)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// For humans (the tool's traditional output).
+    Text,
+    Tsv,
+    /// CSV for Excel (see `excel::push_csv_record`).
+    Csv(CsvSeparator),
+    Json,
+}
+
+impl FromStr for OutputFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "text" => Ok(OutputFormat::Text),
+            "tsv" => Ok(OutputFormat::Tsv),
+            "csv" => Ok(OutputFormat::Csv(CsvSeparator::Comma)),
+            "csv-semicolon" => Ok(OutputFormat::Csv(CsvSeparator::Semicolon)),
+            "json" => Ok(OutputFormat::Json),
+            _ => bail!(
+                "unknown output format {s:?}, expecting \
+                 text|tsv|csv|csv-semicolon|json"
+            ),
+        }
+    }
+}
+
+#[derive(clap::Args, Debug)]
+pub struct OutputArgs {
+    /// Whether to use ANSI colors in text output: auto (when stdout
+    /// is a terminal and NO_COLOR is not set), always, or never.
+    #[clap(long, default_value = "auto")]
+    pub color: ColorMode,
+
+    /// The output format: text (for humans), tsv (with a header
+    /// line), csv or csv-semicolon (for Excel, with a header line; the
+    /// latter for locales with a decimal comma), or json (one object
+    /// per line).
+    #[clap(long, default_value = "text")]
+    pub output: OutputFormat,
+}
+
+impl OutputArgs {
+    /// An `Output` to stdout with records with the given column
+    /// names.
+    pub fn output(&self, columns: &[&'static str]) -> Output {
+        self.output_to(1, columns)
+    }
+
+    /// Like `output`, for output to `fd` (which decides about the
+    /// colors in auto mode).
+    pub fn output_to(&self, fd: RawFd, columns: &[&'static str]) -> Output {
+        Output::new(self.output, self.color.enabled_for(fd), columns)
+    }
+}
+
+/// SGR codes for `Output::paint`.
+pub const BOLD: &str = "1";
+pub const DIM: &str = "2";
+pub const RED: &str = "31";
+pub const GREEN: &str = "32";
+pub const BLUE: &str = "34";
+
+/// A field value of a record; numbers are written without quotes in
+/// JSON (the string must be a valid JSON number).
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value<'a> {
+    Str(Cow<'a, str>),
+    Number(String),
+}
+
+impl<'a> From<&'a str> for Value<'a> {
+    fn from(s: &'a str) -> Self {
+        Value::Str(Cow::Borrowed(s))
+    }
+}
+
+impl<'a> From<String> for Value<'a> {
+    fn from(s: String) -> Self {
+        Value::Str(Cow::Owned(s))
+    }
+}
+
+impl<'a> From<Cow<'a, str>> for Value<'a> {
+    fn from(s: Cow<'a, str>) -> Self {
+        Value::Str(s)
+    }
+}
+
+macro_rules! value_from_number {
+    ($($t:ty)*) => {
+        $(impl<'a> From<$t> for Value<'a> {
+            fn from(x: $t) -> Self {
+                Value::Number(x.to_string())
+            }
+        })*
+    };
+}
+
+value_from_number!(u64 i64 usize f64);
+
+/// Writes records in the chosen format, and paints text if colors
+/// are enabled.
+#[derive(Debug)]
+pub struct Output {
+    pub format: OutputFormat,
+    pub color: bool,
+    columns: Vec<&'static str>,
+    header_written: bool,
+}
+
+impl Output {
+    pub fn new(
+        format: OutputFormat,
+        color: bool,
+        columns: &[&'static str],
+    ) -> Self {
+        Output {
+            format,
+            color,
+            columns: columns.into(),
+            header_written: false,
+        }
+    }
+
+    pub fn is_text(&self) -> bool {
+        self.format == OutputFormat::Text
+    }
+
+    /// `s` wrapped in the ANSI escapes for `sgr` (e.g. `BOLD`) if
+    /// colors are enabled (and the format is text).
+    pub fn paint<'s>(&self, sgr: &str, s: &'s str) -> Cow<'s, str> {
+        if self.color && self.is_text() {
+            Cow::Owned(format!("\x1b[{sgr}m{s}\x1b[0m"))
+        } else {
+            Cow::Borrowed(s)
+        }
+    }
+
+    /// Write a record with a value for each column. TSV gets a header
+    /// line before the first record (tabs and newlines in values are
+    /// replaced by spaces); the text format is the values separated
+    /// by tabs, for tools that don't have a better representation. CSV
+    /// also gets a header line, starting with the UTF-8 BOM.
+    pub fn write_record(
+        &mut self,
+        out: &mut impl Write,
+        values: &[Value],
+    ) -> Result<()> {
+        assert_eq!(values.len(), self.columns.len());
+        let mut line = String::new();
+        match self.format {
+            OutputFormat::Text | OutputFormat::Tsv => {
+                if self.format == OutputFormat::Tsv && !self.header_written {
+                    self.header_written = true;
+                    writeln!(out, "{}", self.columns.join("\t"))?;
+                }
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        line.push('\t');
+                    }
+                    match value {
+                        Value::Str(s) => line.extend(s.chars().map(|c| {
+                            if c == '\t' || c == '\n' {
+                                ' '
+                            } else {
+                                c
+                            }
+                        })),
+                        Value::Number(s) => line.push_str(s),
+                    }
+                }
+            }
+            OutputFormat::Csv(separator) => {
+                if !self.header_written {
+                    self.header_written = true;
+                    push_csv_record(&mut line, &self.columns, separator, true);
+                }
+                push_csv_record(
+                    &mut line,
+                    values.iter().map(|value| match value {
+                        Value::Str(s) => s.as_ref(),
+                        Value::Number(s) => s.as_str(),
+                    }),
+                    separator,
+                    false,
+                );
+                out.write_all(line.as_bytes())?;
+                return Ok(());
+            }
+            OutputFormat::Json => {
+                line.push('{');
+                for (i, (column, value)) in
+                    self.columns.iter().zip(values).enumerate()
+                {
+                    if i > 0 {
+                        line.push(',');
+                    }
+                    push_json_string(&mut line, column);
+                    line.push(':');
+                    match value {
+                        Value::Str(s) => push_json_string(&mut line, s),
+                        Value::Number(s) => line.push_str(s),
+                    }
+                }
+                line.push('}');
+            }
+        }
+        line.push('\n');
+        out.write_all(line.as_bytes())?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn t_write_record() -> Result<()> {
+        let t = |format: OutputFormat| -> Result<String> {
+            let mut output = Output::new(format, true, &["path", "size"]);
+            let mut out = Vec::new();
+            output.write_record(&mut out, &["a\tb".into(), 3u64.into()])?;
+            output.write_record(&mut out, &["\"c\"".into(), 4.5.into()])?;
+            Ok(String::from_utf8(out)?)
+        };
+        assert_eq!(t(OutputFormat::Tsv)?, "path\tsize\na b\t3\n\"c\"\t4.5\n");
+        assert_eq!(t(OutputFormat::Text)?, "a b\t3\n\"c\"\t4.5\n");
+        assert_eq!(
+            t("csv-semicolon".parse()?)?,
+            "\u{feff}path;size\r\na\tb;3\r\n\"\"\"c\"\"\";4,5\r\n"
+        );
+        assert_eq!(
+            t(OutputFormat::Json)?,
+            "{\"path\":\"a\\tb\",\"size\":3}\n\
+             {\"path\":\"\\\"c\\\"\",\"size\":4.5}\n"
+        );
+
+        let output = Output::new(OutputFormat::Text, true, &[]);
+        assert_eq!(output.paint(BOLD, "x"), "\x1b[1mx\x1b[0m");
+        let output = Output::new(OutputFormat::Tsv, true, &[]);
+        assert_eq!(output.paint(BOLD, "x"), "x");
+        assert!(!ColorMode::Never.enabled_for(1));
+        assert!("rainbow".parse::<ColorMode>().is_err());
+        Ok(())
+    }
+}
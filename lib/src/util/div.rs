@@ -16,12 +16,12 @@ use std::{
 // that rustc (pre polonius) does not let go of the reference in the
 // None case; so we use an Err instead and pick up the reference from
 // there.
-pub fn hashmap_get_mut<'m, K: Eq + Hash, P: Eq + Hash + ?Sized, V>(
+pub fn hashmap_get_mut<'m, K, P: Eq + Hash + ?Sized, V>(
     m: &'m mut HashMap<K, V>,
     k: &P,
 ) -> Result<&'m mut V, &'m mut HashMap<K, V>>
 where
-    K: Borrow<P>,
+    K: Eq + Hash + Borrow<P>,
 {
     let pm: *mut _ = m;
     // Safe because in the true branch we track using the lifetimes
@@ -35,12 +35,12 @@ where
 }
 
 // Same (see hashmap_get_mut) for BTreeMap.
-pub fn btreemap_get_mut<'m, K: Ord, P: Ord + ?Sized, V>(
+pub fn btreemap_get_mut<'m, K, P: Ord + ?Sized, V>(
     m: &'m mut BTreeMap<K, V>,
     k: &P,
 ) -> Result<&'m mut V, &'m mut BTreeMap<K, V>>
 where
-    K: Borrow<P>,
+    K: Ord + Borrow<P>,
 {
     let pm: *mut _ = m;
     // Safe because in the true branch we track using the lifetimes
@@ -58,11 +58,11 @@ where
 // https://doc.rust-lang.org/src/std/collections/hash/map.rs.html#1132-1137,
 // avoiding OccupiedError because that's also unstable. FUTURE:
 // replace with try_insert.
-pub fn hashmap_try_insert<'m, K: Eq + Hash, V>(
-    m: &'m mut HashMap<K, V>,
+pub fn hashmap_try_insert<K: Eq + Hash, V>(
+    m: &mut HashMap<K, V>,
     key: K,
     value: V,
-) -> Result<&mut V, OccupiedEntry<K, V>> {
+) -> Result<&mut V, OccupiedEntry<'_, K, V>> {
     match m.entry(key) {
         Entry::Occupied(entry) => Err(entry),
         Entry::Vacant(entry) => Ok(entry.insert(value)),
@@ -73,11 +73,11 @@ pub fn hashmap_try_insert<'m, K: Eq + Hash, V>(
 // "82766")] from
 // https://doc.rust-lang.org/src/alloc/collections/btree/map.rs.html#1016-1018;
 // see comments on hashmap_try_insert.
-pub fn btreemap_try_insert<'m, K: Ord, V>(
-    m: &'m mut BTreeMap<K, V>,
+pub fn btreemap_try_insert<K: Ord, V>(
+    m: &mut BTreeMap<K, V>,
     key: K,
     value: V,
-) -> Result<&'m mut V, btree_map::OccupiedEntry<K, V>> {
+) -> Result<&mut V, btree_map::OccupiedEntry<'_, K, V>> {
     match m.entry(key) {
         btree_map::Entry::Occupied(entry) => Err(entry),
         btree_map::Entry::Vacant(entry) => Ok(entry.insert(value)),
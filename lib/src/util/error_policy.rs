@@ -0,0 +1,140 @@
+//! What to do about errors in individual items (lines, records) of a
+//! stream being parsed: stop, warn and continue, or silently
+//! continue, optionally with a limit on the number of errors after
+//! which processing stops anyway.
+
+use std::str::FromStr;
+
+use anyhow::{anyhow, bail, Error, Result};
+
+use crate::sequences::ErrorClass;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorMode {
+    Abort,
+    Warn,
+    Ignore,
+}
+
+impl FromStr for ErrorMode {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "abort" => Ok(ErrorMode::Abort),
+            "warn" => Ok(ErrorMode::Warn),
+            "ignore" => Ok(ErrorMode::Ignore),
+            _ => bail!("unknown error mode {s:?}, expecting abort|warn|ignore"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ErrorPolicy {
+    pub mode: ErrorMode,
+    /// Stop (return the error) when there are more errors than this.
+    pub max_errors: Option<usize>,
+    num_errors: usize,
+}
+
+impl ErrorPolicy {
+    pub fn new(mode: ErrorMode, max_errors: Option<usize>) -> Self {
+        ErrorPolicy {
+            mode,
+            max_errors,
+            num_errors: 0,
+        }
+    }
+
+    /// The number of errors passed to `handle` or `classify` so far.
+    pub fn num_errors(&self) -> usize {
+        self.num_errors
+    }
+
+    fn too_many(&self) -> Option<usize> {
+        self.max_errors.filter(|max| self.num_errors > *max)
+    }
+
+    /// Count and classify an error for a single item, printing it as
+    /// a warning in `Warn` mode if processing continues. For use with
+    /// `sequences::try_tally_errors`.
+    pub fn classify(&mut self, e: &Error) -> ErrorClass {
+        self.num_errors += 1;
+        if self.mode == ErrorMode::Abort || self.too_many().is_some() {
+            return ErrorClass::Abort;
+        }
+        if self.mode == ErrorMode::Warn {
+            eprintln!("Warning: {e:?}");
+        }
+        ErrorClass::Skip
+    }
+
+    /// Handle an error for a single item: returns `Ok(())` if
+    /// processing should continue with the next item (after printing
+    /// the error as a warning in `Warn` mode), or the error if it
+    /// should stop.
+    pub fn handle(&mut self, e: Error) -> Result<()> {
+        match self.classify(&e) {
+            ErrorClass::Skip => Ok(()),
+            ErrorClass::Abort => match self.too_many() {
+                Some(max) => Err(e.context(anyhow!(
+                    "more than {max} errors (--max-errors), giving up"
+                ))),
+                None => Err(e),
+            },
+        }
+    }
+
+    /// Print the number of errors that were ignored, if any (call at
+    /// the end of processing).
+    pub fn report_ignored(&self) {
+        if self.mode == ErrorMode::Ignore && self.num_errors > 0 {
+            eprintln!("Note: ignored {} error(s)", self.num_errors);
+        }
+    }
+}
+
+/// Command line options for an `ErrorPolicy`, to be `flatten`ed into
+/// a binary's options.
+#[derive(clap::Args, Debug)]
+pub struct ErrorPolicyArgs {
+    /// What to do about input that can't be parsed: abort, warn (print
+    /// the error and continue), or ignore (continue silently).
+    #[clap(long, default_value = "warn")]
+    errors: ErrorMode,
+
+    /// Abort anyway when there are more than this many errors.
+    #[clap(long)]
+    max_errors: Option<usize>,
+}
+
+impl ErrorPolicyArgs {
+    pub fn policy(&self) -> ErrorPolicy {
+        ErrorPolicy::new(self.errors, self.max_errors)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn t_handle() {
+        let mut p = ErrorPolicy::new(ErrorMode::Abort, None);
+        assert!(p.handle(anyhow!("a")).is_err());
+
+        let mut p = ErrorPolicy::new(ErrorMode::Ignore, Some(2));
+        assert!(p.handle(anyhow!("a")).is_ok());
+        assert!(p.handle(anyhow!("b")).is_ok());
+        let e = p.handle(anyhow!("c")).unwrap_err();
+        assert_eq!(
+            format!("{e:#}"),
+            "more than 2 errors (--max-errors), giving up: c"
+        );
+        assert_eq!(p.num_errors(), 3);
+        assert_eq!(p.classify(&anyhow!("d")), ErrorClass::Abort);
+
+        assert_eq!("warn".parse::<ErrorMode>().unwrap(), ErrorMode::Warn);
+        assert!("stop".parse::<ErrorMode>().is_err());
+    }
+}
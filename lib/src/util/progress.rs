@@ -0,0 +1,177 @@
+//! Progress reports on stderr for long-running processing of input
+//! files: records and bytes processed, the rate, and (if the size of
+//! the input is known) the percentage and an ETA.
+//!
+//! ```ignore
+//! let mut progress = Progress::new("input.txt", Some(file_size));
+//! while inp.easy_read_line(&mut line)? {
+//!     progress.tick(inp.next_byte_offset());
+//!     ..
+//! }
+//! progress.finish();
+//! ```
+
+use std::io::{stderr, Write};
+use std::time::{Duration, Instant};
+
+use crate::cli::program_name;
+use crate::text::humanize::{self, ByteUnits};
+
+/// Only check the time every this many ticks.
+const TICKS_PER_CHECK: u64 = 1024;
+
+#[derive(Debug)]
+pub struct Progress {
+    label: String,
+    total_bytes: Option<u64>,
+    interval: Duration,
+    /// Overwrite the report line instead of writing new lines (if
+    /// stderr is a terminal).
+    overwrite: bool,
+    start: Instant,
+    last_report: Instant,
+    bytes: u64,
+    records: u64,
+    reported: bool,
+}
+
+impl Progress {
+    /// Report on the processing of an input called `label`, of
+    /// `total_bytes` if known (pass None for decompressed input, where
+    /// the byte positions are not comparable to the file size).
+    pub fn new(label: impl Into<String>, total_bytes: Option<u64>) -> Self {
+        let now = Instant::now();
+        Progress {
+            label: label.into(),
+            total_bytes,
+            interval: Duration::from_secs(1),
+            overwrite: nix::unistd::isatty(2).unwrap_or(false),
+            start: now,
+            last_report: now,
+            bytes: 0,
+            records: 0,
+            reported: false,
+        }
+    }
+
+    /// How often to report (default: every second).
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Record that another record has been processed, with `bytes`
+    /// now being the position in the input; reports if the interval
+    /// has passed.
+    pub fn tick(&mut self, bytes: u64) {
+        self.bytes = bytes;
+        self.records += 1;
+        if self.records.is_multiple_of(TICKS_PER_CHECK) {
+            let now = Instant::now();
+            if now.duration_since(self.last_report) >= self.interval {
+                self.last_report = now;
+                self.report(now, false);
+            }
+        }
+    }
+
+    /// Report the final numbers; does nothing if nothing was reported
+    /// yet (i.e. the input took less than the interval), or since the
+    /// last call.
+    pub fn finish(&mut self) {
+        if self.reported {
+            self.report(Instant::now(), true);
+            self.reported = false;
+        }
+    }
+
+    fn report(&mut self, now: Instant, done: bool) {
+        let message = self.message(now.duration_since(self.start), done);
+        let mut err = stderr().lock();
+        // (Failing to write the report is not worth aborting for)
+        let _ = if self.overwrite {
+            write!(
+                err,
+                "\r\x1b[K{}: {message}{}",
+                program_name(),
+                if done { "\n" } else { "" }
+            )
+        } else {
+            writeln!(err, "{}: {message}", program_name())
+        };
+        let _ = err.flush();
+        self.reported = true;
+    }
+
+    /// The report after `elapsed` time.
+    pub fn message(&self, elapsed: Duration, done: bool) -> String {
+        let secs = elapsed.as_secs_f64();
+        let mut s = format!("{}: {}", self.label, fmt_bytes(self.bytes as f64));
+        if let Some(total) = self.total_bytes {
+            s.push_str(&format!(" of {}", fmt_bytes(total as f64)));
+            if total > 0 && !done {
+                s.push_str(&format!(
+                    " ({:.0}%)",
+                    (self.bytes as f64 / total as f64 * 100.).min(100.)
+                ));
+            }
+        }
+        s.push_str(&format!(", {} records", self.records));
+        if secs > 0. {
+            s.push_str(&format!(", {}/s", fmt_bytes(self.bytes as f64 / secs)));
+        }
+        if done {
+            s.push_str(&format!(", done in {}", format_seconds(secs)));
+        } else if let Some(total) = self.total_bytes {
+            if self.bytes > 0 && total >= self.bytes {
+                let remaining =
+                    (total - self.bytes) as f64 * secs / self.bytes as f64;
+                s.push_str(&format!(", ETA {}", format_seconds(remaining)));
+            }
+        }
+        s
+    }
+}
+
+fn fmt_bytes(n: f64) -> String {
+    humanize::format_bytes(n, ByteUnits::Decimal, None)
+}
+
+/// `42s`, `3m05s`, `2h07m`
+fn format_seconds(secs: f64) -> String {
+    let secs = secs.round() as u64;
+    if secs < 60 {
+        format!("{secs}s")
+    } else if secs < 3600 {
+        format!("{}m{:02}s", secs / 60, secs % 60)
+    } else {
+        format!("{}h{:02}m", secs / 3600, secs % 3600 / 60)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn t_message() {
+        let mut p = Progress::new("foo", Some(4_000_000));
+        for i in 1..=10 {
+            p.tick(i * 100_000);
+        }
+        assert_eq!(
+            p.message(Duration::from_secs(10), false),
+            "foo: 1.0 MB of 4.0 MB (25%), 10 records, 100 kB/s, ETA 30s"
+        );
+        assert_eq!(
+            p.message(Duration::from_secs(200), true),
+            "foo: 1.0 MB of 4.0 MB, 10 records, 5.0 kB/s, done in 3m20s"
+        );
+        let p = Progress::new("bar", None);
+        assert_eq!(
+            p.message(Duration::from_secs(0), false),
+            "bar: 0 B, 0 records"
+        );
+        assert_eq!(format_seconds(7322.), "2h02m");
+    }
+}
@@ -0,0 +1,117 @@
+//! Clean termination on SIGINT/SIGTERM/SIGHUP: instead of being
+//! killed right away, the process gets a flag set that its main loop
+//! can check, so that it can flush and finalize its outputs (commit
+//! or remove temporary files, write state) before exiting. A second
+//! signal terminates immediately, in case the process doesn't get to
+//! check the flag (e.g. because it is blocked reading input).
+
+use std::convert::TryFrom;
+use std::sync::atomic::{AtomicI32, Ordering};
+
+use anyhow::Result;
+use nix::sys::signal::{sigaction, SaFlags, SigAction, SigHandler, SigSet};
+pub use nix::sys::signal::Signal;
+use thiserror::Error;
+
+/// 0 means no signal received yet.
+static RECEIVED: AtomicI32 = AtomicI32::new(0);
+
+extern "C" fn handler(signum: libc::c_int) {
+    if RECEIVED.swap(signum, Ordering::SeqCst) != 0 {
+        // Second signal: give up on cleaning up.
+        unsafe { libc::_exit(128 + signum) }
+    }
+}
+
+pub const TERMINATION_SIGNALS: &[Signal] =
+    &[Signal::SIGINT, Signal::SIGTERM, Signal::SIGHUP];
+
+/// Install the handler for the given signals (usually
+/// `TERMINATION_SIGNALS`).
+pub fn install_termination_handler(signals: &[Signal]) -> Result<()> {
+    let action = SigAction::new(
+        SigHandler::Handler(handler),
+        SaFlags::empty(),
+        SigSet::empty(),
+    );
+    for signal in signals {
+        unsafe { sigaction(*signal, &action) }?;
+    }
+    Ok(())
+}
+
+/// The signal that requested termination, if any.
+pub fn termination_signal() -> Option<Signal> {
+    match RECEIVED.load(Ordering::SeqCst) {
+        0 => None,
+        signum => Signal::try_from(signum).ok(),
+    }
+}
+
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+#[error("terminated by signal {0}")]
+pub struct Terminated(pub Signal);
+
+impl Terminated {
+    /// The conventional shell exit code for termination by the
+    /// signal.
+    pub fn exit_code(self) -> i32 {
+        128 + self.0 as i32
+    }
+
+    /// Exit the process with `exit_code()`.
+    pub fn exit(self) -> ! {
+        std::process::exit(self.exit_code())
+    }
+}
+
+/// For use with `?` in loops: returns an error if termination was
+/// requested.
+pub fn check_termination() -> Result<(), Terminated> {
+    match termination_signal() {
+        None => Ok(()),
+        Some(signal) => Err(Terminated(signal)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn t_termination_flag() -> Result<()> {
+        // Run in a forked child, since the flag is process global and
+        // would otherwise make other tests that check it see a
+        // termination request. (SIGUSR1 so as not to interfere with
+        // the test runner.)
+        use nix::sys::wait::{waitpid, WaitStatus};
+        use nix::unistd::{fork, ForkResult};
+
+        match unsafe { fork() }? {
+            ForkResult::Child => {
+                let code = (|| {
+                    if check_termination() != Ok(()) {
+                        return 1;
+                    }
+                    if install_termination_handler(&[Signal::SIGUSR1]).is_err()
+                        || nix::sys::signal::raise(Signal::SIGUSR1).is_err()
+                    {
+                        return 2;
+                    }
+                    if termination_signal() != Some(Signal::SIGUSR1) {
+                        return 3;
+                    }
+                    match check_termination() {
+                        Err(e) if e.exit_code() == 128 + 10 => 0,
+                        _ => 4,
+                    }
+                })();
+                unsafe { libc::_exit(code) }
+            }
+            ForkResult::Parent { child } => {
+                assert_eq!(waitpid(child, None)?, WaitStatus::Exited(child, 0));
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,149 @@
+use std::fs::File;
+use std::io::{stdin, stdout, BufRead, BufReader, BufWriter, Write};
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+
+use chj_rustbin::cli::{self, VerbosityArgs};
+use chj_rustbin::impl_cli_opt;
+use chj_rustbin::text::delimited::DelimiterArgs;
+use chj_rustbin::text::parseutil::{split_fields, FieldSyntax};
+use chj_rustbin::text::table::{print_table, TableOptions};
+use chj_rustbin::util::cli_output::ColorMode;
+
+#[derive(clap::Parser, Debug)]
+/// Print delimited data (TSV by default) as an aligned table. Unlike
+/// `column -t`, empty fields keep their column (except with
+/// `--whitespace`), and columns containing only numbers are
+/// right-aligned.
+#[clap(name = chj_rustbin::cli_name!())]
+struct Opt {
+    #[clap(flatten)]
+    delimiter_args: DelimiterArgs,
+
+    /// Honor `"` quotes around fields also for other delimiters.
+    #[clap(long)]
+    quoted: bool,
+
+    /// Split fields at runs of whitespace (like `column -t`),
+    /// ignoring leading and trailing whitespace.
+    #[clap(short = 'W', long, conflicts_with_all = &["delimiter", "csv", "quoted"])]
+    whitespace: bool,
+
+    /// The first line is a header (shown emphasized, and counted
+    /// separately from `--rows`).
+    #[clap(short = 'H', long)]
+    header: bool,
+
+    /// Truncate fields to at most this many characters.
+    #[clap(short = 'w', long)]
+    max_width: Option<usize>,
+
+    /// Only show the first this many rows (not counting the header).
+    #[clap(short = 'n', long)]
+    rows: Option<usize>,
+
+    /// Don't right-align numeric columns.
+    #[clap(long)]
+    no_align_numbers: bool,
+
+    /// Whether to use ANSI escapes for emphasis: auto (when stdout
+    /// is a terminal and NO_COLOR is not set), always, or never.
+    #[clap(long, default_value = "auto")]
+    color: ColorMode,
+
+    /// The files to read (stdin if none given). The rows of all
+    /// files are shown as one table.
+    #[clap(parse(from_os_str))]
+    paths: Vec<PathBuf>,
+
+    #[clap(flatten)]
+    verbosity: VerbosityArgs,
+}
+
+impl_cli_opt!(Opt);
+
+enum Splitting {
+    Fields(FieldSyntax),
+    Whitespace,
+}
+
+impl Splitting {
+    fn split(&self, line: &str) -> Result<Vec<String>> {
+        match self {
+            Splitting::Fields(syntax) => split_fields(line, syntax),
+            Splitting::Whitespace => {
+                Ok(line.split_whitespace().map(String::from).collect())
+            }
+        }
+    }
+}
+
+/// Read rows from `inp` into `rows`, until `max_rows` is reached.
+fn read_rows(
+    inp: impl BufRead,
+    splitting: &Splitting,
+    max_rows: usize,
+    rows: &mut Vec<Vec<String>>,
+) -> Result<()> {
+    for (i, line) in inp.lines().enumerate() {
+        if rows.len() >= max_rows {
+            break;
+        }
+        let line = line?;
+        rows.push(
+            splitting
+                .split(&line)
+                .with_context(|| format!("line {}", i + 1))?,
+        );
+    }
+    Ok(())
+}
+
+fn main() {
+    cli::main(run)
+}
+
+fn run(opt: Opt) -> Result<()> {
+    let splitting = if opt.whitespace {
+        Splitting::Whitespace
+    } else {
+        let mut syntax = opt.delimiter_args.syntax()?;
+        if opt.quoted {
+            syntax.quote = Some('"');
+        }
+        Splitting::Fields(syntax)
+    };
+    let max_rows = match opt.rows {
+        Some(n) => n + opt.header as usize,
+        None => usize::MAX,
+    };
+    let color = opt.color.enabled_for(1);
+
+    let mut rows = Vec::new();
+    if opt.paths.is_empty() {
+        read_rows(stdin().lock(), &splitting, max_rows, &mut rows)
+            .context("reading stdin")?;
+    } else {
+        for path in &opt.paths {
+            let inp = BufReader::new(
+                File::open(path)
+                    .with_context(|| format!("opening file {:?}", path))?,
+            );
+            read_rows(inp, &splitting, max_rows, &mut rows)
+                .with_context(|| format!("reading file {:?}", path))?;
+        }
+    }
+
+    let opts = TableOptions {
+        header: opt.header,
+        max_width: opt.max_width,
+        color,
+        align_numbers: !opt.no_align_numbers,
+        ..Default::default()
+    };
+    let mut out = BufWriter::new(stdout().lock());
+    print_table(&mut out, &rows, &opts)?;
+    out.flush()?;
+    Ok(())
+}
@@ -0,0 +1,300 @@
+use std::io::{stdout, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use anyhow::{anyhow, bail, Result};
+
+use chj_rustbin::alist::AListBuf;
+use chj_rustbin::cli::{self, VerbosityArgs};
+use chj_rustbin::error::ErrorKind;
+use chj_rustbin::impl_cli_opt;
+use chj_rustbin::io::readwithcontext::{Preprocessing, ReadWithContext};
+use chj_rustbin::text::parseutil::{
+    cleanwhite, parse_key_val_blocks, KeyValNode,
+};
+use chj_rustbin::util::cli_output::{Output, OutputArgs, GREEN, RED};
+
+#[derive(clap::Parser, Debug)]
+/// Compare two config files by their entries instead of their lines:
+/// report the keys that were added, removed, or whose value changed,
+/// ignoring the order of the entries, whitespace around keys and
+/// values, comments (from `#` at the start of a line or after
+/// whitespace), blank lines, and how lines are split with backslash
+/// continuations. Exits with code 1 if there are differences, 0 if
+/// not (like diff(1)); errors exit with the codes from the `error`
+/// module (2 and higher).
+#[clap(name = chj_rustbin::cli_name!())]
+struct Opt {
+    /// The file format: `kv` for `key = value` lines (see
+    /// --separator), or `blocks` for indented `key: value` lines
+    /// (like the output of `wg` or `ip -s link`), where keys are
+    /// reported with the path of their parent lines, separated by `/`
+    /// (e.g. `peer: abc/endpoint`).
+    #[clap(long, default_value = "kv")]
+    format: Format,
+
+    /// With `--format kv`, the character between keys and values.
+    #[clap(short, long, default_value = "=")]
+    separator: char,
+
+    /// Only set the exit code, don't print the differences.
+    #[clap(long)]
+    brief: bool,
+
+    #[clap(flatten)]
+    output_args: OutputArgs,
+
+    /// The old config file.
+    #[clap(parse(from_os_str))]
+    old: PathBuf,
+
+    /// The new config file.
+    #[clap(parse(from_os_str))]
+    new: PathBuf,
+
+    #[clap(flatten)]
+    verbosity: VerbosityArgs,
+}
+
+impl_cli_opt!(Opt);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    KeyValue,
+    Blocks,
+}
+
+impl FromStr for Format {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "kv" => Ok(Format::KeyValue),
+            "blocks" => Ok(Format::Blocks),
+            _ => bail!("unknown format {s:?}, expecting kv or blocks"),
+        }
+    }
+}
+
+type Entries = AListBuf<String, String>;
+
+/// The preprocessed lines of the file at `path`, passed to `f` with
+/// the reader (for error contexts).
+fn read_lines(
+    path: &Path,
+    mut f: impl FnMut(&ReadWithContext, &str) -> Result<()>,
+) -> Result<()> {
+    let mut inp = ReadWithContext::open_path(path)?;
+    inp.set_preprocessing(Preprocessing::all());
+    let mut line = String::new();
+    while inp.easy_read_line(&mut line)? {
+        f(&inp, &line)?;
+    }
+    Ok(())
+}
+
+fn read_key_values(path: &Path, separator: char) -> Result<Entries> {
+    let mut entries = Entries::default();
+    read_lines(path, |inp, line| {
+        let (key, val) = match line.split_once(separator) {
+            Some(kv) => kv,
+            None => {
+                return inp.err_with_context(anyhow!(
+                    "missing {separator:?} between key and value"
+                ))
+            }
+        };
+        let key = cleanwhite(key);
+        if key.is_empty() {
+            return inp.err_with_context(anyhow!("empty key"));
+        }
+        if entries.set(key.into(), cleanwhite(val).into()).is_some() {
+            return inp.err_with_context(anyhow!("duplicate key {key:?}"));
+        }
+        Ok(())
+    })?;
+    Ok(entries)
+}
+
+/// Add the leaves under `nodes` to `entries`, with their paths as
+/// keys (see `Opt::format`).
+fn flatten_blocks(
+    nodes: &[KeyValNode],
+    prefix: &str,
+    entries: &mut Entries,
+) -> Result<()> {
+    for node in nodes {
+        let mut key = format!("{prefix}{}", node.key);
+        if node.children.is_empty() {
+            let val = node.value.clone().unwrap_or_default();
+            if entries.set(key.clone(), val).is_some() {
+                bail!("duplicate key {key:?}")
+            }
+        } else {
+            if let Some(val) = &node.value {
+                key.push_str(": ");
+                key.push_str(val);
+            }
+            key.push('/');
+            flatten_blocks(&node.children, &key, entries)?;
+        }
+    }
+    Ok(())
+}
+
+fn read_blocks(path: &Path) -> Result<Entries> {
+    let mut lines = Vec::new();
+    read_lines(path, |_, line| {
+        lines.push(line.to_string());
+        Ok(())
+    })?;
+    let nodes = parse_key_val_blocks(lines.iter().map(String::as_str));
+    let mut entries = Entries::default();
+    flatten_blocks(&nodes, "", &mut entries)
+        .map_err(|e| ErrorKind::Parse.wrap(e.context(format!("{path:?}"))))?;
+    Ok(entries)
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Change<'t> {
+    Added(&'t str, &'t str),
+    Removed(&'t str, &'t str),
+    Changed(&'t str, &'t str, &'t str),
+}
+
+/// The differences, in the order of the entries in `old`, followed by
+/// the added entries in the order in `new`.
+fn changes<'t>(old: &'t Entries, new: &'t Entries) -> Vec<Change<'t>> {
+    let mut changes = Vec::new();
+    for (key, old_val) in &old.0 {
+        match new.get(key) {
+            None => changes.push(Change::Removed(key, old_val)),
+            Some(new_val) if new_val != old_val => {
+                changes.push(Change::Changed(key, old_val, new_val))
+            }
+            Some(_) => (),
+        }
+    }
+    for (key, new_val) in &new.0 {
+        if old.get(key).is_none() {
+            changes.push(Change::Added(key, new_val));
+        }
+    }
+    changes
+}
+
+fn write_change(
+    output: &mut Output,
+    out: &mut impl Write,
+    change: &Change,
+) -> Result<()> {
+    if output.is_text() {
+        match change {
+            Change::Added(key, val) => writeln!(
+                out,
+                "{}",
+                output.paint(GREEN, &format!("+ {key} = {val}"))
+            )?,
+            Change::Removed(key, val) => writeln!(
+                out,
+                "{}",
+                output.paint(RED, &format!("- {key} = {val}"))
+            )?,
+            Change::Changed(key, old, new) => writeln!(
+                out,
+                "~ {key} = {} -> {}",
+                output.paint(RED, old),
+                output.paint(GREEN, new)
+            )?,
+        }
+    } else {
+        let (change, key, old, new) = match change {
+            Change::Added(key, val) => ("added", key, "", *val),
+            Change::Removed(key, val) => ("removed", key, *val, ""),
+            Change::Changed(key, old, new) => ("changed", key, *old, *new),
+        };
+        output.write_record(
+            out,
+            &[change.into(), (*key).into(), old.into(), new.into()],
+        )?;
+    }
+    Ok(())
+}
+
+fn main() {
+    cli::main(run)
+}
+
+fn run(opt: Opt) -> Result<()> {
+    let read = |path: &Path| match opt.format {
+        Format::KeyValue => read_key_values(path, opt.separator),
+        Format::Blocks => read_blocks(path),
+    };
+    let old = read(&opt.old)?;
+    let new = read(&opt.new)?;
+    let changes = changes(&old, &new);
+    if !opt.brief {
+        let mut output =
+            opt.output_args.output(&["change", "key", "old", "new"]);
+        let mut out = BufWriter::new(stdout().lock());
+        for change in &changes {
+            write_change(&mut output, &mut out, change)?;
+        }
+        out.flush()?;
+    }
+    if opt.verbosity.is_verbose() {
+        eprintln!("confdiff: {} differences", changes.len());
+    }
+    if !changes.is_empty() {
+        std::process::exit(1)
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn t_changes() -> Result<()> {
+        let entries = |s: &[(&str, &str)]| {
+            AListBuf(
+                s.iter()
+                    .map(|(k, v)| (k.to_string(), v.to_string()))
+                    .collect(),
+            )
+        };
+        let old = entries(&[("a", "1"), ("b", "2"), ("c", "3")]);
+        let new = entries(&[("d", "4"), ("c", "3"), ("a", "10")]);
+        assert_eq!(
+            changes(&old, &new),
+            [
+                Change::Changed("a", "1", "10"),
+                Change::Removed("b", "2"),
+                Change::Added("d", "4"),
+            ]
+        );
+        assert_eq!(changes(&new, &new), []);
+
+        let nodes = parse_key_val_blocks(
+            "interface: wg0\n  port: 1\npeer: x\n  endpoint: a\n  \
+             allowed ips: b\npeer: y\n  endpoint: c\nflag"
+                .lines(),
+        );
+        let mut blocks = Entries::default();
+        flatten_blocks(&nodes, "", &mut blocks)?;
+        assert_eq!(
+            blocks,
+            entries(&[
+                ("interface: wg0/port", "1"),
+                ("peer: x/endpoint", "a"),
+                ("peer: x/allowed ips", "b"),
+                ("peer: y/endpoint", "c"),
+                ("flag", ""),
+            ])
+        );
+        let nodes = parse_key_val_blocks("a\n  b: 1\na\n  b: 2".lines());
+        assert!(flatten_blocks(&nodes, "", &mut Entries::default()).is_err());
+        Ok(())
+    }
+}
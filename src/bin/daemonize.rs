@@ -0,0 +1,220 @@
+use anyhow::{anyhow, bail, Context, Result};
+use nix::fcntl::OFlag;
+use nix::sys::stat::Mode;
+use nix::unistd::{close, getpid, pipe2, read, write, Pid};
+use std::ffi::{CString, OsString};
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::os::unix::ffi::OsStringExt;
+use std::os::unix::io::{FromRawFd, RawFd};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use chj_rustbin::cli::{self, VerbosityArgs};
+use chj_rustbin::impl_cli_opt;
+use chj_rustbin::io::child_fds::ChildFds;
+use chj_rustbin::io::logfile::{
+    LogEntry, LogEvent, LogFile, LogFormat, Rotation,
+};
+use chj_rustbin::process::{
+    double_fork, pid_exists, read_pidfile, spawnp, waitpid_until_gone,
+    write_pidfile, SpawnFds, Status,
+};
+
+#[derive(clap::Parser, Debug)]
+/// Run a command as a daemon: in a new session, detached from the
+/// terminal (thus protected from ctrl-C and hangups) via a double
+/// fork, with its stdin from /dev/null and its output appended to a
+/// log file with timestamps. Exits once the command has been started
+/// (with an error if it couldn't be), the process logging its output
+/// stays around until the command exits (and then removes the
+/// pidfile).
+#[clap(name = chj_rustbin::cli_name!())]
+#[clap(trailing_var_arg = true)]
+struct Opt {
+    /// The log file for the output of the command (with mode 0600 if
+    /// created).
+    #[clap(long, parse(from_os_str))]
+    log: PathBuf,
+
+    /// `plain` (`unixtime\t(pid)\tline`) or `jsonl` (which also
+    /// records how the command exited).
+    #[clap(long, default_value = "plain")]
+    log_format: LogFormat,
+
+    /// Write the pid of the command to this file (refusing to start
+    /// if it names a running process).
+    #[clap(long, parse(from_os_str))]
+    pidfile: Option<PathBuf>,
+
+    #[clap(flatten)]
+    verbosity: VerbosityArgs,
+
+    /// The command to run, and its arguments.
+    #[clap(parse(from_os_str), required = true)]
+    command: Vec<OsString>,
+}
+
+impl_cli_opt!(Opt);
+
+/// The started command, in the daemon process.
+struct Daemon<'t> {
+    pid: Pid,
+    output: RawFd,
+    log: LogFile,
+    command: Vec<String>,
+    pidfile: Option<&'t Path>,
+}
+
+impl<'t> Daemon<'t> {
+    fn start(opt: &'t Opt, command: &[CString]) -> Result<Self> {
+        let log =
+            LogFile::open(&opt.log, opt.log_format, &Rotation::default())?;
+        // (Close-on-exec, thus only inherited via the dups.)
+        let (output, output_w) = pipe2(OFlag::O_CLOEXEC)?;
+        let devnull = CString::new("/dev/null")?;
+        let pid = spawnp(
+            command,
+            &SpawnFds::new()
+                .open(0, &devnull, OFlag::O_RDONLY, Mode::empty())
+                .dup_to(output_w, 1)
+                .dup_to(output_w, 2),
+        );
+        close(output_w)?;
+        let pid = pid?;
+        if let Some(pidfile) = &opt.pidfile {
+            write_pidfile(pidfile, pid)?;
+        }
+        Ok(Daemon {
+            pid,
+            output,
+            log,
+            command: opt
+                .command
+                .iter()
+                .map(|s| s.to_string_lossy().into_owned())
+                .collect(),
+            pidfile: opt.pidfile.as_deref(),
+        })
+    }
+
+    fn log_event(&mut self, event: LogEvent) -> Result<()> {
+        self.log.write_entry(&LogEntry {
+            time: SystemTime::now(),
+            pid: getpid().as_raw(),
+            child_pid: Some(self.pid.as_raw()),
+            command: &self.command,
+            event,
+        })
+    }
+
+    /// Log the output of the command until it exits, returning the
+    /// exit code to use.
+    fn run(mut self) -> Result<i32> {
+        let output = unsafe { File::from_raw_fd(self.output) };
+        let mut inp = BufReader::new(output);
+        let mut buf = Vec::new();
+        loop {
+            buf.clear();
+            if inp.read_until(b'\n', &mut buf)? == 0 {
+                break;
+            }
+            if buf.last() == Some(&b'\n') {
+                buf.pop();
+            }
+            let line = String::from_utf8_lossy(&buf).into_owned();
+            self.log_event(LogEvent::Output(&line))?;
+        }
+        let status = waitpid_until_gone(self.pid)?;
+        if let Some(pidfile) = self.pidfile {
+            std::fs::remove_file(pidfile)
+                .with_context(|| anyhow!("removing pidfile {:?}", pidfile))?;
+        }
+        Ok(match status {
+            Status::Normalexit(code) => {
+                self.log_event(LogEvent::Exit(code))?;
+                code
+            }
+            Status::Signalexit(signal) => {
+                self.log_event(LogEvent::Signal(signal.as_str()))?;
+                128 + signal as i32
+            }
+        })
+    }
+}
+
+/// Read the startup report from the daemon: `+` and the pid of the
+/// command if it was started, an error message otherwise.
+fn read_report(fd: RawFd) -> Result<i32> {
+    let mut report = Vec::new();
+    let mut buf = [0; 4096];
+    loop {
+        let n = read(fd, &mut buf)?;
+        if n == 0 {
+            break;
+        }
+        report.extend_from_slice(&buf[..n]);
+    }
+    close(fd)?;
+    let report = String::from_utf8_lossy(&report);
+    match report.strip_prefix('+') {
+        Some(pid) => Ok(pid.parse()?),
+        None if report.is_empty() => bail!("the daemon exited during startup"),
+        None => bail!("{report}"),
+    }
+}
+
+fn main() {
+    cli::main(run)
+}
+
+fn run(opt: Opt) -> Result<()> {
+    if let Some(pidfile) = &opt.pidfile {
+        if pidfile.exists() {
+            let pid = read_pidfile(pidfile)?;
+            if pid_exists(pid)? {
+                bail!("already running as pid {pid} (from {pidfile:?})")
+            }
+        }
+    }
+    let command = opt
+        .command
+        .iter()
+        .map(|s| CString::new(s.clone().into_vec()))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let (report_r, report_w) = pipe2(OFlag::O_CLOEXEC)?;
+    let daemon_pid = double_fork(|| {
+        close(report_r)?;
+        let daemon = match Daemon::start(&opt, &command) {
+            Ok(daemon) => daemon,
+            Err(e) => {
+                write(report_w, format!("{e:#}").as_bytes())?;
+                return Ok(1);
+            }
+        };
+        let devnull =
+            nix::fcntl::open("/dev/null", OFlag::O_RDWR, Mode::empty())?;
+        ChildFds::new()
+            .dup_to(devnull, 0)
+            .dup_to(devnull, 1)
+            .dup_to(devnull, 2)
+            .inherit_others()
+            .apply()?;
+        if devnull > 2 {
+            close(devnull)?;
+        }
+        write(report_w, format!("+{}", daemon.pid).as_bytes())?;
+        close(report_w)?;
+        daemon.run()
+    })?;
+    close(report_w)?;
+    let pid = read_report(report_r)?;
+    if opt.verbosity.is_verbose() {
+        eprintln!(
+            "daemonize: started {:?} as pid {pid} (daemon pid {daemon_pid})",
+            opt.command
+        );
+    }
+    Ok(())
+}
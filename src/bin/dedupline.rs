@@ -0,0 +1,209 @@
+use std::collections::{HashMap, HashSet};
+use std::io::{stdin, stdout, BufRead, BufWriter, Write};
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Context, Result};
+use kstring::KString;
+
+use chj_rustbin::cli::{self, VerbosityArgs};
+use chj_rustbin::impl_cli_opt;
+use chj_rustbin::io::readwithcontext::{open_file, trim};
+use chj_rustbin::text::parseutil::LineNormalization;
+
+#[derive(clap::Parser, Debug)]
+/// Print the lines of the input with duplicates removed, in the order
+/// in which they were first seen (unlike `sort -u`, and unlike `uniq`
+/// the duplicates don't need to be adjacent).
+#[clap(name = chj_rustbin::cli_name!())]
+struct Opt {
+    /// Keep the last occurrence of each line instead of the first
+    /// (output is in the order of the last occurrences).
+    #[clap(long)]
+    last: bool,
+
+    /// Prefix each output line with the number of its occurrences
+    /// and a tab.
+    #[clap(short, long)]
+    count: bool,
+
+    /// Ignore leading and trailing whitespace when comparing lines.
+    #[clap(long)]
+    trim: bool,
+
+    /// Ignore case when comparing lines.
+    #[clap(short, long)]
+    ignore_case: bool,
+
+    /// The files to read (stdin if none given), treated as one
+    /// input.
+    #[clap(parse(from_os_str))]
+    paths: Vec<PathBuf>,
+
+    #[clap(flatten)]
+    verbosity: VerbosityArgs,
+}
+
+impl_cli_opt!(Opt);
+
+struct Seen {
+    /// The position of the first (or last) occurrence, for the
+    /// output order.
+    position: usize,
+    /// The first (or last) occurrence as it was in the input.
+    line: String,
+    count: usize,
+}
+
+/// Collects the lines, unless it can stream them out directly.
+struct Dedup<'o, W: Write> {
+    normalization: LineNormalization,
+    last: bool,
+    /// Set when streaming (keeping the first occurrence without
+    /// counting).
+    out: Option<&'o mut W>,
+    streamed: HashSet<KString>,
+    seen: HashMap<KString, Seen>,
+    position: usize,
+}
+
+impl<'o, W: Write> Dedup<'o, W> {
+    fn new(
+        normalization: LineNormalization,
+        last: bool,
+        count: bool,
+        out: &'o mut W,
+    ) -> Self {
+        Dedup {
+            normalization,
+            last,
+            out: if last || count { None } else { Some(out) },
+            streamed: HashSet::new(),
+            seen: HashMap::new(),
+            position: 0,
+        }
+    }
+
+    fn add(&mut self, line: &str) -> Result<()> {
+        let key = self.normalization.normalize(line);
+        if let Some(out) = &mut self.out {
+            if !self.streamed.contains(key.as_ref()) {
+                self.streamed.insert(KString::from_ref(&key));
+                out.write_all(line.as_bytes())?;
+                out.write_all(b"\n")?;
+            }
+            return Ok(());
+        }
+        self.position += 1;
+        match self.seen.get_mut(key.as_ref()) {
+            Some(seen) => {
+                seen.count += 1;
+                if self.last {
+                    seen.position = self.position;
+                    seen.line.clear();
+                    seen.line.push_str(line);
+                }
+            }
+            None => {
+                self.seen.insert(
+                    KString::from_ref(&key),
+                    Seen {
+                        position: self.position,
+                        line: line.to_string(),
+                        count: 1,
+                    },
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// The collected lines with their counts, in output order (empty
+    /// when streaming).
+    fn finish(self) -> Vec<(usize, String)> {
+        let mut seen: Vec<Seen> = self.seen.into_values().collect();
+        seen.sort_by_key(|s| s.position);
+        seen.into_iter().map(|s| (s.count, s.line)).collect()
+    }
+}
+
+fn add_lines<W: Write>(
+    mut inp: impl BufRead,
+    dedup: &mut Dedup<W>,
+) -> Result<()> {
+    let mut line = String::new();
+    let mut linenumber = 0;
+    loop {
+        line.clear();
+        linenumber += 1;
+        if inp
+            .read_line(&mut line)
+            .with_context(|| anyhow!("line {linenumber}"))?
+            == 0
+        {
+            return Ok(());
+        }
+        trim(&mut line);
+        dedup.add(&line)?;
+    }
+}
+
+fn main() {
+    cli::main(run)
+}
+
+fn run(opt: Opt) -> Result<()> {
+    let normalization = LineNormalization {
+        trim: opt.trim,
+        ignore_case: opt.ignore_case,
+    };
+    let mut out = BufWriter::new(stdout().lock());
+    let mut dedup = Dedup::new(normalization, opt.last, opt.count, &mut out);
+    if opt.paths.is_empty() {
+        add_lines(stdin().lock(), &mut dedup).context("reading stdin")?;
+    } else {
+        for path in &opt.paths {
+            add_lines(open_file(path)?, &mut dedup)
+                .with_context(|| anyhow!("reading file {:?}", path))?;
+        }
+    }
+    let lines = dedup.finish();
+    for (count, line) in lines {
+        if opt.count {
+            write!(out, "{count}\t")?;
+        }
+        out.write_all(line.as_bytes())?;
+        out.write_all(b"\n")?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn t(last: bool, count: bool, ignore_case: bool) -> String {
+        let mut out = Vec::new();
+        let normalization = LineNormalization {
+            trim: false,
+            ignore_case,
+        };
+        let mut dedup = Dedup::new(normalization, last, count, &mut out);
+        add_lines(&b"b\na\nB\nc\na\nb"[..], &mut dedup).unwrap();
+        let lines = dedup.finish();
+        let mut s = String::from_utf8(out).unwrap();
+        for (count, line) in lines {
+            s.push_str(&format!("{count} {line},"));
+        }
+        s
+    }
+
+    #[test]
+    fn t_dedup() {
+        assert_eq!(t(false, false, false), "b\na\nB\nc\n");
+        assert_eq!(t(false, true, false), "2 b,2 a,1 B,1 c,");
+        assert_eq!(t(false, true, true), "3 b,2 a,1 c,");
+        assert_eq!(t(true, false, false), "1 B,1 c,2 a,2 b,");
+        assert_eq!(t(true, true, true), "1 c,2 a,3 b,");
+    }
+}
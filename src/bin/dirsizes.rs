@@ -0,0 +1,141 @@
+use std::io::{stdout, BufWriter, Write};
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use anyhow::{anyhow, bail, Result};
+
+use chj_rustbin::cli::{self, VerbosityArgs};
+use chj_rustbin::error::ErrorKind;
+use chj_rustbin::impl_cli_opt;
+use chj_rustbin::io::unix_fs::{disk_usage, DirUsage, SizeMode};
+use chj_rustbin::util::cli_output::{Output, OutputArgs};
+
+#[derive(clap::Parser, Debug)]
+/// Show the disk usage of a directory and its subdirectories, like
+/// du(1), but scanning subdirectories in parallel, and counting files
+/// with multiple hard links only once. Like du, prints the
+/// subdirectories before their parent. Unreadable entries are
+/// reported as warnings, and make the program exit with an error at
+/// the end.
+#[clap(name = chj_rustbin::cli_name!())]
+struct Opt {
+    /// count the sizes of the files instead of the disk blocks
+    /// allocated to them
+    #[clap(long)]
+    apparent_size: bool,
+
+    /// don't count directories on other mounts (file systems, or
+    /// bind mounts) than the given directory
+    #[clap(short = 'x', long, alias = "xdev")]
+    one_file_system: bool,
+
+    /// only show directories up to this many levels below the given
+    /// ones (their sizes still include everything below them)
+    #[clap(short = 'd', long)]
+    max_depth: Option<usize>,
+
+    /// only show the totals of the given directories (same as
+    /// `--max-depth 0`)
+    #[clap(short, long, conflicts_with = "max-depth")]
+    summarize: bool,
+
+    /// the order of the subdirectories of each directory: `name` or
+    /// `size` (the largest last)
+    #[clap(long, default_value = "name")]
+    sort: Sort,
+
+    #[clap(flatten)]
+    output_args: OutputArgs,
+
+    /// the directories to scan
+    #[clap(parse(from_os_str), default_value = ".")]
+    directory_paths: Vec<PathBuf>,
+
+    #[clap(flatten)]
+    verbosity: VerbosityArgs,
+}
+
+impl_cli_opt!(Opt);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Sort {
+    Name,
+    Size,
+}
+
+impl FromStr for Sort {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "name" => Ok(Sort::Name),
+            "size" => Ok(Sort::Size),
+            _ => bail!("unknown sort order {s:?}, expecting name or size"),
+        }
+    }
+}
+
+/// Write `usage` and its subdirs up to `max_depth`, subdirs first.
+fn write_usage(
+    output: &mut Output,
+    out: &mut impl Write,
+    usage: &DirUsage,
+    depth: usize,
+    max_depth: Option<usize>,
+    sort: Sort,
+) -> Result<()> {
+    if max_depth.is_none_or(|max| depth < max) {
+        let mut subdirs: Vec<&DirUsage> = usage.subdirs.iter().collect();
+        if sort == Sort::Size {
+            subdirs.sort_by_key(|d| d.bytes);
+        }
+        for subdir in subdirs {
+            write_usage(output, out, subdir, depth + 1, max_depth, sort)?;
+        }
+    }
+    if output.is_text() {
+        writeln!(out, "{}\t{}", usage.bytes, usage.path.to_string_lossy())?;
+    } else {
+        output.write_record(
+            out,
+            &[
+                usage.path.to_string_lossy().into(),
+                usage.bytes.into(),
+                usage.files.into(),
+            ],
+        )?;
+    }
+    Ok(())
+}
+
+fn main() {
+    cli::main(run)
+}
+
+fn run(opt: Opt) -> Result<()> {
+    let size_mode = if opt.apparent_size {
+        SizeMode::Apparent
+    } else {
+        SizeMode::Blocks
+    };
+    let max_depth = if opt.summarize {
+        Some(0)
+    } else {
+        opt.max_depth
+    };
+    let mut output = opt.output_args.output(&["path", "bytes", "files"]);
+    let mut out = BufWriter::new(stdout().lock());
+    let mut errors = 0;
+    for path in &opt.directory_paths {
+        let usage = disk_usage(path, size_mode, opt.one_file_system)?;
+        write_usage(&mut output, &mut out, &usage, 0, max_depth, opt.sort)?;
+        errors += usage.errors;
+    }
+    out.flush()?;
+    if errors > 0 {
+        return Err(ErrorKind::Io.wrap(anyhow!(
+            "{errors} entries could not be read, the sizes are incomplete"
+        )));
+    }
+    Ok(())
+}
@@ -0,0 +1,491 @@
+use std::collections::{HashMap, VecDeque};
+use std::ffi::{CString, OsString};
+use std::fs;
+use std::os::unix::ffi::OsStringExt;
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+use anyhow::{anyhow, Context, Result};
+use clap::ArgMatches;
+use log::warn;
+use nix::errno::Errno;
+use nix::sys::inotify::{
+    AddWatchFlags, InitFlags, Inotify, InotifyEvent, WatchDescriptor,
+};
+
+use chj_rustbin::cli::{self, VerbosityArgs};
+use chj_rustbin::impl_cli_opt;
+use chj_rustbin::io::dirscan::{DirScan, Recursion};
+use chj_rustbin::io::excludes::{
+    default_excludes, empty_excludes, merge_rules, rules_from_env, ExcludeArgs,
+    Excludes,
+};
+use chj_rustbin::io::file_path_type::ItemOptions;
+use chj_rustbin::process::{spawnp, wait_readable, waitpid_until_gone};
+use chj_rustbin::process::{SpawnFds, Status};
+use chj_rustbin::time::realtime::parse_duration;
+
+#[derive(clap::Parser, Debug)]
+/// Watch directories (recursively) and run a command whenever
+/// something in them changes, once the changes have settled for the
+/// `--debounce` time. Uses inotify, or if that is not available (or
+/// the limit on watches is reached), polls by scanning the
+/// directories. Changes made while the command runs trigger another
+/// run, thus exclude the files the command writes itself (e.g. with
+/// `--exclude-profile build`; exclude patterns can also be given as a
+/// colon-separated list in the `DIRWATCH_EXCLUDE` env var). Runs
+/// until interrupted.
+#[clap(name = chj_rustbin::cli_name!())]
+#[clap(trailing_var_arg = true)]
+struct Opt {
+    /// a directory to watch; can be given multiple times (default:
+    /// the current directory)
+    #[clap(
+        short = 'd',
+        long = "dir",
+        multiple_occurrences = true,
+        parse(from_os_str)
+    )]
+    dirs: Vec<PathBuf>,
+
+    /// run the command once there were no further changes for this
+    /// long (e.g. `0.5`, `2s`)
+    #[clap(long, default_value = "0.2")]
+    debounce: String,
+
+    /// run the command once at startup, too
+    #[clap(long)]
+    initial: bool,
+
+    /// exit (with the command's exit code) when the command fails,
+    /// instead of continuing to watch
+    #[clap(long)]
+    exit_on_failure: bool,
+
+    /// always poll instead of using inotify
+    #[clap(long)]
+    poll: bool,
+
+    /// the interval between scans when polling
+    #[clap(long, default_value = "1")]
+    interval: String,
+
+    /// do not ignore dot and Emacs backup (ending in '~') files
+    #[clap(short, long)]
+    all: bool,
+
+    /// do not ignore special file and dir names that are ignored by
+    /// default, like .git; you still need `--all` as well to lift its
+    /// ignores, too, if you want to not ignore anything
+    #[clap(long)]
+    no_ignore: bool,
+
+    #[clap(flatten)]
+    exclude_args: ExcludeArgs,
+
+    #[clap(flatten)]
+    verbosity: VerbosityArgs,
+
+    /// the command to run, and its arguments
+    #[clap(parse(from_os_str), required = true)]
+    command: Vec<OsString>,
+}
+
+impl_cli_opt!(Opt);
+
+const WATCH_FLAGS: AddWatchFlags = AddWatchFlags::from_bits_truncate(
+    AddWatchFlags::IN_MODIFY.bits()
+        | AddWatchFlags::IN_ATTRIB.bits()
+        | AddWatchFlags::IN_CREATE.bits()
+        | AddWatchFlags::IN_DELETE.bits()
+        | AddWatchFlags::IN_MOVED_FROM.bits()
+        | AddWatchFlags::IN_MOVED_TO.bits()
+        | AddWatchFlags::IN_DELETE_SELF.bits()
+        | AddWatchFlags::IN_MOVE_SELF.bits()
+        | AddWatchFlags::IN_ONLYDIR.bits()
+        | AddWatchFlags::IN_DONT_FOLLOW.bits(),
+);
+
+/// Whether `e` means that inotify can't be used (any more), as
+/// opposed to a problem with the watched directories.
+fn is_inotify_unavailable(e: &anyhow::Error) -> bool {
+    matches!(
+        e.downcast_ref::<Errno>(),
+        Some(Errno::ENOSPC | Errno::EMFILE | Errno::ENOSYS)
+    )
+}
+
+struct InotifyWatcher<'t> {
+    inotify: Inotify,
+    excludes: &'t Excludes,
+    roots: &'t [PathBuf],
+    dirs: HashMap<WatchDescriptor, PathBuf>,
+    /// Changed paths read but not returned yet.
+    pending: VecDeque<PathBuf>,
+}
+
+impl<'t> InotifyWatcher<'t> {
+    fn new(excludes: &'t Excludes, roots: &'t [PathBuf]) -> Result<Self> {
+        let mut watcher = InotifyWatcher {
+            inotify: Inotify::init(
+                InitFlags::IN_CLOEXEC | InitFlags::IN_NONBLOCK,
+            )?,
+            excludes,
+            roots,
+            dirs: HashMap::new(),
+            pending: VecDeque::new(),
+        };
+        for root in roots {
+            watcher.add_tree(root, true)?;
+        }
+        Ok(watcher)
+    }
+
+    /// Watch `dir` and the non-excluded dirs below it. Dirs other than
+    /// the roots that vanished in the meantime are skipped.
+    fn add_tree(&mut self, dir: &Path, is_root: bool) -> Result<()> {
+        let wd = match self.inotify.add_watch(dir, WATCH_FLAGS) {
+            Ok(wd) => wd,
+            Err(Errno::ENOENT | Errno::ENOTDIR) if !is_root => return Ok(()),
+            Err(e) => {
+                return Err(
+                    anyhow::Error::from(e).context(anyhow!("watching {dir:?}"))
+                )
+            }
+        };
+        self.dirs.insert(wd, dir.to_owned());
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(e) if !is_root && e.kind() == std::io::ErrorKind::NotFound => {
+                return Ok(())
+            }
+            Err(e) => {
+                return Err(e).with_context(|| anyhow!("reading {dir:?}"))
+            }
+        };
+        for entry in entries {
+            let entry = entry.with_context(|| anyhow!("reading {dir:?}"))?;
+            let is_dir = entry.file_type().is_ok_and(|t| t.is_dir());
+            if is_dir
+                && !self.excludes.filename_is_excluded(&entry.file_name(), true)
+            {
+                self.add_tree(&entry.path(), false)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn handle_event(&mut self, event: InotifyEvent) -> Result<()> {
+        if event.mask.contains(AddWatchFlags::IN_Q_OVERFLOW) {
+            // Directories may have been created unnoticed
+            for root in self.roots {
+                self.add_tree(root, true)?;
+                self.pending.push_back(root.clone());
+            }
+            return Ok(());
+        }
+        if event.mask.contains(AddWatchFlags::IN_IGNORED) {
+            self.dirs.remove(&event.wd);
+            return Ok(());
+        }
+        let dir = match self.dirs.get(&event.wd) {
+            Some(dir) => dir.clone(),
+            None => return Ok(()),
+        };
+        match event.name {
+            Some(name) => {
+                let is_dir = event.mask.contains(AddWatchFlags::IN_ISDIR);
+                if self.excludes.filename_is_excluded(&name, is_dir) {
+                    return Ok(());
+                }
+                let path = dir.join(name);
+                if is_dir
+                    && event.mask.intersects(
+                        AddWatchFlags::IN_CREATE | AddWatchFlags::IN_MOVED_TO,
+                    )
+                {
+                    self.add_tree(&path, false)?;
+                }
+                self.pending.push_back(path);
+            }
+            None => self.pending.push_back(dir),
+        }
+        Ok(())
+    }
+
+    /// A changed path, or None if `deadline` passed first.
+    fn wait_change(
+        &mut self,
+        deadline: Option<Instant>,
+    ) -> Result<Option<PathBuf>> {
+        loop {
+            if let Some(path) = self.pending.pop_front() {
+                return Ok(Some(path));
+            }
+            if !wait_readable(self.inotify.as_raw_fd(), deadline)? {
+                return Ok(None);
+            }
+            let events = match self.inotify.read_events() {
+                Ok(events) => events,
+                Err(Errno::EAGAIN) => continue,
+                Err(e) => return Err(e.into()),
+            };
+            for event in events {
+                self.handle_event(event)?;
+            }
+        }
+    }
+}
+
+type Snapshot = HashMap<PathBuf, (SystemTime, u64)>;
+
+struct PollWatcher<'t> {
+    scan: DirScan<'t>,
+    roots: &'t [PathBuf],
+    interval: Duration,
+    snapshot: Snapshot,
+    next_scan: Instant,
+}
+
+impl<'t> PollWatcher<'t> {
+    fn new(
+        excludes: &'t Excludes,
+        roots: &'t [PathBuf],
+        interval: Duration,
+    ) -> Result<Self> {
+        let mut watcher = PollWatcher {
+            scan: DirScan {
+                opt: ItemOptions {
+                    dirs: true,
+                    files: true,
+                    other: true,
+                    follow_symlinks: false,
+                    one_file_system: false,
+                },
+                excludes,
+                recursion: Recursion::All,
+            },
+            roots,
+            interval,
+            snapshot: Snapshot::new(),
+            next_scan: Instant::now() + interval,
+        };
+        watcher.snapshot = watcher.take_snapshot()?;
+        Ok(watcher)
+    }
+
+    fn take_snapshot(&self) -> Result<Snapshot> {
+        let mut snapshot = Snapshot::new();
+        for root in self.roots {
+            for item in self.scan.collect(root)? {
+                snapshot.insert(item.path(), (item.mtime, item.size));
+            }
+        }
+        Ok(snapshot)
+    }
+
+    fn wait_change(
+        &mut self,
+        deadline: Option<Instant>,
+    ) -> Result<Option<PathBuf>> {
+        loop {
+            if let Some(deadline) = deadline {
+                if deadline < self.next_scan {
+                    std::thread::sleep(
+                        deadline.saturating_duration_since(Instant::now()),
+                    );
+                    return Ok(None);
+                }
+            }
+            std::thread::sleep(
+                self.next_scan.saturating_duration_since(Instant::now()),
+            );
+            self.next_scan = Instant::now() + self.interval;
+            let snapshot = match self.take_snapshot() {
+                Ok(snapshot) => snapshot,
+                Err(e) => {
+                    // Probably entries vanishing during the scan
+                    warn!("scan failed, retrying: {e:#}");
+                    continue;
+                }
+            };
+            let changed = snapshot
+                .iter()
+                .find(|(path, md)| self.snapshot.get(*path) != Some(md))
+                .map(|(path, _)| path)
+                .or_else(|| {
+                    self.snapshot
+                        .keys()
+                        .find(|path| !snapshot.contains_key(*path))
+                })
+                .cloned();
+            self.snapshot = snapshot;
+            if changed.is_some() {
+                return Ok(changed);
+            }
+        }
+    }
+}
+
+enum Watcher<'t> {
+    Inotify(InotifyWatcher<'t>),
+    Poll(PollWatcher<'t>),
+}
+
+impl<'t> Watcher<'t> {
+    /// Polls if `poll` is true or inotify is not available.
+    fn new(
+        excludes: &'t Excludes,
+        roots: &'t [PathBuf],
+        poll: bool,
+        interval: Duration,
+    ) -> Result<Self> {
+        if !poll {
+            match InotifyWatcher::new(excludes, roots) {
+                Ok(watcher) => return Ok(Watcher::Inotify(watcher)),
+                Err(e) if is_inotify_unavailable(&e) => {
+                    warn!("can't use inotify, polling instead: {e:#}")
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(Watcher::Poll(PollWatcher::new(excludes, roots, interval)?))
+    }
+
+    /// A changed path, or None if `deadline` passed first. Switches
+    /// to polling if inotify fails (reporting a change then, since
+    /// watches are typically added because of one).
+    fn wait_change(
+        &mut self,
+        deadline: Option<Instant>,
+        interval: Duration,
+    ) -> Result<Option<PathBuf>> {
+        match self {
+            Watcher::Inotify(watcher) => match watcher.wait_change(deadline) {
+                Err(e) if is_inotify_unavailable(&e) => {
+                    warn!("inotify failed, polling instead: {e:#}");
+                    let (excludes, roots) = (watcher.excludes, watcher.roots);
+                    *self = Watcher::Poll(PollWatcher::new(
+                        excludes, roots, interval,
+                    )?);
+                    Ok(roots.first().cloned())
+                }
+                result => result,
+            },
+            Watcher::Poll(watcher) => watcher.wait_change(deadline),
+        }
+    }
+}
+
+/// Run the command, returning whether it succeeded (reporting
+/// failures on stderr), and the exit code to use for failures.
+fn run_command(command: &[CString]) -> Result<(bool, i32)> {
+    let pid = spawnp(command, &SpawnFds::new())?;
+    let program = cli::program_name();
+    Ok(match waitpid_until_gone(pid)? {
+        Status::Normalexit(0) => (true, 0),
+        Status::Normalexit(code) => {
+            eprintln!("{program}: command exited with code {code}");
+            (false, code)
+        }
+        Status::Signalexit(signal) => {
+            eprintln!("{program}: command was killed by signal {signal}");
+            (false, 128 + signal as i32)
+        }
+    })
+}
+
+fn main() {
+    cli::main_with_matches(run)
+}
+
+fn run(opt: Opt, matches: &ArgMatches) -> Result<()> {
+    let debounce = parse_duration(&opt.debounce)?;
+    let interval = parse_duration(&opt.interval)?;
+    let command = opt
+        .command
+        .iter()
+        .map(|s| CString::new(s.clone().into_vec()))
+        .collect::<Result<Vec<_>, _>>()?;
+    let dirs = if opt.dirs.is_empty() {
+        vec![PathBuf::from(".")]
+    } else {
+        opt.dirs.clone()
+    };
+
+    let mut excludes = if opt.no_ignore {
+        empty_excludes(opt.all)
+    } else {
+        default_excludes(opt.all)
+    };
+    excludes.rules = merge_rules(vec![
+        rules_from_env("DIRWATCH_EXCLUDE"),
+        opt.exclude_args.rules(matches)?,
+    ]);
+
+    let mut watcher = Watcher::new(&excludes, &dirs, opt.poll, interval)?;
+    let run_once = || -> Result<()> {
+        let (ok, code) = run_command(&command)?;
+        if !ok && opt.exit_on_failure {
+            std::process::exit(code)
+        }
+        Ok(())
+    };
+    if opt.initial {
+        run_once()?;
+    }
+    loop {
+        let path = watcher.wait_change(None, interval)?;
+        if opt.verbosity.is_verbose() {
+            eprintln!("dirwatch: change at {:?}", path.unwrap_or_default());
+        }
+        while watcher
+            .wait_change(Some(Instant::now() + debounce), interval)?
+            .is_some()
+        {}
+        run_once()?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chj_rustbin::io::excludes::ExcludeProfile;
+    use chj_rustbin::io::unix_fs::{cstr_as_path, TempDir};
+    use std::os::unix::ffi::OsStrExt;
+
+    #[test]
+    fn t_watchers() -> Result<()> {
+        let tmp = CString::new(std::env::temp_dir().as_os_str().as_bytes())?;
+        let dir = TempDir::new_in(&tmp, "chj-rustbin-test-")?;
+        let root = cstr_as_path(dir.path()).to_path_buf();
+        fs::create_dir(root.join("target"))?;
+        let mut excludes = empty_excludes(false);
+        excludes.rules = ExcludeProfile::Build.rules();
+        let roots = [root.clone()];
+        let interval = Duration::from_millis(20);
+        let soon = || Some(Instant::now() + Duration::from_millis(200));
+        for poll in [false, true] {
+            let mut watcher = Watcher::new(&excludes, &roots, poll, interval)?;
+            assert_eq!(watcher.wait_change(soon(), interval)?, None);
+            fs::write(root.join("target/out"), "")?;
+            fs::write(root.join(".hidden"), "")?;
+            assert_eq!(watcher.wait_change(soon(), interval)?, None);
+
+            let sub = root.join(format!("sub-{poll}"));
+            fs::create_dir(&sub)?;
+            assert_eq!(
+                watcher.wait_change(soon(), interval)?,
+                Some(sub.clone())
+            );
+            while watcher.wait_change(soon(), interval)?.is_some() {}
+            // Watched now, too (polling may report `sub` itself, whose
+            // mtime changes as well)
+            fs::write(sub.join("a"), "")?;
+            let changed = watcher.wait_change(soon(), interval)?;
+            assert!(changed.is_some_and(|path| path.starts_with(&sub)));
+        }
+        Ok(())
+    }
+}
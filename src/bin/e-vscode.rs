@@ -0,0 +1,15 @@
+//! Open each file given in a separate VS Code window (or Neovim, via
+//! `editor: nvim` in `~/.e-vscode_rs.conf`), see
+//! `chj_rustbin::editor_frontend`.
+
+use anyhow::Result;
+
+use chj_rustbin::editor_frontend::{self, Editor, Frontend};
+
+fn main() -> Result<()> {
+    editor_frontend::main(&Frontend {
+        default_editor: Editor::VsCode,
+        config_file: ".e-vscode_rs.conf",
+        log_file: "._e-vscode_rs.log",
+    })
+}
@@ -0,0 +1,161 @@
+use std::fs;
+use std::io::{stdout, BufWriter, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+use anyhow::{anyhow, Context, Result};
+use clap::ArgMatches;
+
+use chj_rustbin::cli::{self, VerbosityArgs};
+use chj_rustbin::error::ErrorKind;
+use chj_rustbin::impl_cli_opt;
+use chj_rustbin::impl_item_options_from;
+use chj_rustbin::io::dirscan::{DirScan, Recursion};
+use chj_rustbin::io::excludes::{
+    default_excludes, empty_excludes, merge_rules, rules_from_env, ExcludeArgs,
+};
+use chj_rustbin::io::file_path_type::ItemOptions;
+use chj_rustbin::time::realtime::parse_time_spec;
+
+#[derive(clap::Parser, Debug)]
+/// List the files below the given directories whose mtime is newer
+/// than that of a reference file, or than a given point in time. The
+/// sibling of `lastitem`, with the same exclusion options (exclude
+/// patterns can also be given as a colon-separated list in the
+/// `FINDNEWER_EXCLUDE` env var). The output is sorted by path.
+#[clap(name = chj_rustbin::cli_name!())]
+struct Opt {
+    /// list items newer than this file's mtime
+    #[clap(long, parse(from_os_str), required_unless_present = "since")]
+    than: Option<PathBuf>,
+
+    /// list items newer than this point in time, e.g. `15min ago`,
+    /// `2024-05-01 12:00`, Unix time or a TAI64N label
+    #[clap(long, conflicts_with = "than")]
+    since: Option<String>,
+
+    /// list dirs, too (by default, only files are listed)
+    #[clap(long)]
+    dirs: bool,
+
+    /// list other items, too (symlinks, pipes, sockets, device files)
+    #[clap(long)]
+    other: bool,
+
+    /// follow symlinks: consider them as the type of their target,
+    /// and use the target's mtime; descends into symlinked dirs
+    #[clap(short = 'L', long)]
+    deref: bool,
+
+    /// don't descend into directories on other mounts (file systems,
+    /// or bind mounts) than the given directory
+    #[clap(short = 'x', long, alias = "xdev")]
+    one_file_system: bool,
+
+    /// do not ignore dot and Emacs backup (ending in '~') files
+    #[clap(short, long)]
+    all: bool,
+
+    /// do not ignore special file and dir names that are ignored by
+    /// default, like .git; you still need `--all` as well to lift its
+    /// ignores, too, if you want to not ignore anything
+    #[clap(long)]
+    no_ignore: bool,
+
+    #[clap(flatten)]
+    exclude_args: ExcludeArgs,
+
+    /// sort the output by mtime (oldest first) instead of by path
+    #[clap(long)]
+    by_mtime: bool,
+
+    /// terminate the output paths with a NUL byte instead of a
+    /// newline (for `xargs -0`)
+    #[clap(short = '0', long = "null")]
+    null: bool,
+
+    /// the directories to search in
+    #[clap(parse(from_os_str), default_value = ".")]
+    directory_paths: Vec<PathBuf>,
+
+    #[clap(skip = true)]
+    files: bool,
+
+    #[clap(flatten)]
+    verbosity: VerbosityArgs,
+}
+
+impl_cli_opt!(Opt);
+
+impl_item_options_from!(Opt);
+
+fn main() {
+    cli::main_with_matches(run)
+}
+
+fn run(opt: Opt, matches: &ArgMatches) -> Result<()> {
+    let threshold =
+        match (&opt.than, &opt.since) {
+            (Some(path), None) => fs::metadata(path)
+                .and_then(|md| md.modified())
+                .with_context(|| anyhow!("getting mtime of {:?}", path))?,
+            (None, Some(since)) => parse_time_spec(since, SystemTime::now())?,
+            _ => {
+                return Err(ErrorKind::Usage
+                    .wrap(anyhow!("need exactly one of --than or --since")))
+            }
+        };
+
+    let mut excludes = if opt.no_ignore {
+        empty_excludes(opt.all)
+    } else {
+        default_excludes(opt.all)
+    };
+    excludes.rules = merge_rules(vec![
+        rules_from_env("FINDNEWER_EXCLUDE"),
+        opt.exclude_args.rules(matches)?,
+    ]);
+
+    let scan = DirScan {
+        opt: ItemOptions {
+            follow_symlinks: opt.deref,
+            one_file_system: opt.one_file_system,
+            ..ItemOptions::from(&opt)
+        },
+        excludes: &excludes,
+        recursion: Recursion::All,
+    };
+    let mut items = Vec::new();
+    for directory_path in &opt.directory_paths {
+        items.extend(scan.fold(
+            directory_path,
+            Vec::new,
+            |mut found, item| {
+                if item.mtime > threshold {
+                    found.push(item);
+                }
+                Ok(found)
+            },
+            |mut a, b| {
+                a.extend(b);
+                a
+            },
+        )?);
+    }
+    if opt.by_mtime {
+        items.sort_by(|a, b| {
+            a.mtime.cmp(&b.mtime).then_with(|| a.path().cmp(&b.path()))
+        });
+    } else {
+        items.sort_by_cached_key(|item| item.path());
+    }
+
+    let mut out = BufWriter::new(stdout().lock());
+    for item in &items {
+        out.write_all(item.path().as_os_str().as_bytes())?;
+        out.write_all(if opt.null { b"\0" } else { b"\n" })?;
+    }
+    out.flush()?;
+    Ok(())
+}
@@ -0,0 +1,360 @@
+use std::collections::HashMap;
+use std::io::{stdout, BufWriter, Write};
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use anyhow::{anyhow, bail, Context, Result};
+
+use chj_rustbin::cli::{self, VerbosityArgs};
+use chj_rustbin::error::Categorize;
+use chj_rustbin::impl_cli_opt;
+use chj_rustbin::sequences::try_fold_grouped;
+use chj_rustbin::text::delimited::{
+    open_inputs, read_rows, Columns, DelimiterArgs,
+};
+
+#[derive(clap::Parser, Debug)]
+/// Group the rows of delimited data (TSV by default) by key columns,
+/// and compute aggregates over value columns, printing a TSV table
+/// with a header row, one row per group (in the order in which the
+/// groups were first seen).
+#[clap(name = chj_rustbin::cli_name!())]
+struct Opt {
+    #[clap(flatten)]
+    delimiter_args: DelimiterArgs,
+
+    /// The first line of each input file is a header; its column
+    /// names can be used in `--key` and `--aggregate` and are used in
+    /// the output header.
+    #[clap(short = 'H', long)]
+    header: bool,
+
+    /// The key columns, by number (starting at 1) or (with --header)
+    /// by name, comma separated. Can be given multiple times.
+    #[clap(short, long, required = true)]
+    key: Vec<String>,
+
+    /// The aggregates to compute, as `FUNCTION:COLUMN` where
+    /// FUNCTION is one of sum, min, max, mean, count, and COLUMN is
+    /// as for --key; `count` alone counts the rows of the group.
+    /// Comma separated, can be given multiple times.
+    #[clap(short, long, required = true)]
+    aggregate: Vec<String>,
+
+    /// The input is already grouped by the key (e.g. sorted), which
+    /// allows streaming with constant memory.
+    #[clap(long)]
+    sorted: bool,
+
+    /// The files to read (stdin if none given).
+    #[clap(parse(from_os_str))]
+    paths: Vec<PathBuf>,
+
+    #[clap(flatten)]
+    verbosity: VerbosityArgs,
+}
+
+impl_cli_opt!(Opt);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Function {
+    Sum,
+    Min,
+    Max,
+    Mean,
+    Count,
+}
+
+impl FromStr for Function {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "sum" => Ok(Function::Sum),
+            "min" => Ok(Function::Min),
+            "max" => Ok(Function::Max),
+            "mean" => Ok(Function::Mean),
+            "count" => Ok(Function::Count),
+            _ => bail!(
+                "unknown aggregate function {s:?}, expecting \
+                 sum|min|max|mean|count"
+            ),
+        }
+    }
+}
+
+impl Function {
+    fn name(self) -> &'static str {
+        match self {
+            Function::Sum => "sum",
+            Function::Min => "min",
+            Function::Max => "max",
+            Function::Mean => "mean",
+            Function::Count => "count",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Aggregate {
+    function: Function,
+    /// None for counting rows.
+    column: Option<usize>,
+}
+
+/// Parse an aggregate specification, `FUNCTION:COLUMN` or `count`.
+fn parse_aggregate(columns: &Columns, spec: &str) -> Result<Aggregate> {
+    let (function, column) = match spec.split_once(':') {
+        Some((f, c)) => (f.parse()?, Some(columns.index(c)?)),
+        None => (spec.parse()?, None),
+    };
+    if column.is_none() && function != Function::Count {
+        bail!("missing column for aggregate {spec:?}")
+    }
+    Ok(Aggregate { function, column })
+}
+
+fn aggregate_name(columns: &Columns, aggregate: &Aggregate) -> String {
+    match aggregate.column {
+        Some(i) => {
+            format!("{} {}", aggregate.function.name(), columns.name(i))
+        }
+        None => aggregate.function.name().into(),
+    }
+}
+/// Running values for one aggregate of one group.
+#[derive(Debug, Clone, Copy)]
+struct Acc {
+    /// Number of rows, or number of non-empty values.
+    n: usize,
+    sum: f64,
+    min: f64,
+    max: f64,
+}
+
+impl Default for Acc {
+    fn default() -> Self {
+        Acc {
+            n: 0,
+            sum: 0.,
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+        }
+    }
+}
+
+impl Acc {
+    fn add(&mut self, aggregate: &Aggregate, row: &[String]) -> Result<()> {
+        let column = match aggregate.column {
+            Some(column) => column,
+            None => {
+                self.n += 1;
+                return Ok(());
+            }
+        };
+        let field = row
+            .get(column)
+            .ok_or_else(|| anyhow!("missing column {}", column + 1))?;
+        if field.is_empty() {
+            return Ok(());
+        }
+        let x: f64 = field.parse().with_context(|| {
+            anyhow!("column {}: invalid number {field:?}", column + 1)
+        })?;
+        self.n += 1;
+        self.sum += x;
+        self.min = self.min.min(x);
+        self.max = self.max.max(x);
+        Ok(())
+    }
+
+    /// The result as a TSV field (empty if there were no values).
+    fn result(&self, function: Function) -> String {
+        if function == Function::Count {
+            return self.n.to_string();
+        }
+        if self.n == 0 {
+            return String::new();
+        }
+        let x = match function {
+            Function::Sum => self.sum,
+            Function::Min => self.min,
+            Function::Max => self.max,
+            Function::Mean => self.sum / self.n as f64,
+            Function::Count => unreachable!(),
+        };
+        x.to_string()
+    }
+}
+
+struct Group {
+    key: Vec<String>,
+    accs: Vec<Acc>,
+}
+
+struct Grouper {
+    key_columns: Vec<usize>,
+    aggregates: Vec<Aggregate>,
+}
+
+impl Grouper {
+    fn key(&self, row: &[String]) -> Result<Vec<String>> {
+        self.key_columns
+            .iter()
+            .map(|i| {
+                row.get(*i)
+                    .cloned()
+                    .ok_or_else(|| anyhow!("missing key column {}", i + 1))
+            })
+            .collect()
+    }
+
+    fn new_group(&self, key: Vec<String>) -> Group {
+        Group {
+            key,
+            accs: vec![Acc::default(); self.aggregates.len()],
+        }
+    }
+
+    fn add(&self, group: &mut Group, row: &[String]) -> Result<()> {
+        for (acc, aggregate) in group.accs.iter_mut().zip(&self.aggregates) {
+            acc.add(aggregate, row)?;
+        }
+        Ok(())
+    }
+
+    fn write_group(&self, out: &mut impl Write, group: &Group) -> Result<()> {
+        let mut fields = group.key.clone();
+        for (acc, aggregate) in group.accs.iter().zip(&self.aggregates) {
+            fields.push(acc.result(aggregate.function));
+        }
+        writeln!(out, "{}", fields.join("\t"))?;
+        Ok(())
+    }
+}
+
+fn main() {
+    cli::main(run)
+}
+
+fn run(opt: Opt) -> Result<()> {
+    let syntax = opt.delimiter_args.syntax()?;
+    let inputs = open_inputs(&opt.paths)?;
+    let mut rows = read_rows(inputs, syntax, opt.header);
+
+    let columns = Columns(if opt.header {
+        rows.next().transpose()?
+    } else {
+        None
+    });
+    let split_specs = |specs: &[String]| -> Vec<String> {
+        specs
+            .iter()
+            .flat_map(|s| s.split(','))
+            .map(String::from)
+            .collect()
+    };
+    let grouper = Grouper {
+        key_columns: split_specs(&opt.key)
+            .iter()
+            .map(|spec| columns.index(spec))
+            .collect::<Result<_>>()?,
+        aggregates: split_specs(&opt.aggregate)
+            .iter()
+            .map(|spec| parse_aggregate(&columns, spec))
+            .collect::<Result<_>>()
+            .usage_error()?,
+    };
+
+    let mut out = BufWriter::new(stdout().lock());
+    let header: Vec<String> = grouper
+        .key_columns
+        .iter()
+        .map(|i| columns.name(*i))
+        .chain(
+            grouper
+                .aggregates
+                .iter()
+                .map(|a| aggregate_name(&columns, a)),
+        )
+        .collect();
+    writeln!(out, "{}", header.join("\t"))?;
+
+    let keyed_rows = rows.map(|row| -> Result<(Vec<String>, Vec<String>)> {
+        let row = row?;
+        Ok((grouper.key(&row)?, row))
+    });
+    if opt.sorted {
+        let groups = try_fold_grouped(
+            keyed_rows,
+            |a, b| a.0 == b.0,
+            || None,
+            |group: Option<Result<Group>>, (key, row)| {
+                let mut group = match group {
+                    Some(Ok(group)) => group,
+                    Some(Err(e)) => return Some(Err(e)),
+                    None => grouper.new_group(key),
+                };
+                Some(grouper.add(&mut group, &row).map(|()| group))
+            },
+        );
+        for group in groups {
+            let group = group?.expect("groups are non-empty")?;
+            grouper.write_group(&mut out, &group)?;
+        }
+    } else {
+        let mut groups: Vec<Group> = Vec::new();
+        let mut index: HashMap<Vec<String>, usize> = HashMap::new();
+        for keyed_row in keyed_rows {
+            let (key, row) = keyed_row?;
+            let i = match index.get(&key) {
+                Some(i) => *i,
+                None => {
+                    groups.push(grouper.new_group(key.clone()));
+                    index.insert(key, groups.len() - 1);
+                    groups.len() - 1
+                }
+            };
+            grouper.add(&mut groups[i], &row)?;
+        }
+        for group in &groups {
+            grouper.write_group(&mut out, group)?;
+        }
+    }
+    out.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn t_aggregates() {
+        let columns = Columns(Some(vec!["host".into(), "bytes".into()]));
+        let grouper = Grouper {
+            key_columns: vec![0],
+            aggregates: ["sum:bytes", "min:2", "max:2", "mean:2", "count"]
+                .iter()
+                .map(|s| parse_aggregate(&columns, s).unwrap())
+                .collect(),
+        };
+        assert_eq!(
+            aggregate_name(&columns, &grouper.aggregates[0]),
+            "sum bytes"
+        );
+        let mut group = grouper.new_group(vec!["a".into()]);
+        for row in [["a", "3"], ["a", ""], ["a", "1.5"]] {
+            let row: Vec<String> = row.iter().map(|s| s.to_string()).collect();
+            grouper.add(&mut group, &row).unwrap();
+        }
+        let mut out = Vec::new();
+        grouper.write_group(&mut out, &group).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "a\t4.5\t1.5\t3\t2.25\t3\n"
+        );
+        assert!(grouper.add(&mut group, &["a".into(), "x".into()]).is_err());
+        assert!(parse_aggregate(&columns, "sum").is_err());
+    }
+}
@@ -0,0 +1,299 @@
+use anyhow::{anyhow, Context, Result};
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::hash::Hasher;
+use std::io::{stdout, BufWriter, Read, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+
+use chj_rustbin::cli::{self, VerbosityArgs};
+use chj_rustbin::impl_cli_opt;
+use chj_rustbin::io::dirscan::{DirScan, Recursion};
+use chj_rustbin::io::excludes::empty_excludes;
+use chj_rustbin::io::file_path_type::ItemOptions;
+use chj_rustbin::io::unix_fs::{easy_stat, EasyMetadata, FileType};
+use chj_rustbin::util::cli_output::OutputArgs;
+
+#[derive(clap::Parser, Debug)]
+/// Find identical files in the given directory trees and replace the
+/// duplicates with hardlinks to one of them. Files are grouped by
+/// size, then by a hash of their contents, and are compared byte by
+/// byte before being linked. Only files on the same file system, and
+/// with the same owner, group and permissions are linked (so that
+/// linking doesn't change the metadata of any of the paths).
+#[clap(name = chj_rustbin::cli_name!())]
+struct Opt {
+    /// Only report which files would be replaced by hardlinks.
+    #[clap(short = 'n', long)]
+    dry_run: bool,
+
+    /// Ignore files smaller than this many bytes.
+    #[clap(long, default_value = "1")]
+    min_size: u64,
+
+    /// Don't descend into directories on other file systems.
+    #[clap(short = 'x', long)]
+    one_file_system: bool,
+
+    #[clap(flatten)]
+    output_args: OutputArgs,
+
+    /// The trees to scan.
+    #[clap(parse(from_os_str), required = true)]
+    dir_paths: Vec<PathBuf>,
+
+    #[clap(flatten)]
+    verbosity: VerbosityArgs,
+}
+
+impl_cli_opt!(Opt);
+
+/// A file found in the scan, with all of its paths found.
+struct Inode {
+    paths: Vec<PathBuf>,
+    metadata: EasyMetadata,
+}
+
+impl Inode {
+    fn path(&self) -> &Path {
+        &self.paths[0]
+    }
+}
+
+/// Files that can only be linked if they have the same key.
+#[derive(Debug, PartialEq, Eq, Hash)]
+struct Key {
+    dev: u64,
+    size: u64,
+    uid: u32,
+    gid: u32,
+    permissions: u32,
+}
+
+impl Key {
+    fn of(m: &EasyMetadata) -> Self {
+        Key {
+            dev: m.dev,
+            size: m.size,
+            uid: m.uid,
+            gid: m.gid,
+            permissions: m.permissions,
+        }
+    }
+}
+
+const BUFSIZ: usize = 64 * 1024;
+
+fn open(path: &Path) -> Result<File> {
+    File::open(path).with_context(|| anyhow!("opening {:?}", path))
+}
+
+/// Read into `buf` until it is full or at EOF.
+fn read_full(inp: &mut File, buf: &mut [u8], path: &Path) -> Result<usize> {
+    let mut n = 0;
+    while n < buf.len() {
+        match inp
+            .read(&mut buf[n..])
+            .with_context(|| anyhow!("reading {:?}", path))?
+        {
+            0 => break,
+            m => n += m,
+        }
+    }
+    Ok(n)
+}
+
+fn content_hash(path: &Path) -> Result<u64> {
+    let mut inp = open(path)?;
+    let mut hasher = DefaultHasher::new();
+    let mut buf = vec![0; BUFSIZ];
+    loop {
+        let n = read_full(&mut inp, &mut buf, path)?;
+        if n == 0 {
+            return Ok(hasher.finish());
+        }
+        hasher.write(&buf[..n]);
+    }
+}
+
+fn same_contents(a: &Path, b: &Path) -> Result<bool> {
+    let (mut inp_a, mut inp_b) = (open(a)?, open(b)?);
+    let (mut buf_a, mut buf_b) = (vec![0; BUFSIZ], vec![0; BUFSIZ]);
+    loop {
+        let n = read_full(&mut inp_a, &mut buf_a, a)?;
+        let m = read_full(&mut inp_b, &mut buf_b, b)?;
+        if buf_a[..n] != buf_b[..m] {
+            return Ok(false);
+        }
+        if n == 0 {
+            return Ok(true);
+        }
+    }
+}
+
+/// Replace `path` with a hardlink to `to`, via a temporary link in the
+/// same directory and rename.
+fn replace_with_link(path: &Path, to: &Path) -> Result<()> {
+    let file_name = path.file_name().expect("files have a file name");
+    let mut tmp_name = b".".to_vec();
+    tmp_name.extend_from_slice(file_name.as_bytes());
+    tmp_name.extend_from_slice(
+        format!(".hardlink-dedupe-{}", std::process::id()).as_bytes(),
+    );
+    let tmp = path.with_file_name(std::ffi::OsStr::from_bytes(&tmp_name));
+    fs::hard_link(to, &tmp)
+        .with_context(|| anyhow!("linking {:?} to {:?}", tmp, to))?;
+    if let Err(e) = fs::rename(&tmp, path) {
+        let _ = fs::remove_file(&tmp);
+        return Err(e)
+            .with_context(|| anyhow!("renaming {:?} to {:?}", tmp, path));
+    }
+    Ok(())
+}
+
+/// The groups of files with the same content hash among `inodes`
+/// (which all have the same `Key`), the one to keep first.
+fn candidate_groups(inodes: Vec<Inode>) -> Result<Vec<Vec<Inode>>> {
+    if inodes.len() < 2 {
+        return Ok(Vec::new());
+    }
+    let hashed = inodes
+        .into_par_iter()
+        .map(|inode| Ok((content_hash(inode.path())?, inode)))
+        .collect::<Result<Vec<_>>>()?;
+    let mut by_hash: HashMap<u64, Vec<Inode>> = HashMap::new();
+    for (hash, inode) in hashed {
+        by_hash.entry(hash).or_default().push(inode);
+    }
+    Ok(by_hash
+        .into_values()
+        .filter(|group| group.len() > 1)
+        .map(|mut group| {
+            // Keep the file with the most links (fewest to change),
+            // then the first path, for reproducibility
+            group.sort_by(|a, b| {
+                b.metadata
+                    .nlink
+                    .cmp(&a.metadata.nlink)
+                    .then_with(|| a.path().cmp(b.path()))
+            });
+            group
+        })
+        .collect())
+}
+
+fn main() {
+    cli::main(run)
+}
+
+fn run(opt: Opt) -> Result<()> {
+    let excludes = empty_excludes(true);
+    let scan = DirScan {
+        opt: ItemOptions {
+            dirs: false,
+            files: true,
+            other: false,
+            follow_symlinks: false,
+            one_file_system: opt.one_file_system,
+        },
+        excludes: &excludes,
+        recursion: Recursion::All,
+    };
+    let min_size = opt.min_size;
+    let mut by_ino: HashMap<(u64, u64), Inode> = HashMap::new();
+    for dir_path in &opt.dir_paths {
+        let files = scan.fold(
+            dir_path,
+            Vec::new,
+            |mut files, item| {
+                let path = item.path();
+                let metadata = easy_stat(&path, false)?;
+                if metadata.filetype == FileType::File
+                    && metadata.size >= min_size
+                {
+                    files.push((path, metadata));
+                }
+                Ok(files)
+            },
+            |mut a, b| {
+                a.extend(b);
+                a
+            },
+        )?;
+        for (path, metadata) in files {
+            by_ino
+                .entry((metadata.dev, metadata.ino))
+                .or_insert_with(|| Inode {
+                    paths: Vec::new(),
+                    metadata,
+                })
+                .paths
+                .push(path);
+        }
+    }
+    let mut by_key: HashMap<Key, Vec<Inode>> = HashMap::new();
+    for mut inode in by_ino.into_values() {
+        inode.paths.sort();
+        inode.paths.dedup();
+        by_key
+            .entry(Key::of(&inode.metadata))
+            .or_default()
+            .push(inode);
+    }
+
+    let mut groups = Vec::new();
+    for inodes in by_key.into_values() {
+        groups.extend(candidate_groups(inodes)?);
+    }
+    groups.sort_by(|a, b| a[0].path().cmp(b[0].path()));
+
+    let mut output = opt.output_args.output(&["path", "linked_to", "size"]);
+    let mut out = BufWriter::new(stdout().lock());
+    let (mut num_linked, mut bytes_saved) = (0, 0);
+    for group in &groups {
+        let original = &group[0];
+        for inode in &group[1..] {
+            // Different contents with the same hash, or changed since
+            // hashing
+            if !same_contents(original.path(), inode.path())? {
+                continue;
+            }
+            for path in &inode.paths {
+                if output.is_text() {
+                    writeln!(
+                        out,
+                        "{} => {}",
+                        path.display(),
+                        original.path().display()
+                    )?;
+                } else {
+                    output.write_record(
+                        &mut out,
+                        &[
+                            path.to_string_lossy().into(),
+                            original.path().to_string_lossy().into(),
+                            inode.metadata.size.into(),
+                        ],
+                    )?;
+                }
+                if !opt.dry_run {
+                    replace_with_link(path, original.path())?;
+                }
+                num_linked += 1;
+            }
+            // The space is only freed if there are no links outside
+            // the scanned trees
+            if inode.metadata.nlink == inode.paths.len() as u64 {
+                bytes_saved += inode.metadata.size;
+            }
+        }
+    }
+    out.flush()?;
+    eprintln!(
+        "hardlink-dedupe: {} {num_linked} file(s), saving {bytes_saved} bytes",
+        if opt.dry_run { "would link" } else { "linked" }
+    );
+    Ok(())
+}
@@ -0,0 +1,298 @@
+use std::collections::VecDeque;
+use std::io::{stdout, BufWriter, ErrorKind, Read, Write};
+use std::path::PathBuf;
+use std::process::exit;
+
+use anyhow::{Context, Result};
+use clap::Parser;
+
+use chj_rustbin::io::readwithcontext::open_file;
+
+#[derive(clap::Parser, Debug)]
+/// Compare two files byte by byte and show the lines (at the same
+/// offsets in both files) that differ, hexdump style: `-` lines are
+/// from the first file, `+` lines from the second, ` ` lines are
+/// context that is the same in both. Exits with 0 if the files are
+/// the same, 1 if they differ, 2 on errors (like `cmp`).
+#[clap(name = chj_rustbin::cli_name!())]
+struct Opt {
+    /// The number of bytes per line.
+    #[clap(short, long, default_value = "16")]
+    width: usize,
+
+    /// The number of unchanged lines to show before and after each
+    /// difference.
+    #[clap(short = 'C', long, default_value = "1")]
+    context: usize,
+
+    /// Stop after showing this many differences (runs of adjacent
+    /// differing lines).
+    #[clap(long)]
+    max_diffs: Option<usize>,
+
+    /// Don't print anything, only report via the exit code.
+    #[clap(short, long)]
+    quiet: bool,
+
+    #[clap(parse(from_os_str))]
+    path_a: PathBuf,
+
+    #[clap(parse(from_os_str))]
+    path_b: PathBuf,
+}
+
+/// Read until `buf` is full or EOF is reached, returns the number of
+/// bytes read.
+fn read_full(inp: &mut impl Read, buf: &mut [u8]) -> std::io::Result<usize> {
+    let mut n = 0;
+    while n < buf.len() {
+        match inp.read(&mut buf[n..]) {
+            Ok(0) => break,
+            Ok(m) => n += m,
+            Err(e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(n)
+}
+
+/// Format one line: the marker, the offset, the bytes as hex (padded
+/// to `width`), and as ASCII.
+fn format_line(
+    marker: char,
+    offset: u64,
+    bytes: &[u8],
+    width: usize,
+) -> String {
+    let mut s = format!("{marker}{offset:08x} ");
+    for i in 0..width {
+        match bytes.get(i) {
+            Some(b) => s.push_str(&format!(" {b:02x}")),
+            None => s.push_str("   "),
+        }
+    }
+    s.push_str("  |");
+    for b in bytes {
+        s.push(if b.is_ascii_graphic() || *b == b' ' {
+            *b as char
+        } else {
+            '.'
+        });
+    }
+    s.push('|');
+    s
+}
+
+struct Line {
+    offset: u64,
+    a: Vec<u8>,
+    b: Vec<u8>,
+}
+
+struct Printer<'o, W: Write> {
+    out: &'o mut W,
+    width: usize,
+    /// The offset of the line after the last printed one.
+    next_offset: Option<u64>,
+}
+
+impl<'o, W: Write> Printer<'o, W> {
+    fn print(&mut self, line: &Line) -> Result<()> {
+        if let Some(next) = self.next_offset {
+            if next != line.offset {
+                writeln!(self.out, "--")?;
+            }
+        }
+        if line.a == line.b {
+            writeln!(
+                self.out,
+                "{}",
+                format_line(' ', line.offset, &line.a, self.width)
+            )?;
+        } else {
+            if !line.a.is_empty() {
+                writeln!(
+                    self.out,
+                    "{}",
+                    format_line('-', line.offset, &line.a, self.width)
+                )?;
+            }
+            if !line.b.is_empty() {
+                writeln!(
+                    self.out,
+                    "{}",
+                    format_line('+', line.offset, &line.b, self.width)
+                )?;
+            }
+        }
+        self.next_offset = Some(line.offset + self.width as u64);
+        Ok(())
+    }
+}
+
+/// Returns the number of differences found, and whether the
+/// comparison was stopped early (at `max_diffs` when given, or after
+/// the first difference if `out` is None).
+fn hexdump_diff(
+    mut a: impl Read,
+    mut b: impl Read,
+    out: Option<&mut impl Write>,
+    width: usize,
+    context: usize,
+    max_diffs: Option<usize>,
+) -> Result<(usize, bool)> {
+    let mut printer = out.map(|out| Printer {
+        out,
+        width,
+        next_offset: None,
+    });
+    let max_diffs = if printer.is_some() {
+        max_diffs
+    } else {
+        Some(1)
+    };
+    let mut diffs = 0;
+    let mut before: VecDeque<Line> = VecDeque::new();
+    let mut after_remaining = 0;
+    let mut previous_differed = false;
+    let mut offset = 0;
+    let mut stopped = false;
+    loop {
+        let mut line = Line {
+            offset,
+            a: vec![0; width],
+            b: vec![0; width],
+        };
+        let na = read_full(&mut a, &mut line.a).context("reading file A")?;
+        let nb = read_full(&mut b, &mut line.b).context("reading file B")?;
+        if na == 0 && nb == 0 {
+            break;
+        }
+        line.a.truncate(na);
+        line.b.truncate(nb);
+        if line.a != line.b {
+            if !previous_differed {
+                if Some(diffs) == max_diffs {
+                    stopped = true;
+                    break;
+                }
+                diffs += 1;
+            }
+            previous_differed = true;
+            if let Some(printer) = &mut printer {
+                for l in before.drain(..) {
+                    printer.print(&l)?;
+                }
+                printer.print(&line)?;
+            }
+            after_remaining = context;
+        } else {
+            previous_differed = false;
+            if after_remaining > 0 {
+                if let Some(printer) = &mut printer {
+                    printer.print(&line)?;
+                }
+                after_remaining -= 1;
+            } else if context > 0 {
+                if before.len() == context {
+                    before.pop_front();
+                }
+                before.push_back(line);
+            }
+        }
+        offset += width as u64;
+    }
+    Ok((diffs, stopped))
+}
+
+fn run(opt: &Opt) -> Result<bool> {
+    if opt.width == 0 {
+        anyhow::bail!("--width must be at least 1");
+    }
+    let a = open_file(&opt.path_a)?;
+    let b = open_file(&opt.path_b)?;
+    let diffs = if opt.quiet {
+        hexdump_diff(a, b, None::<&mut Vec<u8>>, opt.width, 0, None)?.0
+    } else {
+        let mut out = BufWriter::new(stdout().lock());
+        let (diffs, stopped) = hexdump_diff(
+            a,
+            b,
+            Some(&mut out),
+            opt.width,
+            opt.context,
+            opt.max_diffs,
+        )?;
+        out.flush()?;
+        if stopped {
+            eprintln!("hexdump-diff: stopped after {diffs} difference(s)");
+        }
+        diffs
+    };
+    Ok(diffs == 0)
+}
+
+fn main() {
+    let opt: Opt = Opt::from_args();
+    match run(&opt) {
+        Ok(true) => exit(0),
+        Ok(false) => exit(1),
+        Err(e) => {
+            eprintln!("hexdump-diff: {e:#}");
+            exit(2)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn diff_str(a: &[u8], b: &[u8], max_diffs: Option<usize>) -> String {
+        let mut out = Vec::new();
+        hexdump_diff(a, b, Some(&mut out), 4, 1, max_diffs).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn t_hexdump_diff() {
+        let a = b"0123456789abcdefghijklmnopqrstuv";
+        assert_eq!(diff_str(a, a, None), "");
+        let mut b = *a;
+        b[5] = b'X';
+        b[29] = 0;
+        assert_eq!(
+            diff_str(a, &b, None),
+            " 00000000  30 31 32 33  |0123|
+-00000004  34 35 36 37  |4567|
++00000004  34 58 36 37  |4X67|
+ 00000008  38 39 61 62  |89ab|
+--
+ 00000018  6f 70 71 72  |opqr|
+-0000001c  73 74 75 76  |stuv|
++0000001c  73 00 75 76  |s.uv|
+"
+        );
+        assert_eq!(
+            diff_str(a, &b, Some(1)),
+            " 00000000  30 31 32 33  |0123|
+-00000004  34 35 36 37  |4567|
++00000004  34 58 36 37  |4X67|
+ 00000008  38 39 61 62  |89ab|
+"
+        );
+        // Different lengths
+        assert_eq!(
+            diff_str(b"012345", b"0123", None),
+            " 00000000  30 31 32 33  |0123|
+-00000004  34 35        |45|
+"
+        );
+        // Quiet mode stops at the first difference
+        assert_eq!(
+            hexdump_diff(&a[..], &b[..], None::<&mut Vec<u8>>, 4, 0, None)
+                .unwrap(),
+            (1, true)
+        );
+    }
+}
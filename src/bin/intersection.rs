@@ -1,26 +1,37 @@
-use anyhow::{anyhow, bail, Context, Error, Result};
-use clap::Parser;
-use kstring::KString;
 use std::cmp::Ordering;
-use std::collections::{HashSet, VecDeque};
+use std::collections::hash_map::RandomState;
+use std::collections::{HashMap, VecDeque};
 use std::fs::File;
-use std::i64::MIN;
-use std::io::{stdout, BufReader, BufWriter, Write};
-use std::os::unix::prelude::{FromRawFd, MetadataExt};
-use std::path::PathBuf;
+use std::hash::BuildHasher;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::os::unix::prelude::{AsRawFd, FromRawFd, MetadataExt};
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context, Error, Result};
+use kstring::KString;
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
 use thiserror::Error;
 
+use chj_rustbin::bloom::BloomFilter;
+use chj_rustbin::cli::{self, OutputFileArgs, VerbosityArgs};
+use chj_rustbin::error::{Categorize, ErrorKind};
+use chj_rustbin::impl_cli_opt;
 use chj_rustbin::io::readwithcontext::{
-    easy_read_line, open_file, ReadWithContext,
+    open_decompressed, Decompressor, ReadWithContext,
 };
+use chj_rustbin::io::records::{read_record, RecordSeparator, RecordSpan};
+use chj_rustbin::util::cli_output::{Output, OutputArgs, Value, DIM, GREEN};
+use chj_rustbin::util::progress::Progress;
 
 #[derive(clap::Parser, Debug)]
-/// Print the lines that occur in all input files. By default, files
-/// don't need to be sorted (but see `--sorted`); an in-memory set is
-/// built, the order of the output lines follows the last file, and if
-/// there are repetitions in the last file, those are repeated, too.
-
-#[clap(name = "intersection from chj-rustbin")]
+/// Print the lines that occur in all input files (or, with
+/// `--min-count`, in at least a given number of them). By default,
+/// files don't need to be sorted (but see `--sorted`); an in-memory
+/// set is built, the order of the output lines follows the last file,
+/// and if there are repetitions in the last file, those are repeated,
+/// too. Files compressed with gzip, xz or zstd are decompressed
+/// (using the respective program).
+#[clap(name = chj_rustbin::cli_name!())]
 struct Opt {
     /// Show the set, not the filtered last file (i.e. there will be
     /// no repetitions in the output, and it is lexically sorted
@@ -47,18 +58,325 @@ struct Opt {
     #[clap(long)]
     fddrop: bool,
 
+    /// Prefix each output line with a code showing which input files
+    /// contain it: one character per file, the letters `A`, `B`,
+    /// ... in the order the files were given, or `-` if the
+    /// respective file doesn't contain the line, then a tab (with
+    /// `--output tsv` or `json`, the code is in the `files` column).
+    #[clap(long)]
+    annotate: bool,
+
+    /// Relax the intersection: show the lines that occur in at least
+    /// this many of the input files (by default, all of them). Not
+    /// supported in sorted mode (--sorted or --numeric).
+    #[clap(long, conflicts_with_all = &["sorted", "numeric"])]
+    min_count: Option<usize>,
+
+    /// Invert the filter: show the lines of the last file that are
+    /// *not* in the intersection, i.e. that are missing from some
+    /// other file (or with `--min-count`, that occur in fewer files
+    /// than that). Like `grep -vxF -f other-file last-file`, but fast
+    /// with many lines. With --approximate, lines may be missing
+    /// from the output (false positives of the filters). Not
+    /// supported with --set or in sorted mode (see --fddrop there).
+    #[clap(long, conflicts_with_all = &["set", "sorted", "numeric"])]
+    invert: bool,
+
+    /// Build the in-memory set using all CPUs: lines are read in
+    /// chunks and inserted into hash-partitioned shards in parallel.
+    /// Helps with large files. Not applicable in sorted mode.
+    #[clap(long, conflicts_with_all = &["sorted", "numeric"])]
+    parallel: bool,
+
+    /// Instead of the in-memory set, use a Bloom filter for each file
+    /// but the last: uses little memory even for huge files, but is
+    /// approximate: lines of the last file may be shown although they
+    /// are missing from some other file (false positives), but no
+    /// line is missed. The sizes of the filters and the estimated
+    /// false-positive rates are printed to stderr.
+    #[clap(
+        long,
+        conflicts_with_all = &["set", "sorted", "numeric", "parallel"]
+    )]
+    approximate: bool,
+
+    /// The false-positive rate to size the Bloom filters for, per
+    /// file (default: 0.01).
+    #[clap(long, requires = "approximate")]
+    fp_rate: Option<f64>,
+
+    /// What separates the records to compare (by default, lines):
+    /// `\0` or `nul` (as from `find -print0`), `paragraph` (blocks of
+    /// lines separated by empty lines), or another ASCII character.
+    /// The output records are separated the same way.
+    #[clap(long, default_value = "\\n")]
+    record_separator: RecordSeparator,
+
+    /// Report the progress of reading each file on stderr: the bytes
+    /// and records processed, and the percentage and ETA (unless the
+    /// file is compressed).
+    #[clap(long)]
+    progress: bool,
+
     #[clap(long)]
     structsizes: bool,
 
+    #[clap(flatten)]
+    output_args: OutputArgs,
+
+    #[clap(flatten)]
+    output_file_args: OutputFileArgs,
+
     /// The paths to files to get the intersection of.
     #[clap(parse(from_os_str))]
     file_paths: Vec<PathBuf>,
+
+    #[clap(flatten)]
+    verbosity: VerbosityArgs,
 }
 
-fn println(out: &mut impl Write, line: &String) -> Result<()> {
+impl_cli_opt!(Opt);
+
+fn println(
+    out: &mut impl Write,
+    line: &String,
+    separator: RecordSeparator,
+) -> Result<()> {
     out.write_all(line.as_bytes())?;
-    out.write_all(b"\n")?;
-    Ok(())
+    separator.write_terminator(out)
+}
+
+/// Open `path` for reading records, with progress reports if
+/// `progress` is true.
+fn open_input(path: &Path, progress: bool) -> Result<ReadWithContext<'_>> {
+    let mut inp = ReadWithContext::open_path_auto(path)?;
+    if progress {
+        inp.enable_progress()?;
+    }
+    Ok(inp)
+}
+
+/// The number of records in the file at `path` (to size a Bloom
+/// filter for it); for paragraphs, the number of lines (as an upper
+/// bound).
+fn count_records(path: &Path, separator: RecordSeparator) -> Result<usize> {
+    let terminator = match separator {
+        RecordSeparator::Byte(b) => b,
+        RecordSeparator::Paragraph => b'\n',
+    };
+    let (file, mut decompressor) = open_decompressed(path)?;
+    let mut inp = BufReader::new(file);
+    let mut count = 0;
+    let mut last = terminator;
+    loop {
+        let buf = inp
+            .fill_buf()
+            .with_context(|| anyhow!("reading file {:?}", path))?;
+        let len = buf.len();
+        if len == 0 {
+            break;
+        }
+        count += buf.iter().filter(|b| **b == terminator).count();
+        last = buf[len - 1];
+        inp.consume(len);
+    }
+    if let Some(decompressor) = &mut decompressor {
+        decompressor
+            .finish()
+            .with_context(|| anyhow!("reading file {:?}", path))?;
+    }
+    Ok(count + (last != terminator) as usize)
+}
+
+/// How many of the input files contain a line, and which of the
+/// first 64 (by their position in the arguments) do, as a bitmap
+/// (only used by `--annotate`, which supports fewer files). Files
+/// have to be added one after the other: adding the same file again
+/// right away (for repeated lines) doesn't count it again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Membership {
+    count: u32,
+    /// The index of the file added last, plus 1 (0 for none).
+    last: u32,
+    bits: u64,
+}
+
+const ANNOTATION_LETTERS: &[u8] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+
+impl Membership {
+    fn none() -> Self {
+        Membership {
+            count: 0,
+            last: 0,
+            bits: 0,
+        }
+    }
+    fn all(num_files: usize) -> Self {
+        (0..num_files).fold(Self::none(), Membership::with)
+    }
+    fn with(self, file_index: usize) -> Self {
+        let last = file_index as u32 + 1;
+        if self.last == last {
+            return self;
+        }
+        Membership {
+            count: self.count + 1,
+            last,
+            bits: self.bits | 1u64.checked_shl(file_index as u32).unwrap_or(0),
+        }
+    }
+    fn contains(self, file_index: usize) -> bool {
+        file_index < 64 && self.bits & (1 << file_index) != 0
+    }
+    /// The number of files containing the line.
+    fn count(self) -> usize {
+        self.count as usize
+    }
+    fn annotation(self, num_files: usize) -> String {
+        (0..num_files)
+            .map(|i| {
+                if self.contains(i) {
+                    ANNOTATION_LETTERS[i] as char
+                } else {
+                    '-'
+                }
+            })
+            .collect()
+    }
+}
+
+/// The number of lines read before inserting them into the index in
+/// parallel.
+const PARALLEL_CHUNK_LINES: usize = 1 << 16;
+
+/// The set of lines for the set based modes. Split into shards by
+/// hash, so that it can be built in parallel; with a single shard,
+/// this is just a `HashMap`.
+struct Index {
+    /// For choosing the shard (the shards use their own hashers, so
+    /// that their keys are evenly distributed within them).
+    selector: RandomState,
+    shards: Vec<HashMap<KString, Membership>>,
+}
+
+impl Index {
+    fn new(num_shards: usize) -> Self {
+        Index {
+            selector: RandomState::new(),
+            shards: (0..num_shards).map(|_| HashMap::new()).collect(),
+        }
+    }
+
+    fn shard_index(&self, line: &str) -> usize {
+        if self.shards.len() == 1 {
+            return 0;
+        }
+        (self.selector.hash_one(line) % self.shards.len() as u64) as usize
+    }
+
+    fn is_empty(&self) -> bool {
+        self.shards.iter().all(|shard| shard.is_empty())
+    }
+
+    fn get(&self, line: &str) -> Option<Membership> {
+        self.shards[self.shard_index(line)].get(line).copied()
+    }
+
+    fn get_mut(&mut self, line: &str) -> Option<&mut Membership> {
+        let i = self.shard_index(line);
+        self.shards[i].get_mut(line)
+    }
+
+    /// Add file `file_index` to the membership of `line`, inserting
+    /// it if not present yet.
+    fn add(&mut self, line: KString, file_index: usize) {
+        let i = self.shard_index(&line);
+        add_to_shard(&mut self.shards[i], line, file_index);
+    }
+
+    /// Like `add` for all of `lines`, in parallel.
+    fn add_parallel(&mut self, lines: Vec<KString>, file_index: usize) {
+        let with_shard: Vec<(usize, KString)> = lines
+            .into_par_iter()
+            .map(|line| (self.shard_index(&line), line))
+            .collect();
+        let mut buckets: Vec<Vec<KString>> =
+            self.shards.iter().map(|_| Vec::new()).collect();
+        for (i, line) in with_shard {
+            buckets[i].push(line);
+        }
+        self.shards
+            .iter_mut()
+            .zip(buckets)
+            .collect::<Vec<_>>()
+            .into_par_iter()
+            .for_each(|(shard, lines)| {
+                for line in lines {
+                    add_to_shard(shard, line, file_index);
+                }
+            });
+    }
+
+    fn retain(&mut self, mut keep: impl FnMut(Membership) -> bool) {
+        for shard in &mut self.shards {
+            shard.retain(|_, membership| keep(*membership));
+        }
+    }
+
+    fn into_vec(self) -> Vec<(KString, Membership)> {
+        self.shards.into_iter().flatten().collect()
+    }
+}
+
+fn add_to_shard(
+    shard: &mut HashMap<KString, Membership>,
+    line: KString,
+    file_index: usize,
+) {
+    let membership = shard.entry(line).or_insert_with(Membership::none);
+    *membership = membership.with(file_index);
+}
+
+/// Prints the result lines, in the format chosen via `--output`.
+struct Printer {
+    /// `Some(num_files)` to show the annotation code.
+    annotate: Option<usize>,
+    output: Output,
+    separator: RecordSeparator,
+}
+
+impl Printer {
+    /// Print `line`, prefixed with the code for `membership` if
+    /// annotating.
+    fn println(
+        &mut self,
+        out: &mut impl Write,
+        membership: Membership,
+        line: &String,
+    ) -> Result<()> {
+        if !self.output.is_text() {
+            let line = Value::from(line.as_str());
+            return match self.annotate {
+                Some(num_files) => self.output.write_record(
+                    out,
+                    &[membership.annotation(num_files).into(), line],
+                ),
+                None => self.output.write_record(out, &[line]),
+            };
+        }
+        if let Some(num_files) = self.annotate {
+            let code = membership.annotation(num_files);
+            let mut buf = [0; 4];
+            for c in code.chars() {
+                let sgr = if c == '-' { DIM } else { GREEN };
+                let c = c.encode_utf8(&mut buf);
+                out.write_all(self.output.paint(sgr, c).as_bytes())?;
+            }
+            out.write_all(b"\t")?;
+        }
+        println(out, line, self.separator)
+    }
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -70,10 +388,11 @@ enum SortOrder {
 impl SortOrder {
     fn perhaps_parse_number(self, line: &str) -> Result<i64> {
         match self {
-            SortOrder::Lexical => Ok(MIN),
+            SortOrder::Lexical => Ok(i64::MIN),
             SortOrder::Numeric => line
                 .parse()
-                .with_context(|| anyhow!("not an i64 number: {:?}", line)),
+                .with_context(|| anyhow!("not an i64 number: {:?}", line))
+                .parse_error(),
         }
     }
     fn compare(self, l1: &Line, l2: &Line) -> Ordering {
@@ -89,27 +408,28 @@ impl SortOrder {
 #[derive(Debug)]
 struct Line {
     string: String,
-    i64: i64, // only if opt.numeric; MIN by default in either case
+    i64: i64, // only if opt.numeric; i64::MIN by default in either case
 }
 
 impl Line {
     fn new() -> Line {
         Line {
             string: String::new(),
-            i64: MIN,
+            i64: i64::MIN,
         }
     }
     fn read_and_parse_line(
         &mut self,
         inp: &mut BufReader<File>,
         sortorder: SortOrder,
-    ) -> Result<bool> {
+        separator: RecordSeparator,
+    ) -> Result<Option<RecordSpan>> {
         let line = &mut self.string;
-        let have_line = easy_read_line(inp, line)?;
-        if have_line {
+        let span = read_record(inp, separator, line)?;
+        if span.is_some() {
             self.i64 = sortorder.perhaps_parse_number(line)?;
         }
-        Ok(have_line)
+        Ok(span)
     }
 }
 
@@ -117,6 +437,8 @@ impl Line {
 struct Input {
     path: PathBuf,
     input: BufReader<File>,
+    /// Set if the file is compressed.
+    decompressor: Option<Decompressor>,
     /// Filehandle to write non-intersecting entries to if --fddrop is given.
     output: Option<BufWriter<File>>,
     /// Using two line buffers so as to read a line in advance without
@@ -126,6 +448,10 @@ struct Input {
     current_line_is_1: bool,
     current_line_is_in_set: bool,
     linenum: u64,
+    separator: RecordSeparator,
+    /// The number of bytes read (decompressed).
+    bytes: u64,
+    progress: Option<Progress>,
 }
 
 impl Input {
@@ -161,7 +487,7 @@ impl Input {
                     };
                     // eprintln!("next({:?}): drop {:?}", self.path,
                     //           &current_line.string);
-                    println(output, &current_line.string)?;
+                    println(output, &current_line.string, self.separator)?;
                 }
                 self.current_line_is_in_set = false;
             }
@@ -174,13 +500,28 @@ impl Input {
                 &mut self.line2
             };
             self.linenum += 1;
-            if current_line.read_and_parse_line(&mut self.input, sortorder)? {
+            if let Some(span) = current_line.read_and_parse_line(
+                &mut self.input,
+                sortorder,
+                self.separator,
+            )? {
+                self.bytes += (span.skipped + span.len) as u64;
+                if let Some(progress) = &mut self.progress {
+                    progress.tick(self.bytes);
+                }
                 // eprintln!("next({:?}): new: {:?}", self.path, &current_line.string);
                 if !self.is_ordered(sortorder)? {
-                    bail!("file is not ordered")
+                    return Err(ErrorKind::Parse
+                        .wrap(anyhow!("file is not ordered")));
                 }
                 Ok(true)
             } else {
+                if let Some(decompressor) = &mut self.decompressor {
+                    decompressor.finish()?;
+                }
+                if let Some(progress) = &mut self.progress {
+                    progress.finish();
+                }
                 // Mis-use this flag as iterator exhaustion marker, to
                 // prevent subsequent calls from println'ing the empty
                 // line:
@@ -240,6 +581,7 @@ enum Signal {
 enum Mode {
     SetThenLinear,
     Set,
+    Approximate,
     Sorted(SortOrder),
     StructSizes,
 }
@@ -249,6 +591,7 @@ impl Mode {
         match self {
             Mode::SetThenLinear => "default",
             Mode::Set => "--set",
+            Mode::Approximate => "--approximate",
             // Mode::Sorted(SortOrder::Lexical) => "--sorted",
             // Mode::Sorted(SortOrder::Numeric) => "--numeric",
             Mode::Sorted(_) => "--sorted / --numeric",
@@ -259,6 +602,7 @@ impl Mode {
         match self {
             Mode::SetThenLinear => 2,
             Mode::Set => 1,
+            Mode::Approximate => 2,
             Mode::Sorted(_) => 2,
             Mode::StructSizes => 0,
         }
@@ -286,21 +630,31 @@ fn print_sizes() {
     p! {SortOrder};
     p! {Signal};
     p! {Mode};
+    p! {Membership};
 }
 
 fn output_fd_for_input_index(i: usize) -> i32 {
     10 + i as i32
 }
 
-fn main() -> Result<()> {
-    let (mode, mut paths, fddrop) = {
-        let opt: Opt = Opt::from_args();
+fn main() {
+    cli::main(run)
+}
+
+fn run(opt: Opt) -> Result<()> {
+    let separator = opt.record_separator;
+    let progress = opt.progress;
+    let invert = opt.invert;
+    let mut out = opt.output_file_args.open()?;
+    let (mode, mut paths, fddrop, mut printer, min_count, parallel, fp_rate) = {
         let paths: VecDeque<PathBuf> = opt.file_paths.into();
 
         let mode = if opt.numeric {
             Mode::Sorted(SortOrder::Numeric)
         } else if opt.sorted {
             Mode::Sorted(SortOrder::Lexical)
+        } else if opt.approximate {
+            Mode::Approximate
         } else if opt.set {
             Mode::Set
         } else if opt.structsizes {
@@ -309,26 +663,62 @@ fn main() -> Result<()> {
             Mode::SetThenLinear
         };
 
-        match mode {
-            Mode::Sorted(_) => {
-                if opt.set {
-                    bail!(
-                        "only one of --set or --sorted (or --numeric) is valid"
-                    );
-                }
+        if let Mode::Sorted(_) = mode {
+            if opt.set {
+                return Err(ErrorKind::Usage.wrap(anyhow!(
+                    "only one of --set or --sorted (or --numeric) is valid"
+                )));
             }
-            _ => (),
         }
 
-        (mode, paths, opt.fddrop)
+        if opt.annotate && paths.len() > ANNOTATION_LETTERS.len() {
+            return Err(ErrorKind::Usage.wrap(anyhow!(
+                "--annotate supports at most {} input files",
+                ANNOTATION_LETTERS.len()
+            )));
+        }
+        let printer = if opt.annotate {
+            Printer {
+                annotate: Some(paths.len()),
+                output: opt
+                    .output_args
+                    .output_to(out.as_raw_fd(), &["files", "line"]),
+                separator,
+            }
+        } else {
+            Printer {
+                annotate: None,
+                output: opt.output_args.output_to(out.as_raw_fd(), &["line"]),
+                separator,
+            }
+        };
+
+        let min_count = opt.min_count.unwrap_or(paths.len());
+        if min_count == 0 || min_count > paths.len() {
+            return Err(ErrorKind::Usage.wrap(anyhow!(
+                "--min-count must be between 1 and the number of input \
+                 files ({})",
+                paths.len()
+            )));
+        }
+
+        (
+            mode,
+            paths,
+            opt.fddrop,
+            printer,
+            min_count,
+            opt.parallel,
+            opt.fp_rate.unwrap_or(0.01),
+        )
     };
 
     if paths.len() < mode.min_paths_len() {
-        bail!(
+        return Err(ErrorKind::Usage.wrap(anyhow!(
             "need at least {} input file(s) in {} mode",
             mode.min_paths_len(),
             mode.name()
-        );
+        )));
     }
 
     match mode {
@@ -337,13 +727,29 @@ fn main() -> Result<()> {
                 .into_iter()
                 .enumerate()
                 .map(|(i, path)| {
-                    let mut input = open_file(&path).map_err(Signal::Error)?;
+                    let (file, decompressor) =
+                        open_decompressed(&path).map_err(Signal::Error)?;
+                    let total = file
+                        .metadata()
+                        .with_context(|| anyhow!("stat on file {:?}", path))
+                        .map_err(Signal::Error)?
+                        .len();
+                    let mut input = BufReader::new(file);
                     let mut line = Line::new();
-                    if line
-                        .read_and_parse_line(&mut input, sortorder)
+                    if let Some(span) = line
+                        .read_and_parse_line(&mut input, sortorder, separator)
                         .with_context(|| anyhow!("file {:?} line 1", path))
                         .map_err(Signal::Error)?
                     {
+                        let bytes = (span.skipped + span.len) as u64;
+                        let progress = progress.then(|| {
+                            let mut progress = Progress::new(
+                                path.to_string_lossy(),
+                                decompressor.is_none().then_some(total),
+                            );
+                            progress.tick(bytes);
+                            progress
+                        });
                         let output = if fddrop {
                             Some(BufWriter::new(unsafe {
                                 File::from_raw_fd(output_fd_for_input_index(i))
@@ -354,12 +760,16 @@ fn main() -> Result<()> {
                         Ok(Input {
                             path,
                             input,
+                            decompressor,
                             output,
                             line1: Line::new(),
                             line2: line,
                             current_line_is_1: false,
                             current_line_is_in_set: false,
                             linenum: 1,
+                            separator,
+                            bytes,
+                            progress,
                         })
                     } else {
                         Err(Signal::Finished)
@@ -371,7 +781,6 @@ fn main() -> Result<()> {
                     // eprintln!("inputs = {:?}", inputs.iter().map(
                     //     |input| &input.current_line().string).collect::<Vec<_>>());
                     let mut inputs = Inputs { inputs };
-                    let mut out = BufWriter::new(stdout());
 
                     'full: loop {
                         // eprintln!("--- loop... ------------");
@@ -416,7 +825,11 @@ fn main() -> Result<()> {
                         }
                         if all_same {
                             // eprintln!("all_same: {:?}", &largest.string);
-                            println(&mut out, &largest.string)?;
+                            printer.println(
+                                &mut out,
+                                Membership::all(inputs.len()),
+                                &largest.string,
+                            )?;
 
                             // Mark them all as in set
                             for input in &mut inputs.inputs {
@@ -432,7 +845,8 @@ fn main() -> Result<()> {
                     }
 
                     // eprintln!("---finish----");
-                    out.flush().with_context(|| anyhow!("flushing stdout"))?;
+                    out.flush()
+                        .with_context(|| anyhow!("flushing the output"))?;
                     for (i, input) in inputs.inputs.iter_mut().enumerate() {
                         if input.output.is_some() {
                             // Re-use next() to copy over the
@@ -457,77 +871,148 @@ fn main() -> Result<()> {
             }
         }
         Mode::Set | Mode::SetThenLinear => {
-            let mut set: HashSet<KString> = HashSet::new();
+            let mut set = Index::new(if parallel {
+                rayon::current_num_threads()
+            } else {
+                1
+            });
             let mut tmpline = String::new();
 
             let last_path = match mode {
                 Mode::Set => None,
-                Mode::SetThenLinear => Some(paths.pop_back().unwrap()),
+                Mode::SetThenLinear => {
+                    Some((paths.len() - 1, paths.pop_back().unwrap()))
+                }
                 _ => panic!(),
             };
 
-            let mut paths_meta: VecDeque<(PathBuf, u64)> = paths
+            let mut paths_meta: VecDeque<(usize, PathBuf, u64)> = paths
                 .into_iter()
-                .map(|path| {
+                .enumerate()
+                .map(|(i, path)| {
                     let s = path
                         .metadata()
                         .with_context(|| anyhow!("stat on file {:?}", path))?
                         .size();
-                    Ok((path, s))
+                    Ok((i, path, s))
                 })
                 .collect::<Result<_>>()?;
-            paths_meta.make_contiguous().sort_by_key(|x| x.1);
-
-            let first_path = paths_meta.pop_front().unwrap().0;
-            {
-                let path = first_path;
-                let mut inp = ReadWithContext::open_path(&path)?;
-                while inp.easy_read_line(&mut tmpline)? {
-                    set.insert(KString::from(&tmpline));
-                }
-            }
-
-            for (path, _) in paths_meta {
-                if set.is_empty() {
+            paths_meta.make_contiguous().sort_by_key(|x| x.2);
+
+            // The number of files processed by the time the set is
+            // complete (the last file in SetThenLinear mode is only
+            // looked up in the set)
+            let num_set_files = paths_meta.len();
+            let num_files = num_set_files + last_path.is_some() as usize;
+            for (pos, (i, path, _)) in paths_meta.into_iter().enumerate() {
+                // Lines with fewer occurrences than `min_count` minus
+                // the number of remaining files can't make it
+                let remaining = num_files - pos - 1;
+                if set.is_empty() && remaining + 1 < min_count {
                     break;
                 }
-                let mut inp = ReadWithContext::open_path(&path)?;
-                let mut newset = HashSet::new();
-                while inp.easy_read_line(&mut tmpline)? {
-                    let line = KString::from(&tmpline);
-                    if set.contains(&line) {
-                        newset.insert(line);
+                let mut inp = open_input(&path, progress)?;
+                if remaining + 1 >= min_count {
+                    if parallel {
+                        let mut chunk =
+                            Vec::with_capacity(PARALLEL_CHUNK_LINES);
+                        while inp.easy_read_record(separator, &mut tmpline)? {
+                            chunk.push(KString::from(&tmpline));
+                            if chunk.len() == PARALLEL_CHUNK_LINES {
+                                set.add_parallel(std::mem::take(&mut chunk), i);
+                            }
+                        }
+                        set.add_parallel(chunk, i);
+                    } else {
+                        while inp.easy_read_record(separator, &mut tmpline)? {
+                            set.add(KString::from(&tmpline), i);
+                        }
+                    }
+                } else {
+                    // No new lines can make it, thus only add
+                    // memberships to existing ones
+                    while inp.easy_read_record(separator, &mut tmpline)? {
+                        if let Some(membership) = set.get_mut(&tmpline) {
+                            *membership = membership.with(i);
+                        }
                     }
                 }
-                set = newset;
+                set.retain(|membership| {
+                    membership.count() + remaining >= min_count
+                });
             }
 
-            let mut out = BufWriter::new(stdout());
             match mode {
                 Mode::Set => {
-                    let mut v: Vec<KString> = set.into_iter().collect();
-                    v.sort();
-                    for line in v {
+                    let mut v = set.into_vec();
+                    v.sort_by(|a, b| a.0.cmp(&b.0));
+                    for (line, membership) in v {
                         tmpline.clear();
                         tmpline.push_str(&line);
-                        println(&mut out, &mut tmpline)?;
+                        printer.println(&mut out, membership, &tmpline)?;
                     }
                 }
                 Mode::SetThenLinear => {
-                    let path = last_path.unwrap();
-                    let mut inp = ReadWithContext::open_path(&path)?;
-                    while inp.easy_read_line(&mut tmpline)? {
-                        if set.contains(&KString::from(&tmpline)) {
-                            println(&mut out, &mut tmpline)?;
+                    let (last_i, path) = last_path.unwrap();
+                    let mut inp = open_input(&path, progress)?;
+                    while inp.easy_read_record(separator, &mut tmpline)? {
+                        let membership = set
+                            .get(&tmpline)
+                            .unwrap_or_else(Membership::none)
+                            .with(last_i);
+                        if (membership.count() >= min_count) != invert {
+                            printer.println(
+                                &mut out, membership, &tmpline,
+                            )?;
                         }
                     }
                 }
                 _ => panic!(),
             }
-            out.flush()?;
+        }
+        Mode::Approximate => {
+            let mut tmpline = String::new();
+            let last_i = paths.len() - 1;
+            let last_path = paths.pop_back().unwrap();
+            let filters = paths
+                .iter()
+                .map(|path| -> Result<BloomFilter> {
+                    let mut filter = BloomFilter::with_rate(
+                        count_records(path, separator)?,
+                        fp_rate,
+                    )?;
+                    let mut inp = open_input(path, progress)?;
+                    while inp.easy_read_record(separator, &mut tmpline)? {
+                        filter.insert(tmpline.as_str());
+                    }
+                    eprintln!(
+                        "intersection: Bloom filter for {:?}: {} bits, {} \
+                         hashes, estimated false-positive rate {:.2e}",
+                        path,
+                        filter.num_bits(),
+                        filter.num_hashes(),
+                        filter.estimated_fp_rate()
+                    );
+                    Ok(filter)
+                })
+                .collect::<Result<Vec<_>>>()?;
+
+            let mut inp = open_input(&last_path, progress)?;
+            while inp.easy_read_record(separator, &mut tmpline)? {
+                let membership = filters
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, filter)| filter.contains(tmpline.as_str()))
+                    .fold(Membership::none().with(last_i), |m, (i, _)| {
+                        m.with(i)
+                    });
+                if (membership.count() >= min_count) != invert {
+                    printer.println(&mut out, membership, &tmpline)?;
+                }
+            }
         }
         Mode::StructSizes => print_sizes(),
     }
 
-    Ok(())
+    out.commit()
 }
@@ -1,36 +1,50 @@
+use std::cmp::Ordering;
 use std::convert::From;
 use std::env;
-use std::ffi::OsString;
+use std::ffi::{OsStr, OsString};
 use std::fmt::Debug;
 use std::fs;
-use std::io;
-use std::io::Write;
+use std::io::{stdin, Read, Write};
 use std::os::unix::ffi::OsStrExt;
+use std::os::unix::io::AsRawFd;
 use std::path::Path;
 use std::path::PathBuf;
-use std::time::SystemTime;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 use anyhow::{anyhow, bail, Context, Result};
-use chj_rustbin::region::Region;
-use clap::Parser;
-use rayon::iter::IntoParallelIterator;
-use rayon::iter::ParallelIterator;
+use clap::ArgMatches;
 
+use chj_rustbin::cli::{self, OutputFileArgs, VerbosityArgs};
+use chj_rustbin::error::{Categorize, ErrorKind};
+use chj_rustbin::impl_cli_opt;
 use chj_rustbin::impl_item_options_from;
-use chj_rustbin::io::excludes::{default_excludes, empty_excludes, Excludes};
-use chj_rustbin::io::file_path_type::{
-    file_path_types_vec, FilePathType, ItemOptions,
+use chj_rustbin::io::dirscan::{DirScan, Recursion};
+use chj_rustbin::io::excludes::{
+    default_excludes, empty_excludes, merge_rules, rules_from_env,
+    ExcludeArgs, Excludes,
 };
+use chj_rustbin::io::file_path_type::ItemOptions;
+use chj_rustbin::io::item::Item;
+use chj_rustbin::numbers::natural_cmp;
 
 use chj_rustbin::text::naturallanguagejoin::NaturalLanguageJoin;
+use chj_rustbin::time::realtime::parse_duration;
+use chj_rustbin::util::cli_output::{OutputArgs, OutputFormat};
 
 #[derive(clap::Parser, Debug)]
 /// Show the newest (with regards to mtime) item in a directory. If
 /// called via a symlink as `lastfile`, shows the last file, if called
 /// as `lastdir`, the last dir, if called as `lastitem`, any kind of
 /// filesystem entry. Alternatively, if the --dirs or --files option
-/// is given, that takes precedence.
-#[clap(name = "lastitem from chj-rustbin")]
+/// is given, that takes precedence. With `--by version`, shows the
+/// item with the highest version number embedded in its name instead
+/// (e.g. `foo-1.2.10.tar.gz` over `foo-1.2.9.tar.gz`). With
+/// `--oldest`, shows the oldest (or lowest version) item. With
+/// `--stdin`, selects among the paths given on stdin instead. Exclude
+/// patterns can also be given as a colon-separated list in the
+/// `LASTITEM_EXCLUDE` env var (overridden by the options).
+#[clap(name = chj_rustbin::cli_name!())]
 struct Opt {
     /// consider dirs
     #[clap(long)]
@@ -44,10 +58,30 @@ struct Opt {
     #[clap(long)]
     other: bool,
 
+    /// follow symlinks: consider them as the type of their target,
+    /// and use the target's mtime and size
+    #[clap(short = 'L', long)]
+    deref: bool,
+
+    /// with --depth, don't descend into directories on other mounts
+    /// (file systems, or bind mounts) than the given directory
+    #[clap(short = 'x', long, alias = "xdev")]
+    one_file_system: bool,
+
     /// do not ignore dot and Emacs backup (ending in '~') files
     #[clap(short, long)]
     all: bool,
 
+    /// do not ignore dot files and dirs (hidden items), but still
+    /// ignore Emacs backup files unless `--all` is given
+    #[clap(long, overrides_with = "no-hidden")]
+    hidden: bool,
+
+    /// ignore dot files and dirs even if `--all` is given (of
+    /// `--hidden` and `--no-hidden`, the last one given wins)
+    #[clap(long, overrides_with = "hidden")]
+    no_hidden: bool,
+
     /// do not ignore special file and dir names that are ignored by
     /// default, like .git; you still need `--all` as well to lift its
     /// ignores, too, if you want to not ignore anything
@@ -64,60 +98,172 @@ struct Opt {
     #[clap(long, multiple = true)]
     ignore_dir: Vec<OsString>,
 
+    #[clap(flatten)]
+    exclude_args: ExcludeArgs,
+
     /// look for an item DEPTH levels deeper than the given directory
     /// (i.e. with DEPTH levels of directories inbetween), default: 0
     #[clap(long)]
     depth: Option<u8>,
 
+    /// instead of scanning a directory, select among the paths read
+    /// from stdin, separated by newlines, or by NUL bytes if there
+    /// are any (e.g. from `find -print0` or `git ls-files -z`);
+    /// relative paths are taken from the current directory, and shown
+    /// as given; the item kind and exclusion options still apply (the
+    /// latter to the file names)
+    #[clap(long, conflicts_with_all = &["depth", "one-file-system"])]
+    stdin: bool,
+
     /// if a directory has no files after filtering, succeed without
     /// showing a result (the default is to report an error)
     #[clap(long)]
     allow_empty: bool,
 
+    /// for cron/monitoring checks: still show the item, but exit
+    /// with status 1 unless it is younger than the given duration
+    /// (e.g. `24h`; units: s, m, h, d, w, seconds if no unit is
+    /// given); a directory without items is an error. Errors exit
+    /// with other statuses: 2 for invalid options, 3 for failing to
+    /// read directories or finding no items
+    #[clap(long, conflicts_with = "allow-empty")]
+    newer_than: Option<String>,
+
+    /// what to select the last item by: `mtime` (the default), or
+    /// `version` (natural sorting of the file names, comparing
+    /// embedded numbers numerically)
+    #[clap(long, default_value = "mtime")]
+    by: By,
+
+    /// how to choose between items that are equal with regards to
+    /// `--by`: `name` (the default; the file name sorting first
+    /// wins), `size` (the larger item wins), or `path` (the path
+    /// sorting first wins); the path is used as the last resort in
+    /// any case, so that the result is deterministic
+    #[clap(long, default_value = "name")]
+    tie: Tie,
+
+    /// show the oldest item (or the one with the lowest version
+    /// number) instead; doesn't change the tie-breaking
+    #[clap(long)]
+    oldest: bool,
+
     /// show the full path instead of just the filename
     #[clap(short, long)]
     fullpath: bool,
 
+    /// terminate the output path with a NUL byte instead of a
+    /// newline (for `xargs -0`)
+    #[clap(short = '0', long = "null", conflicts_with = "json")]
+    null: bool,
+
+    /// print a JSON object with the path, mtime (Unix time in
+    /// seconds) and size (in bytes) of the item (same as `--output
+    /// json`; `--output tsv` prints them as TSV with a header line);
+    /// paths that are not valid UTF-8 are converted lossily
+    #[clap(long)]
+    json: bool,
+
+    #[clap(flatten)]
+    output_args: OutputArgs,
+
+    #[clap(flatten)]
+    output_file_args: OutputFileArgs,
+
     /// the directory to find the item in
     #[clap(parse(from_os_str), default_value = ".")]
     directory_path: PathBuf,
 
-    /// show some information about what's being done
-    #[clap(short, long)]
-    verbose: bool,
+    #[clap(flatten)]
+    verbosity: VerbosityArgs,
 }
 
+impl_cli_opt!(Opt);
+
 impl_item_options_from!(Opt);
 
-#[derive(Debug)]
-pub struct NoPath;
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum By {
+    Mtime,
+    Version,
+}
+
+impl FromStr for By {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "mtime" => Ok(By::Mtime),
+            "version" => Ok(By::Version),
+            _ => bail!("unknown sort key {s:?}, expecting mtime or version"),
+        }
+    }
+}
 
-#[derive(Debug)]
-pub struct Item<P: Debug> {
-    pub parentdir: P,
-    pub filename: OsString,
-    pub mtime: SystemTime,
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tie {
+    Name,
+    Size,
+    Path,
 }
 
-impl Item<NoPath> {
-    pub fn with_parent(self, parentdir: &PathBuf) -> Item<PathBuf> {
-        Item {
-            parentdir: parentdir.clone(),
-            filename: self.filename,
-            mtime: self.mtime,
+impl FromStr for Tie {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "name" => Ok(Tie::Name),
+            "size" => Ok(Tie::Size),
+            "path" => Ok(Tie::Path),
+            _ => {
+                bail!("unknown tie-breaker {s:?}, expecting name, size or path")
+            }
         }
     }
 }
 
-pub fn newer_item<P: Debug>(
-    a: Option<Item<P>>,
-    b: Option<Item<P>>,
-) -> Option<Item<P>> {
+/// Which item to select.
+#[derive(Debug, Clone, Copy)]
+pub struct Selection {
+    pub by: By,
+    pub tie: Tie,
+    pub oldest: bool,
+}
+
+impl Selection {
+    /// `Greater` if `a` is preferred over `b`. Only returns `Equal`
+    /// for items with the same path.
+    pub fn cmp(&self, a: &Item<PathBuf>, b: &Item<PathBuf>) -> Ordering {
+        let primary = match self.by {
+            By::Mtime => a.mtime.cmp(&b.mtime),
+            By::Version => {
+                natural_cmp(a.filename.as_bytes(), b.filename.as_bytes())
+            }
+        };
+        let primary = if self.oldest {
+            primary.reverse()
+        } else {
+            primary
+        };
+        // For the tie-breakers, sorting first wins (except for size)
+        primary
+            .then_with(|| match self.tie {
+                Tie::Name => b.filename.cmp(&a.filename),
+                Tie::Size => a.size.cmp(&b.size),
+                Tie::Path => Ordering::Equal,
+            })
+            .then_with(|| b.path().cmp(&a.path()))
+    }
+}
+
+pub fn newer_item(
+    selection: &Selection,
+    a: Option<Item<PathBuf>>,
+    b: Option<Item<PathBuf>>,
+) -> Option<Item<PathBuf>> {
     match (a, b) {
         (Some(a), Some(b)) => {
-            if (a.mtime < b.mtime)
-                || ((a.mtime == b.mtime) && (a.filename > b.filename))
-            {
+            if selection.cmp(&a, &b) == Ordering::Less {
                 Some(b)
             } else {
                 Some(a)
@@ -128,79 +274,64 @@ pub fn newer_item<P: Debug>(
     }
 }
 
-fn lastitem(
-    dir_path: &PathBuf,
-    opt: ItemOptions,
-    excludes: &Excludes,
-) -> Result<Option<Item<PathBuf>>> {
-    let region = Region::new();
-    let dir_path_id = region.store(dir_path.clone());
-    let items =
-        file_path_types_vec(&region, dir_path_id, opt, excludes, false)?;
-    let newest_item = items
-        .into_par_iter()
-        .try_fold(
-            || None,
-            |newest_item: Option<Item<NoPath>>,
-             FilePathType { file_name, .. }|
-             -> Result<Option<Item<NoPath>>> {
-                let path = dir_path.join(&file_name);
-                let md = fs::symlink_metadata(&path).with_context(|| {
-                    anyhow!("symlink_metadata on {file_name:?}")
-                })?;
-                let mtime = md
-                    .modified()
-                    .with_context(|| anyhow!("modified on {file_name:?}"))?;
-                Ok(newer_item(
-                    newest_item,
-                    Some(Item {
-                        parentdir: NoPath,
-                        filename: file_name,
-                        mtime,
-                    }),
-                ))
-            },
-        )
-        .try_reduce(|| None, |a, b| Ok(newer_item(a, b)))?;
-    Ok(newest_item.map(|item| item.with_parent(dir_path)))
+/// The paths in a list separated by newlines, or NUL bytes if there
+/// are any (see `Opt::stdin`).
+fn parse_path_list(input: &[u8]) -> Vec<PathBuf> {
+    let separator = if input.contains(&0) { 0 } else { b'\n' };
+    input
+        .split(|b| *b == separator)
+        .filter(|path| !path.is_empty())
+        .map(|path| PathBuf::from(OsStr::from_bytes(path)))
+        .collect()
 }
 
-fn deeper_lastitem(
-    dir_path: PathBuf,
-    depth: u8,
-    opt: ItemOptions,
+/// The item at `path`, None if it is not of a kind selected by
+/// `opt` or its file name is excluded. Paths without a file name
+/// (like `.`, `..` or `/`, as output by find(1) for the starting
+/// point) are skipped, too.
+fn item_from_path(
+    path: &Path,
+    opt: &ItemOptions,
     excludes: &Excludes,
 ) -> Result<Option<Item<PathBuf>>> {
-    if depth == 0 {
-        lastitem(&dir_path, opt, excludes)
+    let file_name = match path.file_name() {
+        Some(file_name) => file_name,
+        None => return Ok(None),
+    };
+    let md = if opt.follow_symlinks {
+        fs::metadata(path).or_else(|_| fs::symlink_metadata(path))
     } else {
-        let region = Region::new();
-        let dir_path = region.store(dir_path);
-        let dir_items = file_path_types_vec(
-            &region,
-            dir_path,
-            ItemOptions {
-                dirs: true,
-                files: false,
-                other: false,
-            },
-            excludes,
-            false,
-        )?;
-        dir_items
-            .into_par_iter()
-            .map(|FilePathType { file_name, .. }| {
-                let path = region.get(dir_path).join(file_name);
-                deeper_lastitem(path, depth - 1, opt, excludes)
-            })
-            .try_fold(|| None, |a, b_result| b_result.map(|b| newer_item(a, b)))
-            .try_reduce(|| None, |a, b| Ok(newer_item(a, b)))
+        fs::symlink_metadata(path)
     }
+    .with_context(|| anyhow!("getting metadata of {path:?}"))?;
+    let file_type = md.file_type();
+    let selected = if file_type.is_dir() {
+        opt.dirs
+    } else if file_type.is_file() {
+        opt.files
+    } else {
+        opt.other
+    };
+    if !selected || excludes.filename_is_excluded(file_name, file_type.is_dir())
+    {
+        return Ok(None);
+    }
+    Ok(Some(Item {
+        parentdir: path.parent().unwrap_or(Path::new("")).to_path_buf(),
+        filename: file_name.to_owned(),
+        mtime: md
+            .modified()
+            .with_context(|| anyhow!("modified on {path:?}"))?,
+        size: md.len(),
+    }))
 }
 
-fn main() -> Result<()> {
-    let mut opt = Opt::from_args();
+fn main() {
+    cli::check_main_with_matches(ErrorKind::Io, run)
+}
 
+/// Returns false if the item is too old (see `--newer-than`).
+fn run(mut opt: Opt, matches: &ArgMatches) -> Result<bool> {
     if !opt.files && !opt.dirs && !opt.other {
         let arg0 = env::args_os().next();
         let exepath = arg0
@@ -218,10 +349,10 @@ fn main() -> Result<()> {
         } else if exename == "lastdir" {
             opt.dirs = true;
         } else {
-            bail!(
+            return Err(ErrorKind::Usage.wrap(anyhow!(
                 "inacceptable executable name: {}",
                 exename.to_string_lossy()
-            );
+            )));
         }
     }
 
@@ -231,6 +362,13 @@ fn main() -> Result<()> {
         default_excludes(opt.all)
     };
 
+    if opt.hidden {
+        excludes.exclude_dot_files = false;
+    }
+    if opt.no_hidden {
+        excludes.exclude_dot_files = true;
+    }
+
     for s in &opt.ignore_file {
         excludes.files.insert(s.clone());
     }
@@ -239,35 +377,85 @@ fn main() -> Result<()> {
         excludes.dirs.insert(s.clone());
     }
 
-    if opt.verbose {
+    excludes.rules = merge_rules(vec![
+        rules_from_env("LASTITEM_EXCLUDE"),
+        opt.exclude_args.rules(matches).usage_error()?,
+    ]);
+
+    if opt.verbosity.is_verbose() {
         eprintln!("lastitem: {excludes:?}");
     }
 
-    env::set_current_dir(&opt.directory_path)
-        .with_context(|| format!("can't chdir to {:?}", opt.directory_path))?;
-
-    let last = deeper_lastitem(
-        PathBuf::from("."),
-        opt.depth.unwrap_or(0),
-        ItemOptions::from(&opt),
-        &excludes,
-    )?;
+    let item_options = ItemOptions {
+        follow_symlinks: opt.deref,
+        one_file_system: opt.one_file_system,
+        ..ItemOptions::from(&opt)
+    };
+    let max_age = opt
+        .newer_than
+        .as_deref()
+        .map(parse_duration)
+        .transpose()
+        .usage_error()?;
+    let selection = Selection {
+        by: opt.by,
+        tie: opt.tie,
+        oldest: opt.oldest,
+    };
+    let last = if opt.stdin {
+        let mut last = None;
+        let mut input = Vec::new();
+        stdin()
+            .lock()
+            .read_to_end(&mut input)
+            .context("reading stdin")?;
+        for path in parse_path_list(&input) {
+            let item = item_from_path(&path, &item_options, &excludes)?;
+            last = newer_item(&selection, last, item);
+        }
+        last
+    } else {
+        // Relative output paths are relative to the original working
+        // directory, not the scanned one (opening the output file
+        // here instead would make its temporary file show up in the
+        // scan)
+        if let Some(path) = &opt.output_file_args.output_file {
+            if path.is_relative() && path.as_os_str() != "-" {
+                let cwd = env::current_dir()
+                    .context("getting the current directory")?;
+                opt.output_file_args.output_file = Some(cwd.join(path));
+            }
+        }
+        env::set_current_dir(&opt.directory_path).with_context(|| {
+            format!("can't chdir to {:?}", opt.directory_path)
+        })?;
+        let scan = DirScan {
+            opt: item_options,
+            excludes: &excludes,
+            recursion: Recursion::AtDepth(opt.depth.unwrap_or(0)),
+        };
+        scan.fold(
+            Path::new("."),
+            || None,
+            |newest, item| Ok(newer_item(&selection, newest, Some(item))),
+            |a, b| newer_item(&selection, a, b),
+        )?
+    };
 
     match last {
-        Some(Item {
-            parentdir,
-            filename,
-            mtime: _,
-        }) => {
-            // todo: it is offering `join`, yet then we use the
-            // archaic "./" stripping.
-            let clean_parentdir: &Path =
-                parentdir.strip_prefix("./").unwrap_or(&parentdir);
-            let path = clean_parentdir.join(&filename);
-            let full_path = if opt.fullpath {
+        Some(item) => {
+            let Item { mtime, size, .. } = item;
+            let path = item.path();
+            // An mtime in the future counts as young enough
+            let too_old = max_age.is_some_and(|max_age| {
+                SystemTime::now()
+                    .duration_since(mtime)
+                    .is_ok_and(|age| age >= max_age)
+            });
+            let full_path = if opt.fullpath && !opt.stdin {
                 opt.directory_path.join(path)
             } else {
-                path.into()
+                path
             };
             // (todo: is going via OsString for bytes the correct approach?)
 
@@ -275,17 +463,46 @@ fn main() -> Result<()> {
             // io::stdout().write_all_vectored(&mut [
             //     IoSlice::new(full_path.into_os_string().as_bytes()),
             //     IoSlice::new(b"\n")])?;
-            let mut lock = io::stdout().lock();
-            lock.write_all(full_path.into_os_string().as_bytes())?;
-            lock.write_all(b"\n")?;
-            Ok(())
+            let mut out = opt.output_file_args.open()?;
+            let mut output = opt
+                .output_args
+                .output_to(out.as_raw_fd(), &["path", "mtime", "size"]);
+            if opt.json {
+                output.format = OutputFormat::Json;
+            }
+            if !output.is_text() {
+                let mtime = match mtime.duration_since(UNIX_EPOCH) {
+                    Ok(d) => d.as_secs_f64(),
+                    Err(e) => -e.duration().as_secs_f64(),
+                };
+                output.write_record(
+                    &mut out,
+                    &[
+                        full_path.to_string_lossy().into(),
+                        mtime.into(),
+                        size.into(),
+                    ],
+                )?;
+            } else {
+                out.write_all(full_path.into_os_string().as_bytes())?;
+                out.write_all(if opt.null { b"\0" } else { b"\n" })?;
+            }
+            out.commit()?;
+            if too_old && opt.verbosity.is_verbose() {
+                eprintln!(
+                    "lastitem: the item is not newer than {}",
+                    opt.newer_than.as_ref().expect("given if too_old")
+                );
+            }
+            Ok(!too_old)
         }
         None => {
             if opt.allow_empty {
-                Ok(())
+                opt.output_file_args.open()?.commit()?;
+                Ok(true)
             } else {
                 bail!(
-                    "No {} found in given directory",
+                    "No {} found in given {}",
                     if opt.dirs && opt.files && opt.other {
                         String::from("items")
                     } else {
@@ -303,9 +520,96 @@ fn main() -> Result<()> {
                             panic!("no option is set")
                         }
                         which.natural_language_join()
-                    }
+                    },
+                    if opt.stdin { "paths" } else { "directory" }
                 )
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chj_rustbin::io::unix_fs::{cstr_as_path, TempDir};
+    use std::ffi::CString;
+    use std::time::Duration;
+
+    fn item(dir: &str, name: &str, mtime: u64, size: u64) -> Item<PathBuf> {
+        Item {
+            parentdir: dir.into(),
+            filename: name.into(),
+            mtime: UNIX_EPOCH + Duration::from_secs(mtime),
+            size,
+        }
+    }
+
+    #[test]
+    fn t_selection() {
+        let items = || {
+            vec![
+                item("./b", "x", 20, 1),
+                item("./a", "y", 20, 3),
+                item("./a", "z", 20, 3),
+                item("./a", "old", 10, 9),
+            ]
+        };
+        let t = |by, tie, oldest| {
+            let selection = Selection { by, tie, oldest };
+            items()
+                .into_iter()
+                .fold(None, |a, b| newer_item(&selection, a, Some(b)))
+                .expect("non-empty")
+                .path()
+        };
+        assert_eq!(t(By::Mtime, Tie::Name, false), Path::new("b/x"));
+        assert_eq!(t(By::Mtime, Tie::Size, false), Path::new("a/y"));
+        assert_eq!(t(By::Mtime, Tie::Path, false), Path::new("a/y"));
+        assert_eq!(t(By::Mtime, Tie::Name, true), Path::new("a/old"));
+        assert_eq!(t(By::Version, Tie::Name, false), Path::new("a/z"));
+        assert_eq!(t(By::Version, Tie::Name, true), Path::new("a/old"));
+    }
+
+    #[test]
+    fn t_parse_path_list() {
+        assert_eq!(
+            parse_path_list(b"a\nb c\n\nd"),
+            ["a", "b c", "d"].map(PathBuf::from)
+        );
+        assert_eq!(
+            parse_path_list(b"./a\nb\0c\0"),
+            ["./a\nb", "c"].map(PathBuf::from)
+        );
+        assert!(parse_path_list(b"").is_empty());
+    }
+
+    #[test]
+    fn t_item_from_path() -> Result<()> {
+        let tmp = CString::new(env::temp_dir().as_os_str().as_bytes())?;
+        let dir = TempDir::new_in(&tmp, "chj-rustbin-test-")?;
+        let root = cstr_as_path(dir.path()).to_path_buf();
+        fs::create_dir(root.join("sub"))?;
+        fs::write(root.join("sub/a"), "a")?;
+        let opt = ItemOptions {
+            dirs: true,
+            files: true,
+            other: true,
+            follow_symlinks: false,
+            one_file_system: false,
+        };
+        let excludes = empty_excludes(false);
+        // Like the output of `find .`, `find /` and `find ..`
+        let mut input = b".\n./\n/\n..\n".to_vec();
+        input.extend_from_slice(root.join("sub/a").as_os_str().as_bytes());
+        let items = parse_path_list(&input)
+            .iter()
+            .map(|path| item_from_path(path, &opt, &excludes))
+            .collect::<Result<Vec<_>>>()?;
+        assert_eq!(items.len(), 5);
+        assert!(items[..4].iter().all(Option::is_none));
+        let item = items[4].as_ref().expect("a file");
+        assert_eq!(item.path(), root.join("sub/a"));
+        assert_eq!(item.size, 1);
+        Ok(())
+    }
+}
@@ -0,0 +1,301 @@
+use std::collections::BTreeMap;
+use std::io::{stdout, BufWriter, Write};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{bail, Result};
+use chrono::{DateTime, Local, Utc};
+use clap::ArgMatches;
+
+use chj_rustbin::cli::{self, VerbosityArgs};
+use chj_rustbin::impl_cli_opt;
+use chj_rustbin::io::dirscan::{DirScan, Recursion};
+use chj_rustbin::io::excludes::{
+    default_excludes, empty_excludes, ExcludeArgs,
+};
+use chj_rustbin::io::file_path_type::ItemOptions;
+use chj_rustbin::numbers::{OnlineStats, SortedNumbers};
+use chj_rustbin::util::cli_output::{OutputArgs, BOLD};
+
+#[derive(clap::Parser, Debug)]
+/// Show how many files in a directory were last modified in each day
+/// (or hour), as a histogram, followed by a summary (the oldest,
+/// median and newest mtime, and the busiest period). Helps answering
+/// "when did this directory last receive lots of files". With
+/// `--output tsv` or `json`, prints the periods as records (with the
+/// summary on stderr if `-v` is given). Periods without any files are
+/// not shown.
+#[clap(name = chj_rustbin::cli_name!())]
+struct Opt {
+    /// the size of the periods: `day` (the default) or `hour`
+    #[clap(long, default_value = "day")]
+    by: Period,
+
+    /// the periods are in UTC instead of the local time zone
+    #[clap(long)]
+    utc: bool,
+
+    /// include the files in subdirectories, at all levels
+    #[clap(short, long)]
+    recursive: bool,
+
+    /// with --recursive, don't descend into directories on other
+    /// mounts (file systems, or bind mounts) than the given directory
+    #[clap(short = 'x', long, alias = "xdev")]
+    one_file_system: bool,
+
+    /// do not ignore dot and Emacs backup (ending in '~') files
+    #[clap(short, long)]
+    all: bool,
+
+    /// do not ignore special file and dir names that are ignored by
+    /// default, like .git
+    #[clap(long)]
+    no_ignore: bool,
+
+    #[clap(flatten)]
+    exclude_args: ExcludeArgs,
+
+    /// the width of the longest histogram bar, in characters
+    #[clap(long, default_value = "50")]
+    width: usize,
+
+    #[clap(flatten)]
+    output_args: OutputArgs,
+
+    /// the directory to scan
+    #[clap(parse(from_os_str), default_value = ".")]
+    directory_path: PathBuf,
+
+    #[clap(flatten)]
+    verbosity: VerbosityArgs,
+}
+
+impl_cli_opt!(Opt);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Period {
+    Day,
+    Hour,
+}
+
+impl FromStr for Period {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "day" => Ok(Period::Day),
+            "hour" => Ok(Period::Hour),
+            _ => bail!("unknown period {s:?}, expecting day or hour"),
+        }
+    }
+}
+
+/// Formats times as the period they fall into; the strings sort
+/// chronologically.
+#[derive(Debug, Clone, Copy)]
+struct Periods {
+    period: Period,
+    utc: bool,
+}
+
+impl Periods {
+    fn format(&self, t: SystemTime, format: &str) -> String {
+        if self.utc {
+            DateTime::<Utc>::from(t).format(format).to_string()
+        } else {
+            DateTime::<Local>::from(t).format(format).to_string()
+        }
+    }
+
+    fn of(&self, t: SystemTime) -> String {
+        self.format(
+            t,
+            match self.period {
+                Period::Day => "%Y-%m-%d",
+                Period::Hour => "%Y-%m-%d %H:00",
+            },
+        )
+    }
+
+    fn time(&self, t: SystemTime) -> String {
+        self.format(t, "%Y-%m-%d %H:%M:%S")
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+struct Bucket {
+    files: u64,
+    bytes: u64,
+}
+
+fn histogram(
+    periods: Periods,
+    files: &[(SystemTime, u64)],
+) -> BTreeMap<String, Bucket> {
+    let mut buckets: BTreeMap<String, Bucket> = BTreeMap::new();
+    for (mtime, size) in files {
+        let bucket = buckets.entry(periods.of(*mtime)).or_default();
+        bucket.files += 1;
+        bucket.bytes += size;
+    }
+    buckets
+}
+
+fn unixtime(t: SystemTime) -> f64 {
+    match t.duration_since(UNIX_EPOCH) {
+        Ok(d) => d.as_secs_f64(),
+        Err(e) => -e.duration().as_secs_f64(),
+    }
+}
+
+fn from_unixtime(t: f64) -> SystemTime {
+    if t >= 0. {
+        UNIX_EPOCH + Duration::from_secs_f64(t)
+    } else {
+        UNIX_EPOCH - Duration::from_secs_f64(-t)
+    }
+}
+
+fn main() {
+    cli::main_with_matches(run)
+}
+
+fn run(opt: Opt, matches: &ArgMatches) -> Result<()> {
+    let mut excludes = if opt.no_ignore {
+        empty_excludes(opt.all)
+    } else {
+        default_excludes(opt.all)
+    };
+    excludes.rules = opt.exclude_args.rules(matches)?;
+    let scan = DirScan {
+        opt: ItemOptions {
+            dirs: false,
+            files: true,
+            other: false,
+            follow_symlinks: false,
+            one_file_system: opt.one_file_system,
+        },
+        excludes: &excludes,
+        recursion: if opt.recursive {
+            Recursion::All
+        } else {
+            Recursion::None
+        },
+    };
+    let files = scan.fold(
+        &opt.directory_path,
+        Vec::new,
+        |mut files, item| {
+            files.push((item.mtime, item.size));
+            Ok(files)
+        },
+        |mut a, b| {
+            a.extend(b);
+            a
+        },
+    )?;
+    if files.is_empty() {
+        bail!("no files found in {:?}", opt.directory_path)
+    }
+
+    let periods = Periods {
+        period: opt.by,
+        utc: opt.utc,
+    };
+    let buckets = histogram(periods, &files);
+    let mut output = opt.output_args.output(&["period", "files", "bytes"]);
+    let mut out = BufWriter::new(stdout().lock());
+    let max_files = buckets.values().map(|b| b.files).max().unwrap_or(0);
+    for (period, bucket) in &buckets {
+        if output.is_text() {
+            let bar_len = (bucket.files as f64 / max_files as f64
+                * opt.width as f64)
+                .ceil() as usize;
+            writeln!(
+                out,
+                "{period}  {:>7}  {}",
+                bucket.files,
+                "#".repeat(bar_len)
+            )?;
+        } else {
+            output.write_record(
+                &mut out,
+                &[
+                    period.as_str().into(),
+                    bucket.files.into(),
+                    bucket.bytes.into(),
+                ],
+            )?;
+        }
+    }
+    out.flush()?;
+
+    if output.is_text() || opt.verbosity.is_verbose() {
+        let stats: OnlineStats =
+            files.iter().map(|(mtime, _)| unixtime(*mtime)).collect();
+        let sorted: SortedNumbers =
+            files.iter().map(|(mtime, _)| unixtime(*mtime)).collect();
+        let time = |t: Option<f64>| {
+            periods.time(from_unixtime(t.expect("have files")))
+        };
+        let (busiest, busiest_bucket) = buckets
+            .iter()
+            .max_by_key(|(_, bucket)| bucket.files)
+            .expect("have files");
+        let bytes: u64 = files.iter().map(|(_, size)| size).sum();
+        let summary = format!(
+            "{} files ({bytes} bytes) in {} {}s; oldest: {}, median: {}, \
+             newest: {}; busiest: {busiest} ({} files)",
+            stats.count(),
+            buckets.len(),
+            match opt.by {
+                Period::Day => "day",
+                Period::Hour => "hour",
+            },
+            time(stats.min()),
+            time(sorted.median()),
+            time(stats.max()),
+            busiest_bucket.files,
+        );
+        if output.is_text() {
+            writeln!(out, "{}", output.paint(BOLD, &summary))?;
+            out.flush()?;
+        } else {
+            eprintln!("mtimedir: {summary}");
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn t_histogram() {
+        let t =
+            |secs: u64, size| (UNIX_EPOCH + Duration::from_secs(secs), size);
+        let files = [t(0, 1), t(3599, 2), t(3600, 4), t(86400, 8)];
+        let buckets = |period| {
+            histogram(Periods { period, utc: true }, &files)
+                .into_iter()
+                .map(|(p, b)| (p, b.files, b.bytes))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            buckets(Period::Day),
+            [("1970-01-01".into(), 3, 7), ("1970-01-02".into(), 1, 8)]
+        );
+        assert_eq!(
+            buckets(Period::Hour),
+            [
+                ("1970-01-01 00:00".into(), 2, 3),
+                ("1970-01-01 01:00".into(), 1, 4),
+                ("1970-01-02 00:00".into(), 1, 8)
+            ]
+        );
+        assert_eq!(from_unixtime(unixtime(files[1].0)), files[1].0);
+    }
+}
@@ -0,0 +1,336 @@
+use std::cell::RefCell;
+use std::convert::TryFrom;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+use anyhow::{anyhow, bail, Result};
+use tai64::Tai64N;
+
+use chj_rustbin::cli::{self, VerbosityArgs};
+use chj_rustbin::impl_cli_opt;
+use chj_rustbin::io::readwithcontext::ReadWithContext;
+use chj_rustbin::netcounters::{
+    log_files_in_dirs, process_hourly, Datapoint, HourlyOptions, Transfer,
+};
+use chj_rustbin::pipeline::try_gen;
+use chj_rustbin::text::parseutil::{parse_key_val_blocks, KeyValNode};
+use chj_rustbin::time::excel::CsvSeparator;
+use chj_rustbin::time::tai::{
+    parse_timestamp_tolerant, Tai64Format, TimeFormatting, TimestampedLine,
+};
+use chj_rustbin::util::error_policy::{ErrorPolicy, ErrorPolicyArgs};
+use chj_rustbin::util::signals::{
+    install_termination_handler, TERMINATION_SIGNALS,
+};
+
+#[derive(clap::Parser, Debug)]
+/// Parse a log file consisting of repeated output of `ip -s link`,
+/// with tai64n timestamps prepended to each line (DJB daemontools log
+/// format), and write the same tables as parse-wg-log. (vnstat's
+/// formats are not supported.)
+#[clap(name = chj_rustbin::cli_name!())]
+struct Opt {
+    /// Show parsed data directly
+    #[clap(long)]
+    show_direct: bool,
+
+    /// Calculate derived values and save as TSV files, one for each
+    /// interface. The option specifies the base path, to which
+    /// `$interfacename.tsv` is appended for the hourly tables, and
+    /// `$interfacename-summary.tsv` is appended for the monthly
+    /// summary tables.
+    #[clap(long)]
+    tsv: Option<String>,
+
+    /// With --tsv, write the tables as CSV for Excel instead (with
+    /// `.csv` extensions): `comma`, or `semicolon` for the locales
+    /// with a decimal comma (also written for the numbers).
+    #[clap(long, requires = "tsv")]
+    excel_csv: Option<CsvSeparator>,
+
+    /// Only process the interface with this name (can be given
+    /// multiple times). By default, all interfaces except `lo` are
+    /// processed.
+    #[clap(short, long)]
+    interface: Vec<String>,
+
+    /// Don't drop the samples in the middle of runs of unchanged
+    /// counters (within the same hour) before grouping (the TSV output
+    /// is the same either way, this is just for verification).
+    #[clap(long)]
+    no_dedup: bool,
+
+    #[clap(flatten)]
+    error_policy: ErrorPolicyArgs,
+
+    /// The paths to dirs with files to parse
+    #[clap(parse(from_os_str))]
+    dir_paths: Vec<PathBuf>,
+
+    #[clap(flatten)]
+    verbosity: VerbosityArgs,
+}
+
+impl_cli_opt!(Opt);
+
+/// Interface names, indexed by the interface number used in
+/// `Datapoint`s (in the order of appearance).
+#[derive(Default)]
+struct InterfaceNames(Vec<String>);
+
+impl InterfaceNames {
+    fn index(&mut self, name: &str) -> Result<u16> {
+        let i = match self.0.iter().position(|n| n == name) {
+            Some(i) => i,
+            None => {
+                self.0.push(name.to_string());
+                self.0.len() - 1
+            }
+        };
+        u16::try_from(i).map_err(|_| anyhow!("too many interfaces"))
+    }
+}
+
+/// A line like `2: eth0: <BROADCAST,...> mtu ...` starts the block
+/// for an interface.
+fn is_block_start(line: &str) -> bool {
+    line.split_once(": ").is_some_and(|(index, _)| {
+        !index.is_empty() && index.chars().all(|c| c.is_ascii_digit())
+    })
+}
+
+/// The value in the column `column` from a header node like
+/// `RX:  bytes packets errors ...`, which is followed by a line with
+/// the values.
+fn stats_value(
+    block: &KeyValNode,
+    header: &str,
+    column: &str,
+) -> Result<usize> {
+    let i = block
+        .children
+        .iter()
+        .position(|c| c.key == header)
+        .ok_or_else(|| anyhow!("missing {header:?} line"))?;
+    let columns = block.children[i].value.as_deref().unwrap_or("");
+    let col = columns
+        .split_whitespace()
+        .position(|c| c == column)
+        .ok_or_else(|| anyhow!("missing {column:?} column in {header:?}"))?;
+    // The values line is usually indented more than the header, thus
+    // its child, but might also be at the same level
+    let values = block.children[i]
+        .children
+        .first()
+        .or_else(|| block.children.get(i + 1))
+        .ok_or_else(|| anyhow!("missing values after {header:?} line"))?;
+    let value =
+        values.key.split_whitespace().nth(col).ok_or_else(|| {
+            anyhow!("missing value for {column:?} in {header:?}")
+        })?;
+    Ok(value.parse()?)
+}
+
+/// Returns the interface name and its counters.
+fn parse_block(lines: &[String]) -> Result<(String, Transfer)> {
+    let nodes = parse_key_val_blocks(lines.iter().map(|l| l.as_str()));
+    let block = match nodes.as_slice() {
+        [block] => block,
+        _ => bail!("expected a single block, got {}", nodes.len()),
+    };
+    let value = block.value.as_deref().unwrap_or("");
+    let name = value
+        .split(':')
+        .next()
+        .expect("split always gives one item");
+    // "veth0@if5" -> "veth0"
+    let name = name.split('@').next().expect("split always gives one item");
+    let transfer = Transfer {
+        received: stats_value(block, "RX", "bytes")?,
+        sent: stats_value(block, "TX", "bytes")?,
+    };
+    Ok((name.to_string(), transfer))
+}
+
+fn parse_files(
+    files: Vec<PathBuf>,
+    interfaces: Vec<String>,
+    names: Rc<RefCell<InterfaceNames>>,
+    mut error_policy: ErrorPolicy,
+) -> impl Iterator<Item = Result<Datapoint>> {
+    try_gen(|co| async move {
+        let mut line = String::new();
+        // The lines of the current block, and the timestamp of its
+        // first line
+        let mut block: Vec<String> = Vec::new();
+        let mut block_timestamp: Option<Tai64N> = None;
+        let accept = |name: &str| {
+            if interfaces.is_empty() {
+                name != "lo"
+            } else {
+                interfaces.iter().any(|i| i == name)
+            }
+        };
+        let finish = |block: &[String],
+                      timestamp: Option<Tai64N>|
+         -> Result<Option<Datapoint>> {
+            let timestamp = match timestamp {
+                Some(t) => t,
+                None => return Ok(None),
+            };
+            let (name, transfer) = parse_block(block)?;
+            if !accept(&name) {
+                return Ok(None);
+            }
+            let i = names.borrow_mut().index(&name)?;
+            Ok(Some(Datapoint::new(i, timestamp, transfer)))
+        };
+        for file in files {
+            let mut inp = ReadWithContext::open_path(&file)?;
+
+            while inp.easy_read_line(&mut line)? {
+                let (timestamp, rest) = match parse_timestamp_tolerant(&line) {
+                    TimestampedLine::Timestamped(timestamp, rest) => {
+                        (timestamp, rest)
+                    }
+                    TimestampedLine::Continuation(rest) => {
+                        // Part of a multi-line message: attach it to
+                        // the current block (making it fail to parse
+                        // once, instead of an error per line)
+                        if block_timestamp.is_some() {
+                            block.push(rest.to_string());
+                        } else {
+                            error_policy.handle(
+                                inp.err_with_context::<()>(anyhow!(
+                                    "line without timestamp"
+                                ))
+                                .unwrap_err(),
+                            )?;
+                        }
+                        continue;
+                    }
+                };
+                if is_block_start(rest) {
+                    match finish(&block, block_timestamp) {
+                        Ok(Some(dp)) => co.yield_(Ok(dp)).await,
+                        Ok(None) => {}
+                        Err(e) => error_policy.handle(
+                            inp.err_with_context::<()>(e).unwrap_err(),
+                        )?,
+                    }
+                    block.clear();
+                    block_timestamp = Some(timestamp);
+                }
+                if block_timestamp.is_some() {
+                    block.push(rest.to_string());
+                }
+            }
+            // Blocks don't span files
+            match finish(&block, block_timestamp.take()) {
+                Ok(Some(dp)) => co.yield_(Ok(dp)).await,
+                Ok(None) => {}
+                Err(e) => {
+                    error_policy.handle(e.context(anyhow!("file {file:?}")))?
+                }
+            }
+            block.clear();
+        }
+        error_policy.report_ignored();
+        Ok(())
+    })
+}
+
+fn main() {
+    cli::main(run)
+}
+
+fn run(opt: Opt) -> Result<()> {
+    if !opt.show_direct && opt.tsv.is_none() {
+        eprintln!(
+            "WARNING: neither --tsv nor --show-direct given, \
+                   going to parse without output"
+        );
+    }
+
+    let file_paths = log_files_in_dirs(&opt.dir_paths)?;
+    let names: Rc<RefCell<InterfaceNames>> = Default::default();
+    let datapoints = parse_files(
+        file_paths,
+        opt.interface,
+        names.clone(),
+        opt.error_policy.policy(),
+    );
+    let name = |i: u16| names.borrow().0[i as usize].clone();
+    if opt.show_direct {
+        for datapoint in datapoints {
+            let datapoint = datapoint?;
+            println!(
+                "{}: {}: {} {}",
+                datapoint.timestamp.to_rfc2822_local(),
+                name(datapoint.interface),
+                datapoint.transfer.received,
+                datapoint.transfer.sent
+            );
+        }
+        return Ok(());
+    }
+    if opt.tsv.is_some() {
+        // Finish the output files with the data processed so far if
+        // interrupted
+        install_termination_handler(TERMINATION_SIGNALS)?;
+        let outcome = process_hourly(
+            datapoints,
+            name,
+            &HourlyOptions {
+                basepath: opt.tsv.as_deref(),
+                csv: opt.excel_csv,
+                dedup: !opt.no_dedup,
+                max_snapshot_seconds: 8,
+                chart: None,
+                fill_gaps: false,
+                flush_rows: false,
+                time_format: TimeFormatting::default(),
+            },
+            None,
+        )?;
+        if let Some(e) = outcome.terminated {
+            eprintln!(
+                "parse-ip-link-log: {e}, wrote output for the data \
+                 processed so far"
+            );
+            e.exit();
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn t_parse_block() {
+        let lines: Vec<String> = "\
+2: eth0@if5: <BROADCAST,MULTICAST,UP,LOWER_UP> mtu 1500 qdisc noqueue
+    link/ether 02:42:ac:11:00:02 brd ff:ff:ff:ff:ff:ff link-netnsid 0
+    RX:  bytes packets errors dropped  missed   mcast
+         12345     100      0       0       0       0
+    TX:  bytes packets errors dropped carrier collsns
+          6789      50      0       0       0       0"
+            .lines()
+            .map(String::from)
+            .collect();
+        assert!(is_block_start(&lines[0]));
+        assert!(!is_block_start(&lines[2]));
+        let (name, transfer) = parse_block(&lines).unwrap();
+        assert_eq!(name, "eth0");
+        assert_eq!(
+            transfer,
+            Transfer {
+                received: 12345,
+                sent: 6789
+            }
+        );
+        assert!(parse_block(&lines[0..4]).is_err());
+    }
+}
@@ -1,32 +1,52 @@
 use anyhow::{anyhow, bail, Context, Result};
-use chj_rustbin::util::div::{hashmap_add, hashmap_get_mut_vivify};
-use chrono::{Datelike, NaiveDate, Timelike};
-use clap::Parser;
-use genawaiter::rc::Gen;
-use std::collections::HashMap;
-use std::io::Write;
-use std::ops::Add;
-use std::{fmt::Display, fs::File, io::BufWriter, path::PathBuf};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
+use std::fs::File;
+use std::io::{BufWriter, ErrorKind, Write};
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+use std::rc::Rc;
+use std::time::Duration;
+use std::{fmt::Display, path::PathBuf};
+
 use tai64::Tai64N;
 
-use chj_rustbin::gen_try_result;
-use chj_rustbin::numbers::{max_f64, nandropping_add, numbers_within};
-use chj_rustbin::sequences::try_group;
+use chj_rustbin::alist::AListBuf;
+use chj_rustbin::cli::{self, OutputFileArgs, VerbosityArgs};
+use chj_rustbin::error;
+use chj_rustbin::impl_cli_opt;
+use chj_rustbin::io::unix_fs::write_file_atomically;
+use chj_rustbin::netcounters::{
+    log_files_in_dirs, process_hourly, write_summary_table, Datapoint,
+    HourlyOptions, HourlyState, LatestCounters, Transfer,
+};
+use chj_rustbin::numbers::{f64_to_usize, Rounding};
+use chj_rustbin::pipeline::try_gen;
+use chj_rustbin::sequences::{try_sort_within, Window};
+use chj_rustbin::time::excel::CsvSeparator;
+use chj_rustbin::time::realtime::parse_duration;
+use chj_rustbin::time::tai::TimeFormatting;
+use chj_rustbin::util::cli_output::{Output, OutputFormat};
+use chj_rustbin::util::error_policy::{ErrorPolicy, ErrorPolicyArgs};
+use chj_rustbin::util::signals::{
+    install_termination_handler, termination_signal, Terminated,
+    TERMINATION_SIGNALS,
+};
 use chj_rustbin::{
-    fp::on,
     io::readwithcontext::ReadWithContext,
-    text::parseutil::{
-        after_white, cleanwhite, is_all_white, key_val, parse_byte_multiplier,
-    },
-    time::tai::{parse_timestamp, Tai64Format},
+    text::parseutil::{cleanwhite, parse_byte_multiplier, LineTokenizer},
+    time::tai::{parse_timestamp_tolerant, TimeFormatArgs, TimestampedLine},
 };
 
 #[derive(clap::Parser, Debug)]
 /// Parse a log file consisting of repeated output of `wg` (wireguard
 /// command line tool), with tai64n timestamps prepended to each line
-/// (DJB daemontools log format).
-
-#[clap(name = "parse-wg-log from chj-rustbin")]
+/// (DJB daemontools log format). The transfers of all peers of an
+/// interface are summed up (see --per-peer). With --state, keep
+/// passing the same --time-format and --utc options, since rows are
+/// appended to the existing tables.
+#[clap(name = chj_rustbin::cli_name!())]
 struct Opt {
     /// Show parsed data directly
     #[clap(long)]
@@ -40,35 +60,130 @@ struct Opt {
     #[clap(long)]
     tsv: Option<String>,
 
-    /// The paths to dirs with files to parse
+    /// With --tsv, write the tables as CSV for Excel instead (with
+    /// `.csv` extensions): `comma`, or `semicolon` for the locales
+    /// with a decimal comma (also written for the numbers).
+    #[clap(long, requires = "tsv")]
+    excel_csv: Option<CsvSeparator>,
+
+    /// With --tsv, also write an SVG chart of the hourly traffic of
+    /// each interface to this path.
+    #[clap(long, requires = "tsv", parse(from_os_str))]
+    chart: Option<PathBuf>,
+
+    /// With --tsv, write rows with only the time columns filled in for
+    /// hours without data (e.g. because the logger was down), so that
+    /// there's a row for every hour from the first to the last.
+    #[clap(long, requires = "tsv")]
+    fill_gaps: bool,
+
+    /// Print statistics per interface over the whole log range: total
+    /// received and sent, the busiest hour, the average hourly
+    /// traffic, and the first and last timestamps. Can be combined
+    /// with --tsv.
+    #[clap(long)]
+    summary: bool,
+
+    // For --summary
+    #[clap(flatten)]
+    output_file_args: OutputFileArgs,
+
+    /// Write the last logged (cumulative) counters of each interface
+    /// to this path, in the format of the Prometheus node_exporter
+    /// textfile collector (the file is replaced atomically, so this
+    /// can be run from cron). Can be combined with the other outputs.
+    #[clap(long, parse(from_os_str))]
+    prometheus_textfile: Option<PathBuf>,
+
+    /// Write the events worth a security review to this path:
+    /// changes of the listening port of an interface, or of the IP
+    /// address of the endpoint of a peer, and peers whose latest
+    /// handshake became older than --stale-handshake (reported again
+    /// only after a newer handshake). The columns are: time,
+    /// interface, peer, event (`listening-port`, `endpoint`, or
+    /// `stale-handshake`), old, new. Can be combined with the other
+    /// outputs.
+    #[clap(long, parse(from_os_str), conflicts_with = "state")]
+    events: Option<PathBuf>,
+
+    /// With --events, the format of the file: tsv (with a header
+    /// line), json (one object per line), or csv.
+    #[clap(long, requires = "events", default_value = "tsv")]
+    events_format: OutputFormat,
+
+    /// With --events, the age of the latest handshake of a peer from
+    /// which on it counts as stale (while there is traffic, WireGuard
+    /// does a handshake every 2 minutes).
+    #[clap(long, requires = "events", default_value = "5m")]
+    stale_handshake: String,
+
+    /// Don't drop the samples in the middle of runs of unchanged
+    /// counters (within the same hour) before grouping (the TSV output
+    /// is the same either way, this is just for verification).
+    #[clap(long)]
+    no_dedup: bool,
+
+    /// Report the transfers of each peer separately, as if it were
+    /// an interface named `$interfacename-$publickey` (with `/` in the
+    /// key replaced by `_`), instead of their sum per interface.
+    #[clap(long, conflicts_with = "state")]
+    per_peer: bool,
+
+    /// Sort the datapoints that are logged out of order by at most
+    /// this duration (e.g. after the clock was adjusted) back into
+    /// order before grouping; datapoints that are further out of order
+    /// may be processed where they are (with a warning). With
+    /// --follow, delays processing by this duration.
+    #[clap(long, default_value = "10")]
+    reorder_window: String,
+
+    /// Keep running after parsing the existing files, and parse the
+    /// lines appended to the `current` file of the (single) log dir,
+    /// also after it is rotated. The hourly TSV rows are written as
+    /// the hours are completed (the current hour is not written when
+    /// terminated); the other outputs (summary tables, chart,
+    /// --summary, Prometheus textfile) when terminated by
+    /// SIGINT/SIGTERM/SIGHUP.
+    #[clap(long, conflicts_with = "show-direct")]
+    follow: bool,
+
+    /// With --follow, how often to check for new data.
+    #[clap(long, requires = "follow", default_value = "1")]
+    poll_interval: String,
+
+    /// Keep the positions reached in the log files, and the data
+    /// needed to continue the TSV files, in this file (created if
+    /// missing). Subsequent runs then only parse the data added since
+    /// and append the new rows (for running from cron). The last hour
+    /// is only written once data for a later hour has been logged.
+    #[clap(
+        long,
+        parse(from_os_str),
+        requires = "tsv",
+        conflicts_with_all = &["follow", "summary", "chart", "show-direct"]
+    )]
+    state: Option<PathBuf>,
+
+    #[clap(flatten)]
+    error_policy: ErrorPolicyArgs,
+
+    // For --show-direct and --summary, and the "time window start"
+    // column of the tables
+    #[clap(flatten)]
+    time_format_args: TimeFormatArgs,
+
+    /// The paths to dirs with files to parse (files compressed with
+    /// gzip, xz or zstd, e.g. by a multilog processor, are
+    /// decompressed)
     #[clap(parse(from_os_str))]
     dir_paths: Vec<PathBuf>,
-}
 
-#[derive(Debug)]
-struct Transfer {
-    /// bytes total since interface was activated
-    received: usize,
-    /// bytes total since interface was activated
-    sent: usize,
-}
-impl Transfer {
-    fn total(&self) -> usize {
-        self.received + self.sent
-    }
-    fn sub(&self, old: &Transfer) -> Result<Transfer> {
-        let received =
-            self.received.checked_sub(old.received).ok_or_else(|| {
-                anyhow!("old has higher received transfers than new")
-            })?;
-        let sent = self
-            .sent
-            .checked_sub(old.sent)
-            .ok_or_else(|| anyhow!("old has higher sent transfers than new"))?;
-        Ok(Transfer { received, sent })
-    }
+    #[clap(flatten)]
+    verbosity: VerbosityArgs,
 }
 
+impl_cli_opt!(Opt);
+
 fn parse_transfer(s: &str) -> Result<Transfer> {
     // "19.52 GiB received, 134.39 GiB sent"
     let mut received_f = None;
@@ -90,10 +205,13 @@ fn parse_transfer(s: &str) -> Result<Transfer> {
             bail!("unknown label {label:?}")
         }
     }
-    // so verbose code, todo better?
-    let received =
-        received_f.ok_or_else(|| anyhow!("missing 'received'"))? as usize; // well
-    let sent = sent_f.ok_or_else(|| anyhow!("missing 'sent'"))? as usize;
+    let to_usize = |num: Option<f64>, label: &str| -> Result<usize> {
+        let num = num.ok_or_else(|| anyhow!("missing {label:?}"))?;
+        f64_to_usize(num, Rounding::Down)
+            .with_context(|| anyhow!("{label:?} in {s:?}"))
+    };
+    let received = to_usize(received_f, "received")?;
+    let sent = to_usize(sent_f, "sent")?;
     Ok(Transfer { received, sent })
 }
 
@@ -102,8 +220,8 @@ struct WireguardInterface(pub u16);
 
 impl WireguardInterface {
     fn from_str(s: &str) -> Result<Self> {
-        if s.starts_with("wg") {
-            Ok(Self(s[2..].parse()?))
+        if let Some(n) = s.strip_prefix("wg") {
+            Ok(Self(n.parse()?))
         } else {
             bail!("interface name does not start with \"wg\": {s:?}")
         }
@@ -119,273 +237,577 @@ impl Display for WireguardInterface {
     }
 }
 
-struct UnfinishedPeer {
+/// The interface block being parsed: the "interface" line and the
+/// "peer" blocks following it, up to the next "interface" line.
+struct InterfaceBlock {
     interface: WireguardInterface,
+    /// The index of the file in which it started.
+    file_i: usize,
+    /// The peer whose block is being parsed.
+    peer: Option<String>,
+    /// Whether the "transfer" line of `peer` was seen.
+    peer_transfer_seen: bool,
+    /// The sum of the transfers of the peers so far, with the
+    /// timestamp of the first one (unless reported per peer).
+    transfer: Option<(Tai64N, Transfer)>,
 }
 
-#[derive(Debug, PartialEq, Clone, Copy)]
-struct DateHourUtc {
-    hour: u8,
-    date: NaiveDate,
-}
+impl InterfaceBlock {
+    fn add_transfer(&mut self, timestamp: Tai64N, transfer: Transfer) {
+        self.transfer = Some(match self.transfer {
+            Some((first, sum)) => (first, sum + transfer),
+            None => (timestamp, transfer),
+        });
+    }
 
-#[derive(Debug)]
-struct Datapoint {
-    interface: WireguardInterface,
-    timestamp: Tai64N,
-    date_and_hour: DateHourUtc, // cache, derived from timestamp
-    transfer: Transfer,
+    /// The datapoint for the sum, if there was any peer with a
+    /// transfer.
+    fn datapoint(&self) -> Option<Datapoint> {
+        self.transfer.map(|(timestamp, transfer)| {
+            Datapoint::new(self.interface.0, timestamp, transfer)
+        })
+    }
 }
 
-#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash, PartialOrd, Ord)]
-struct YearMonth {
-    year: i32,
-    month: u8,
+/// What the datapoints are reported for: interfaces (the index being
+/// the interface number), or with --per-peer, the peers of the
+/// interfaces (numbered in the order they are seen).
+struct Series {
+    peers: Option<RefCell<Vec<String>>>,
 }
-impl YearMonth {
-    fn from_naivedate(nd: NaiveDate) -> YearMonth {
-        YearMonth {
-            year: nd.year(),
-            month: nd.month() as u8,
+
+impl Series {
+    fn new(per_peer: bool) -> Self {
+        Series {
+            peers: per_peer.then(RefCell::default),
         }
     }
-}
-impl Display for YearMonth {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.write_fmt(format_args!("{}/{:02}", self.year, self.month))
+
+    fn is_per_peer(&self) -> bool {
+        self.peers.is_some()
+    }
+
+    /// The index for `peer` of `interface`, with --per-peer.
+    fn peer_index(
+        &self,
+        interface: &WireguardInterface,
+        peer: &str,
+    ) -> Result<u16> {
+        let mut peers = self
+            .peers
+            .as_ref()
+            .expect("only called with --per-peer")
+            .borrow_mut();
+        let name = format!("{interface}-{}", peer.replace('/', "_"));
+        let i = match peers.iter().position(|p| *p == name) {
+            Some(i) => i,
+            None => {
+                peers.push(name);
+                peers.len() - 1
+            }
+        };
+        u16::try_from(i).map_err(|_| anyhow!("too many peers"))
     }
-}
 
-const NUM_INTERFACES: usize = 2;
-// Well. a vec with that many *inlined* elements would be best.
+    fn name(&self, i: u16) -> String {
+        match &self.peers {
+            Some(peers) => peers.borrow()[usize::from(i)].clone(),
+            None => WireguardInterface(i).to_string(),
+        }
+    }
+}
 
-#[derive(Debug)]
-struct Timepoint([Option<Datapoint>; NUM_INTERFACES]);
-impl Timepoint {
-    fn _new() -> Self {
-        Self(std::array::from_fn(|_i| None))
+/// The age of the latest handshake, from a value like `1 minute, 5
+/// seconds ago` or `Now`.
+fn parse_handshake_age(s: &str) -> Result<Duration> {
+    if s == "Now" {
+        return Ok(Duration::ZERO);
     }
-    fn _insert(&mut self, dp: Datapoint) -> Result<()> {
-        let r = self.0.get_mut(dp.interface.0 as usize).ok_or_else(|| {
-            anyhow!(
-                "interface outside supported range hard-coded \
-                        in NUM_INTERFACES: {}",
-                dp.interface
-            )
+    let parts = s
+        .strip_suffix(" ago")
+        .ok_or_else(|| anyhow!("missing \" ago\" in handshake time {s:?}"))?;
+    let mut secs = 0;
+    for part in parts.split(", ") {
+        let (num, unit) = part
+            .split_once(' ')
+            .ok_or_else(|| anyhow!("missing unit in handshake time {s:?}"))?;
+        let num: u64 = num.parse().with_context(|| {
+            anyhow!("invalid number in handshake time {s:?}")
         })?;
-        *r = Some(dp);
-        Ok(())
+        let multiplier = match unit.strip_suffix('s').unwrap_or(unit) {
+            "second" => 1,
+            "minute" => 60,
+            "hour" => 3600,
+            "day" => 24 * 3600,
+            "year" => 365 * 24 * 3600,
+            _ => bail!("unknown unit {unit:?} in handshake time {s:?}"),
+        };
+        secs += num * multiplier;
     }
-    pub fn get(&self, i: usize) -> Option<&Datapoint> {
-        // if let Some(opt_dp) = self.0.get(i) {
-        //     opt_dp.as_ref()
-        // } else {
-        //     None
-        // }
-        self.0.get(i).and_then(|v| v.as_ref())
-    }
-    pub fn from_iter(points: impl Iterator<Item = Datapoint>) -> Result<Self> {
-        let mut ps = Self::_new();
-        let mut ok = false;
-        for p in points {
-            ps._insert(p)?;
-            ok = true;
-        }
-        if ok {
-            Ok(ps)
-        } else {
-            bail!("trying to construct Datapoints with empty input iterator")
+    Ok(Duration::from_secs(secs))
+}
+
+/// The IP address part of an endpoint like `1.2.3.4:51820` or
+/// `[fe80::1]:51820`.
+fn endpoint_ip(endpoint: &str) -> &str {
+    let ip = endpoint.rsplit_once(':').map_or(endpoint, |(ip, _port)| ip);
+    ip.trim_start_matches('[').trim_end_matches(']')
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum EventKind {
+    ListeningPort,
+    Endpoint,
+    StaleHandshake,
+}
+
+impl EventKind {
+    fn name(self) -> &'static str {
+        match self {
+            EventKind::ListeningPort => "listening-port",
+            EventKind::Endpoint => "endpoint",
+            EventKind::StaleHandshake => "stale-handshake",
         }
     }
-    /// The first timestamp from the left
-    pub fn timestamp(&self) -> &Tai64N {
-        for dp in &self.0 {
-            if let Some(dp) = dp {
-                return &dp.timestamp;
-            }
+}
+
+/// For --events.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Event {
+    timestamp: Tai64N,
+    interface: WireguardInterface,
+    peer: Option<String>,
+    kind: EventKind,
+    old: String,
+    new: String,
+}
+
+/// Keeps the values needed to detect the --events.
+struct EventTracker {
+    stale_after: Duration,
+    listening_ports: HashMap<WireguardInterface, String>,
+    endpoints: HashMap<(WireguardInterface, String), String>,
+    /// The peers currently reported as stale.
+    stale: HashSet<(WireguardInterface, String)>,
+}
+
+impl EventTracker {
+    fn new(stale_after: Duration) -> Self {
+        EventTracker {
+            stale_after,
+            listening_ports: HashMap::new(),
+            endpoints: HashMap::new(),
+            stale: HashSet::new(),
         }
-        panic!("always having at least one entry")
-    }
-    pub fn timestamp_seconds(&self) -> u64 {
-        // broken up just because rust-analyzer has some issue with .0.0
-        let a = self.timestamp().0;
-        a.0
     }
-    pub fn date_and_hour(&self) -> DateHourUtc {
-        for dp in &self.0 {
-            if let Some(dp) = dp {
-                return dp.date_and_hour;
+
+    /// The event for the line with `key` and `val` in `block`, if any.
+    fn observe(
+        &mut self,
+        block: &InterfaceBlock,
+        timestamp: Tai64N,
+        key: &str,
+        val: &str,
+    ) -> Result<Option<Event>> {
+        let event = |kind, old: &str| Event {
+            timestamp,
+            interface: block.interface.clone(),
+            peer: block.peer.clone(),
+            kind,
+            old: old.into(),
+            new: val.into(),
+        };
+        let peer = match &block.peer {
+            Some(peer) => (block.interface.clone(), peer.clone()),
+            None => {
+                if key != "listening port" {
+                    return Ok(None);
+                }
+                let old = self
+                    .listening_ports
+                    .insert(block.interface.clone(), val.into());
+                return Ok(old
+                    .filter(|old| old != val)
+                    .map(|old| event(EventKind::ListeningPort, &old)));
+            }
+        };
+        match key {
+            "endpoint" => {
+                let old = self.endpoints.insert(peer, val.into());
+                Ok(old
+                    .filter(|old| endpoint_ip(old) != endpoint_ip(val))
+                    .map(|old| event(EventKind::Endpoint, &old)))
+            }
+            "latest handshake" => {
+                if parse_handshake_age(val)? < self.stale_after {
+                    self.stale.remove(&peer);
+                    Ok(None)
+                } else if self.stale.insert(peer) {
+                    Ok(Some(event(EventKind::StaleHandshake, "")))
+                } else {
+                    Ok(None)
+                }
             }
+            _ => Ok(None),
         }
-        panic!("always having at least one entry")
     }
 }
 
-struct Group(pub Vec<Timepoint>);
-impl Group {
-    fn first_timepoint(&self) -> &Timepoint {
-        self.0
-            .first()
-            .expect("Group always has at least 1 Timepoint")
+/// The --events file.
+struct EventsOutput {
+    output: Output,
+    out: BufWriter<File>,
+    time_format: TimeFormatting,
+}
+
+impl EventsOutput {
+    fn create(
+        path: &Path,
+        format: OutputFormat,
+        time_format: TimeFormatting,
+    ) -> Result<Self> {
+        let out = BufWriter::new(
+            File::create(path).with_context(|| anyhow!("creating {path:?}"))?,
+        );
+        let output = Output::new(
+            format,
+            false,
+            &["time", "interface", "peer", "event", "old", "new"],
+        );
+        Ok(EventsOutput {
+            output,
+            out,
+            time_format,
+        })
     }
-    fn last_timepoint(&self) -> &Timepoint {
-        self.0
-            .last()
-            .expect("Group always has at least 1 Timepoint")
+
+    /// Flushed right away, for --follow.
+    fn write(&mut self, event: &Event) -> Result<()> {
+        self.output.write_record(
+            &mut self.out,
+            &[
+                self.time_format.format(&event.timestamp).into(),
+                event.interface.to_string().into(),
+                event.peer.as_deref().unwrap_or("").into(),
+                event.kind.name().into(),
+                event.old.as_str().into(),
+                event.new.as_str().into(),
+            ],
+        )?;
+        self.out.flush()?;
+        Ok(())
     }
-    fn first_datapoint(&self, i: usize) -> Option<&Datapoint> {
-        for tp in &self.0 {
-            if let Some(dp) = tp.get(i) {
-                return Some(dp);
+}
+
+/// Identifies a file also after it was renamed (by log rotation):
+/// (device, inode).
+type FileId = (u64, u64);
+
+/// Where parsing a file can continue: the offset of the next line,
+/// and the number of the line before it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Position {
+    offset: u64,
+    linenumber: i64,
+}
+
+enum Parsed {
+    Datapoint(Datapoint),
+    Event(Event),
+    /// Where to continue parsing the file in the next run, reported
+    /// at the end of each file with --state. For the last file this
+    /// is before the last interface block, as more peers of it may
+    /// still be appended.
+    Position(FileId, Position),
+}
+
+/// The contents of the --state file.
+#[derive(Debug, Default)]
+struct State {
+    positions: HashMap<FileId, Position>,
+    hourly: HourlyState,
+}
+
+impl State {
+    /// The default state if `path` doesn't exist.
+    fn load(path: &Path) -> Result<Self> {
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                return Ok(State::default())
             }
-        }
-        None
-    }
-    fn last_datapoint(&self, i: usize) -> Option<&Datapoint> {
-        for tp in self.0.iter().rev() {
-            if let Some(dp) = tp.get(i) {
-                return Some(dp);
+            Err(e) => {
+                return Err(e).with_context(|| anyhow!("reading {path:?}"))
             }
-        }
-        None
-    }
-    pub fn transfer_diffs<'a>(
-        &'a self,
-        previous: Option<&'a Self>,
-    ) -> impl Iterator<Item = (WireguardInterface, Transfer)> + 'a {
-        Gen::new(|co| async move {
-            for i in 0..NUM_INTERFACES {
-                // Get the last Datapoint for `i` from `previous` if
-                // that's from the preceding hour and a Datapoint for i
-                // is present, or the first from self if present. If
-                // present, also get the last Datapoint from self if
-                // present, and calculate and yield the transfer diff.
-
-                if let Some(dp1) = previous
-                    .and_then(|group| {
-                        let l = group.last_timepoint();
-                        let f = self.first_timepoint();
-                        if let Some(timediff) = f
-                            .timestamp_seconds()
-                            .checked_sub(l.timestamp_seconds())
-                        {
-                            if timediff < 3600 {
-                                // adjacent hours
-                                group.last_datapoint(i)
-                            } else {
-                                None
-                            }
-                        } else {
-                            eprintln!(
-                                "WARNING: unexpected non-increasing time \
-                                           in subsequent groups: {} to {}",
-                                l.timestamp().to_rfc2822_local(),
-                                f.timestamp().to_rfc2822_local()
-                            );
-                            None
-                        }
-                    })
-                    .or_else(|| self.first_datapoint(i))
-                {
-                    if let Some(dp2) = self.last_datapoint(i) {
-                        match dp2.transfer.sub(&dp1.transfer) {
-                            Ok(d) => {
-                                co.yield_((WireguardInterface(i as u16), d))
-                                    .await
-                            }
-                            Err(e) => eprintln!(
-                                "can't calculate diff({:?}, {:?}): {e}",
-                                dp1.transfer, dp2.transfer
-                            ),
-                        }
-                    }
+        };
+        (|| -> Result<Self> {
+            let alist = AListBuf::from_text(&text)?;
+            let mut positions = HashMap::new();
+            for (key, val) in &alist.0 {
+                if let Some(id) = key.strip_prefix("file.") {
+                    let (dev, ino) = id
+                        .split_once('.')
+                        .ok_or_else(|| anyhow!("invalid key {key:?}"))?;
+                    let (offset, linenumber) = val
+                        .split_once(' ')
+                        .ok_or_else(|| anyhow!("invalid value for {key:?}"))?;
+                    positions.insert(
+                        (dev.parse()?, ino.parse()?),
+                        Position {
+                            offset: offset.parse()?,
+                            linenumber: linenumber.parse()?,
+                        },
+                    );
                 }
             }
-        })
-        .into_iter()
+            Ok(State {
+                positions,
+                hourly: HourlyState::from_alist(&alist, "hourly.")?,
+            })
+        })()
+        .with_context(|| anyhow!("parsing state file {path:?}"))
+    }
+
+    /// Replace the file at `path` atomically.
+    fn save(&self, path: &Path) -> Result<()> {
+        let mut alist = AListBuf::default();
+        let mut positions: Vec<_> = self.positions.iter().collect();
+        positions.sort_by_key(|(id, _)| **id);
+        for ((dev, ino), position) in positions {
+            alist.set(
+                format!("file.{dev}.{ino}"),
+                format!("{} {}", position.offset, position.linenumber),
+            );
+        }
+        self.hourly.add_to_alist(&mut alist, "hourly.");
+        write_file_atomically(path, alist.to_text()?.as_bytes(), 0o644)
     }
 }
 
-const MAX_ERRORS: usize = 2000000;
+/// Whether `path` (still) refers to the file open in `inp`. False
+/// if it can't be accessed (e.g. a log file being rotated).
+fn is_same_file(inp: &ReadWithContext, path: &Path) -> Result<bool> {
+    let open = inp.file().metadata()?;
+    Ok(match std::fs::metadata(path) {
+        Ok(m) => m.dev() == open.dev() && m.ino() == open.ino(),
+        Err(_) => false,
+    })
+}
 
-fn parse_files(files: Vec<PathBuf>) -> impl Iterator<Item = Result<Datapoint>> {
-    Gen::new(|co| async move {
+/// Parse the given files in order. If `follow` is given, the last
+/// file is followed instead of stopping at its end: it is checked for
+/// appended lines every `follow` interval, and reopened when the path
+/// starts to refer to a new file (after draining the old one, and
+/// waiting for the new one to appear), until termination is requested
+/// (see `util::signals`). If `start_positions` is given, the files
+/// found in it are parsed from the given positions on, and the
+/// positions reached are reported. The datapoint for an interface
+/// block is reported when the next one starts (or at the end of the
+/// input), unless `series` is per peer. Events are only reported if
+/// `events` is given.
+fn parse_files(
+    files: Vec<PathBuf>,
+    mut error_policy: ErrorPolicy,
+    follow: Option<Duration>,
+    start_positions: Option<HashMap<FileId, Position>>,
+    series: Rc<Series>,
+    mut events: Option<EventTracker>,
+) -> impl Iterator<Item = Result<Parsed>> {
+    try_gen(|co| async move {
+        let incremental = start_positions.is_some();
+        let start_positions = start_positions.unwrap_or_default();
         let mut line = String::new();
-        let mut current_interface: Option<WireguardInterface> = None;
-        let mut current_peer: Option<UnfinishedPeer> = None;
-        let mut num_errors = 0;
-        for file in files {
-            let mut inp =
-                gen_try_result!(ReadWithContext::open_path(&file), co);
-
-            while gen_try_result!(inp.easy_read_line(&mut line), co) {
-                let res = (|current_interface: &mut Option<
-                    WireguardInterface,
-                >|
-                 -> Result<Option<Datapoint>> {
-                    let (timestamp, rest) =
-                        inp.context(parse_timestamp(&line))?;
-                    if is_all_white(rest) {
-                        return Ok(None);
-                    }
-                    if let Some((indentkey, val)) = key_val(rest) {
-                        let val = cleanwhite(val);
-                        if indentkey == "interface" {
-                            if current_interface.is_some() {
-                                inp.err_with_context(anyhow!(
-                                    "missed \"peer\" before another \
-                                     \"interface\""
-                                ))?
+        let mut current_block: Option<InterfaceBlock> = None;
+        'files: for (file_i, file) in files.iter().enumerate() {
+            let follow = follow.filter(|_| file_i == files.len() - 1);
+            let mut inp = ReadWithContext::open_path_auto(file)?;
+            let metadata = inp.file().metadata()?;
+            let id = (metadata.dev(), metadata.ino());
+            // The start of the current block, or the start position
+            let mut clean = Position {
+                offset: 0,
+                linenumber: 0,
+            };
+            if let Some(position) = start_positions.get(&id) {
+                if position.offset <= metadata.len() {
+                    inp.seek_to(position.offset, position.linenumber)?;
+                    clean = *position;
+                } else {
+                    eprintln!(
+                        "WARNING: {file:?} is shorter than in the last \
+                         run, parsing it from the start"
+                    );
+                }
+            }
+
+            let mut tokenizer = LineTokenizer::new(&[':']);
+            // Whether the previous line was without timestamp, too
+            let mut in_continuation = false;
+            // Whether the followed path refers to a new file, which
+            // is opened once the old one is drained
+            let mut rotated = false;
+            loop {
+                let have_line = if follow.is_some() || incremental {
+                    inp.read_appended_line(&mut line)?
+                } else {
+                    inp.easy_read_line(&mut line)?
+                };
+                if !have_line {
+                    let interval = match follow {
+                        None => {
+                            let is_last = file_i == files.len() - 1;
+                            // With --state, a block in the last file is
+                            // parsed again in the next run instead
+                            let block_may_grow = incremental
+                                && current_block
+                                    .as_ref()
+                                    .is_some_and(|b| b.file_i == file_i);
+                            if is_last && !block_may_grow {
+                                if let Some(datapoint) = current_block
+                                    .take()
+                                    .and_then(|b| b.datapoint())
+                                {
+                                    co.yield_(Ok(Parsed::Datapoint(datapoint)))
+                                        .await;
+                                }
                             }
-                            *current_interface =
-                                Some(WireguardInterface::from_str(val)?);
-                            Ok(None)
-                        } else if indentkey == "peer" {
-                            if current_peer.is_some() {
-                                inp.err_with_context(anyhow!(
-                                    "got \"peer\" again"
-                                ))?
+                            if incremental {
+                                let position = if !is_last || !block_may_grow {
+                                    Position {
+                                        offset: inp.next_byte_offset(),
+                                        linenumber: inp.linenumber(),
+                                    }
+                                } else {
+                                    clean
+                                };
+                                co.yield_(Ok(Parsed::Position(id, position)))
+                                    .await;
                             }
-                            if let Some(interface) = current_interface.take() {
-                                current_peer =
-                                    Some(UnfinishedPeer { interface });
-                                *current_interface = None;
+                            break;
+                        }
+                        Some(interval) => interval,
+                    };
+                    if rotated && file.is_file() {
+                        inp = ReadWithContext::open_path_auto(file)?;
+                        tokenizer = LineTokenizer::new(&[':']);
+                        in_continuation = false;
+                        rotated = false;
+                    } else if !rotated && !is_same_file(&inp, file)? {
+                        // Read the lines written before the rotation
+                        rotated = true;
+                    } else if termination_signal().is_some() {
+                        break 'files;
+                    } else {
+                        std::thread::sleep(interval);
+                    }
+                    continue;
+                }
+                let (timestamp, rest) = match parse_timestamp_tolerant(&line) {
+                    TimestampedLine::Timestamped(timestamp, rest) => {
+                        in_continuation = false;
+                        (timestamp, rest)
+                    }
+                    TimestampedLine::Continuation(_) => {
+                        // Report only the first line of a multi-line
+                        // message
+                        if !in_continuation {
+                            in_continuation = true;
+                            error_policy.handle(
+                                inp.err_with_context::<()>(anyhow!(
+                                    "line without timestamp"
+                                ))
+                                .unwrap_err(),
+                            )?;
+                        }
+                        continue;
+                    }
+                };
+                let res = (|current_block: &mut Option<InterfaceBlock>|
+                 -> Result<Option<Parsed>> {
+                    let token = match inp.context(tokenizer.next_line(rest))? {
+                        Some(token) => token,
+                        None => return Ok(None),
+                    };
+                    // For error messages
+                    let key_span =
+                        token.key_span.shifted(line.len() - rest.len());
+                    if token.separator.is_some() {
+                        let val = token.value;
+                        if token.level == 0 && token.key == "interface" {
+                            let interface = WireguardInterface::from_str(val)?;
+                            let finished = current_block.replace(
+                                InterfaceBlock {
+                                    interface,
+                                    file_i,
+                                    peer: None,
+                                    peer_transfer_seen: false,
+                                    transfer: None,
+                                },
+                            );
+                            clean = Position {
+                                offset: inp.byte_offset(),
+                                linenumber: inp.linenumber() - 1,
+                            };
+                            let label = format!(
+                                "while parsing interface block started at \
+                                 line {} (byte offset {})",
+                                inp.linenumber(),
+                                inp.byte_offset()
+                            );
+                            inp.set_label(label);
+                            Ok(finished
+                                .and_then(|b| b.datapoint())
+                                .map(Parsed::Datapoint))
+                        } else if token.level == 0 && token.key == "peer" {
+                            if let Some(block) = current_block {
+                                // (The previous peer may have had no
+                                // transfer yet)
+                                block.peer = Some(val.to_string());
+                                block.peer_transfer_seen = false;
                             } else {
                                 inp.err_with_context(anyhow!(
-                                    "missed \"peer\" before another \
-                                     \"interface\""
+                                    "missing \"interface\" before \"peer\""
                                 ))?
                             }
                             Ok(None)
-                        } else if let Some(key) = after_white(indentkey) {
-                            if key == "public key" {
-                                Ok(None)
-                            } else if key == "private key" {
-                                Ok(None)
-                            } else if key == "listening port" {
-                                Ok(None)
-                            } else if key == "endpoint" {
-                                Ok(None)
-                            } else if key == "allowed ips" {
-                                Ok(None)
-                            } else if key == "latest handshake" {
-                                Ok(None)
+                        } else if token.level > 0 {
+                            let key = token.key;
+                            if key == "public key"
+                                || key == "private key"
+                                || key == "listening port"
+                                || key == "endpoint"
+                                || key == "allowed ips"
+                                || key == "latest handshake"
+                            {
+                                match (&mut events, &*current_block) {
+                                    (Some(events), Some(block)) => Ok(inp
+                                        .context(events.observe(
+                                            block, timestamp, key, val,
+                                        ))?
+                                        .map(Parsed::Event)),
+                                    _ => Ok(None),
+                                }
                             } else if key == "transfer" {
                                 let transfer =
                                     inp.context(parse_transfer(val))?;
-                                if let Some(peer) = current_peer.take() {
-                                    let dt = timestamp.to_datetime_utc();
-                                    let datehour = DateHourUtc {
-                                        date: dt.date_naive(),
-                                        hour: dt.hour() as u8,
-                                    };
-                                    let dp = Datapoint {
-                                        timestamp,
-                                        date_and_hour: datehour,
-                                        transfer,
-                                        interface: peer.interface,
-                                    };
-                                    Ok(Some(dp))
+                                let block = current_block.as_mut().filter(|b| {
+                                    b.peer.is_some() && !b.peer_transfer_seen
+                                });
+                                if let Some(block) = block {
+                                    block.peer_transfer_seen = true;
+                                    if series.is_per_peer() {
+                                        let peer = block.peer.as_ref().unwrap();
+                                        let i = series
+                                            .peer_index(&block.interface, peer)?;
+                                        Ok(Some(Parsed::Datapoint(
+                                            Datapoint::new(
+                                                i, timestamp, transfer,
+                                            ),
+                                        )))
+                                    } else {
+                                        block.add_transfer(timestamp, transfer);
+                                        Ok(None)
+                                    }
                                 } else {
                                     inp.err_with_context(anyhow!(
                                         "missing peer before key {key:?}"
@@ -393,12 +815,13 @@ fn parse_files(files: Vec<PathBuf>) -> impl Iterator<Item = Result<Datapoint>> {
                                 }
                             } else {
                                 inp.err_with_context(anyhow!(
-                                    "unknown indented key {key:?}"
+                                    "unknown indented key {key:?} ({key_span})"
                                 ))
                             }
                         } else {
                             inp.err_with_context(anyhow!(
-                                "unknown key {indentkey:?}"
+                                "unknown key {:?} ({key_span})",
+                                token.key
                             ))
                         }
                     } else {
@@ -406,302 +829,288 @@ fn parse_files(files: Vec<PathBuf>) -> impl Iterator<Item = Result<Datapoint>> {
                             "line does not match `key: val` pattern"
                         ))
                     }
-                })(&mut current_interface);
+                })(&mut current_block);
                 match res {
                     Ok(None) => {}
                     Ok(Some(v)) => co.yield_(Ok(v)).await,
-                    Err(e) => {
-                        if num_errors < MAX_ERRORS {
-                            num_errors += 1;
-                            eprintln!("Warning: {e:?}");
-                        } else {
-                            //return Err(e)
-                            //  Is there a way to give an endless error?
-                            co.yield_(Err(e)).await
-                        }
-                    }
+                    Err(e) => error_policy.handle(e)?,
                 }
             }
         }
-        ()
+        error_policy.report_ignored();
+        Ok(())
     })
-    .into_iter()
-}
-
-#[derive(Copy, Clone, Debug)]
-struct BilledCost {
-    billed_cost: f64,
-    your_cost: f64,
-}
-impl Add for BilledCost {
-    type Output = BilledCost;
-
-    fn add(self, rhs: Self) -> Self::Output {
-        // evil to drop NaN?
-        let billed_cost = nandropping_add(self.billed_cost, rhs.billed_cost);
-        let your_cost = nandropping_add(self.your_cost, rhs.your_cost);
-        BilledCost {
-            billed_cost,
-            your_cost,
-        }
-    }
-}
-
-struct RowShared {
-    time: Tai64N,
-    total_all_ifaces_hour: usize,
-    num_servers_running: u32,
-}
-struct RowUser {
-    received_cum: usize,
-    sent_cum: usize,
-    received_hour: usize,
-    sent_hour: usize,
 }
 
-struct Row<'a> {
-    shared: &'a RowShared,
-    user: &'a RowUser,
+fn main() {
+    cli::main(run)
 }
-impl<'a> Row<'a> {
-    fn write_header(outp: &mut impl Write) -> Result<(), std::io::Error> {
-        writeln!(
-            outp,
-            "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
-            "time window start",
-            "time excel",
-            "received B",
-            "sent B",
-            "received B/hour",
-            "sent B/hour",
-            "total B/hour",
-            "all interfaces B/hour",
-            "fraction of all traffic",
-            "num servers running",
-            "free traffic B/hour",
-            "billed traffic B",
-            "billed cost EUR",
-            "your cost EUR"
-        )
-    }
-    fn write(
-        &self,
-        outp: &mut impl Write,
-    ) -> Result<BilledCost, std::io::Error> {
-        let total = self.user.received_hour + self.user.sent_hour;
-        let part = total as f64 / (self.shared.total_all_ifaces_hour as f64);
-        let included_traffic = self.shared.num_servers_running as f64 * 1.42e9;
-        let billed_traffic = max_f64(
-            0.,
-            self.shared.total_all_ifaces_hour as f64 - included_traffic,
-        );
-        let billed_cost = billed_traffic * (0.02000000 / 1e9);
-        let your_cost = part * billed_cost;
-
-        writeln!(
-            outp,
-            "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
-            self.shared.time.to_rfc2822_local(),
-            // XX Hard coding +01:00 for central europe, since
-            // daylight savings time is the fake one, thus this
-            // is closest without introducing discontinuities
-            // (because this is easier than switching for DST,
-            // introducing wrong time points while at it, and
-            // discontinuities which might matter e.g. for
-            // plots):
-            self.shared.time.to_exceldays(1.),
-            self.user.received_cum,
-            self.user.sent_cum,
-            self.user.received_hour,
-            self.user.sent_hour,
-            total,
-            self.shared.total_all_ifaces_hour,
-            part, // * 100. ? use Excel formatting for that
-            self.shared.num_servers_running,
-            included_traffic,
-            billed_traffic,
-            billed_cost,
-            your_cost
-        )?;
 
-        // Hack: return calculated values for summary
-        Ok(BilledCost {
-            billed_cost,
-            your_cost,
-        })
-    }
-}
-
-fn main() -> Result<()> {
-    let opt: Opt = Opt::from_args();
-    if !opt.show_direct && !opt.tsv.is_some() {
+fn run(opt: Opt) -> Result<()> {
+    let follow = if opt.follow {
+        Some(parse_duration(&opt.poll_interval)?)
+    } else {
+        None
+    };
+    if !opt.show_direct
+        && opt.tsv.is_none()
+        && !opt.summary
+        && opt.prometheus_textfile.is_none()
+        && opt.events.is_none()
+    {
         eprintln!(
-            "WARNING: neither --tsv nor --show-direct given, \
-                   going to parse without output"
+            "WARNING: none of --tsv, --summary, --prometheus-textfile, \
+             --events, --show-direct given, going to parse without output"
         );
     }
 
-    let mut file_paths: Vec<PathBuf> = Vec::new();
-
-    for dir_path in &opt.dir_paths {
-        let mut items: Vec<PathBuf> =
-            std::fs::read_dir(dir_path).with_context(
-                || anyhow!("can't open dir {dir_path:?} for reading"))?
-            .filter_map(
-                |entry_result: Result<std::fs::DirEntry, std::io::Error>|
-                                      -> Option<Result<PathBuf,
-                                                       std::io::Error>>
-                {
-                    match entry_result {
-                        Ok(entry) => {
-                            let ft = entry.file_type()
-                                .expect("does this fail on OSes needing stat?");
-                            if ft.is_file() {
-                                Some(Ok(entry.path()))
-                            } else {
-                                None
-                            }
-                        }
-                        Err(e) =>
-                            Some(Err(e))
-                    }
-                })
-            .collect::<Result<_,_>>()?;
-        file_paths.append(&mut items);
+    let mut file_paths = log_files_in_dirs(&opt.dir_paths)?;
+    if follow.is_some() {
+        let dir_path = match opt.dir_paths.as_slice() {
+            [dir_path] => dir_path,
+            _ => {
+                return Err(error::ErrorKind::Usage
+                    .wrap(anyhow!("--follow needs exactly one log dir")))
+            }
+        };
+        let current = dir_path.join("current");
+        if !current.is_file() {
+            bail!("no file {current:?} to follow")
+        }
+        // Follow it after all the others, also if there are other
+        // files sorting after it
+        file_paths.retain(|path| *path != current);
+        file_paths.push(current);
     }
-    file_paths.sort(); // Not ideal, should sort on filenames only.
-
-    let datapoints = parse_files(file_paths);
+    let reorder_window = parse_duration(&opt.reorder_window)?;
+    // Opened before the (possibly long) parsing to fail early
+    let summary_out = if opt.summary {
+        Some(opt.output_file_args.open()?)
+    } else {
+        None
+    };
+    let state = opt.state.as_deref().map(State::load).transpose()?;
+    let time_format = opt.time_format_args.formatting();
+    let series = Rc::new(Series::new(opt.per_peer));
+    let mut events = match &opt.events {
+        Some(path) => {
+            Some(EventsOutput::create(path, opt.events_format, time_format)?)
+        }
+        None => None,
+    };
+    let event_tracker = if events.is_some() {
+        Some(EventTracker::new(parse_duration(&opt.stale_handshake)?))
+    } else {
+        None
+    };
+    let parsed = parse_files(
+        file_paths,
+        opt.error_policy.policy(),
+        follow,
+        state.as_ref().map(|state| state.positions.clone()),
+        series.clone(),
+        event_tracker,
+    );
     if opt.show_direct {
-        for datapoint in datapoints {
-            let datapoint = datapoint?;
+        for parsed in parsed {
+            let datapoint = match parsed? {
+                Parsed::Datapoint(datapoint) => datapoint,
+                Parsed::Event(event) => {
+                    if let Some(events) = &mut events {
+                        events.write(&event)?;
+                    }
+                    continue;
+                }
+                Parsed::Position(..) => continue,
+            };
             println!(
                 "{}: {}: {} {}",
-                datapoint.timestamp.to_rfc2822_local(),
-                datapoint.interface,
+                time_format.format(&datapoint.timestamp),
+                series.name(datapoint.interface),
                 datapoint.transfer.received,
                 datapoint.transfer.sent
             );
         }
         return Ok(());
     }
-    if let Some(tsv_basepath) = opt.tsv {
-        // Go through the values by time, if time difference is <5
-        // seconds they belong together. But how do I know all the
-        // interfaces? A first scan through them. -- Well, rather
-        // split them up anyway and produce a separate TSV for each
-        // interface.
-
-        // rust-analyzer can't handle this (rustc can):
-        // |datapoint: &Datapoint| -> u64 { datapoint.timestamp.0.0 }
-        // so:
-        fn timestamp_second(datapoint: &Datapoint) -> u64 {
-            datapoint.timestamp.0 .0
-        }
-
-        let mut outputs = (0..NUM_INTERFACES)
-            .map(|interfacenumber| -> Result<BufWriter<File>> {
-                let iface = WireguardInterface(interfacenumber as u16);
-                let path = format!("{tsv_basepath}{iface}.tsv");
-                Ok(BufWriter::new(File::create(&path)?))
+    if opt.tsv.is_some() || opt.summary || opt.prometheus_textfile.is_some() {
+        // Finish the output files with the data processed so far if
+        // interrupted
+        install_termination_handler(TERMINATION_SIGNALS)?;
+        let name = |i| series.name(i);
+        let mut latest = LatestCounters::default();
+        // The positions reached, and the first error if any: with
+        // --state, errors end the input, so that the outputs and state
+        // are still written for the data before it (failing to write
+        // an event ends it, too)
+        let mut positions = HashMap::new();
+        let mut parse_error = None;
+        let datapoints = parsed
+            .map_while(|parsed| match parsed {
+                Ok(Parsed::Datapoint(datapoint)) => Some(Some(Ok(datapoint))),
+                Ok(Parsed::Event(event)) => {
+                    let events = events.as_mut().expect("only with --events");
+                    match events.write(&event) {
+                        Ok(()) => Some(None),
+                        Err(e) => {
+                            parse_error = Some(e);
+                            None
+                        }
+                    }
+                }
+                Ok(Parsed::Position(id, position)) => {
+                    positions.insert(id, position);
+                    Some(None)
+                }
+                Err(e) if state.is_some() => {
+                    parse_error = Some(e);
+                    None
+                }
+                Err(e) => Some(Some(Err(e))),
             })
-            .collect::<Result<Vec<_>, _>>()?;
-
-        let timepoints = try_group(
+            .flatten();
+        let mut datapoints = try_sort_within(
             datapoints,
-            on(timestamp_second, numbers_within(8)),
-            |points| {
-                Timepoint::from_iter(points.as_mut().unwrap().drain(..)).expect(
-                    "groups are guaranteed to be non-empty, \
-                         and we just panic for now if interfaces \
-                         are > NUM_INTERFACES",
-                )
+            |datapoint| {
+                let Tai64N(secs, nanos) = datapoint.timestamp;
+                u128::from(secs.0) * 1_000_000_000 + u128::from(nanos)
             },
+            Window::Distance(reorder_window.as_nanos()),
         );
-
-        let groups = try_group(
-            timepoints,
-            on(|tp: &Timepoint| tp.date_and_hour(), |a, b| a == b),
-            |pointss| Group(pointss.take().unwrap()),
-        );
-
-        for output in &mut outputs {
-            Row::write_header(output)?;
+        if let Some(state) = &state {
+            // Also covered if there is no new data
+            for datapoint in state.hourly.datapoints() {
+                latest.update(datapoint);
+            }
         }
-
-        let mut by_user_month: HashMap<u16, HashMap<YearMonth, BilledCost>> =
-            Default::default();
-
-        let mut last_group: Option<Group> = None;
-        let mut rows: HashMap<u16, RowUser> = Default::default();
-        for group in groups {
-            let group = group?;
-
-            rows.clear();
-            let mut total_all_ifaces_hour = 0; // B
-            for (iface, transferdiff) in
-                group.transfer_diffs(last_group.as_ref())
-            {
-                total_all_ifaces_hour += transferdiff.total();
-                let i = iface.0 as usize;
-                let f = group
-                    .first_datapoint(i)
-                    .expect("exists because we have a transferdiff");
-                let row = RowUser {
-                    received_cum: f.transfer.received,
-                    sent_cum: f.transfer.sent,
-                    received_hour: transferdiff.received,
-                    sent_hour: transferdiff.sent,
-                };
-                rows.insert(iface.0, row);
+        let sorted_datapoints = datapoints.by_ref().inspect(|datapoint| {
+            if let Ok(datapoint) = datapoint {
+                latest.update(datapoint);
             }
-
-            let num_servers_running = 3; // configure XX
-            let shared = RowShared {
-                time: group.first_timepoint().timestamp().clone(),
-                total_all_ifaces_hour,
-                num_servers_running,
-            };
-            let ym = YearMonth::from_naivedate(
-                shared.time.to_datetime_utc().date_naive(),
+        });
+        let outcome = process_hourly(
+            sorted_datapoints,
+            name,
+            &HourlyOptions {
+                basepath: opt.tsv.as_deref(),
+                csv: opt.excel_csv,
+                dedup: !opt.no_dedup,
+                max_snapshot_seconds: 8,
+                chart: opt.chart.as_deref(),
+                fill_gaps: opt.fill_gaps,
+                flush_rows: opt.follow,
+                time_format,
+            },
+            state.as_ref().map(|state| state.hourly.clone()),
+        )?;
+        if datapoints.late() > 0 {
+            eprintln!(
+                "WARNING: {} datapoints were logged out of order by more \
+                 than --reorder-window, the results for their hours may be \
+                 off",
+                datapoints.late()
             );
-            for (i, user) in &mut rows {
-                let outp = &mut outputs[*i as usize];
-                let row = Row {
-                    shared: &shared,
-                    user,
+        }
+        if let (Some(path), Some(old)) = (&opt.state, state) {
+            let mut hourly = outcome.state.expect("passed a state");
+            let positions =
+                if outcome.terminated.is_none() && parse_error.is_none() {
+                    positions
+                } else {
+                    // The input will be read again from the old
+                    // positions
+                    hourly.restart_from(old.hourly);
+                    old.positions
                 };
-                let calculated = row.write(outp)?;
-                hashmap_add(
-                    hashmap_get_mut_vivify(&mut by_user_month, i, || {
-                        HashMap::new()
-                    }),
-                    ym,
-                    calculated,
-                );
-            }
-
-            last_group = Some(group);
+            State { positions, hourly }.save(path)?;
         }
-
-        for (i, by_month) in &by_user_month {
-            let mut summary: Vec<_> = by_month.iter().collect();
-            summary.sort_by(|a, b| (*a).0.cmp(b.0));
-            let iface = WireguardInterface(*i);
-            let mut outp = BufWriter::new(File::create(format!(
-                "{tsv_basepath}{iface}-summary.tsv"
-            ))?);
-            writeln!(&mut outp, "year/month\tbilled cost EUR\tyour cost EUR")?;
-            for (month, cost) in summary {
-                writeln!(
-                    &mut outp,
-                    "{month}\t{:.2}\t{:.2}",
-                    cost.billed_cost, cost.your_cost
-                )?;
-            }
+        if let Some(e) = parse_error {
+            return Err(e);
+        }
+        if let Some(path) = &opt.prometheus_textfile {
+            latest.write_prometheus_textfile(path, "wireguard", name)?;
+        }
+        if let Some(mut out) = summary_out {
+            write_summary_table(
+                &mut out,
+                &outcome.summaries,
+                name,
+                &time_format,
+            )?;
+            out.commit()?;
+        }
+        // With --follow, termination ends the input instead
+        let terminated = outcome
+            .terminated
+            .or_else(|| termination_signal().map(Terminated));
+        if let Some(e) = terminated {
+            eprintln!(
+                "parse-wg-log: {e}, wrote output for the data processed \
+                 so far"
+            );
+            e.exit();
         }
-
         return Ok(());
     }
+    if let Some(events) = &mut events {
+        for parsed in parsed {
+            if let Parsed::Event(event) = parsed? {
+                events.write(&event)?;
+            }
+        }
+        if let Some(signal) = termination_signal() {
+            Terminated(signal).exit();
+        }
+    }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn t_events() -> Result<()> {
+        assert_eq!(parse_handshake_age("Now")?, Duration::ZERO);
+        assert_eq!(
+            parse_handshake_age("1 hour, 2 minutes, 1 second ago")?,
+            Duration::from_secs(3721)
+        );
+        assert!(parse_handshake_age("1 fortnight ago").is_err());
+        assert_eq!(endpoint_ip("[fe80::1]:51820"), "fe80::1");
+        assert_eq!(endpoint_ip("1.2.3.4:5"), "1.2.3.4");
+
+        let mut tracker = EventTracker::new(Duration::from_secs(300));
+        let mut block = InterfaceBlock {
+            interface: WireguardInterface(0),
+            file_i: 0,
+            peer: None,
+            peer_transfer_seen: false,
+            transfer: None,
+        };
+        let t = Tai64N::UNIX_EPOCH;
+        let mut kinds = |block: &InterfaceBlock, key, val| {
+            tracker
+                .observe(block, t, key, val)
+                .map(|event| event.map(|e| (e.kind, e.old)))
+        };
+        assert_eq!(kinds(&block, "listening port", "1")?, None);
+        assert_eq!(
+            kinds(&block, "listening port", "2")?,
+            Some((EventKind::ListeningPort, "1".into()))
+        );
+        block.peer = Some("p".into());
+        assert_eq!(kinds(&block, "endpoint", "1.2.3.4:5")?, None);
+        assert_eq!(kinds(&block, "endpoint", "1.2.3.4:6")?, None);
+        assert_eq!(
+            kinds(&block, "endpoint", "1.2.3.5:6")?,
+            Some((EventKind::Endpoint, "1.2.3.4:6".into()))
+        );
+        let stale = Some((EventKind::StaleHandshake, "".into()));
+        assert_eq!(kinds(&block, "latest handshake", "5 minutes ago")?, stale);
+        assert_eq!(kinds(&block, "latest handshake", "6 minutes ago")?, None);
+        assert_eq!(kinds(&block, "latest handshake", "Now")?, None);
+        assert_eq!(kinds(&block, "latest handshake", "1 day ago")?, stale);
+        Ok(())
+    }
+}
@@ -0,0 +1,372 @@
+use std::io::{stdout, BufWriter, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::time::Instant;
+
+use anyhow::{anyhow, bail, Context, Result};
+use nix::unistd::Pid;
+
+use chj_rustbin::cli::{self, VerbosityArgs};
+use chj_rustbin::error::ErrorKind;
+use chj_rustbin::impl_cli_opt;
+use chj_rustbin::process::wait_pid_gone;
+use chj_rustbin::time::realtime::parse_duration;
+use chj_rustbin::util::cli_output::{OutputArgs, BOLD};
+
+#[derive(clap::Parser, Debug)]
+/// List the processes whose name contains one of the given patterns
+/// (all processes if none are given), with their state, parent pid,
+/// and age, read from /proc. Replaces `ps -eo pid,ppid,stat,etimes,
+/// comm | grep ..` one-liners, e.g. `pidstat --zombies` to find
+/// zombies and their parents. Exits with code 1 if no process
+/// matches (like pgrep(1)); with `--wait`, waits until all matching
+/// processes have exited instead, and exits with 124 (like
+/// timeout(1)) if `--timeout` expired first.
+#[clap(name = chj_rustbin::cli_name!())]
+struct Opt {
+    /// the name must be equal to a pattern instead of containing it
+    #[clap(long)]
+    exact: bool,
+
+    /// match the patterns against the full command line instead of
+    /// the process name
+    #[clap(short, long)]
+    full: bool,
+
+    /// only list processes in one of the given states, e.g. `DZ`
+    /// (see proc(5): R running, S sleeping, D disk sleep, Z zombie,
+    /// T stopped, ..)
+    #[clap(long)]
+    state: Option<String>,
+
+    /// only list zombie processes (same as `--state Z`)
+    #[clap(short, long, conflicts_with = "state")]
+    zombies: bool,
+
+    /// don't list the processes, wait until they have exited
+    /// (zombies count as exited, since only their parent can reap
+    /// them)
+    #[clap(long)]
+    wait: bool,
+
+    /// with --wait, give up after this duration (e.g. `30s`, `5m`);
+    /// units: s, m, h, d, w (seconds if no unit is given)
+    #[clap(long, requires = "wait")]
+    timeout: Option<String>,
+
+    /// with --wait, the maximum interval between checks when polling
+    #[clap(long, default_value = "1")]
+    max_interval: String,
+
+    #[clap(flatten)]
+    output_args: OutputArgs,
+
+    /// substrings of the names of the processes to list
+    patterns: Vec<String>,
+
+    #[clap(flatten)]
+    verbosity: VerbosityArgs,
+}
+
+impl_cli_opt!(Opt);
+
+/// The fields of /proc/<pid>/stat that we need.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Stat {
+    pid: i32,
+    comm: String,
+    state: char,
+    ppid: i32,
+    /// Clock ticks after boot.
+    starttime: u64,
+}
+
+impl Stat {
+    /// Parse the contents of /proc/<pid>/stat: `pid (comm) state ppid
+    /// ..`, where comm may contain spaces and parentheses.
+    fn parse(s: &str) -> Result<Self> {
+        let open = s.find(" (").ok_or_else(|| anyhow!("missing comm"))?;
+        let close = s.rfind(") ").ok_or_else(|| anyhow!("missing comm"))?;
+        if close < open {
+            bail!("invalid comm")
+        }
+        let pid = s[..open].parse().context("invalid pid")?;
+        let comm = s[open + 2..close].to_string();
+        // Fields from the 3rd (state) on
+        let fields: Vec<&str> = s[close + 2..].split_whitespace().collect();
+        let field = |i: usize| {
+            fields
+                .get(i - 3)
+                .copied()
+                .ok_or_else(|| anyhow!("missing field {i}"))
+        };
+        let mut state = field(3)?.chars();
+        let state = match (state.next(), state.next()) {
+            (Some(c), None) => c,
+            _ => bail!("invalid state {:?}", field(3)?),
+        };
+        Ok(Stat {
+            pid,
+            comm,
+            state,
+            ppid: field(4)?.parse().context("invalid ppid")?,
+            starttime: field(22)?.parse().context("invalid starttime")?,
+        })
+    }
+}
+
+#[derive(Debug)]
+struct Process {
+    stat: Stat,
+    /// The arguments joined by spaces, empty for kernel threads and
+    /// zombies.
+    cmdline: String,
+}
+
+/// All processes, except those that vanish while being read.
+fn processes() -> Result<Vec<Process>> {
+    let mut processes = Vec::new();
+    for entry in std::fs::read_dir("/proc").context("reading /proc")? {
+        let entry = entry?;
+        let name = entry.file_name();
+        if !name.as_bytes().iter().all(u8::is_ascii_digit) {
+            continue;
+        }
+        let read = |file: &str| std::fs::read(entry.path().join(file));
+        let (stat, cmdline) = match (read("stat"), read("cmdline")) {
+            (Ok(stat), Ok(cmdline)) => (stat, cmdline),
+            // Gone in the meantime
+            _ => continue,
+        };
+        let stat = Stat::parse(&String::from_utf8_lossy(&stat))
+            .with_context(|| anyhow!("parsing /proc/{:?}/stat", name))?;
+        let cmdline = cmdline
+            .split(|b| *b == 0)
+            .filter(|arg| !arg.is_empty())
+            .map(|arg| String::from_utf8_lossy(arg))
+            .collect::<Vec<_>>()
+            .join(" ");
+        processes.push(Process { stat, cmdline });
+    }
+    processes.sort_by_key(|p| p.stat.pid);
+    Ok(processes)
+}
+
+/// Seconds since boot.
+fn uptime() -> Result<f64> {
+    let s = std::fs::read_to_string("/proc/uptime")
+        .context("reading /proc/uptime")?;
+    s.split_whitespace()
+        .next()
+        .and_then(|secs| secs.parse().ok())
+        .ok_or_else(|| anyhow!("invalid /proc/uptime contents {s:?}"))
+}
+
+fn clock_ticks_per_second() -> Result<u64> {
+    let ticks = unsafe { libc::sysconf(libc::_SC_CLK_TCK) };
+    if ticks <= 0 {
+        bail!("could not get the clock tick rate")
+    }
+    Ok(ticks as u64)
+}
+
+/// `42s`, `3m05s`, `2h07m`, `5d03h`
+fn format_age(secs: u64) -> String {
+    if secs < 60 {
+        format!("{secs}s")
+    } else if secs < 3600 {
+        format!("{}m{:02}s", secs / 60, secs % 60)
+    } else if secs < 86400 {
+        format!("{}h{:02}m", secs / 3600, secs % 3600 / 60)
+    } else {
+        format!("{}d{:02}h", secs / 86400, secs % 86400 / 3600)
+    }
+}
+
+struct Filter<'t> {
+    patterns: &'t [String],
+    exact: bool,
+    full: bool,
+    states: Option<&'t str>,
+}
+
+impl<'t> Filter<'t> {
+    fn matches(&self, p: &Process) -> bool {
+        if let Some(states) = self.states {
+            if !states.contains(p.stat.state) {
+                return false;
+            }
+        }
+        if self.patterns.is_empty() {
+            return true;
+        }
+        let s = if self.full { &p.cmdline } else { &p.stat.comm };
+        self.patterns.iter().any(|pattern| {
+            if self.exact {
+                s == pattern
+            } else {
+                s.contains(pattern.as_str())
+            }
+        })
+    }
+}
+
+fn main() {
+    cli::main(run)
+}
+
+fn run(opt: Opt) -> Result<()> {
+    let deadline = match &opt.timeout {
+        Some(timeout) => Some(Instant::now() + parse_duration(timeout)?),
+        None => None,
+    };
+    let max_interval = parse_duration(&opt.max_interval)?;
+    if max_interval.is_zero() {
+        return Err(ErrorKind::Usage
+            .wrap(anyhow!("--max-interval must be greater than zero")));
+    }
+    let filter = Filter {
+        patterns: &opt.patterns,
+        exact: opt.exact,
+        full: opt.full,
+        states: if opt.zombies {
+            Some("Z")
+        } else {
+            opt.state.as_deref()
+        },
+    };
+
+    let own_pid = std::process::id() as i32;
+    let matching: Vec<Process> = processes()?
+        .into_iter()
+        .filter(|p| p.stat.pid != own_pid && filter.matches(p))
+        .collect();
+
+    if opt.wait {
+        for p in &matching {
+            if opt.verbosity.is_verbose() {
+                eprintln!(
+                    "pidstat: waiting for {} ({})",
+                    p.stat.pid, p.stat.comm
+                );
+            }
+            if !wait_pid_gone(
+                Pid::from_raw(p.stat.pid),
+                deadline,
+                max_interval,
+            )? {
+                if opt.verbosity.is_verbose() {
+                    eprintln!(
+                        "pidstat: timeout, process {} is still running",
+                        p.stat.pid
+                    );
+                }
+                std::process::exit(124)
+            }
+        }
+        return Ok(());
+    }
+
+    if matching.is_empty() {
+        if opt.verbosity.is_verbose() {
+            eprintln!("pidstat: no matching processes");
+        }
+        std::process::exit(1)
+    }
+    let uptime = uptime()?;
+    let ticks = clock_ticks_per_second()?;
+    let age = |p: &Process| {
+        (uptime - p.stat.starttime as f64 / ticks as f64).max(0.) as u64
+    };
+    let mut output = opt
+        .output_args
+        .output(&["pid", "ppid", "state", "age", "name", "cmdline"]);
+    let mut out = BufWriter::new(stdout().lock());
+    if output.is_text() {
+        writeln!(
+            out,
+            "{}",
+            output.paint(
+                BOLD,
+                &format!(
+                    "{:>7} {:>7} S {:>6}  NAME (COMMAND)",
+                    "PID", "PPID", "AGE"
+                )
+            )
+        )?;
+    }
+    for p in &matching {
+        if output.is_text() {
+            write!(
+                out,
+                "{:>7} {:>7} {} {:>6}  {}",
+                p.stat.pid,
+                p.stat.ppid,
+                p.stat.state,
+                format_age(age(p)),
+                p.stat.comm
+            )?;
+            if !p.cmdline.is_empty() {
+                write!(out, " ({})", p.cmdline)?;
+            }
+            writeln!(out)?;
+        } else {
+            output.write_record(
+                &mut out,
+                &[
+                    i64::from(p.stat.pid).into(),
+                    i64::from(p.stat.ppid).into(),
+                    p.stat.state.to_string().into(),
+                    age(p).into(),
+                    p.stat.comm.as_str().into(),
+                    p.cmdline.as_str().into(),
+                ],
+            )?;
+        }
+    }
+    out.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn t_stat_parse() -> Result<()> {
+        let line = "4711 (a (b) c) Z 42 4711 4711 0 -1 4228164 0 0 0 0 0 0 \
+                    0 0 20 0 1 0 123456 0 0 18446744073709551615";
+        assert_eq!(
+            Stat::parse(line)?,
+            Stat {
+                pid: 4711,
+                comm: "a (b) c".into(),
+                state: 'Z',
+                ppid: 42,
+                starttime: 123456,
+            }
+        );
+        assert!(Stat::parse("4711 (foo) S 1").is_err());
+        assert!(Stat::parse("4711 foo").is_err());
+        assert_eq!(format_age(7322), "2h02m");
+        assert_eq!(format_age(90000), "1d01h");
+        Ok(())
+    }
+
+    #[test]
+    fn t_own_process() -> Result<()> {
+        let own_pid = std::process::id() as i32;
+        let own = processes()?
+            .into_iter()
+            .find(|p| p.stat.pid == own_pid)
+            .expect("found ourselves");
+        assert_eq!(own.stat.ppid, nix::unistd::getppid().as_raw());
+        let filter = Filter {
+            patterns: std::slice::from_ref(&own.stat.comm),
+            exact: true,
+            full: false,
+            states: Some("RS"),
+        };
+        assert!(filter.matches(&own));
+        Ok(())
+    }
+}
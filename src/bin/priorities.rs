@@ -18,9 +18,10 @@ use chrono::{
     DateTime, Datelike, Duration, NaiveDate, NaiveDateTime, NaiveTime,
     Timelike, Utc, Weekday,
 };
-use clap::Parser;
 use kstring::KString;
 
+use chj_rustbin::cli::{self, VerbosityArgs};
+use chj_rustbin::impl_cli_opt;
 use chj_rustbin::{
     conslist::{cons, List},
     fp::compose,
@@ -48,6 +49,7 @@ macro_rules! warning {
 
 #[derive(clap::Parser, Debug)]
 /// Parse a folder with todo files with "OPEN.." or "TODO.." markers.
+#[clap(name = chj_rustbin::cli_name!())]
 struct Opts {
     /// consider dirs
     #[clap(long)]
@@ -65,10 +67,6 @@ struct Opts {
     #[clap(short, long)]
     all: bool,
 
-    /// show what the program is doing
-    #[clap(short, long)]
-    verbose: bool,
-
     /// do not show calculated priority left of each path, separated by a '\t'
     #[clap(short, long)]
     no_priority: bool,
@@ -81,17 +79,23 @@ struct Opts {
     /// The base directories holding the todo files. If none given,
     /// uses `.`.
     directories: Vec<PathBuf>,
+
+    #[clap(flatten)]
+    verbosity: VerbosityArgs,
 }
 
+impl_cli_opt!(Opts);
+
 impl_item_options_from! {Opts}
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Default)]
 pub enum TaskSize {
     Minutes,
     Hours,
     Days,
     Weeks,
     Months,
+    #[default]
     Unknown,
 }
 
@@ -109,12 +113,6 @@ impl TaskSize {
     }
 }
 
-impl Default for TaskSize {
-    fn default() -> Self {
-        Self::Unknown
-    }
-}
-
 impl FromParseableStr for TaskSize {
     type Err = ParseError;
 
@@ -138,7 +136,7 @@ impl FromParseableStr for TaskSize {
 
 pub type ManualPriorityLevel = u8;
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
 pub enum Priority {
     /// Due on that date
     Date(NaiveDateTime),
@@ -151,6 +149,7 @@ pub enum Priority {
     /// No particular due date or priority
     Ongoing,
     /// None given
+    #[default]
     Unknown,
     /// 1 is highest priority (manually specified)
     Level(ManualPriorityLevel),
@@ -248,12 +247,6 @@ pub fn priority_level(
     }
 }
 
-impl Default for Priority {
-    fn default() -> Self {
-        Self::Unknown
-    }
-}
-
 // If you're looking for an `impl From<NaiveDateTime> for Priority`,
 // just use `Priority::Date(ndt)`.
 
@@ -281,7 +274,7 @@ impl FromParseableStr for Priority {
                 return Ok(Priority::Date(ndt));
             } else {
                 return Err(parse_error! {
-                    message: format!("garbage after date/time"),
+                    message: "garbage after date/time".into(),
                     position: rest.position
                 });
             }
@@ -313,14 +306,14 @@ fn t_parse_priority() {
                 .unwrap(),
         )
     };
-    assert_eq!(t("2024-11-01 11:37"), ymd_hms(2024, 11, 01, 11, 37, 0));
-    assert_eq!(t("2024-11-01 11:37:13"), ymd_hms(2024, 11, 01, 11, 37, 13));
+    assert_eq!(t("2024-11-01 11:37"), ymd_hms(2024, 11, 1, 11, 37, 0));
+    assert_eq!(t("2024-11-01 11:37:13"), ymd_hms(2024, 11, 1, 11, 37, 13));
     assert_eq!(te("2024-11-01 12:00}"), "garbage after date/time at \"}\"");
     assert_eq!(
         te("2024-11-01 12:00:01 a"),
         "garbage after date/time at \"a\""
     );
-    assert_eq!(t("2024-11-01 12:00:01  "), ymd_hms(2024, 11, 01, 12, 0, 1));
+    assert_eq!(t("2024-11-01 12:00:01  "), ymd_hms(2024, 11, 1, 12, 0, 1));
 }
 
 #[derive(Default, Debug, Clone)]
@@ -483,16 +476,7 @@ pub enum DependencyKey {
 
 impl PartialOrd for DependencyKey {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        match self {
-            DependencyKey::String(s1) => match other {
-                DependencyKey::String(s2) => s1.partial_cmp(s2),
-                DependencyKey::NaiveDateTime(_) => Some(Ordering::Less),
-            },
-            DependencyKey::NaiveDateTime(t1) => match other {
-                DependencyKey::String(_) => Some(Ordering::Greater),
-                DependencyKey::NaiveDateTime(t2) => t1.partial_cmp(t2),
-            },
-        }
+        Some(self.cmp(other))
     }
 }
 
@@ -904,8 +888,8 @@ fn parse_inside<'s>(
                     // currently not valid, even a date always starts
                     // with a number, not identifier, thus:
                     return Err(parse_error! {
-                        message: format!(
-                            "expecting a class (identifier), keyword, date or priority"),
+                        message: "expecting a class (identifier), keyword, \
+                                  date or priority".into(),
                         position: ident.position
                     });
                 }
@@ -932,6 +916,13 @@ struct NaiveDateTimeWithoutYear {
     second: u8,
 }
 
+/// The date-time, weekday with its position if given, and the rest.
+type DatWithoutYear<'s> = (
+    NaiveDateTimeWithoutYear,
+    Option<(Weekday, usize)>,
+    ParseableStr<'s>,
+);
+
 impl NaiveDateTimeWithoutYear {
     pub fn for_year(self, year: u16) -> Result<NaiveDateTime, ParseError> {
         let NaiveDateTimeWithoutYear {
@@ -985,24 +976,14 @@ fn parse_time_wday<'s>(
     options: &ParseDatOptions,
     month: u8,
     day: u8,
-) -> Result<
-    (
-        NaiveDateTimeWithoutYear,
-        Option<(Weekday, usize)>,
-        ParseableStr<'s>,
-    ),
-    (ParseTimeWdayErrorKind, ParseError),
-> {
+) -> Result<DatWithoutYear<'s>, (ParseTimeWdayErrorKind, ParseError)> {
     let ((hour, minute, second), rest) =
         (|| -> Result<((u8, u8, u8), ParseableStr<'s>),
                       ParseError> {
             let rest = T!(s.expect_separator(&options.separator_between_parts))?;
 
             let (digits, rest_after_digits) = rest.take_while(is_ascii_digit_char);
-            let is_separator_less = match digits.len() {
-                4 | 6 => true,
-                _ => false
-            };
+            let is_separator_less = matches!(digits.len(), 4 | 6);
             let ((hh, mm, opt_ss), rest) = if is_separator_less {
                 if options.time_separator.required {
                     // XX actually take ':' from time_separator
@@ -1032,7 +1013,7 @@ fn parse_time_wday<'s>(
                 let rest = T!(rest.expect_separator(&options.time_separator))?;
                 let (mm, rest) = T!(rest.take_n_while(2, is_ascii_digit_char, msg))?;
                 let rest = T!(rest.expect_separator(&options.time_separator))?;
-                if let Some((ss, rest)) = rest.take_n_while(2, is_ascii_digit_char, msg).ok() {
+                if let Ok((ss, rest)) = rest.take_n_while(2, is_ascii_digit_char, msg) {
                     ((hh, mm, Some(ss)), rest)
                 } else {
                     ((hh, mm, None), rest)
@@ -1075,9 +1056,9 @@ fn parse_time_wday<'s>(
             let (wdaystr, rest) =
                 rest.take_while(|c: char| c.is_ascii_alphabetic());
             match wdaystr.len() {
-                2 | 3 | 4 | 5 | 6 | 7 | 8 => (),
+                2..=8 => (),
                 _ => Err(
-                    parse_error! { message: format!("no match for weekday name"),
+                    parse_error! { message: "no match for weekday name".into(),
                     position: wdaystr.position },
                 )?,
             }
@@ -1107,14 +1088,7 @@ fn parse_time_wday<'s>(
 fn parse_dat_without_year<'s>(
     s: ParseableStr<'s>,
     options: &ParseDatOptions,
-) -> Result<
-    (
-        NaiveDateTimeWithoutYear,
-        Option<(Weekday, usize)>,
-        ParseableStr<'s>,
-    ),
-    ParseError,
-> {
+) -> Result<DatWithoutYear<'s>, ParseError> {
     let rest = s;
     let (month, rest) = T!(rest.take_n_while(
         2,
@@ -1501,14 +1475,17 @@ fn parse_path(
     })
 }
 
-fn main() -> Result<()> {
-    let opts: Opts = Opts::from_args();
+fn main() {
+    cli::main(run)
+}
+
+fn run(opts: Opts) -> Result<()> {
     let now: NaiveDateTime = if let Some(time) = &opts.time {
-        parse_date_time_argument(ParseableStr::new(&time), true).map_err(
+        parse_date_time_argument(ParseableStr::new(time), true).map_err(
             |e| {
                 anyhow!(
                     "can't parse --time option value: {}",
-                    e.to_string_in_context(&time)
+                    e.to_string_in_context(time)
                 )
             },
         )?
@@ -1535,6 +1512,8 @@ fn main() -> Result<()> {
         dirs: false,
         files: true,
         other: false,
+        follow_symlinks: false,
+        one_file_system: false,
     };
 
     let mut taskinfos: Vec<Rc<TaskInfo>> = Default::default();
@@ -1556,7 +1535,12 @@ fn main() -> Result<()> {
         {
             let item = item?; // XX context?
             if item.is_file() || item.is_dir() {
-                match parse_path(id, opts.verbose, &region, &item) {
+                match parse_path(
+                    id,
+                    opts.verbosity.is_verbose(),
+                    &region,
+                    &item,
+                ) {
                     Ok(taskinfo) => {
                         let taskinfo = Rc::new(taskinfo);
                         if let Some(key) = &taskinfo.dependency_key {
@@ -1625,7 +1609,7 @@ fn main() -> Result<()> {
         recur(ti, &List::Null)
     }
 
-    if opts.verbose {
+    if opts.verbosity.is_verbose() {
         dbg!(&taskinfos);
     }
 
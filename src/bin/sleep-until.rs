@@ -0,0 +1,85 @@
+use std::time::{Duration, SystemTime};
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Local};
+
+use chj_rustbin::cli::{self, VerbosityArgs};
+use chj_rustbin::error::ErrorKind;
+use chj_rustbin::impl_cli_opt;
+use chj_rustbin::time::realtime::{
+    next_aligned, parse_duration, parse_time_spec, sleep_until,
+};
+
+#[derive(clap::Parser, Debug)]
+/// Sleep until the given point in time, or until the next boundary
+/// of the interval given via `--align`. The wall clock is re-checked
+/// at least every `--max-step` seconds, so that changes to the system
+/// clock or suspend are handled.
+#[clap(name = chj_rustbin::cli_name!())]
+struct Opt {
+    /// Sleep until the next multiple of this duration since the Unix
+    /// epoch (e.g. `1h` for the next full hour, `1d` for midnight
+    /// UTC). Units: s, m, h, d, w (seconds if no unit is given).
+    #[clap(long)]
+    align: Option<String>,
+
+    /// Shift the boundaries given by `--align` by this duration
+    /// (e.g. `--align 1h --offset 5m` for 5 minutes past each hour).
+    #[clap(long, requires = "align")]
+    offset: Option<String>,
+
+    /// The maximum duration of a single sleep before re-checking the
+    /// wall clock.
+    #[clap(long, default_value = "60")]
+    max_step: String,
+
+    /// The point in time to sleep until: Unix time in seconds,
+    /// TAI64N label (`@4000...`), RFC 3339 (`2024-05-01T12:00:00Z`),
+    /// local `2024-05-01 12:00[:00]`, local `2024-05-01`, or local
+    /// time of day `12:00[:00]` (the next such time).
+    time: Option<String>,
+
+    #[clap(flatten)]
+    verbosity: VerbosityArgs,
+}
+
+impl_cli_opt!(Opt);
+
+fn main() {
+    cli::main(run)
+}
+
+fn run(opt: Opt) -> Result<()> {
+    let now = SystemTime::now();
+    let target = match (&opt.time, &opt.align) {
+        (Some(time), None) => parse_time_spec(time, now)?,
+        (None, Some(align)) => {
+            let offset = match &opt.offset {
+                Some(offset) => parse_duration(offset)?,
+                None => Duration::ZERO,
+            };
+            next_aligned(now, parse_duration(align)?, offset)?
+        }
+        (Some(_), Some(_)) => {
+            return Err(ErrorKind::Usage
+                .wrap(anyhow!("please give either a time or --align")))
+        }
+        (None, None) => {
+            return Err(
+                ErrorKind::Usage.wrap(anyhow!("need either a time or --align"))
+            )
+        }
+    };
+    let max_step = parse_duration(&opt.max_step)?;
+    if max_step.is_zero() {
+        return Err(ErrorKind::Usage
+            .wrap(anyhow!("--max-step must be greater than zero")));
+    }
+
+    if opt.verbosity.is_verbose() {
+        let t: DateTime<Local> = target.into();
+        eprintln!("sleep-until: sleeping until {}", t.to_rfc3339());
+    }
+    sleep_until(target, max_step);
+    Ok(())
+}
@@ -2,7 +2,6 @@
 // https://github.com/pflanze/chj-scripts
 
 use anyhow::{Context, Result};
-use clap::Parser;
 use log::trace;
 use std::collections::HashMap;
 use std::env;
@@ -12,6 +11,7 @@ use std::io::{stdin, stdout, BufRead, BufWriter, Write};
 use std::os::unix::ffi::{OsStrExt, OsStringExt};
 use std::path::{Path, PathBuf};
 
+use chj_rustbin::cli::{self, CliOpt, VerbosityArgs};
 use chj_rustbin::text::startswith::StartsWith;
 
 #[derive(clap::Parser, Debug)]
@@ -25,9 +25,9 @@ use chj_rustbin::text::startswith::StartsWith;
 ///
 /// See the `coproc` feature in Bash for how to integrate this
 /// into scripts.
-#[clap(name = "symlinks-index from chj-rustbin")]
+#[clap(name = chj_rustbin::cli_name!())]
 struct Opt {
-    /// show debugging output
+    /// show debugging output (the same as -vvv)
     #[clap(short, long, parse(from_occurrences))]
     debug: u8,
     /// use the null byte as record terminator for writing
@@ -45,6 +45,19 @@ struct Opt {
     /// to index
     #[clap(name = "DIR", parse(from_os_str), required(true))]
     directory_paths: Vec<PathBuf>,
+
+    #[clap(flatten)]
+    verbosity: VerbosityArgs,
+}
+
+impl CliOpt for Opt {
+    fn verbosity(&self) -> i8 {
+        if self.debug > 0 {
+            3
+        } else {
+            self.verbosity.level()
+        }
+    }
 }
 
 fn cleanup_target(path: &Path) -> PathBuf {
@@ -137,20 +150,16 @@ fn serve(
     Ok(())
 }
 
-fn main() -> Result<()> {
-    let opt = Opt::from_args();
+fn main() {
+    cli::main(run)
+}
 
+fn run(opt: Opt) -> Result<()> {
     let remove_base = opt.remove_base.map(OsString::from);
     let input_separator = if opt.zz { 0 } else { b'\n' };
     let output_separator = if opt.zz || opt.z { 0 } else { b'\n' };
     let dirpaths = opt.directory_paths;
 
-    let debug = opt.debug;
-    if debug > 0 {
-        env::set_var("RUST_LOG", "trace");
-    }
-    env_logger::init();
-
     let target_to_items = dirs_index(&dirpaths, remove_base.as_deref())
         .with_context(|| "indexing")?;
 
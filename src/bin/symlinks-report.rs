@@ -0,0 +1,266 @@
+use anyhow::{anyhow, Context, Result};
+use std::fs;
+use std::io::{stdout, BufWriter, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Component, Path, PathBuf};
+
+use chj_rustbin::cli::{self, VerbosityArgs};
+use chj_rustbin::impl_cli_opt;
+use chj_rustbin::io::dirscan::{DirScan, Recursion};
+use chj_rustbin::io::excludes::empty_excludes;
+use chj_rustbin::io::file_path_type::ItemOptions;
+use chj_rustbin::io::unix_fs::{easy_stat, FileType};
+use chj_rustbin::util::cli_output::{OutputArgs, BLUE, RED};
+
+#[derive(clap::Parser, Debug)]
+/// Scan directory trees for symlinks, and report those that are
+/// broken, those that are part of a cycle, and absolute ones that
+/// point into the tree they are in (which could be relative, so that
+/// the tree keeps working when moved or mounted elsewhere).
+#[clap(name = chj_rustbin::cli_name!())]
+struct Opt {
+    /// Rewrite the absolute links that point into their tree to be
+    /// relative (each link is replaced atomically).
+    #[clap(long)]
+    fix_relative: bool,
+
+    /// With --fix-relative, only report what would be rewritten.
+    #[clap(short = 'n', long, requires = "fix-relative")]
+    dry_run: bool,
+
+    /// Don't descend into directories on other file systems.
+    #[clap(short = 'x', long)]
+    one_file_system: bool,
+
+    #[clap(flatten)]
+    output_args: OutputArgs,
+
+    /// The trees to scan.
+    #[clap(parse(from_os_str), required = true)]
+    dir_paths: Vec<PathBuf>,
+
+    #[clap(flatten)]
+    verbosity: VerbosityArgs,
+}
+
+impl_cli_opt!(Opt);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Problem {
+    Broken,
+    Cycle,
+    /// Absolute, pointing into the tree.
+    Absolute,
+}
+
+impl Problem {
+    fn name(self) -> &'static str {
+        match self {
+            Problem::Broken => "broken",
+            Problem::Cycle => "cycle",
+            Problem::Absolute => "absolute",
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Report {
+    problem: Problem,
+    path: PathBuf,
+    target: PathBuf,
+    /// The relative target for `Problem::Absolute`.
+    relative: Option<PathBuf>,
+}
+
+/// The path to `to` relative to the directory `from_dir`; both must be
+/// absolute and without `..` components.
+fn relative_path(from_dir: &Path, to: &Path) -> PathBuf {
+    let from: Vec<Component> = from_dir.components().collect();
+    let to: Vec<Component> = to.components().collect();
+    let common = from.iter().zip(&to).take_while(|(a, b)| a == b).count();
+    let mut path = PathBuf::new();
+    for _ in common..from.len() {
+        path.push("..");
+    }
+    for component in &to[common..] {
+        path.push(component);
+    }
+    if path.as_os_str().is_empty() {
+        path.push(".");
+    }
+    path
+}
+
+/// The problems with the link at `path`, inside a tree with the
+/// canonical path `root`.
+fn check_link(path: &Path, root: &Path) -> Result<Vec<Report>> {
+    let target = fs::read_link(path)
+        .with_context(|| anyhow!("reading link {:?}", path))?;
+    let mut reports = Vec::new();
+    let mut report = |problem, relative| {
+        reports.push(Report {
+            problem,
+            path: path.to_owned(),
+            target: target.clone(),
+            relative,
+        })
+    };
+    match fs::metadata(path) {
+        Ok(_) => {}
+        Err(e) if e.raw_os_error() == Some(libc::ELOOP) => {
+            report(Problem::Cycle, None)
+        }
+        Err(_) => report(Problem::Broken, None),
+    }
+    if target.is_absolute()
+        && target.starts_with(root)
+        && !target.components().any(|c| c == Component::ParentDir)
+    {
+        let dir = path.parent().expect("links found in a dir have a parent");
+        let dir = fs::canonicalize(dir)
+            .with_context(|| anyhow!("canonicalizing {:?}", dir))?;
+        report(Problem::Absolute, Some(relative_path(&dir, &target)));
+    }
+    Ok(reports)
+}
+
+/// Replace the link at `path` with one pointing to `target`, via a
+/// temporary link in the same directory and rename.
+fn replace_link(path: &Path, target: &Path) -> Result<()> {
+    let file_name = path.file_name().expect("links have a file name");
+    let mut tmp_name = b".".to_vec();
+    tmp_name.extend_from_slice(file_name.as_bytes());
+    tmp_name.extend_from_slice(
+        format!(".symlinks-report-{}", std::process::id()).as_bytes(),
+    );
+    let tmp = path.with_file_name(std::ffi::OsStr::from_bytes(&tmp_name));
+    std::os::unix::fs::symlink(target, &tmp)
+        .with_context(|| anyhow!("creating symlink {:?}", tmp))?;
+    if let Err(e) = fs::rename(&tmp, path) {
+        let _ = fs::remove_file(&tmp);
+        return Err(e)
+            .with_context(|| anyhow!("renaming {:?} to {:?}", tmp, path));
+    }
+    Ok(())
+}
+
+fn main() {
+    cli::main(run)
+}
+
+fn run(opt: Opt) -> Result<()> {
+    let excludes = empty_excludes(true);
+    let scan = DirScan {
+        opt: ItemOptions {
+            dirs: false,
+            files: false,
+            other: true,
+            follow_symlinks: false,
+            one_file_system: opt.one_file_system,
+        },
+        excludes: &excludes,
+        recursion: Recursion::All,
+    };
+    let mut reports = Vec::new();
+    for dir_path in &opt.dir_paths {
+        let root = fs::canonicalize(dir_path)
+            .with_context(|| anyhow!("canonicalizing {:?}", dir_path))?;
+        let mut found = scan.fold(
+            dir_path,
+            Vec::new,
+            |mut reports, item| {
+                let path = item.path();
+                if easy_stat(&path, false)?.filetype == FileType::Link {
+                    reports.extend(check_link(&path, &root)?);
+                }
+                Ok(reports)
+            },
+            |mut a, b| {
+                a.extend(b);
+                a
+            },
+        )?;
+        found.sort_by(|a, b| a.path.cmp(&b.path));
+        reports.extend(found);
+    }
+
+    let mut output = opt
+        .output_args
+        .output(&["problem", "path", "target", "relative"]);
+    let mut out = BufWriter::new(stdout().lock());
+    let mut num_fixed = 0;
+    for report in &reports {
+        if output.is_text() {
+            let problem = output.paint(
+                if report.problem == Problem::Absolute {
+                    BLUE
+                } else {
+                    RED
+                },
+                report.problem.name(),
+            );
+            write!(
+                out,
+                "{problem}: {} -> {}",
+                report.path.display(),
+                report.target.display()
+            )?;
+            if let Some(relative) = &report.relative {
+                write!(out, " (could be {})", relative.display())?;
+            }
+            writeln!(out)?;
+        } else {
+            output.write_record(
+                &mut out,
+                &[
+                    report.problem.name().into(),
+                    report.path.to_string_lossy().into(),
+                    report.target.to_string_lossy().into(),
+                    report
+                        .relative
+                        .as_ref()
+                        .map(|p| p.to_string_lossy())
+                        .unwrap_or_default()
+                        .into(),
+                ],
+            )?;
+        }
+        if let (true, Some(relative)) = (opt.fix_relative, &report.relative) {
+            if !opt.dry_run {
+                replace_link(&report.path, relative)?;
+            }
+            num_fixed += 1;
+        }
+    }
+    out.flush()?;
+    if opt.fix_relative {
+        eprintln!(
+            "symlinks-report: {} {num_fixed} link(s) to be relative",
+            if opt.dry_run {
+                "would rewrite"
+            } else {
+                "rewrote"
+            }
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn t_relative_path() {
+        let t = |from: &str, to: &str| {
+            relative_path(Path::new(from), Path::new(to))
+                .to_string_lossy()
+                .into_owned()
+        };
+        assert_eq!(t("/a/b", "/a/b/c"), "c");
+        assert_eq!(t("/a/b", "/a/c/d"), "../c/d");
+        assert_eq!(t("/a/b/c", "/a"), "../..");
+        assert_eq!(t("/a", "/a"), ".");
+        assert_eq!(t("/a/b", "/x"), "../../x");
+    }
+}
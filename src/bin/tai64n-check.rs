@@ -0,0 +1,128 @@
+use anyhow::{bail, Result};
+use std::io::{stdout, BufWriter, Write};
+use std::path::PathBuf;
+
+use chj_rustbin::cli::{self, VerbosityArgs};
+use chj_rustbin::impl_cli_opt;
+use chj_rustbin::io::readwithcontext::ReadWithContext;
+use chj_rustbin::time::realtime::parse_duration;
+use chj_rustbin::time::tai::{
+    check_sequence, parse_timestamp_tolerant, SequenceIssue, Tai64Format,
+    TimestampedLine,
+};
+use chj_rustbin::util::cli_output::{OutputArgs, RED};
+
+#[derive(clap::Parser, Debug)]
+/// Check logs with TAI64N timestamps (as written by daemontools'
+/// `multilog` or `tai64n`) for timestamps that are out of order or
+/// duplicated, and optionally for gaps larger than `--max-gap`. Lines
+/// without a timestamp (continuations of multi-line messages) are
+/// ignored. Each file is checked separately, gzip/xz/zstd compressed
+/// ones are decompressed. Exits with an error if any issues were
+/// found.
+#[clap(name = chj_rustbin::cli_name!())]
+struct Opt {
+    /// Report gaps between subsequent timestamps larger than this
+    /// (e.g. `30s`, `5m`); units: s, m, h, d, w (seconds if no unit
+    /// is given).
+    #[clap(long)]
+    max_gap: Option<String>,
+
+    #[clap(flatten)]
+    output_args: OutputArgs,
+
+    /// The log files to check.
+    #[clap(parse(from_os_str), required = true)]
+    file_paths: Vec<PathBuf>,
+
+    #[clap(flatten)]
+    verbosity: VerbosityArgs,
+}
+
+impl_cli_opt!(Opt);
+
+fn main() {
+    cli::main(run)
+}
+
+fn run(opt: Opt) -> Result<()> {
+    let max_gap = opt.max_gap.as_deref().map(parse_duration).transpose()?;
+    let mut output = opt
+        .output_args
+        .output(&["file", "line", "issue", "previous", "current", "seconds"]);
+    let mut out = BufWriter::new(stdout().lock());
+    let mut num_issues = 0;
+    for path in &opt.file_paths {
+        let mut inp = ReadWithContext::open_path_auto(path)?;
+        let mut stamps = Vec::new();
+        let mut line = String::new();
+        while inp.easy_read_line(&mut line)? {
+            if let TimestampedLine::Timestamped(t, _) =
+                parse_timestamp_tolerant(&line)
+            {
+                stamps.push((inp.linenumber(), t));
+            }
+        }
+        let report = check_sequence(stamps, max_gap);
+        for (linenumber, t, issue) in &report.issues {
+            let (previous, seconds) = match issue {
+                SequenceIssue::OutOfOrder { previous, by } => {
+                    (Some(*previous), -by.as_secs_f64())
+                }
+                SequenceIssue::Duplicate => (None, 0.),
+                SequenceIssue::Gap { previous, gap } => {
+                    (Some(*previous), gap.as_secs_f64())
+                }
+            };
+            if output.is_text() {
+                write!(
+                    out,
+                    "{}:{linenumber}: {}: {}",
+                    path.display(),
+                    output.paint(RED, issue.name()),
+                    t.to_rfc2822_local()
+                )?;
+                if let Some(previous) = previous {
+                    write!(
+                        out,
+                        " after {} ({seconds}s)",
+                        previous.to_rfc2822_local()
+                    )?;
+                }
+                writeln!(out)?;
+            } else {
+                output.write_record(
+                    &mut out,
+                    &[
+                        path.to_string_lossy().into(),
+                        (*linenumber).into(),
+                        issue.name().into(),
+                        previous
+                            .map(|p| p.to_rfc2822_local())
+                            .unwrap_or_default()
+                            .into(),
+                        t.to_rfc2822_local().into(),
+                        seconds.into(),
+                    ],
+                )?;
+            }
+        }
+        if opt.verbosity.is_verbose() {
+            eprintln!(
+                "tai64n-check: {:?}: {} timestamps, {} out of order, \
+                 {} duplicates, {} gaps",
+                path,
+                report.count,
+                report.num_out_of_order,
+                report.num_duplicates,
+                report.num_gaps,
+            );
+        }
+        num_issues += report.issues.len();
+    }
+    out.flush()?;
+    if num_issues > 0 {
+        bail!("found {num_issues} issue(s)")
+    }
+    Ok(())
+}
@@ -0,0 +1,419 @@
+use std::io::{stdout, BufRead, BufWriter, Write};
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+use anyhow::{anyhow, bail, Context, Result};
+
+use chj_rustbin::cli::{self, VerbosityArgs};
+use chj_rustbin::error::Categorize;
+use chj_rustbin::impl_cli_opt;
+use chj_rustbin::text::delimited::{
+    open_inputs, parse_delimiter, read_rows, Columns,
+};
+use chj_rustbin::text::json::parse_json_object;
+use chj_rustbin::text::parseutil::FieldSyntax;
+use chj_rustbin::time::realtime::{parse_duration, parse_time_spec};
+
+#[derive(clap::Parser, Debug)]
+/// Join two files of events with time ranges (TSV, or JSON lines with
+/// `--jsonl`): output the pairs of an event of the left file and an
+/// event of the right file whose ranges overlap (or, with `--within`,
+/// where the range of the right event lies within the range of the
+/// left one). Timestamps can be unix time, TAI64N labels, RFC 3339,
+/// RFC 2822 or local `YYYY-MM-DD HH:MM[:SS]`. Outputs TSV: the fields
+/// of the left event, then those of the right event, then the length
+/// of the overlap in seconds; with `--jsonl`, objects with `left`,
+/// `right` and `overlap_seconds` members. The right file is held in
+/// memory; the output is in the order of the left file, then of the
+/// start of the right events.
+#[clap(name = chj_rustbin::cli_name!())]
+struct Opt {
+    /// Read JSON lines (one object per line; the time columns are
+    /// member names), and output JSON lines.
+    #[clap(long)]
+    jsonl: bool,
+
+    /// The field delimiter of the TSV files (a single character).
+    /// `\t` is accepted for tab.
+    #[clap(short, long, default_value = "\\t", conflicts_with = "jsonl")]
+    delimiter: String,
+
+    /// The first line of both TSV files is a header; its column names
+    /// can be used for the time columns, and are used in the output
+    /// header.
+    #[clap(short = 'H', long, conflicts_with = "jsonl")]
+    header: bool,
+
+    /// The time columns of the left file: `START,END`, or `START` for
+    /// events without an end (see --left-duration), by number
+    /// (starting at 1) or (with --header or --jsonl) by name.
+    #[clap(short = '1', long, default_value = "start,end")]
+    left: String,
+
+    /// The time columns of the right file, if different from --left.
+    #[clap(short = '2', long)]
+    right: Option<String>,
+
+    /// The length of the left events if they have no end column (e.g.
+    /// `1h` for the hourly tables of parse-wg-log); they are points in
+    /// time otherwise.
+    #[clap(long)]
+    left_duration: Option<String>,
+
+    /// The length of the right events if they have no end column.
+    #[clap(long)]
+    right_duration: Option<String>,
+
+    /// Only output the pairs where the right event lies within the
+    /// range of the left one.
+    #[clap(long)]
+    within: bool,
+
+    #[clap(parse(from_os_str))]
+    left_path: PathBuf,
+
+    #[clap(parse(from_os_str))]
+    right_path: PathBuf,
+
+    #[clap(flatten)]
+    verbosity: VerbosityArgs,
+}
+
+impl_cli_opt!(Opt);
+
+/// Where the time range of an event is taken from; `C` is a column
+/// name or index.
+#[derive(Debug, Clone, PartialEq)]
+struct RangeSpec<C> {
+    start: C,
+    end: Option<C>,
+    /// The length of the range if `end` is None.
+    duration: Duration,
+}
+
+impl RangeSpec<String> {
+    fn parse(spec: &str, duration: Option<&str>) -> Result<Self> {
+        let (start, end) = match spec.split_once(',') {
+            Some((start, end)) => (start, Some(end.to_string())),
+            None => (spec, None),
+        };
+        if end.is_some() && duration.is_some() {
+            bail!("a duration is only used for events without an end column")
+        }
+        Ok(RangeSpec {
+            start: start.into(),
+            end,
+            duration: duration
+                .map(parse_duration)
+                .transpose()?
+                .unwrap_or_default(),
+        })
+    }
+}
+
+impl<C> RangeSpec<C> {
+    fn map<D>(&self, f: impl Fn(&C) -> Result<D>) -> Result<RangeSpec<D>> {
+        Ok(RangeSpec {
+            start: f(&self.start)?,
+            end: self.end.as_ref().map(f).transpose()?,
+            duration: self.duration,
+        })
+    }
+
+    /// The range of the event whose fields are retrieved via `value`.
+    fn range<'r>(
+        &self,
+        value: impl Fn(&C) -> Result<&'r str>,
+        now: SystemTime,
+    ) -> Result<(SystemTime, SystemTime)> {
+        let start = parse_time_spec(value(&self.start)?, now)?;
+        let end = match &self.end {
+            Some(end) => parse_time_spec(value(end)?, now)?,
+            None => start + self.duration,
+        };
+        if end < start {
+            bail!("the end of the event is before its start")
+        }
+        Ok((start, end))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Record {
+    Fields(Vec<String>),
+    /// A line of JSONL.
+    Json(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Event {
+    start: SystemTime,
+    end: SystemTime,
+    record: Record,
+}
+
+/// The right events, sorted by start, for finding those overlapping a
+/// range.
+struct Index {
+    events: Vec<Event>,
+    /// The length of the longest event.
+    max_duration: Duration,
+}
+
+impl Index {
+    fn new(mut events: Vec<Event>) -> Self {
+        events.sort_by_key(|e| e.start);
+        let max_duration = events
+            .iter()
+            .map(|e| e.end.duration_since(e.start).unwrap_or_default())
+            .max()
+            .unwrap_or_default();
+        Index {
+            events,
+            max_duration,
+        }
+    }
+
+    /// The events overlapping the range from `start` to `end`
+    /// (inclusive), or, if `within`, those lying within it.
+    fn matches(
+        &self,
+        start: SystemTime,
+        end: SystemTime,
+        within: bool,
+    ) -> impl Iterator<Item = &Event> {
+        // Events starting earlier are too short to reach `start`
+        let from = match start.checked_sub(self.max_duration) {
+            Some(earliest) => {
+                self.events.partition_point(|e| e.start < earliest)
+            }
+            None => 0,
+        };
+        let to = self.events.partition_point(|e| e.start <= end);
+        self.events[from..to].iter().filter(move |e| {
+            if within {
+                e.start >= start && e.end <= end
+            } else {
+                e.end >= start
+            }
+        })
+    }
+}
+
+/// The seconds that `a` and `b` overlap.
+fn overlap_seconds(a: &Event, b: &Event) -> f64 {
+    a.end
+        .min(b.end)
+        .duration_since(a.start.max(b.start))
+        .unwrap_or_default()
+        .as_secs_f64()
+}
+
+type Events = Box<dyn Iterator<Item = Result<Event>>>;
+
+/// The header (for TSV with `--header`) and the events of the file at
+/// `path`.
+fn read_events(
+    opt: &Opt,
+    path: &PathBuf,
+    spec: &RangeSpec<String>,
+) -> Result<(Option<Vec<String>>, Events)> {
+    let now = SystemTime::now();
+    let context = {
+        let path = path.clone();
+        let offset = opt.header as usize + 1;
+        move |i: usize| anyhow!("file {:?} line {}", path, i + offset)
+    };
+    let input = open_inputs(std::slice::from_ref(path))?
+        .pop()
+        .expect("one input per path");
+    if opt.jsonl {
+        let spec = spec.clone();
+        let events = input
+            .1
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| {
+                line.as_ref().map_or(true, |line| !line.trim().is_empty())
+            })
+            .map(move |(i, line)| {
+                (|| -> Result<Event> {
+                    let line = line?;
+                    let members = parse_json_object(&line)?;
+                    let (start, end) = spec.range(
+                        |name| {
+                            members
+                                .iter()
+                                .find(|(key, _)| key == name)
+                                .map(|(_, value)| value.as_str())
+                                .ok_or_else(|| {
+                                    anyhow!("missing member {name:?}")
+                                })
+                        },
+                        now,
+                    )?;
+                    Ok(Event {
+                        start,
+                        end,
+                        record: Record::Json(line),
+                    })
+                })()
+                .with_context(|| context(i))
+            });
+        Ok((None, Box::new(events)))
+    } else {
+        let syntax = FieldSyntax {
+            delimiter: parse_delimiter(&opt.delimiter)?,
+            ..FieldSyntax::tsv()
+        };
+        let mut rows = read_rows(vec![input], syntax, opt.header);
+        let header = if opt.header {
+            rows.next().transpose()?
+        } else {
+            None
+        };
+        let columns = Columns(header);
+        let spec = spec.map(|name| columns.index(name))?;
+        let events = rows.enumerate().map(move |(i, row)| {
+            let row = row?;
+            let (start, end) = spec
+                .range(
+                    |i| {
+                        row.get(*i)
+                            .map(String::as_str)
+                            .ok_or_else(|| anyhow!("missing column {}", i + 1))
+                    },
+                    now,
+                )
+                .with_context(|| context(i))?;
+            Ok(Event {
+                start,
+                end,
+                record: Record::Fields(row),
+            })
+        });
+        Ok((columns.0, Box::new(events)))
+    }
+}
+
+fn main() {
+    cli::main(run)
+}
+
+fn run(opt: Opt) -> Result<()> {
+    let left_spec = RangeSpec::parse(&opt.left, opt.left_duration.as_deref())
+        .usage_error()?;
+    let right_spec = RangeSpec::parse(
+        opt.right.as_ref().unwrap_or(&opt.left),
+        opt.right_duration.as_deref(),
+    )
+    .usage_error()?;
+    let (left_header, left_events) =
+        read_events(&opt, &opt.left_path, &left_spec)?;
+    let (right_header, right_events) =
+        read_events(&opt, &opt.right_path, &right_spec)?;
+    let index = Index::new(right_events.collect::<Result<_>>()?);
+
+    let mut out = BufWriter::new(stdout().lock());
+    // Pad the fields of each side to the width of its header
+    let left_width = left_header.as_ref().map_or(0, |h| h.len());
+    let right_width = right_header.as_ref().map_or(0, |h| h.len());
+    if let (Some(left), Some(right)) = (&left_header, &right_header) {
+        writeln!(
+            out,
+            "{}\t{}\toverlap seconds",
+            left.join("\t"),
+            right.join("\t")
+        )?;
+    }
+    for left in left_events {
+        let left = left?;
+        for right in index.matches(left.start, left.end, opt.within) {
+            let overlap = overlap_seconds(&left, right);
+            let mut line = String::new();
+            match (&left.record, &right.record) {
+                (Record::Fields(l), Record::Fields(r)) => {
+                    for (fields, width) in [(l, left_width), (r, right_width)] {
+                        for field in fields {
+                            line.push_str(field);
+                            line.push('\t');
+                        }
+                        for _ in fields.len()..width {
+                            line.push('\t');
+                        }
+                    }
+                    line.push_str(&overlap.to_string());
+                }
+                (Record::Json(l), Record::Json(r)) => {
+                    line.push_str(&format!(
+                        "{{\"left\":{l},\"right\":{r},\
+                         \"overlap_seconds\":{overlap}}}"
+                    ));
+                }
+                _ => unreachable!("both files are read the same way"),
+            }
+            line.push('\n');
+            out.write_all(line.as_bytes())?;
+        }
+    }
+    out.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::UNIX_EPOCH;
+
+    fn event(start: u64, end: u64) -> Event {
+        Event {
+            start: UNIX_EPOCH + Duration::from_secs(start),
+            end: UNIX_EPOCH + Duration::from_secs(end),
+            record: Record::Fields(vec![format!("{start}-{end}")]),
+        }
+    }
+
+    #[test]
+    fn t_matches() {
+        let index = Index::new(vec![
+            event(50, 60),
+            event(0, 100),
+            event(10, 10),
+            event(99, 120),
+            event(101, 102),
+        ]);
+        let t = |start, end, within| -> Vec<String> {
+            let e = event(start, end);
+            index
+                .matches(e.start, e.end, within)
+                .map(|e| match &e.record {
+                    Record::Fields(f) => f[0].clone(),
+                    Record::Json(_) => unreachable!(),
+                })
+                .collect()
+        };
+        assert_eq!(t(5, 10, false), ["0-100", "10-10"]);
+        assert_eq!(t(100, 100, false), ["0-100", "99-120"]);
+        assert_eq!(t(103, 200, false), ["99-120"]);
+        assert_eq!(t(200, 300, false), [] as [&str; 0]);
+        assert_eq!(t(5, 100, true), ["10-10", "50-60"]);
+        assert_eq!(overlap_seconds(&event(0, 100), &event(99, 120)), 1.);
+        assert_eq!(overlap_seconds(&event(10, 10), &event(0, 100)), 0.);
+    }
+
+    #[test]
+    fn t_range_spec() -> Result<()> {
+        let spec = RangeSpec::parse("time", Some("1h"))?;
+        assert_eq!(spec.duration, Duration::from_secs(3600));
+        let now = SystemTime::now();
+        let (start, end) = spec.range(|_| Ok("1000"), now)?;
+        assert_eq!(end.duration_since(start)?.as_secs(), 3600);
+        assert!(RangeSpec::parse("a,b", Some("1h")).is_err());
+        let spec = RangeSpec::parse("a,b", None)?;
+        assert_eq!(spec.end.as_deref(), Some("b"));
+        assert!(spec
+            .range(|c| Ok(if c == "a" { "20" } else { "10" }), now)
+            .is_err());
+        Ok(())
+    }
+}
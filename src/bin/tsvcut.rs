@@ -0,0 +1,95 @@
+use std::io::{stdout, BufWriter};
+use std::path::PathBuf;
+
+use anyhow::Result;
+
+use chj_rustbin::cli::{self, VerbosityArgs};
+use chj_rustbin::impl_cli_opt;
+use chj_rustbin::text::delimited::{
+    open_inputs, parse_delimiter, read_rows, selected_indices, Columns,
+    DelimiterArgs, RowWriter,
+};
+use chj_rustbin::time::excel::CsvSeparator;
+
+#[derive(clap::Parser, Debug)]
+/// Select and reorder columns of delimited data (TSV by default),
+/// like cut(1), but columns can also be given by header name, and
+/// are output in the order given. Columns missing in a row are output
+/// as empty fields.
+#[clap(name = chj_rustbin::cli_name!())]
+struct Opt {
+    #[clap(flatten)]
+    delimiter_args: DelimiterArgs,
+
+    /// The delimiter for the output (a single character, or `\t`).
+    #[clap(long, default_value = "\\t")]
+    output_delimiter: String,
+
+    /// Output CSV for Excel instead: `comma`, or `semicolon` for the
+    /// locales with a decimal comma (also written for the numbers).
+    #[clap(long, conflicts_with = "output-delimiter")]
+    excel_csv: Option<CsvSeparator>,
+
+    /// The first line of each input file is a header; its column
+    /// names can be used in `--fields`. The header of the first file
+    /// is output (with the selected columns), the others are dropped.
+    #[clap(short = 'H', long)]
+    header: bool,
+
+    /// The columns to output: by number (starting at 1) or (with
+    /// --header) by name, or ranges `FROM-TO`, `FROM-`, `-TO` of
+    /// those, comma separated. Can be given multiple times.
+    #[clap(short, long, required = true)]
+    fields: Vec<String>,
+
+    /// The files to read (stdin if none given).
+    #[clap(parse(from_os_str))]
+    paths: Vec<PathBuf>,
+
+    #[clap(flatten)]
+    verbosity: VerbosityArgs,
+}
+
+impl_cli_opt!(Opt);
+
+fn main() {
+    cli::main(run)
+}
+
+fn run(opt: Opt) -> Result<()> {
+    let syntax = opt.delimiter_args.syntax()?;
+    let output_delimiter = parse_delimiter(&opt.output_delimiter)?;
+    let mut rows = read_rows(open_inputs(&opt.paths)?, syntax, opt.header);
+
+    let header = if opt.header {
+        rows.next().transpose()?
+    } else {
+        None
+    };
+    let columns = Columns(header.clone());
+    let mut selection = Vec::new();
+    for spec in &opt.fields {
+        selection.extend(columns.selection(spec)?);
+    }
+
+    let mut out = RowWriter::new(
+        BufWriter::new(stdout().lock()),
+        output_delimiter,
+        opt.excel_csv,
+    );
+    let mut write_row = |row: &[String]| -> Result<()> {
+        let fields: Vec<&str> = selected_indices(&selection, row.len())
+            .into_iter()
+            .map(|i| row.get(i).map_or("", |s| s.as_str()))
+            .collect();
+        out.write_row(&fields)
+    };
+    if let Some(header) = &header {
+        write_row(header)?;
+    }
+    for row in rows {
+        write_row(&row?)?;
+    }
+    out.flush()?;
+    Ok(())
+}
@@ -0,0 +1,316 @@
+use std::collections::HashMap;
+use std::io::{stdout, BufWriter};
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use anyhow::{anyhow, bail, Result};
+
+use chj_rustbin::cli::{self, VerbosityArgs};
+use chj_rustbin::error::ErrorKind;
+use chj_rustbin::impl_cli_opt;
+use chj_rustbin::text::delimited::{
+    open_inputs, read_rows, Columns, DelimiterArgs, RowWriter,
+};
+use chj_rustbin::time::excel::CsvSeparator;
+
+#[derive(clap::Parser, Debug)]
+/// Join the rows of two files of delimited data (TSV by default) on
+/// key columns, like join(1), but the inputs don't need to be sorted
+/// (the right file is held in memory), and columns can be given by
+/// header name. Outputs TSV: the key columns, then the other columns
+/// of the left file, then those of the right file, in the order of
+/// the left file (for `outer`, followed by the unmatched rows of the
+/// right file). Missing values are output as empty fields.
+#[clap(name = chj_rustbin::cli_name!())]
+struct Opt {
+    #[clap(flatten)]
+    delimiter_args: DelimiterArgs,
+
+    /// The first line of both files is a header; its column names
+    /// can be used for the keys, and are used in the output header.
+    #[clap(short = 'H', long)]
+    header: bool,
+
+    /// The key columns for both files, by number (starting at 1) or
+    /// (with --header) by name, comma separated.
+    #[clap(
+        short,
+        long,
+        required_unless_present_all = &["left-key", "right-key"]
+    )]
+    key: Option<String>,
+
+    /// The key columns of the left file, if different from --key.
+    #[clap(short = '1', long)]
+    left_key: Option<String>,
+
+    /// The key columns of the right file, if different from --key.
+    #[clap(short = '2', long)]
+    right_key: Option<String>,
+
+    /// inner: only rows with a match in both files; left: all rows of
+    /// the left file; outer: all rows of both files.
+    #[clap(short, long, default_value = "inner")]
+    mode: Mode,
+
+    /// Output CSV for Excel instead of TSV: `comma`, or `semicolon`
+    /// for the locales with a decimal comma (also written for the
+    /// numbers).
+    #[clap(long)]
+    excel_csv: Option<CsvSeparator>,
+
+    #[clap(parse(from_os_str))]
+    left_path: PathBuf,
+
+    #[clap(parse(from_os_str))]
+    right_path: PathBuf,
+
+    #[clap(flatten)]
+    verbosity: VerbosityArgs,
+}
+
+impl_cli_opt!(Opt);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Inner,
+    Left,
+    Outer,
+}
+
+impl FromStr for Mode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "inner" => Ok(Mode::Inner),
+            "left" => Ok(Mode::Left),
+            "outer" => Ok(Mode::Outer),
+            _ => bail!("unknown join mode {s:?}, expecting inner|left|outer"),
+        }
+    }
+}
+
+/// The key columns of one input, and the number of columns to output
+/// for it.
+struct Side {
+    key_columns: Vec<usize>,
+    width: usize,
+}
+
+impl Side {
+    fn key(&self, row: &[String]) -> Result<Vec<String>> {
+        self.key_columns
+            .iter()
+            .map(|i| {
+                row.get(*i)
+                    .cloned()
+                    .ok_or_else(|| anyhow!("missing key column {}", i + 1))
+            })
+            .collect()
+    }
+
+    /// The non-key fields of `row` (None: of a missing row), padded to
+    /// `width`.
+    fn others<'r>(&self, row: Option<&'r [String]>) -> Vec<&'r str> {
+        (0..self.width)
+            .filter(|i| !self.key_columns.contains(i))
+            .map(|i| row.and_then(|row| row.get(i)).map_or("", |s| s.as_str()))
+            .collect()
+    }
+}
+
+struct Joiner {
+    mode: Mode,
+    left: Side,
+    right: Side,
+}
+
+impl Joiner {
+    fn output_row(
+        &self,
+        key: &[String],
+        left: Option<&[String]>,
+        right: Option<&[String]>,
+    ) -> Vec<String> {
+        key.iter()
+            .map(String::as_str)
+            .chain(self.left.others(left))
+            .chain(self.right.others(right))
+            .map(String::from)
+            .collect()
+    }
+
+    /// Join the `left` rows with `right`, passing the output rows to
+    /// `out`. The width of the left side is extended to the widest
+    /// row seen as the rows are processed.
+    fn join(
+        &mut self,
+        left: impl Iterator<Item = Result<Vec<String>>>,
+        right: Vec<Vec<String>>,
+        mut out: impl FnMut(Vec<String>) -> Result<()>,
+    ) -> Result<()> {
+        let mut index: HashMap<Vec<String>, Vec<usize>> = HashMap::new();
+        let mut right_keys = Vec::new();
+        for (i, row) in right.iter().enumerate() {
+            let key = self.right.key(row)?;
+            self.right.width = self.right.width.max(row.len());
+            index.entry(key.clone()).or_default().push(i);
+            right_keys.push(key);
+        }
+        let mut matched = vec![false; right.len()];
+        for row in left {
+            let row = row?;
+            self.left.width = self.left.width.max(row.len());
+            let key = self.left.key(&row)?;
+            match index.get(&key) {
+                Some(is) => {
+                    for i in is {
+                        matched[*i] = true;
+                        out(self.output_row(
+                            &key,
+                            Some(&row),
+                            Some(&right[*i]),
+                        ))?;
+                    }
+                }
+                None => {
+                    if self.mode != Mode::Inner {
+                        out(self.output_row(&key, Some(&row), None))?;
+                    }
+                }
+            }
+        }
+        if self.mode == Mode::Outer {
+            for (i, row) in right.iter().enumerate() {
+                if !matched[i] {
+                    out(self.output_row(&right_keys[i], None, Some(row)))?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn main() {
+    cli::main(run)
+}
+
+fn run(opt: Opt) -> Result<()> {
+    let syntax = opt.delimiter_args.syntax()?;
+    let read = |path: &PathBuf| -> Result<_> {
+        let mut rows = read_rows(
+            open_inputs(std::slice::from_ref(path))?,
+            syntax,
+            opt.header,
+        );
+        let header = if opt.header {
+            rows.next().transpose()?
+        } else {
+            None
+        };
+        Ok((Columns(header), rows))
+    };
+    let (left_columns, left_rows) = read(&opt.left_path)?;
+    let (right_columns, right_rows) = read(&opt.right_path)?;
+
+    let side = |columns: &Columns, spec: &Option<String>| -> Result<Side> {
+        let spec = spec
+            .as_ref()
+            .or(opt.key.as_ref())
+            .ok_or_else(|| anyhow!("missing --key"))?;
+        Ok(Side {
+            key_columns: spec
+                .split(',')
+                .map(|s| columns.index(s))
+                .collect::<Result<_>>()?,
+            width: columns.0.as_ref().map_or(0, |names| names.len()),
+        })
+    };
+    let mut joiner = Joiner {
+        mode: opt.mode,
+        left: side(&left_columns, &opt.left_key)?,
+        right: side(&right_columns, &opt.right_key)?,
+    };
+    if joiner.left.key_columns.len() != joiner.right.key_columns.len() {
+        return Err(ErrorKind::Usage.wrap(anyhow!(
+            "the left and right keys have different numbers of columns"
+        )));
+    }
+
+    let mut out =
+        RowWriter::new(BufWriter::new(stdout().lock()), '\t', opt.excel_csv);
+    if opt.header {
+        let names = |columns: &Columns, side: &Side| -> Vec<String> {
+            (0..side.width)
+                .filter(|i| !side.key_columns.contains(i))
+                .map(|i| columns.name(i))
+                .collect()
+        };
+        let header: Vec<String> = joiner
+            .left
+            .key_columns
+            .iter()
+            .map(|i| left_columns.name(*i))
+            .chain(names(&left_columns, &joiner.left))
+            .chain(names(&right_columns, &joiner.right))
+            .collect();
+        out.write_row(&header)?;
+    }
+    let right_rows = right_rows.collect::<Result<Vec<_>>>()?;
+    joiner.join(left_rows, right_rows, |row| out.write_row(&row))?;
+    out.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rows(rows: &[&[&str]]) -> Vec<Vec<String>> {
+        rows.iter()
+            .map(|row| row.iter().map(|s| s.to_string()).collect())
+            .collect()
+    }
+
+    fn t(mode: Mode) -> Vec<Vec<String>> {
+        let mut joiner = Joiner {
+            mode,
+            left: Side {
+                key_columns: vec![0],
+                width: 0,
+            },
+            right: Side {
+                key_columns: vec![1],
+                width: 0,
+            },
+        };
+        let left = rows(&[&["a", "1"], &["b", "2"], &["a", "3"]]);
+        let right = rows(&[&["x", "a"], &["y", "c"], &["z", "a"]]);
+        let mut result = Vec::new();
+        joiner
+            .join(left.into_iter().map(Ok), right, |row| {
+                result.push(row);
+                Ok(())
+            })
+            .unwrap();
+        result
+    }
+
+    #[test]
+    fn t_join() {
+        let inner: &[&[&str]] = &[
+            &["a", "1", "x"],
+            &["a", "1", "z"],
+            &["a", "3", "x"],
+            &["a", "3", "z"],
+        ];
+        assert_eq!(t(Mode::Inner), rows(inner));
+        let mut left = inner.to_vec();
+        left.insert(2, &["b", "2", ""]);
+        assert_eq!(t(Mode::Left), rows(&left));
+        let mut outer = left.clone();
+        outer.push(&["c", "", "y"]);
+        assert_eq!(t(Mode::Outer), rows(&outer));
+    }
+}
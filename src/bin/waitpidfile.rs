@@ -0,0 +1,99 @@
+use std::path::PathBuf;
+use std::time::Instant;
+
+use anyhow::{anyhow, Result};
+use nix::unistd::Pid;
+
+use chj_rustbin::cli::{self, VerbosityArgs};
+use chj_rustbin::error::ErrorKind;
+use chj_rustbin::impl_cli_opt;
+use chj_rustbin::process::{read_pidfile, wait_pid_gone};
+use chj_rustbin::time::realtime::parse_duration;
+
+#[derive(clap::Parser, Debug)]
+/// Wait until the process whose pid is stored in the given pidfile
+/// (or given via `--pid`) is gone, instead of looping over `kill -0`
+/// in shell. Uses a pidfd on Linux, otherwise polls with increasing
+/// intervals. Exits with code 0 when the process is gone, 124 (like
+/// timeout(1)) if `--timeout` expired first, 1 on errors. The exit
+/// status of the process itself can't be passed through, as the
+/// kernel only reports it to the process's parent.
+#[clap(name = chj_rustbin::cli_name!())]
+struct Opt {
+    /// Give up after this duration (e.g. `30s`, `5m`); units: s, m,
+    /// h, d, w (seconds if no unit is given).
+    #[clap(long)]
+    timeout: Option<String>,
+
+    /// The maximum interval between checks when polling.
+    #[clap(long, default_value = "1")]
+    max_interval: String,
+
+    /// Treat a missing pidfile as the process being gone (daemons
+    /// often remove their pidfile on exit).
+    #[clap(long)]
+    missing_ok: bool,
+
+    /// Wait for this pid instead of reading it from a pidfile.
+    #[clap(long, conflicts_with = "pidfile")]
+    pid: Option<i32>,
+
+    /// The file holding the pid of the process to wait for.
+    #[clap(parse(from_os_str), required_unless_present = "pid")]
+    pidfile: Option<PathBuf>,
+
+    #[clap(flatten)]
+    verbosity: VerbosityArgs,
+}
+
+impl_cli_opt!(Opt);
+
+fn main() {
+    cli::main(run)
+}
+
+fn run(opt: Opt) -> Result<()> {
+    let deadline = match &opt.timeout {
+        Some(timeout) => Some(Instant::now() + parse_duration(timeout)?),
+        None => None,
+    };
+    let max_interval = parse_duration(&opt.max_interval)?;
+    if max_interval.is_zero() {
+        return Err(ErrorKind::Usage
+            .wrap(anyhow!("--max-interval must be greater than zero")));
+    }
+
+    let pid = match (opt.pid, &opt.pidfile) {
+        (Some(pid), None) => {
+            if pid <= 0 {
+                return Err(ErrorKind::Usage.wrap(anyhow!("invalid pid {pid}")));
+            }
+            Pid::from_raw(pid)
+        }
+        (None, Some(pidfile)) => {
+            if opt.missing_ok && !pidfile.exists() {
+                if opt.verbosity.is_verbose() {
+                    eprintln!("waitpidfile: {pidfile:?} does not exist");
+                }
+                return Ok(());
+            }
+            read_pidfile(pidfile)?
+        }
+        _ => {
+            return Err(ErrorKind::Usage
+                .wrap(anyhow!("need exactly one of --pid or a pidfile")))
+        }
+    };
+
+    if wait_pid_gone(pid, deadline, max_interval)? {
+        if opt.verbosity.is_verbose() {
+            eprintln!("waitpidfile: process {pid} is gone");
+        }
+        Ok(())
+    } else {
+        if opt.verbosity.is_verbose() {
+            eprintln!("waitpidfile: timeout, process {pid} is still running");
+        }
+        std::process::exit(124)
+    }
+}
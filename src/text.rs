@@ -1,3 +0,0 @@
-pub mod naturallanguagejoin;
-pub mod parseutil;
-pub mod startswith;
@@ -1,3 +0,0 @@
-pub mod div;
-pub mod map_trait;
-pub mod scope;